@@ -13,6 +13,8 @@ pub enum ResourceKind {
     Template,
     Shortcode,
     AssetRoute,
+    Data,
+    Taxonomy,
 }
 
 impl ResourceKind {
@@ -298,14 +298,458 @@ pub struct RevisionConfig {
     /// SVG font face collection
     #[serde(default)]
     pub svg_fonts: Vec<SvgFontSpec>,
+
+    /// language of pages without a language suffix (`_index.md` as opposed
+    /// to `_index.fr.md`), e.g. `en` or `pt-BR`. Defaults to `en`.
+    #[serde(default)]
+    pub default_lang: Option<String>,
+
+    /// languages pages can be translated into, e.g. `["fr", "pt-BR"]`. Only
+    /// these count as language suffixes, so `node.js.md` stays `node.js`.
+    #[serde(default)]
+    pub languages: Vec<String>,
+
+    /// membership tiers, from lowest to highest. If empty, the built-in
+    /// Bronze/Silver/Gold tiers are used (see [`RevisionConfig::tiers`]).
+    #[serde(default)]
+    pub tiers: Vec<TierConfig>,
+
+    /// content sections, e.g. `/articles` or `/notes`. If empty, the built-in
+    /// articles/episodes/series/tests layout is used (see [`RevisionConfig::sections`]).
+    #[serde(default)]
+    pub sections: Vec<SectionConfig>,
+
+    /// ways to classify pages besides tags, e.g. `categories` or `authors`.
+    /// Pages set their terms with the `taxonomies` frontmatter field.
+    #[serde(default)]
+    pub taxonomies: Vec<TaxonomyConfig>,
+
+    /// people who write on the site, for bylines. Pages refer to them by `id`
+    /// in an `authors` taxonomy.
+    #[serde(default)]
+    pub authors: Vec<AuthorConfig>,
+
+    /// tags that are really another tag, e.g. `async-rust` => `async`.
+    /// Applied when loading pages, and `/tags/async-rust` redirects to `/tags/async`.
+    #[serde(default)]
+    pub tag_aliases: HashMap<String, String>,
+
+    /// custom fields pages can set under `extra` in their frontmatter, and
+    /// templates read as `page.extra.*`
+    #[serde(default)]
+    pub frontmatter_fields: Vec<FrontmatterFieldConfig>,
 }
 
 merde::derive! {
     impl (Serialize, Deserialize) for struct RevisionConfig {
-        id, patreon_campaign_ids, admin_github_ids, admin_patreon_ids, svg_fonts
+        id, patreon_campaign_ids, admin_github_ids, admin_patreon_ids, svg_fonts, default_lang, languages, tiers, sections,
+        taxonomies, authors, tag_aliases, frontmatter_fields
     }
 }
 
+impl RevisionConfig {
+    pub fn default_lang(&self) -> &str {
+        self.default_lang.as_deref().unwrap_or("en")
+    }
+
+    /// Whether pages can be translated into `lang` (see `languages`)
+    pub fn is_translation_lang(&self, lang: &str) -> bool {
+        lang != self.default_lang() && self.languages.iter().any(|l| l == lang)
+    }
+
+    /// Configured tiers, or the built-in ones if none are configured.
+    pub fn tiers(&self) -> &[TierConfig] {
+        if self.tiers.is_empty() {
+            &DEFAULT_TIERS
+        } else {
+            &self.tiers
+        }
+    }
+
+    /// Finds a tier by its name or one of its aliases (e.g. a Patreon tier title)
+    pub fn tier_by_title(&self, title: &str) -> Option<&TierConfig> {
+        self.tiers()
+            .iter()
+            .find(|t| t.name == title || t.aliases.iter().any(|a| a == title))
+    }
+
+    /// Finds a tier by name, ignoring case — used for `?admin_means_silver`-style overrides
+    pub fn tier_by_name_ignore_case(&self, name: &str) -> Option<&TierConfig> {
+        self.tiers()
+            .iter()
+            .find(|t| t.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The highest tier a GitHub sponsor paying `monthly_dollars` qualifies for
+    pub fn tier_for_github_monthly_dollars(&self, monthly_dollars: u32) -> Option<&TierConfig> {
+        self.tiers()
+            .iter()
+            .filter(|t| {
+                t.github_min_monthly_dollars
+                    .is_some_and(|min| monthly_dollars >= min)
+            })
+            .max_by_key(|t| t.rank)
+    }
+
+    /// The lowest-ranked tier, which is what "any paying member" means
+    pub fn lowest_tier(&self) -> Option<&TierConfig> {
+        self.tiers().iter().min_by_key(|t| t.rank)
+    }
+
+    /// Configured sections, or the built-in ones if none are configured.
+    pub fn sections(&self) -> &[SectionConfig] {
+        if self.sections.is_empty() {
+            &DEFAULT_SECTIONS
+        } else {
+            &self.sections
+        }
+    }
+
+    /// The section a route is in (or is the listing of), if any. The longest
+    /// prefix wins, so `/notes/til` can be its own section inside `/notes`.
+    pub fn section_for_route(&self, route: &str) -> Option<&SectionConfig> {
+        self.sections()
+            .iter()
+            .filter(|section| section.depth_of(route).is_some())
+            .max_by_key(|section| section.route.len())
+    }
+
+    /// The tag `tag` stands for, after aliases (`async-rust` => `async`),
+    /// following chains of them. Loops are rejected when loading the
+    /// revision, but this gives up rather than spin if there's one anyway.
+    pub fn canonical_tag<'a>(&'a self, tag: &'a str) -> &'a str {
+        let mut tag = tag;
+        for _ in 0..self.tag_aliases.len() {
+            match self.tag_aliases.get(tag) {
+                Some(target) => tag = target,
+                None => break,
+            }
+        }
+        tag
+    }
+
+    /// Finds a custom frontmatter field by name
+    pub fn frontmatter_field(&self, name: &str) -> Option<&FrontmatterFieldConfig> {
+        self.frontmatter_fields.iter().find(|f| f.name == name)
+    }
+
+    /// Finds a taxonomy by name, e.g. `categories`
+    pub fn taxonomy(&self, name: &str) -> Option<&TaxonomyConfig> {
+        self.taxonomies.iter().find(|t| t.name == name)
+    }
+
+    /// Finds an author by id, e.g. `amos`
+    pub fn author(&self, id: &str) -> Option<&AuthorConfig> {
+        self.authors.iter().find(|a| a.id == id)
+    }
+}
+
+/// A part of the site where pages of the same kind live, e.g. `/articles`
+#[derive(Facet, Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SectionConfig {
+    /// how templates refer to the section (`page.section`), e.g. `notes`
+    pub name: String,
+
+    /// route of the section's listing page, e.g. `/notes`. Its items are the
+    /// pages right below it, e.g. `/notes/some-note`
+    pub route: String,
+
+    /// what the items are, which decides how they're nested and described
+    pub kind: SectionKind,
+
+    /// how the listing orders items
+    #[serde(default)]
+    pub sort: SectionSort,
+
+    /// whether items show up in search results, related pages and feeds
+    #[serde(default)]
+    pub indexed: bool,
+
+    /// template for items that don't set one in their frontmatter. Defaults
+    /// to `page.html`
+    #[serde(default)]
+    pub template: Option<String>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct SectionConfig {
+        name, route, kind, sort, indexed, template
+    }
+}
+
+impl SectionConfig {
+    /// How many segments below the section's route `route` is: 0 for the
+    /// listing itself, 1 for items, 2 for series parts. `None` if it's
+    /// elsewhere or nested deeper than the section allows.
+    pub fn depth_of(&self, route: &str) -> Option<usize> {
+        let prefix = self.route.trim_end_matches('/');
+        let rest = route.strip_prefix(prefix)?;
+        if rest.is_empty() {
+            return Some(0);
+        }
+        let rest = rest.strip_prefix('/')?;
+        let depth = rest.split('/').count();
+        let max_depth = match self.kind {
+            SectionKind::Series => 2,
+            _ => 1,
+        };
+        (depth <= max_depth).then_some(depth)
+    }
+}
+
+/// What kind of pages a section holds
+#[derive(Facet, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[facet(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum SectionKind {
+    /// standalone written pieces
+    Articles,
+    /// pages built around a video
+    Episodes,
+    /// each item is a series index, with `part-N` pages below it
+    Series,
+    /// pages that are reachable but never listed anywhere
+    Tests,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for enum SectionKind string_like {
+        "articles" => Articles,
+        "episodes" => Episodes,
+        "series" => Series,
+        "tests" => Tests,
+    }
+}
+
+/// How a section's listing orders its items
+#[derive(Facet, Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[facet(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum SectionSort {
+    /// most recent first
+    #[default]
+    Newest,
+    /// oldest first
+    Oldest,
+    /// alphabetically, by title
+    Title,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for enum SectionSort string_like {
+        "newest" => Newest,
+        "oldest" => Oldest,
+        "title" => Title,
+    }
+}
+
+/// The sections used before they were configurable
+static DEFAULT_SECTIONS: LazyLock<Vec<SectionConfig>> = LazyLock::new(|| {
+    let section = |name: &str, kind, indexed| SectionConfig {
+        name: name.into(),
+        route: format!("/{name}"),
+        kind,
+        sort: SectionSort::Newest,
+        indexed,
+        template: None,
+    };
+    vec![
+        section("articles", SectionKind::Articles, true),
+        section("episodes", SectionKind::Episodes, false),
+        section("series", SectionKind::Series, true),
+        section("tests", SectionKind::Tests, false),
+    ]
+});
+
+/// A custom frontmatter field, e.g. a `subtitle` string or a `difficulty`
+/// that's one of `beginner`, `intermediate` or `advanced`
+#[derive(Facet, Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FrontmatterFieldConfig {
+    /// key under `extra` in the frontmatter, e.g. `subtitle`
+    pub name: String,
+
+    /// what values it takes
+    pub kind: FrontmatterFieldKind,
+
+    /// whether every page must set it
+    #[serde(default)]
+    pub required: bool,
+
+    /// value for pages that don't set it, as YAML, e.g. `false` or `[a, b]`
+    #[serde(default)]
+    pub default: Option<String>,
+
+    /// if non-empty, the only values allowed (for `string` and `strings`)
+    #[serde(default)]
+    pub values: Vec<String>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct FrontmatterFieldConfig {
+        name, kind, required, default, values
+    }
+}
+
+/// What values a custom frontmatter field takes
+#[derive(Facet, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[facet(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum FrontmatterFieldKind {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    /// RFC3339, like `date`
+    Date,
+    /// a list of strings
+    Strings,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for enum FrontmatterFieldKind string_like {
+        "string" => String,
+        "integer" => Integer,
+        "float" => Float,
+        "boolean" => Boolean,
+        "date" => Date,
+        "strings" => Strings,
+    }
+}
+
+/// A way to classify pages, e.g. `categories`. Each term gets a listing at
+/// `/{name}/{term}`, and the taxonomy itself lists its terms at `/{name}`.
+#[derive(Facet, Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaxonomyConfig {
+    /// plural, lowercase name, used in frontmatter and routes, e.g. `categories`
+    pub name: String,
+
+    /// whether each term gets an Atom feed at `/{name}/{term}/index.xml`
+    #[serde(default)]
+    pub feed: bool,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct TaxonomyConfig {
+        name, feed
+    }
+}
+
+impl TaxonomyConfig {
+    /// Route of the list of terms, e.g. `/categories`
+    pub fn route(&self) -> String {
+        format!("/{}", self.name)
+    }
+}
+
+/// Someone who writes on the site
+#[derive(Facet, Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthorConfig {
+    /// how pages refer to them, e.g. `amos`
+    pub id: String,
+
+    /// how bylines show them, e.g. `Amos Wenger`
+    pub name: String,
+
+    /// a sentence or two about them, in markdown
+    #[serde(default)]
+    pub bio: Option<String>,
+
+    /// input path of their picture, e.g. `/content/img/authors/amos.jpg`
+    #[serde(default)]
+    pub avatar: Option<String>,
+
+    /// where else to find them
+    #[serde(default)]
+    pub links: Vec<AuthorLink>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct AuthorConfig {
+        id, name, bio, avatar, links
+    }
+}
+
+/// A link on an author's byline, e.g. to their website
+#[derive(Facet, Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthorLink {
+    /// e.g. `Mastodon`
+    pub title: String,
+
+    pub url: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct AuthorLink {
+        title, url
+    }
+}
+
+/// A membership tier, e.g. "Silver"
+#[derive(Facet, Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TierConfig {
+    /// how the tier is shown and referred to, e.g. `Silver`
+    pub name: String,
+
+    /// higher ranks can see everything lower ranks can. Anonymous and free
+    /// viewers have rank 0, so tiers should start at 1.
+    pub rank: u32,
+
+    /// other titles that map to this tier, e.g. Patreon or GitHub Sponsors tier titles
+    #[serde(default)]
+    pub aliases: Vec<String>,
+
+    /// GitHub sponsors paying at least this much per month get this tier
+    #[serde(default)]
+    pub github_min_monthly_dollars: Option<u32>,
+
+    /// whether members of this tier are listed in the credits
+    #[serde(default)]
+    pub credited: bool,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct TierConfig {
+        name, rank, aliases, github_min_monthly_dollars, credited
+    }
+}
+
+/// The tiers used before they were configurable: Bronze, then Silver, then
+/// Gold (which Patreon's "Creator" tier maps to).
+static DEFAULT_TIERS: LazyLock<Vec<TierConfig>> = LazyLock::new(|| {
+    vec![
+        TierConfig {
+            name: "Bronze".into(),
+            rank: 1,
+            aliases: vec![],
+            github_min_monthly_dollars: Some(0),
+            credited: false,
+        },
+        TierConfig {
+            name: "Silver".into(),
+            rank: 2,
+            aliases: vec![],
+            github_min_monthly_dollars: Some(10),
+            credited: true,
+        },
+        TierConfig {
+            name: "Gold".into(),
+            rank: 3,
+            aliases: vec!["Creator".into()],
+            github_min_monthly_dollars: Some(50),
+            credited: true,
+        },
+    ]
+});
+
 #[derive(Clone, Facet, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct SvgFontSpec {
@@ -571,10 +1015,36 @@ pub struct MomSecrets {
     /// Can read/write specific tenants, used by humans
     #[serde(default)]
     pub scoped_api_keys: HashMap<MomApiKey, ScopedMomApiKey>,
+
+    /// Used to send sign-in links. If unset, emails are written to
+    /// `{tenant_data_dir}/mail-drop` instead.
+    #[serde(default)]
+    pub smtp: Option<SmtpSecrets>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct MomSecrets { readonly_api_key, scoped_api_keys, smtp }
+}
+
+#[derive(Clone, Facet, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SmtpSecrets {
+    /// e.g. `smtp.fastmail.com` — we always use STARTTLS
+    pub host: String,
+
+    /// defaults to 587
+    #[serde(default)]
+    pub port: Option<u16>,
+
+    pub username: String,
+    pub password: String,
+
+    /// sender address, e.g. `noreply@example.org`
+    pub from: String,
 }
 
 merde::derive! {
-    impl (Serialize, Deserialize) for struct MomSecrets { readonly_api_key, scoped_api_keys }
+    impl (Serialize, Deserialize) for struct SmtpSecrets { host, port, username, password, from }
 }
 
 pub const MOM_DEV_API_KEY: &MomApiKeyRef = MomApiKeyRef::from_static("mom_KEY_IN_DEV");
@@ -20,7 +20,10 @@ use objectstore_types::ObjectStoreKey;
 pub use time::OffsetDateTime;
 
 use closest::{GetOrHelp, ResourceKind};
-use config_types::{FontStyle, FontWeight, RevisionConfig, TenantConfig, TenantInfo, WebConfig};
+use config_types::{
+    FontStyle, FontWeight, RevisionConfig, SectionConfig, SectionKind, SectionSort, TenantConfig,
+    TenantInfo, WebConfig,
+};
 use plait::plait;
 
 mod av;
@@ -29,6 +32,12 @@ pub use av::*;
 mod derivations;
 pub use derivations::*;
 
+mod redirects;
+pub use redirects::*;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 /// An error that occurred while loading a revision
 #[derive(Debug, Clone)]
 pub struct RevisionError(pub String);
@@ -81,6 +90,12 @@ pub trait CacheBuster: Send + Sync + 'static {
         let _ = (path, self);
         unimplemented!()
     }
+
+    /// Get a data file by name, e.g. `talks` for `/data/talks.yaml`
+    fn data(&self, name: &str) -> Result<&LoadedDataFile> {
+        let _ = (name, self);
+        unimplemented!()
+    }
 }
 
 impl RevisionView for () {
@@ -106,6 +121,11 @@ impl CacheBuster for Revision {
         let rev = self.rev()?;
         Ok(rev.media.get_or_help(ResourceKind::Media, path)?)
     }
+
+    fn data(&self, name: &str) -> Result<&LoadedDataFile> {
+        let rev = self.rev()?;
+        Ok(rev.data.get_or_help(ResourceKind::Data, name)?)
+    }
 }
 
 pub type Toc = Vec<TocEntry>;
@@ -142,64 +162,93 @@ pub enum PageKind {
     Other,
 }
 
-impl From<&RouteRef> for PageKind {
-    fn from(path: &RouteRef) -> Self {
-        let tokens: Vec<&str> = path.as_str().split('/').skip(1).collect();
-        match tokens.as_slice() {
-            ["episodes"] => PageKind::EpisodesListing,
-            ["episodes", _slug] => PageKind::Episode,
-            ["articles"] => PageKind::ArticleListing,
-            ["articles", _slug] => PageKind::Article,
-            ["series"] => PageKind::SeriesListing,
-            ["series", _slug] => PageKind::SeriesIndex,
-            ["series", _slug, _part] => PageKind::SeriesPart,
-            ["tests", _slug] => PageKind::Test,
-            _ => PageKind::Other,
+impl PageKind {
+    /// What kind of page lives at `route`, given the revision's sections
+    pub fn from_route(route: &RouteRef, rc: &RevisionConfig) -> Self {
+        let route = route.as_str();
+        let Some(section) = rc.section_for_route(route) else {
+            return PageKind::Other;
+        };
+        match (section.kind, section.depth_of(route)) {
+            (SectionKind::Articles, Some(0)) => PageKind::ArticleListing,
+            (SectionKind::Articles, Some(_)) => PageKind::Article,
+            (SectionKind::Episodes, Some(0)) => PageKind::EpisodesListing,
+            (SectionKind::Episodes, Some(_)) => PageKind::Episode,
+            (SectionKind::Series, Some(0)) => PageKind::SeriesListing,
+            (SectionKind::Series, Some(1)) => PageKind::SeriesIndex,
+            (SectionKind::Series, Some(_)) => PageKind::SeriesPart,
+            (SectionKind::Tests, Some(0)) => PageKind::Other,
+            (SectionKind::Tests, Some(_)) => PageKind::Test,
+            (_, None) => PageKind::Other,
         }
     }
 }
 
 #[test]
 fn pagekind_from_path() {
+    let rc = RevisionConfig::default();
+    let kind = |route: &str| PageKind::from_route(RouteRef::from_str(route), &rc);
+
+    assert_eq!(kind("/articles"), PageKind::ArticleListing);
+    assert_eq!(kind("/articles/10-months-of-itch"), PageKind::Article);
+    assert_eq!(kind("/series"), PageKind::SeriesListing);
+    assert_eq!(kind("/series/making-our-own-ping"), PageKind::SeriesIndex);
     assert_eq!(
-        PageKind::from(RouteRef::from_str("/articles")),
-        PageKind::ArticleListing
-    );
-    assert_eq!(
-        PageKind::from(RouteRef::from_str("/articles/10-months-of-itch")),
-        PageKind::Article
-    );
-    assert_eq!(
-        PageKind::from(RouteRef::from_str("/series")),
-        PageKind::SeriesListing
-    );
-    assert_eq!(
-        PageKind::from(RouteRef::from_str("/series/making-our-own-ping")),
-        PageKind::SeriesIndex
-    );
-    assert_eq!(
-        PageKind::from(RouteRef::from_str("/series/making-our-own-ping/part-1")),
+        kind("/series/making-our-own-ping/part-1"),
         PageKind::SeriesPart
     );
+    assert_eq!(kind("/about"), PageKind::Other);
+    assert_eq!(kind("/"), PageKind::Other);
+    assert_eq!(kind("/episodes"), PageKind::EpisodesListing);
+    assert_eq!(kind("/episodes/dma"), PageKind::Episode);
+    assert_eq!(kind("/tests/example"), PageKind::Test);
+    assert_eq!(kind("/articles-old/foo"), PageKind::Other);
+}
+
+#[test]
+fn pagekind_from_configured_sections() {
+    let section = |name: &str, route: &str, kind| SectionConfig {
+        name: name.into(),
+        route: route.into(),
+        kind,
+        sort: Default::default(),
+        indexed: true,
+        template: None,
+    };
+    let rc = RevisionConfig {
+        sections: vec![
+            section("notes", "/notes", SectionKind::Articles),
+            section("til", "/notes/til", SectionKind::Articles),
+            section("talks", "/talks", SectionKind::Episodes),
+        ],
+        ..Default::default()
+    };
+    let kind = |route: &str| PageKind::from_route(RouteRef::from_str(route), &rc);
+
+    assert_eq!(kind("/notes"), PageKind::ArticleListing);
+    assert_eq!(kind("/notes/some-note"), PageKind::Article);
+    assert_eq!(kind("/notes/til"), PageKind::ArticleListing);
+    assert_eq!(kind("/notes/til/lifetimes"), PageKind::Article);
+    assert_eq!(kind("/talks/rustconf"), PageKind::Episode);
+    assert_eq!(kind("/articles/10-months-of-itch"), PageKind::Other);
     assert_eq!(
-        PageKind::from(Route::new(String::from("/about")).as_ref()),
-        PageKind::Other
-    );
-    assert_eq!(PageKind::from(RouteRef::from_str("/")), PageKind::Other);
-    assert_eq!(
-        PageKind::from(RouteRef::from_str("/episodes")),
-        PageKind::EpisodesListing
-    );
-    assert_eq!(
-        PageKind::from(RouteRef::from_str("/episodes/dma")),
-        PageKind::Episode
-    );
-    assert_eq!(
-        PageKind::from(RouteRef::from_str("/tests/example")),
-        PageKind::Test
+        rc.section_for_route("/notes/til/lifetimes")
+            .map(|s| s.name.as_str()),
+        Some("til")
     );
 }
 
+/// The value of a custom frontmatter field (see `FrontmatterFieldKind`)
+#[derive(Clone, Debug)]
+pub enum ExtraValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Date(Rfc3339<OffsetDateTime>),
+    Strings(Vec<String>),
+}
+
 #[derive(Clone)]
 pub struct LoadedPage {
     /// the tenant this page belongs to
@@ -214,9 +263,20 @@ pub struct LoadedPage {
     /// the canonical route path of the page — there might be aliases, but this is the canonical one
     pub route: Route,
 
+    /// language of the page: the suffix for translations (`_index.fr.md`),
+    /// the revision's default language otherwise
+    pub lang: String,
+
+    /// for translations, the input path of the original page (even if it
+    /// doesn't exist)
+    pub translation_of: Option<InputPath>,
+
     /// kind of page
     pub kind: PageKind,
 
+    /// the section the page is in (or is the listing of), e.g. `/articles`
+    pub section: Option<SectionConfig>,
+
     /// plain text version of the page
     pub plain_text: String,
 
@@ -229,12 +289,19 @@ pub struct LoadedPage {
     /// table of contents
     pub toc: Toc,
 
+    /// members-only sections, spliced into `html` at request time (see
+    /// [`LoadedPage::html_for`])
+    pub members_sections: Vec<MembersSection>,
+
     /// crates talked about (and which version)
     pub crates: HashMap<String, CrateVersion>,
 
     /// github repos talked about
     pub github_repos: Vec<String>,
 
+    /// files readers can download from this page
+    pub downloads: Vec<Download>,
+
     /// links to other pages, either relative (`/articles/10-months-of-itch`)
     /// or absolute (`https://fasterthanli.me/articles/10-months-of-itch`)
     pub links: Vec<Href>,
@@ -247,10 +314,20 @@ pub struct LoadedPage {
     pub archive: bool,
     pub aliases: Vec<Route>,
     pub tags: Vec<String>,
+    // taxonomy name => terms, e.g. `authors` => [`amos`]
+    pub taxonomies: HashMap<String, Vec<String>>,
+    // custom frontmatter fields, validated against `frontmatter_fields` in the config
+    pub extra: HashMap<String, ExtraValue>,
     pub ongoing: bool,
 
     pub draft_code: Option<String>,
 
+    // tier needed to read the whole page (see `LoadedPage::required_rank`)
+    pub min_tier: Option<String>,
+
+    // if set, `min_tier` only applies until then
+    pub exclusive_until: Option<Rfc3339<OffsetDateTime>>,
+
     pub updated_at: Option<Rfc3339<OffsetDateTime>>,
     pub rust_version: Option<String>,
 
@@ -262,6 +339,9 @@ pub struct LoadedPage {
     // its parts.
     pub parts: Vec<Part>,
 
+    // if this page is a series index, totals for all its parts
+    pub series_stats: Option<SeriesStats>,
+
     // input paths of all the direct children of this page
     pub children: Vec<InputPath>,
 
@@ -280,6 +360,30 @@ pub struct LoadedPage {
     pub parent_thumb: Option<PageThumb>,
 }
 
+/// A file attached to a page, stored under `extra-files/` in the object
+/// store and served from [`Download::route`] with a short-lived signed URL.
+#[derive(Debug, Clone, Serialize)]
+pub struct Download {
+    /// path under `extra-files/`, e.g. `rust-101/slides.pdf`
+    pub path: String,
+
+    /// what to call it, e.g. "Slides"
+    pub title: String,
+
+    /// tier needed to download it, `None` means whoever can read the page
+    pub tier: Option<String>,
+
+    /// where to link to, e.g. `/downloads/rust-101/slides.pdf`
+    pub route: Route,
+}
+
+impl Download {
+    /// The object store key the file lives at
+    pub fn key(&self) -> ObjectStoreKey {
+        ObjectStoreKey::new(format!("extra-files/{}", self.path))
+    }
+}
+
 /// The thumbnail for a page (if it exists)
 #[derive(Debug, Clone)]
 pub struct PageThumb {
@@ -287,40 +391,94 @@ pub struct PageThumb {
     pub media: Media,
 }
 
+/// A members-only part of an otherwise public page. Its HTML is kept out of
+/// the page's `html` (which only has a placeholder for it) and `plain_text`.
+#[derive(Debug, Clone)]
+pub struct MembersSection {
+    /// unique within a page, see [`members_section_placeholder`]
+    pub id: usize,
+
+    /// tier needed to see the section, `None` means any tier
+    pub tier: Option<String>,
+
+    /// the rendered section
+    pub html: String,
+}
+
+impl MembersSection {
+    pub fn is_visible(&self, viewer: &Viewer, rc: &RevisionConfig) -> bool {
+        viewer.is_admin
+            || match &self.tier {
+                Some(tier) => viewer.has_tier(rc, tier),
+                None => viewer.is_member(),
+            }
+    }
+
+    /// What viewers who can't see the section get instead
+    pub fn teaser_html(&self) -> String {
+        let (tier, who) = match &self.tier {
+            Some(tier) => (tier.as_str(), format!("{tier} members and above")),
+            None => ("", "members".to_string()),
+        };
+        let tier = html_escape::encode_double_quoted_attribute(tier);
+        let who = html_escape::encode_safe(&who);
+        format!(
+            r#"<div class="members-only-teaser" data-tier="{tier}"><p>This section is for {who} only.</p></div>"#
+        )
+    }
+}
+
+/// The marker left in a page's HTML where a members-only section goes
+pub fn members_section_placeholder(id: usize) -> String {
+    format!("<!-- home:members-section:{id} -->")
+}
+
+/// How many characters of an exclusive page viewers without the required
+/// tier get to see (it's rounded up to the end of a paragraph).
+pub const PAYWALL_EXCERPT_LENGTH: u64 = 1000;
+
 /// Determines what kind of access someone has to articles etc.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Viewer {
     /// User ID matches admin in config
     pub is_admin: bool,
 
-    /// As of Nov 2022, 5EUR/month or above
+    /// Rank of the viewer's tier (see `RevisionConfig::tiers`), 0 if they
+    /// don't have one.
+    pub tier_rank: u32,
+
+    /// Name of the viewer's tier, e.g. "Silver"
+    pub tier: Option<String>,
+
+    /// Deprecated, for templates written before tiers were configurable:
+    /// whether `tier_rank` reaches the lowest tier (Bronze by default)
     pub has_bronze: bool,
 
-    /// As of Nov 2022, 10EUR/month or above
+    /// Deprecated, like `has_bronze`: whether `tier_rank` reaches the second
+    /// lowest tier (Silver by default)
     pub has_silver: bool,
 }
 
 merde::derive!(
-    impl (Serialize, Deserialize) for struct Viewer { is_admin, has_bronze, has_silver }
+    impl (Serialize, Deserialize) for struct Viewer { is_admin, tier_rank, tier, has_bronze, has_silver }
 );
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum AccessOverride {
-    AdminMeansBronze,
-    AdminMeansSilver,
+    /// Admins see the site as a member of the given tier would (by name, any case)
+    AdminMeansTier(String),
 }
 
 impl AccessOverride {
     /// Determines what kind of access override is applied based on the query string.
-    /// Recognizes "admin_means_bronze" and "admin_means_silver".
+    /// Recognizes `admin_means_<tier>`, e.g. `admin_means_bronze` or `admin_means_silver`.
     pub fn from_raw_query(raw_query: &str) -> Option<Self> {
-        if raw_query.contains("admin_means_bronze") {
-            Some(Self::AdminMeansBronze)
-        } else if raw_query.contains("admin_means_silver") {
-            Some(Self::AdminMeansSilver)
-        } else {
-            None
-        }
+        raw_query.split('&').find_map(|pair| {
+            let key = pair.split('=').next().unwrap_or_default();
+            key.strip_prefix("admin_means_")
+                .filter(|tier| !tier.is_empty())
+                .map(|tier| Self::AdminMeansTier(tier.to_string()))
+        })
     }
 }
 
@@ -329,6 +487,8 @@ impl Viewer {
     pub fn anon() -> Self {
         Self {
             is_admin: false,
+            tier_rank: 0,
+            tier: None,
             has_bronze: false,
             has_silver: false,
         }
@@ -354,33 +514,50 @@ impl Viewer {
             }
 
             if let Some(tier) = &user_info.tier {
-                match tier.title.as_ref() {
-                    "Bronze" => {
-                        v.has_bronze = true;
-                    }
-                    "Silver" | "Gold" | "Creator" => {
-                        v.has_bronze = true;
-                        v.has_silver = true;
-                    }
-                    _ => {}
+                if let Some(tier) = rc.tier_by_title(&tier.title) {
+                    v.tier_rank = tier.rank;
+                    v.tier = Some(tier.name.clone());
                 }
             }
         }
 
         if let Some(access_override) = access_override {
             match access_override {
-                AccessOverride::AdminMeansBronze => {
-                    v.has_bronze = v.is_admin;
-                }
-                AccessOverride::AdminMeansSilver => {
-                    v.has_silver = v.is_admin;
-                    v.has_bronze = v.is_admin; // silver implies bronze
+                AccessOverride::AdminMeansTier(name) => {
+                    if v.is_admin {
+                        if let Some(tier) = rc.tier_by_name_ignore_case(&name) {
+                            v.tier_rank = tier.rank;
+                            v.tier = Some(tier.name.clone());
+                        }
+                    }
                 }
             }
         }
 
+        let mut ranks: Vec<u32> = rc.tiers().iter().map(|tier| tier.rank).collect();
+        ranks.sort_unstable();
+        v.has_bronze = ranks.first().is_some_and(|&rank| v.has_rank(rank));
+        v.has_silver = ranks.get(1).is_some_and(|&rank| v.has_rank(rank));
+
         v
     }
+
+    /// Whether the viewer's tier rank is at least `rank`
+    pub fn has_rank(&self, rank: u32) -> bool {
+        self.tier_rank >= rank
+    }
+
+    /// Whether the viewer has the named tier (or a higher one). Unknown tier
+    /// names are never satisfied.
+    pub fn has_tier(&self, rc: &RevisionConfig, name: &str) -> bool {
+        rc.tier_by_title(name)
+            .is_some_and(|tier| self.has_rank(tier.rank))
+    }
+
+    /// Whether the viewer has any tier at all
+    pub fn is_member(&self) -> bool {
+        self.tier_rank > 0
+    }
 }
 
 impl LoadedPage {
@@ -409,16 +586,39 @@ impl LoadedPage {
         matches!(self.kind, PageKind::ArticleListing)
     }
 
+    /// Whether the page shows up in search, related pages and feeds, which
+    /// is up to its section
     #[inline]
     pub fn is_indexed(&self) -> bool {
         if self.archive {
             return false;
         }
 
-        matches!(
-            self.kind,
-            PageKind::Article | PageKind::SeriesIndex | PageKind::SeriesPart
-        )
+        let in_indexed_section = self.section.as_ref().is_some_and(|s| s.indexed);
+        in_indexed_section
+            && matches!(
+                self.kind,
+                PageKind::Article
+                    | PageKind::Episode
+                    | PageKind::SeriesIndex
+                    | PageKind::SeriesPart
+            )
+    }
+
+    /// Whether `other` is in the same section as this page
+    pub fn same_section(&self, other: &LoadedPage) -> bool {
+        match (&self.section, &other.section) {
+            (Some(a), Some(b)) => a.route == b.route,
+            _ => false,
+        }
+    }
+
+    /// How this page's listing orders its items
+    pub fn listing_sort(&self) -> SectionSort {
+        self.section
+            .as_ref()
+            .map(|section| section.sort)
+            .unwrap_or_default()
     }
 
     #[inline]
@@ -448,9 +648,80 @@ impl LoadedPage {
         self.is_visible(viewer) && !self.archive
     }
 
+    /// Rank needed to read the whole page right now, if it's exclusive.
+    /// Unknown tiers are rejected at load time, so they're treated as open.
+    pub fn required_rank(&self, rc: &RevisionConfig) -> Option<u32> {
+        let min_tier = self.min_tier.as_deref()?;
+        if let Some(exclusive_until) = &self.exclusive_until {
+            if exclusive_until.0 <= OffsetDateTime::now_utc() {
+                return None;
+            }
+        }
+        rc.tier_by_title(min_tier).map(|tier| tier.rank)
+    }
+
+    /// The page's HTML with its members-only sections filled in, or replaced
+    /// with a teaser if the viewer can't see them.
+    pub fn html_for(&self, viewer: &Viewer, rc: &RevisionConfig) -> String {
+        let mut html = self.html.clone();
+        for section in &self.members_sections {
+            let replacement = if section.is_visible(viewer, rc) {
+                section.html.clone()
+            } else {
+                section.teaser_html()
+            };
+            html = html.replacen(&members_section_placeholder(section.id), &replacement, 1);
+        }
+        html
+    }
+
+    /// Whether the viewer only gets an excerpt of the page
+    pub fn is_paywalled_for(&self, viewer: &Viewer, rc: &RevisionConfig) -> bool {
+        !viewer.is_admin
+            && self
+                .required_rank(rc)
+                .is_some_and(|rank| !viewer.has_rank(rank))
+    }
+
+    /// A copy of the page for viewers who can't read all of it: the body is
+    /// replaced with `excerpt_html` (typically the first few paragraphs, see
+    /// [`PAYWALL_EXCERPT_LENGTH`]), and the plain text and table of contents
+    /// are cut down to match.
+    pub fn to_paywalled(&self, excerpt_html: String) -> LoadedPage {
+        let plain_text = self
+            .plain_text
+            .chars()
+            .take(PAYWALL_EXCERPT_LENGTH as usize)
+            .collect();
+        LoadedPage {
+            html: excerpt_html,
+            plain_text,
+            toc: Default::default(),
+            ..self.clone()
+        }
+    }
+
     pub fn canonical_url(&self, web: WebConfig) -> AbsoluteUrl {
         self.route.to_web_url_string(&self.ti.tc, web)
     }
+
+    /// The route without the language prefix, e.g. `/articles/foo` for
+    /// `/fr/articles/foo`. This is what determines the page kind.
+    pub fn unlocalized_route(&self) -> &RouteRef {
+        if self.translation_of.is_none() {
+            return &self.route;
+        }
+        let rest = self
+            .route
+            .as_str()
+            .strip_prefix('/')
+            .and_then(|route| route.strip_prefix(self.lang.as_str()));
+        match rest {
+            Some("") => RouteRef::from_str("/"),
+            Some(rest) if rest.starts_with('/') => RouteRef::from_str(rest),
+            _ => &self.route,
+        }
+    }
 }
 
 impl PartialEq for LoadedPage {
@@ -491,6 +762,21 @@ pub struct SeriesLink {
 
     /// 1-based index: part 1, part 2, etc.
     pub part_number: PartNumber,
+
+    /// title of the series index page
+    pub series_title: String,
+
+    /// stats for the whole series
+    pub stats: SeriesStats,
+
+    /// this part, with cumulative times
+    pub part: Option<Part>,
+
+    /// the part before this one, if any
+    pub prev: Option<Part>,
+
+    /// the part after this one, if any
+    pub next: Option<Part>,
 }
 
 impl InputPathRef {
@@ -584,6 +870,46 @@ impl InputPathRef {
         // Convert the resulting string to a RoutePathRef
         RouteRef::from_str(route_path)
     }
+
+    /// The language suffix of a translated page, if any. Only languages
+    /// listed in the revision config count.
+    /// E.g. "/content/articles/hello-world/_index.fr.md" -> Some("fr")
+    /// E.g. "/content/articles/hello-world/_index.md" -> None
+    pub fn lang(&self, rc: &RevisionConfig) -> Option<&str> {
+        let (base, ext) = self.explode();
+        if ext != "md" {
+            return None;
+        }
+        let (_, lang) = base.rsplit_once('.')?;
+        rc.is_translation_lang(lang).then_some(lang)
+    }
+
+    /// The path of the page a translation is a variant of.
+    /// E.g. "/content/articles/hello-world/_index.fr.md" -> "/content/articles/hello-world/_index.md"
+    pub fn without_lang(&self, rc: &RevisionConfig) -> InputPath {
+        match self.lang(rc) {
+            Some(lang) => {
+                let (base, ext) = self.explode();
+                let base = &base[..base.len() - lang.len() - 1];
+                InputPath::new(format!("{base}.{ext}"))
+            }
+            None => self.to_owned(),
+        }
+    }
+
+    /// Like `to_route_path`, but translations get prefixed with their language.
+    /// E.g. "/content/articles/hello-world.md" -> "/articles/hello-world"
+    /// E.g. "/content/articles/hello-world.fr.md" -> "/fr/articles/hello-world"
+    /// E.g. "/content/_index.fr.md" -> "/fr"
+    pub fn to_localized_route(&self, rc: &RevisionConfig) -> Route {
+        match self.lang(rc) {
+            Some(lang) => match self.without_lang(rc).to_route_path().as_str() {
+                "/" => Route::new(format!("/{lang}")),
+                route => Route::new(format!("/{lang}{route}")),
+            },
+            None => self.to_route_path().to_owned(),
+        }
+    }
 }
 
 #[test]
@@ -606,6 +932,57 @@ fn test_to_route_path() {
     );
 }
 
+#[test]
+fn test_to_localized_route() {
+    let rc = RevisionConfig {
+        languages: vec!["fr".into(), "de".into(), "pt-BR".into()],
+        ..Default::default()
+    };
+
+    let path = InputPathRef::from_str("/content/series/rust-101/part-1/_index.fr.md");
+    assert_eq!(path.lang(&rc), Some("fr"));
+    assert_eq!(
+        path.without_lang(&rc).as_str(),
+        "/content/series/rust-101/part-1/_index.md"
+    );
+    assert_eq!(
+        path.to_localized_route(&rc).as_str(),
+        "/fr/series/rust-101/part-1"
+    );
+
+    assert_eq!(
+        InputPathRef::from_str("/content/_index.pt-BR.md")
+            .to_localized_route(&rc)
+            .as_str(),
+        "/pt-BR"
+    );
+    assert_eq!(
+        InputPathRef::from_str("/content/about.de.md")
+            .to_localized_route(&rc)
+            .as_str(),
+        "/de/about"
+    );
+
+    // untranslated pages are unaffected, and so are suffixes that aren't
+    // configured languages
+    for path in [
+        "/content/about.md",
+        "/content/v1.2.md",
+        "/content/about.Draft.md",
+        "/content/articles/node.js.md",
+        "/content/about.es.md",
+        "/content/about.en.md",
+    ] {
+        let path = InputPathRef::from_str(path);
+        assert_eq!(path.lang(&rc), None);
+        assert_eq!(path.without_lang(&rc).as_str(), path.as_str());
+        assert_eq!(
+            path.to_localized_route(&rc).as_str(),
+            path.to_route_path().as_str()
+        );
+    }
+}
+
 plait! {
     with crates {
         serde
@@ -800,10 +1177,14 @@ pub struct Revision {
     /// loaded pages
     pub pages: HashMap<InputPath, Arc<LoadedPage>>,
 
-    /// maps routes (e.g. `/about`) to page hapas.
-    /// aliases just create extra routes.
+    /// maps routes (e.g. `/about`) to page hapas. only canonical routes,
+    /// aliases are in `redirects`.
     pub page_routes: HashMap<Route, InputPath>,
 
+    /// page aliases and the tenant's `_redirects` file, applied to routes
+    /// that don't match a page
+    pub redirects: Redirects,
+
     /// This helps serve `https://cdn.fasterthanli.me/articles/foo/bar~hash.jxl`:
     /// either from memory, or by pointing at an input that may be processed etc.
     pub assets: HashMap<Route, Asset>,
@@ -816,9 +1197,24 @@ pub struct Revision {
     /// maps tags to page hapas
     pub tags: HashMap<String, Vec<InputPath>>,
 
+    /// maps taxonomy names to their terms, and terms to page hapas
+    /// (e.g. `categories` => `rust` => [...])
+    pub taxonomies: HashMap<String, HashMap<String, Vec<InputPath>>>,
+
     /// media files (including their variants: resized bitmaps, videos, etc.)
     pub media: HashMap<InputPath, Media>,
 
+    /// maps page hapas to related pages, most related first. computed at load
+    /// time, visibility is left to whoever displays them.
+    pub related: HashMap<InputPath, Vec<RelatedPage>>,
+
+    /// maps original page hapas to the hapas of their translations
+    /// (e.g. `_index.md` => [`_index.de.md`, `_index.fr.md`])
+    pub translations: HashMap<InputPath, Vec<InputPath>>,
+
+    /// parsed data files, by name (`talks` for `/data/talks.yaml`)
+    pub data: HashMap<String, LoadedDataFile>,
+
     /// the path mappings that were used to build that revision, or, failing that, the mappings that
     /// we're going to use to load the revision which will impact... I don't know. I guess we don't
     /// need any path mappings if we receive the revision from mother?
@@ -833,6 +1229,42 @@ impl Revision {
     pub fn inputs(&self) -> &HashMap<InputPath, Input> {
         &self.pak.inputs
     }
+
+    /// All language versions of a page, the original first, then
+    /// translations sorted by language. Just the page itself if it
+    /// was never translated.
+    pub fn translations_of(&self, page: &LoadedPage) -> Vec<&Arc<LoadedPage>> {
+        let original = page.translation_of.as_ref().unwrap_or(&page.path);
+        std::iter::once(original)
+            .chain(self.translations.get(original).into_iter().flatten())
+            .filter_map(|path| self.pages.get(path))
+            .collect()
+    }
+
+    /// The next time after `now` when what anonymous viewers get changes on
+    /// its own: a scheduled page goes live, or an exclusive page (dual
+    /// features included) opens up. Anything rendered before then is stale
+    /// after it.
+    pub fn next_time_boundary(&self, now: OffsetDateTime) -> Option<OffsetDateTime> {
+        self.pages
+            .values()
+            .flat_map(|page| [Some(&page.date), page.exclusive_until.as_ref()])
+            .flatten()
+            .map(|date| date.0)
+            .filter(|&date| date > now)
+            .min()
+    }
+}
+
+/// A page that's related to another page (shares tags, crates, links, or
+/// just talks about similar things)
+#[derive(Debug, Clone)]
+pub struct RelatedPage {
+    pub path: InputPath,
+
+    /// higher is more related, only meaningful relative to other scores
+    /// for the same page
+    pub score: f32,
 }
 
 impl fmt::Debug for Revision {
@@ -874,11 +1306,18 @@ pub struct Pak {
 
     /// Revision config (admin github IDs, etc.)
     pub rc: RevisionConfig,
+
+    /// Contents of `/content/_redirects`, if any. Parsed when loading the revision.
+    pub redirects: Option<String>,
+
+    /// Structured data files from `/data`. Optional so paks made before
+    /// data files existed still deserialize.
+    pub data: Option<HashMap<InputPath, DataFile>>,
 }
 
 merde::derive! {
     impl (Serialize, Deserialize) for struct Pak {
-        id, inputs, pages, media_props, templates, svg_font_face_collection, rc
+        id, inputs, pages, media_props, templates, svg_font_face_collection, rc, redirects, data
     }
 }
 
@@ -1072,6 +1511,33 @@ pub struct Part {
     pub title: String,
     pub path: InputPath,
     pub route: Route,
+
+    /// estimated reading time of this part, in minutes
+    pub reading_time: i64,
+
+    /// duration of this part's video, in seconds, if it has one
+    pub video_duration: Option<u64>,
+
+    /// reading time of this part and all the parts before it
+    pub cumulative_reading_time: i64,
+
+    /// video duration of this part and all the parts before it
+    pub cumulative_video_duration: u64,
+}
+
+/// Aggregate data about a series, so templates don't have to add things up
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SeriesStats {
+    pub num_parts: usize,
+
+    /// sum of the reading times of all parts, in minutes
+    pub total_reading_time: i64,
+
+    /// sum of the video durations of all parts, in seconds
+    pub total_video_duration: u64,
+
+    /// whether more parts are coming (`ongoing` in the index's frontmatter)
+    pub ongoing: bool,
 }
 
 /// A 1-based series part number
@@ -1170,6 +1636,47 @@ merde::derive! {
     }
 }
 
+/// A structured data file from `/data`, e.g. `/data/talks.yaml`. Parsed when
+/// loading the revision, see [`LoadedDataFile`].
+#[derive(Clone)]
+pub struct DataFile {
+    pub path: InputPath,
+
+    /// JSON, YAML or TOML markup, depending on the extension
+    pub markup: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct DataFile {
+        path, markup
+    }
+}
+
+impl DataFile {
+    /// What templates call it: `/data/talks.yaml` is `talks`,
+    /// `/data/menus/main.toml` is `menus/main`.
+    pub fn name(&self) -> &str {
+        let name = self
+            .path
+            .as_str()
+            .strip_prefix(DATA_INPUT_PATH.as_str())
+            .unwrap_or(self.path.as_str())
+            .trim_start_matches('/');
+        match name.rfind('.') {
+            Some(dot) => &name[..dot],
+            None => name,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LoadedDataFile {
+    pub path: InputPath,
+
+    /// The parsed contents, ready to hand to templates
+    pub value: minijinja::Value,
+}
+
 #[derive(Clone)]
 pub struct Stylesheet {
     pub path: InputPath,
@@ -1268,12 +1775,17 @@ pub struct PathMappings {
     entries: Vec<PathMapping>,
 }
 
-pub const ROOT_INPUT_PATHS: [&InputPathRef; 3] = [
+pub const ROOT_INPUT_PATHS: [&InputPathRef; 4] = [
     InputPathRef::from_static("/home.json"),
     InputPathRef::from_static("/content"),
     InputPathRef::from_static("/templates"),
+    DATA_INPUT_PATH,
 ];
 
+/// Structured data files available to templates through `get_data`. Unlike the
+/// other root paths, it doesn't have to exist.
+pub const DATA_INPUT_PATH: &InputPathRef = InputPathRef::from_static("/data");
+
 impl PathMappings {
     /// Creates a new PathMappings with a default mapping from `/content` to the content directory.
     pub fn from_ti(ti: &TenantInfo) -> Self {
@@ -1369,6 +1881,48 @@ impl PathMappings {
 
 #[cfg(test)]
 mod tests {
+    #[test]
+    fn test_teaser_html_escapes_the_tier() {
+        let section = crate::MembersSection {
+            id: 0,
+            tier: Some("<b>Gold</b> \"& up\"".into()),
+            html: String::new(),
+        };
+        let html = section.teaser_html();
+        assert!(html.contains("Gold"), "{html}");
+        assert!(!html.contains("<b>"), "{html}");
+        assert!(!html.contains("\"& up"), "{html}");
+    }
+
+    #[test]
+    fn test_next_time_boundary() {
+        use merde::time::Rfc3339;
+        use time::Duration;
+
+        use crate::{OffsetDateTime, testing};
+
+        let rc = config_types::RevisionConfig::default();
+        let now = OffsetDateTime::now_utc();
+        let mut published = testing::page(&rc, "/articles/published");
+        published.date = Rfc3339(now - Duration::days(10));
+        let mut exclusive = testing::page(&rc, "/articles/exclusive");
+        exclusive.date = Rfc3339(now - Duration::days(1));
+        exclusive.exclusive_until = Some(Rfc3339(now + Duration::days(3)));
+        let mut scheduled = testing::page(&rc, "/articles/scheduled");
+        scheduled.date = Rfc3339(now + Duration::days(2));
+
+        let rev = testing::revision(rc.clone(), [published.clone()]);
+        assert_eq!(rev.next_time_boundary(now), None);
+
+        let rev = testing::revision(rc, [published, exclusive, scheduled]);
+        assert_eq!(rev.next_time_boundary(now), Some(now + Duration::days(2)));
+        assert_eq!(
+            rev.next_time_boundary(now + Duration::days(2)),
+            Some(now + Duration::days(3))
+        );
+        assert_eq!(rev.next_time_boundary(now + Duration::days(3)), None);
+    }
+
     #[test]
     fn test_input_path_roundtrip() {
         use camino::Utf8PathBuf;
@@ -1413,6 +1967,122 @@ mod tests {
         assert!(mappings.to_input_path_maybe(&invalid_path).is_none());
     }
 
+    mod viewer {
+        use config_types::{RevisionConfig, TierConfig};
+        use credentials::{Profile, Tier, UserInfo};
+
+        use crate::{AccessOverride, Viewer};
+
+        fn user(tier: Option<&str>, github_id: &str) -> UserInfo {
+            UserInfo {
+                profile: Profile {
+                    github_id: Some(github_id.into()),
+                    ..Default::default()
+                },
+                tier: tier.map(|title| Tier {
+                    title: title.into(),
+                }),
+            }
+        }
+
+        fn rc() -> RevisionConfig {
+            RevisionConfig {
+                admin_github_ids: vec!["admin".into()],
+                ..Default::default()
+            }
+        }
+
+        fn viewer(tier: Option<&str>) -> Viewer {
+            Viewer::new(rc(), Some(&user(tier, "1234")), None)
+        }
+
+        #[test]
+        fn test_anon() {
+            let v = Viewer::anon();
+            assert!(!v.is_admin && !v.is_member());
+            assert_eq!(v.tier_rank, 0);
+            assert!(!v.has_tier(&rc(), "Bronze"));
+            assert!(!v.has_bronze && !v.has_silver);
+
+            // signed in, but not a member
+            let v = viewer(None);
+            assert!(!v.is_member());
+            assert!(v.has_rank(0) && !v.has_rank(1));
+        }
+
+        #[test]
+        fn test_tier_comparisons() {
+            let rc = rc();
+
+            let bronze = viewer(Some("Bronze"));
+            assert_eq!(bronze.tier.as_deref(), Some("Bronze"));
+            assert!(bronze.has_tier(&rc, "Bronze"));
+            assert!(!bronze.has_tier(&rc, "Silver"));
+            assert!(bronze.has_bronze && !bronze.has_silver);
+
+            let silver = viewer(Some("Silver"));
+            assert!(silver.has_tier(&rc, "Bronze") && silver.has_tier(&rc, "Silver"));
+            assert!(!silver.has_tier(&rc, "Gold"));
+            assert!(silver.has_bronze && silver.has_silver);
+
+            // aliases resolve to their tier
+            let creator = viewer(Some("Creator"));
+            assert_eq!(creator.tier.as_deref(), Some("Gold"));
+            assert!(creator.has_tier(&rc, "Creator") && creator.has_tier(&rc, "Silver"));
+            assert!(creator.has_bronze && creator.has_silver);
+
+            // unknown tiers give nothing, and are never satisfied
+            let unknown = viewer(Some("Platinum"));
+            assert!(!unknown.is_member());
+            assert!(!silver.has_tier(&rc, "Platinum"));
+        }
+
+        #[test]
+        fn test_custom_tiers_and_legacy_flags() {
+            let tier = |name: &str, rank| TierConfig {
+                name: name.into(),
+                rank,
+                aliases: vec![],
+                github_min_monthly_dollars: None,
+                credited: false,
+            };
+            // listed out of order on purpose
+            let rc = RevisionConfig {
+                tiers: vec![tier("Hero", 30), tier("Supporter", 10), tier("Patron", 20)],
+                ..Default::default()
+            };
+            let viewer = |title: &str| Viewer::new(rc.clone(), Some(&user(Some(title), "1")), None);
+
+            let supporter = viewer("Supporter");
+            assert!(supporter.has_bronze && !supporter.has_silver);
+            let patron = viewer("Patron");
+            assert!(patron.has_bronze && patron.has_silver);
+            assert!(patron.has_tier(&rc, "Supporter") && !patron.has_tier(&rc, "Hero"));
+        }
+
+        #[test]
+        fn test_admin_means_tier() {
+            let rc = rc();
+            let admin_as = |tier: &str, github_id: &str| {
+                Viewer::new(
+                    rc.clone(),
+                    Some(&user(None, github_id)),
+                    Some(AccessOverride::AdminMeansTier(tier.into())),
+                )
+            };
+
+            let admin = admin_as("bronze", "admin");
+            assert!(admin.is_admin);
+            assert_eq!(admin.tier.as_deref(), Some("Bronze"));
+            assert!(admin.has_bronze && !admin.has_silver);
+            assert!(!admin.has_tier(&rc, "Silver"));
+
+            // only admins get to pretend
+            let not_admin = admin_as("gold", "1234");
+            assert!(!not_admin.is_member());
+        }
+    }
+
     #[test]
     fn test_pathmappings() {
         use super::*;
@@ -1469,4 +2139,36 @@ mod tests {
         let input_path = mappings.to_input_path_maybe(&disk_path).unwrap();
         assert_eq!(input_path.as_str(), "/content");
     }
+
+    #[test]
+    fn data_file_names() {
+        use crate::{DataFile, InputPath};
+
+        let name = |path: &str| {
+            DataFile {
+                path: InputPath::from_static(path),
+                markup: Default::default(),
+            }
+            .name()
+            .to_string()
+        };
+        assert_eq!(name("/data/talks.yaml"), "talks");
+        assert_eq!(name("/data/menus/main.toml"), "menus/main");
+        assert_eq!(name("/data/projects.v2.json"), "projects.v2");
+        assert_eq!(name("/data/talks.yml"), "talks");
+    }
+
+    #[test]
+    fn paks_without_data_files() {
+        use crate::{Pak, testing};
+
+        let pak = testing::revision(Default::default(), []).pak;
+        let json = merde::json::to_string(&pak).unwrap();
+        assert!(json.contains(r#","data":null"#), "got {json}");
+
+        // paks made before data files existed don't have the field at all
+        let json = json.replace(r#","data":null"#, "");
+        let pak: Pak = merde::json::from_str(&json).unwrap();
+        assert!(pak.data.is_none());
+    }
 }
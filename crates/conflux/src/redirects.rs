@@ -0,0 +1,521 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{InputPath, InputPathRef, Route, RouteRef};
+
+/// Where tenants list their redirects, one rule per line, netlify-style:
+///
+/// ```text
+/// # comments are allowed
+/// /old-article            /articles/new-article
+/// /old-series/*           /series/new-series/:splat   302
+/// /drafts/*/preview       /                           301
+/// /deleted-thing                                      410
+/// ```
+pub const REDIRECTS_INPUT_PATH: &InputPathRef = InputPathRef::from_static("/content/_redirects");
+
+/// How many hops we follow when checking for loops
+const MAX_REDIRECT_HOPS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectStatus {
+    /// 301
+    MovedPermanently,
+    /// 302
+    Found,
+    /// 410 — no target
+    Gone,
+}
+
+impl RedirectStatus {
+    pub fn as_u16(self) -> u16 {
+        match self {
+            RedirectStatus::MovedPermanently => 301,
+            RedirectStatus::Found => 302,
+            RedirectStatus::Gone => 410,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RedirectPattern {
+    /// `/foo/bar` matches exactly that route
+    Exact(Route),
+
+    /// `/foo/*` matches `/foo` and anything under it, the rest is
+    /// available as `:splat` in the target
+    Prefix(Route),
+
+    /// any other pattern with `*` in it, e.g. `/*/old`
+    Wildcard(String),
+}
+
+/// Where a redirect rule came from, for error messages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RedirectOrigin {
+    /// `aliases` in a page's frontmatter
+    Alias { page: InputPath },
+
+    /// a line in `/content/_redirects` (1-based)
+    File { line: usize },
+}
+
+impl std::fmt::Display for RedirectOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RedirectOrigin::Alias { page } => write!(f, "alias in {page}"),
+            RedirectOrigin::File { line } => write!(f, "{REDIRECTS_INPUT_PATH}:{line}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RedirectRule {
+    pub pattern: RedirectPattern,
+
+    /// where to send the visitor: a route, or a full URL. `None` for 410s.
+    pub target: Option<String>,
+
+    pub status: RedirectStatus,
+
+    pub origin: RedirectOrigin,
+}
+
+/// What to do with a request that matched a redirect rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedRedirect {
+    pub status: RedirectStatus,
+
+    /// `None` for 410s
+    pub location: Option<String>,
+}
+
+/// All redirect rules for a revision: aliases from frontmatter, and the
+/// tenant's `_redirects` file. These only apply to routes that don't match
+/// a page.
+///
+/// Exact rules win, then the longest matching prefix, then wildcards in the
+/// order they were declared.
+#[derive(Debug, Clone, Default)]
+pub struct Redirects {
+    rules: Vec<RedirectRule>,
+
+    /// indices into `rules`
+    exact: HashMap<Route, usize>,
+
+    /// indices into `rules`, longest prefix first
+    prefixes: Vec<usize>,
+
+    /// indices into `rules`, in declaration order
+    wildcards: Vec<usize>,
+
+    /// problems found while adding rules (duplicate sources, etc.)
+    collisions: Vec<String>,
+}
+
+impl Redirects {
+    /// Parses the contents of a `_redirects` file. Errors mention the line number.
+    pub fn parse(markup: &str) -> eyre::Result<Vec<RedirectRule>> {
+        let mut rules = Vec::new();
+
+        for (index, line) in markup.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (from, target, status) = match fields[..] {
+                [from, "410"] => (from, None, RedirectStatus::Gone),
+                [from, to] => (from, Some(to), RedirectStatus::MovedPermanently),
+                [from, to, "301"] => (from, Some(to), RedirectStatus::MovedPermanently),
+                [from, to, "302"] => (from, Some(to), RedirectStatus::Found),
+                [_, _, "410"] => {
+                    return Err(eyre::eyre!(
+                        "{REDIRECTS_INPUT_PATH}:{line_number}: 410 rules can't have a target"
+                    ));
+                }
+                [_, _, status] => {
+                    return Err(eyre::eyre!(
+                        "{REDIRECTS_INPUT_PATH}:{line_number}: unsupported status {status:?}, expected 301, 302 or 410"
+                    ));
+                }
+                _ => {
+                    return Err(eyre::eyre!(
+                        "{REDIRECTS_INPUT_PATH}:{line_number}: expected `from to [status]` or `from 410`, got {line:?}"
+                    ));
+                }
+            };
+
+            if !from.starts_with('/') {
+                return Err(eyre::eyre!(
+                    "{REDIRECTS_INPUT_PATH}:{line_number}: redirect source should start with '/', got {from:?}"
+                ));
+            }
+            if let Some(target) = target {
+                if !(target.starts_with('/')
+                    || target.starts_with("https://")
+                    || target.starts_with("http://"))
+                {
+                    return Err(eyre::eyre!(
+                        "{REDIRECTS_INPUT_PATH}:{line_number}: redirect target should be a route or an absolute URL, got {target:?}"
+                    ));
+                }
+            }
+
+            let pattern = match from.find('*') {
+                None => RedirectPattern::Exact(RouteRef::from_str(from).trim_trailing_slash()),
+                Some(star) if star == from.len() - 1 && from.ends_with("/*") => {
+                    RedirectPattern::Prefix(RouteRef::from_str(&from[..star]).trim_trailing_slash())
+                }
+                Some(_) => RedirectPattern::Wildcard(from.to_string()),
+            };
+
+            if target.is_some_and(|t| t.contains(":splat"))
+                && !matches!(pattern, RedirectPattern::Prefix(_))
+            {
+                return Err(eyre::eyre!(
+                    "{REDIRECTS_INPUT_PATH}:{line_number}: `:splat` is only available for rules ending in `/*`"
+                ));
+            }
+
+            rules.push(RedirectRule {
+                pattern,
+                target: target.map(|t| t.to_string()),
+                status,
+                origin: RedirectOrigin::File { line: line_number },
+            });
+        }
+
+        Ok(rules)
+    }
+
+    /// Adds a rule. Exact rules for a source that already has one are
+    /// ignored and recorded as a collision.
+    pub fn add(&mut self, rule: RedirectRule) {
+        let index = self.rules.len();
+        match &rule.pattern {
+            RedirectPattern::Exact(route) => {
+                if let Some(&existing) = self.exact.get(route) {
+                    self.collisions.push(format!(
+                        "{route} is redirected twice: by {} and by {} (the latter is ignored)",
+                        self.rules[existing].origin, rule.origin
+                    ));
+                    return;
+                }
+                self.exact.insert(route.clone(), index);
+            }
+            RedirectPattern::Prefix(prefix) => {
+                let len = prefix.as_str().len();
+                let position = self
+                    .prefixes
+                    .iter()
+                    .position(|&i| match &self.rules[i].pattern {
+                        RedirectPattern::Prefix(other) => other.as_str().len() < len,
+                        _ => unreachable!(),
+                    })
+                    .unwrap_or(self.prefixes.len());
+                self.prefixes.insert(position, index);
+            }
+            RedirectPattern::Wildcard(_) => {
+                self.wildcards.push(index);
+            }
+        }
+        self.rules.push(rule);
+    }
+
+    /// Makes `alias` a permanent redirect to the canonical route of `page`
+    pub fn add_alias(&mut self, alias: &RouteRef, page: &InputPathRef, route: &RouteRef) {
+        self.add(RedirectRule {
+            pattern: RedirectPattern::Exact(alias.trim_trailing_slash()),
+            target: Some(route.to_string()),
+            status: RedirectStatus::MovedPermanently,
+            origin: RedirectOrigin::Alias {
+                page: page.to_owned(),
+            },
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn rules(&self) -> &[RedirectRule] {
+        &self.rules
+    }
+
+    /// Finds the rule that applies to `route`, if any, and substitutes `:splat`
+    pub fn resolve(&self, route: &RouteRef) -> Option<ResolvedRedirect> {
+        let route = route.trim_trailing_slash();
+        let exact = self.exact.get(&route);
+        let route = route.as_str();
+
+        let (rule, splat) = if let Some(&i) = exact {
+            (&self.rules[i], "")
+        } else if let Some((rule, splat)) = self.prefixes.iter().find_map(|&i| {
+            let rule = &self.rules[i];
+            let RedirectPattern::Prefix(prefix) = &rule.pattern else {
+                unreachable!()
+            };
+            let prefix = prefix.as_str().trim_end_matches('/');
+            let rest = route.strip_prefix(prefix)?;
+            if rest.is_empty() {
+                Some((rule, ""))
+            } else {
+                rest.strip_prefix('/').map(|splat| (rule, splat))
+            }
+        }) {
+            (rule, splat)
+        } else {
+            let rule = self
+                .wildcards
+                .iter()
+                .map(|&i| &self.rules[i])
+                .find(|rule| {
+                    let RedirectPattern::Wildcard(pattern) = &rule.pattern else {
+                        unreachable!()
+                    };
+                    wildcard_matches(pattern.as_bytes(), route.as_bytes())
+                })?;
+            (rule, "")
+        };
+
+        Some(ResolvedRedirect {
+            status: rule.status,
+            location: rule
+                .target
+                .as_ref()
+                .map(|target| target.replace(":splat", splat)),
+        })
+    }
+
+    /// Returns human-readable descriptions of everything that looks wrong:
+    /// duplicate sources, sources shadowed by pages, and redirect loops.
+    pub fn problems(&self, page_routes: &HashMap<Route, InputPath>) -> Vec<String> {
+        let mut problems = self.collisions.clone();
+
+        for rule in &self.rules {
+            let sample = match &rule.pattern {
+                RedirectPattern::Exact(route) => {
+                    if let Some(page) = page_routes.get(route) {
+                        problems.push(format!(
+                            "{route} (from {}) is never used: {page} is served at that route",
+                            rule.origin
+                        ));
+                        continue;
+                    }
+                    route.clone()
+                }
+                RedirectPattern::Prefix(prefix) => {
+                    let prefix = prefix.as_str().trim_end_matches('/');
+                    Route::new(format!("{prefix}/redirect-loop-check"))
+                }
+                // we can't come up with a route that matches an arbitrary
+                // wildcard, these are only checked as part of other chains
+                RedirectPattern::Wildcard(_) => continue,
+            };
+
+            if let Some(problem) = self.check_chain(&sample, page_routes) {
+                problems.push(format!("{} (from {}): {problem}", sample, rule.origin));
+            }
+        }
+
+        problems
+    }
+
+    /// Follows redirects from `start` until we land on a page, leave the
+    /// site, or hit a 410.
+    fn check_chain(
+        &self,
+        start: &RouteRef,
+        page_routes: &HashMap<Route, InputPath>,
+    ) -> Option<String> {
+        let mut seen: HashSet<Route> = Default::default();
+        let mut current = start.to_owned();
+
+        for _ in 0..MAX_REDIRECT_HOPS {
+            if !seen.insert(current.clone()) {
+                return Some(format!("redirect loop through {current}"));
+            }
+            let resolved = self.resolve(&current)?;
+            let location = resolved.location?;
+            if !location.starts_with('/') {
+                return None;
+            }
+            let next = location.split(['#', '?']).next().unwrap_or_default();
+            current = RouteRef::from_str(next).trim_trailing_slash();
+            if page_routes.contains_key(&current) {
+                return None;
+            }
+        }
+
+        Some(format!(
+            "more than {MAX_REDIRECT_HOPS} redirects in a row, probably a loop"
+        ))
+    }
+}
+
+/// Matches `*` against any sequence of bytes (including `/`). Stars match
+/// as little as possible, and on a mismatch only the last star grows, so
+/// patterns with many stars can't blow up.
+fn wildcard_matches(pattern: &[u8], input: &[u8]) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // position of the last star in `pattern`, and where its match ends in `input`
+    let mut last_star: Option<(usize, usize)> = None;
+
+    while i < input.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                last_star = Some((p, i));
+                p += 1;
+            }
+            Some(&c) if c == input[i] => {
+                p += 1;
+                i += 1;
+            }
+            _ => {
+                let Some((star, end)) = last_star else {
+                    return false;
+                };
+                last_star = Some((star, end + 1));
+                p = star + 1;
+                i = end + 1;
+            }
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redirects(markup: &str) -> Redirects {
+        let mut redirects = Redirects::default();
+        for rule in Redirects::parse(markup).unwrap() {
+            redirects.add(rule);
+        }
+        redirects
+    }
+
+    #[test]
+    fn test_redirects_resolve() {
+        let r = redirects(
+            r#"
+            # a comment
+            /old                /new
+            /series/old/*       /series/new/:splat    302
+            /series/old/part-1  /series/new/intro
+            /series/*           /articles
+            /*/legacy           /legacy
+            /gone               410
+            "#,
+        );
+
+        let resolve = |route: &str| r.resolve(RouteRef::from_str(route));
+
+        assert_eq!(
+            resolve("/old"),
+            Some(ResolvedRedirect {
+                status: RedirectStatus::MovedPermanently,
+                location: Some("/new".into())
+            })
+        );
+        assert_eq!(resolve("/old/").unwrap().location.as_deref(), Some("/new"));
+        assert_eq!(
+            resolve("/series/old/part-2"),
+            Some(ResolvedRedirect {
+                status: RedirectStatus::Found,
+                location: Some("/series/new/part-2".into())
+            })
+        );
+        assert_eq!(
+            resolve("/series/old/part-1").unwrap().location.as_deref(),
+            Some("/series/new/intro")
+        );
+        assert_eq!(
+            resolve("/series/other").unwrap().location.as_deref(),
+            Some("/articles")
+        );
+        assert_eq!(
+            resolve("/foo/bar/legacy").unwrap().location.as_deref(),
+            Some("/legacy")
+        );
+        assert_eq!(
+            resolve("/gone"),
+            Some(ResolvedRedirect {
+                status: RedirectStatus::Gone,
+                location: None
+            })
+        );
+        assert_eq!(resolve("/oldish"), None);
+    }
+
+    #[test]
+    fn test_wildcard_matches() {
+        let matches =
+            |pattern: &str, input: &str| wildcard_matches(pattern.as_bytes(), input.as_bytes());
+
+        assert!(matches("/a/*", "/a/"));
+        assert!(matches("/a/*", "/a/b/c"));
+        assert!(!matches("/a/*", "/a"));
+        assert!(matches("/*/legacy", "/x/y/legacy"));
+        assert!(!matches("/*/legacy", "/x/legacy/y"));
+        assert!(matches("/*/b/*/d", "/a/b/b/c/d"));
+        assert!(matches("/**", "/"));
+        assert!(!matches("/a", "/ab"));
+        assert!(!matches("/ab", "/a"));
+
+        // would take ages with a backtracking matcher
+        let pattern = "/*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*b";
+        let input = format!("/{}", "a".repeat(200));
+        assert!(!matches(pattern, &input));
+    }
+
+    #[test]
+    fn test_redirects_parse_errors() {
+        assert!(Redirects::parse("/a /b 307").is_err());
+        assert!(Redirects::parse("/a /b 410").is_err());
+        assert!(Redirects::parse("a /b").is_err());
+        assert!(Redirects::parse("/a /b/:splat").is_err());
+        assert!(Redirects::parse("/a").is_err());
+    }
+
+    #[test]
+    fn test_redirects_problems() {
+        let mut r = redirects(
+            r#"
+            /a      /b
+            /b      /a
+            /x/*    /x/y/:splat
+            /page   /elsewhere
+            /dup    /a
+            "#,
+        );
+        r.add_alias(
+            RouteRef::from_str("/dup"),
+            InputPathRef::from_str("/content/page.md"),
+            RouteRef::from_str("/page"),
+        );
+
+        let mut page_routes = HashMap::new();
+        page_routes.insert(
+            Route::new("/page".to_string()),
+            InputPath::new("/content/page.md".to_string()),
+        );
+
+        let problems = r.problems(&page_routes);
+        assert!(problems.iter().any(|p| p.contains("redirected twice")));
+        assert!(problems.iter().any(|p| p.contains("never used")));
+        assert!(
+            problems
+                .iter()
+                .any(|p| p.starts_with("/a ") && p.contains("loop"))
+        );
+        assert!(
+            problems
+                .iter()
+                .any(|p| p.starts_with("/x/") && p.contains("in a row"))
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! Pages and revisions for tests, in this crate and others (enable the
+//! `testing` feature from `[dev-dependencies]`).
+
+use std::sync::Arc;
+
+use config_types::{Environment, RevisionConfig, TenantConfig, TenantInfo, WebConfig};
+use merde::time::Rfc3339;
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    InputPath, LoadedPage, PageKind, Pak, PathMappings, Revision, RevisionId, Route, VideoInfo,
+};
+
+/// A tenant that lives nowhere on disk
+pub fn tenant_info() -> Arc<TenantInfo> {
+    Arc::new(TenantInfo {
+        base_dir: "/tmp/conflux-testing".into(),
+        tc: TenantConfig::new("example.org".into()),
+    })
+}
+
+/// A published, public page at `route` (e.g. `/articles/foo`), dated
+/// 2020-01-01. Its kind and section follow from `rc` like they would at
+/// load time.
+pub fn page(rc: &RevisionConfig, route: &str) -> LoadedPage {
+    let route = Route::new(route.to_string());
+    let path = InputPath::new(format!("/content{route}/_index.md"));
+    LoadedPage {
+        ti: tenant_info(),
+        web: WebConfig {
+            env: Environment::Development,
+            port: 1111,
+        },
+        path,
+        kind: PageKind::from_route(&route, rc),
+        section: rc.section_for_route(route.as_str()).cloned(),
+        route,
+        lang: rc.default_lang().to_string(),
+        translation_of: None,
+        plain_text: "Once upon a time".into(),
+        html: "<p>Once upon a time</p>".into(),
+        reading_time: 1,
+        toc: Default::default(),
+        members_sections: Default::default(),
+        crates: Default::default(),
+        github_repos: Default::default(),
+        downloads: Default::default(),
+        links: Default::default(),
+        title: "A page".into(),
+        template: "page.html".into(),
+        date: Rfc3339(OffsetDateTime::UNIX_EPOCH + Duration::days(18262)),
+        draft: false,
+        archive: false,
+        aliases: Default::default(),
+        tags: Default::default(),
+        taxonomies: Default::default(),
+        extra: Default::default(),
+        ongoing: false,
+        draft_code: None,
+        min_tier: None,
+        exclusive_until: None,
+        updated_at: None,
+        rust_version: None,
+        series_link: None,
+        parts: Default::default(),
+        series_stats: None,
+        children: Default::default(),
+        show_patreon_credits: false,
+        hide_patreon_plug: false,
+        hide_comments: false,
+        hide_metadata: false,
+        video_info: VideoInfo {
+            dual_feature: false,
+            tube: None,
+            youtube: None,
+            duration: None,
+        },
+        thumb: None,
+        parent_thumb: None,
+    }
+}
+
+/// A revision with just these pages (and their routes), and nothing else
+pub fn revision(rc: RevisionConfig, pages: impl IntoIterator<Item = LoadedPage>) -> Revision {
+    let mut rev = Revision {
+        pak: Pak {
+            id: RevisionId::new("rev_testing".to_string()),
+            inputs: Default::default(),
+            pages: Default::default(),
+            templates: Default::default(),
+            media_props: Default::default(),
+            svg_font_face_collection: Default::default(),
+            rc,
+            redirects: Default::default(),
+            data: Default::default(),
+        },
+        ti: tenant_info(),
+        pages: Default::default(),
+        page_routes: Default::default(),
+        redirects: Default::default(),
+        assets: Default::default(),
+        asset_routes: Default::default(),
+        tags: Default::default(),
+        taxonomies: Default::default(),
+        media: Default::default(),
+        related: Default::default(),
+        translations: Default::default(),
+        data: Default::default(),
+        mappings: PathMappings::default(),
+    };
+    for page in pages {
+        rev.page_routes
+            .insert(page.route.clone(), page.path.clone());
+        for tag in &page.tags {
+            rev.tags
+                .entry(tag.clone())
+                .or_default()
+                .push(page.path.clone());
+        }
+        rev.pages.insert(page.path.clone(), Arc::new(page));
+    }
+    rev
+}
+
+/// Shorthand for looking a page up by route in a test revision
+pub fn page_at<'a>(rev: &'a Revision, route: &str) -> &'a Arc<LoadedPage> {
+    &rev.pages[&rev.page_routes[route]]
+}
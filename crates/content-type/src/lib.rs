@@ -1,5 +1,5 @@
 macro_rules! content_types {
-    ($($variant:ident => { ext: $ext:literal, mime: $mime:literal, serial: $serial:literal }),* $(,)?) => {
+    ($($variant:ident => { ext: $ext:literal $(, aliases: [$($alias:literal),*])?, mime: $mime:literal, serial: $serial:literal }),* $(,)?) => {
         #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
         pub enum ContentType {
             $($variant),*
@@ -39,7 +39,7 @@ macro_rules! content_types {
                     None => String::new(),
                 };
                 match guess.as_str() {
-                    $($ext => Some(Self::$variant),)*
+                    $($ext $($(| $alias)*)? => Some(Self::$variant),)*
                     _ => None,
                 }
             }
@@ -53,6 +53,8 @@ content_types! {
     CSS => { ext: "css", mime: "text/css; charset=utf-8", serial: "css" },
     SCSS => { ext: "scss", mime: "text/x-scss; charset=utf-8", serial: "scss" },
     JSON => { ext: "json", mime: "application/json; charset=utf-8", serial: "json" },
+    YAML => { ext: "yaml", aliases: ["yml"], mime: "application/yaml; charset=utf-8", serial: "yaml" },
+    TOML => { ext: "toml", mime: "application/toml; charset=utf-8", serial: "toml" },
     JXL => { ext: "jxl", mime: "image/jxl", serial: "jxl" },
     HEIC => { ext: "heic", mime: "image/heic", serial: "heic" },
     AVIF => { ext: "avif", mime: "image/avif", serial: "avif" },
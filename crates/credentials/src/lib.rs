@@ -33,11 +33,14 @@ merde::derive! {
     impl (Serialize, Deserialize) for struct Tier { title }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct Profile {
     pub patreon_id: Option<String>,
     pub github_id: Option<String>,
 
+    // verified email address, for people who signed in with a magic link
+    pub email: Option<String>,
+
     // for GitHub that's `name ?? login`
     pub full_name: String,
 
@@ -46,7 +49,7 @@ pub struct Profile {
 }
 
 merde::derive! {
-    impl (Serialize, Deserialize) for struct Profile { patreon_id, github_id, full_name, thumb_url }
+    impl (Serialize, Deserialize) for struct Profile { patreon_id, github_id, email, full_name, thumb_url }
 }
 
 impl Profile {
@@ -62,13 +65,223 @@ impl Profile {
             .ok_or_else(|| eyre!("no github id"))
     }
 
+    pub fn email(&self) -> Result<&str> {
+        self.email.as_deref().ok_or_else(|| eyre!("no email"))
+    }
+
+    /// The profile's main identity, used as a key for everything we store
+    /// about someone (comments, preferences…). With linked accounts, that's
+    /// the Patreon one, then GitHub, then email.
     pub fn global_id(&self) -> Result<String> {
+        self.identities()
+            .into_iter()
+            .next()
+            .ok_or_else(|| eyre!("no global id"))
+    }
+
+    /// Every identity in this profile, like `patreon:1234`, `github:5678` or
+    /// `email:someone@example.org`, main one first.
+    pub fn identities(&self) -> Vec<String> {
+        let mut identities = Vec::new();
         if let Some(id) = &self.patreon_id {
-            return Ok(format!("patreon:{id}"));
+            identities.push(format!("patreon:{id}"));
         }
         if let Some(id) = &self.github_id {
-            return Ok(format!("github:{id}"));
+            identities.push(format!("github:{id}"));
+        }
+        if let Some(email) = &self.email {
+            identities.push(format!("email:{email}"));
+        }
+        identities
+    }
+
+    /// Adds an identity as returned by [Profile::identities], replacing any
+    /// other identity from the same provider.
+    pub fn set_identity(&mut self, identity: &str) -> Result<()> {
+        let (provider, id) = identity
+            .split_once(':')
+            .ok_or_else(|| eyre!("invalid identity {identity:?}"))?;
+        let field = match provider {
+            "patreon" => &mut self.patreon_id,
+            "github" => &mut self.github_id,
+            "email" => &mut self.email,
+            _ => return Err(eyre!("unknown identity provider {provider:?}")),
+        };
+        *field = Some(id.to_owned());
+        Ok(())
+    }
+
+    /// Removes an identity as returned by [Profile::identities]
+    pub fn remove_identity(&mut self, identity: &str) {
+        match identity.split_once(':') {
+            Some(("patreon", id)) if self.patreon_id.as_deref() == Some(id) => {
+                self.patreon_id = None
+            }
+            Some(("github", id)) if self.github_id.as_deref() == Some(id) => self.github_id = None,
+            Some(("email", id)) if self.email.as_deref() == Some(id) => self.email = None,
+            _ => {}
         }
-        Err(eyre!("no global id"))
+    }
+}
+
+/// Someone to credit for sponsoring, as listed by Patreon or GitHub Sponsors
+#[derive(Debug, Clone)]
+pub struct CreditedSponsor {
+    /// As returned by [Profile::identities], e.g. `github:5678`
+    pub identity: String,
+
+    /// What to credit them as
+    pub name: String,
+
+    /// The tier title, as the provider calls it (see `RevisionConfig::tier_by_title`)
+    pub tier_title: Option<String>,
+
+    /// Where their avatar is hosted by the provider (we don't hotlink it)
+    pub avatar_source_url: Option<String>,
+
+    /// Their public profile page
+    pub profile_url: Option<String>,
+
+    /// When they started sponsoring
+    pub joined_at: Option<OffsetDateTime>,
+}
+
+/// Trims and lowercases an email address, returns `None` if it doesn't look
+/// like one. This is only a sanity check: the address is really verified by
+/// sending it a sign-in link.
+pub fn normalize_email(input: &str) -> Option<String> {
+    let email = input.trim().to_lowercase();
+    if email.len() > 254 || email.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return None;
+    }
+    let (local, domain) = email.split_once('@')?;
+    if local.is_empty() || domain.contains('@') {
+        return None;
+    }
+    let (name, tld) = domain.rsplit_once('.')?;
+    if name.is_empty() || tld.is_empty() {
+        return None;
+    }
+    Some(email)
+}
+
+/// Per-user settings, stored by mom (keyed by [Profile::global_id]) and
+/// exposed to templates as `globals.preferences`.
+#[derive(Debug, Clone, Serialize)]
+pub struct UserPreferences {
+    pub theme: Theme,
+
+    /// Font size for code blocks, in percent of the default
+    pub code_font_size: u32,
+
+    pub reduced_motion: bool,
+
+    /// Send an email when someone replies to one of my comments
+    pub email_on_comment_reply: bool,
+
+    /// Send an email when a new article is published
+    pub email_on_new_article: bool,
+
+    /// Show links instead of embedded video players
+    pub hide_video_embeds: bool,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct UserPreferences {
+        theme,
+        code_font_size,
+        reduced_motion,
+        email_on_comment_reply,
+        email_on_new_article,
+        hide_video_embeds
+    }
+}
+
+impl Default for UserPreferences {
+    fn default() -> Self {
+        Self {
+            theme: Theme::System,
+            code_font_size: 100,
+            reduced_motion: false,
+            email_on_comment_reply: false,
+            email_on_new_article: false,
+            hide_video_embeds: false,
+        }
+    }
+}
+
+impl UserPreferences {
+    pub const MIN_CODE_FONT_SIZE: u32 = 50;
+    pub const MAX_CODE_FONT_SIZE: u32 = 200;
+
+    /// Applies the fields that are set in `patch`, checking they make sense
+    pub fn apply(&mut self, patch: UserPreferencesPatch) -> Result<()> {
+        if let Some(theme) = patch.theme {
+            self.theme = theme;
+        }
+        if let Some(size) = patch.code_font_size {
+            if !(Self::MIN_CODE_FONT_SIZE..=Self::MAX_CODE_FONT_SIZE).contains(&size) {
+                return Err(eyre!(
+                    "code_font_size must be between {} and {}, got {size}",
+                    Self::MIN_CODE_FONT_SIZE,
+                    Self::MAX_CODE_FONT_SIZE
+                ));
+            }
+            self.code_font_size = size;
+        }
+        if let Some(v) = patch.reduced_motion {
+            self.reduced_motion = v;
+        }
+        if let Some(v) = patch.email_on_comment_reply {
+            self.email_on_comment_reply = v;
+        }
+        if let Some(v) = patch.email_on_new_article {
+            self.email_on_new_article = v;
+        }
+        if let Some(v) = patch.hide_video_embeds {
+            self.hide_video_embeds = v;
+        }
+        Ok(())
+    }
+}
+
+/// A partial update to [UserPreferences]: unset fields are left alone.
+/// Stored preferences are also read through this, so that adding a field
+/// doesn't break existing rows.
+#[derive(Debug, Clone, Default)]
+pub struct UserPreferencesPatch {
+    pub theme: Option<Theme>,
+    pub code_font_size: Option<u32>,
+    pub reduced_motion: Option<bool>,
+    pub email_on_comment_reply: Option<bool>,
+    pub email_on_new_article: Option<bool>,
+    pub hide_video_embeds: Option<bool>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct UserPreferencesPatch {
+        theme,
+        code_font_size,
+        reduced_motion,
+        email_on_comment_reply,
+        email_on_new_article,
+        hide_video_embeds
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// Follow the OS/browser setting
+    System,
+    Light,
+    Dark,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for enum Theme string_like {
+        "system" => System,
+        "light" => Light,
+        "dark" => Dark,
     }
 }
@@ -1,8 +1,10 @@
 use ahash::AHashMap;
-use rand::seq::IteratorRandom;
 use std::{
     fmt::Display,
-    sync::{LazyLock, RwLock, atomic::AtomicUsize},
+    sync::{
+        LazyLock, RwLock,
+        atomic::{AtomicU64, AtomicUsize},
+    },
 };
 
 static DUMBCACHE_TRACING_ENABLED: LazyLock<bool> =
@@ -17,7 +19,36 @@ macro_rules! trace {
 }
 
 pub struct CacheInner {
-    data: AHashMap<String, String>,
+    data: AHashMap<String, Entry>,
+}
+
+struct Entry {
+    value: String,
+
+    /// Value of [`Cache::clock`] when this was last read or written, the
+    /// least recently used entry is evicted first
+    last_used: AtomicU64,
+}
+
+/// Point-in-time counters for a [`Cache`], for logging
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl CacheStats {
+    /// Fraction of lookups that were hits, between 0 and 1
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total > 0 {
+            self.hits as f64 / total as f64
+        } else {
+            0.0
+        }
+    }
 }
 
 pub struct Cache {
@@ -26,6 +57,9 @@ pub struct Cache {
     capacity: usize,
     hits: AtomicUsize,
     misses: AtomicUsize,
+
+    /// Ticks on every read and write, so lookups only need a read lock
+    clock: AtomicU64,
 }
 
 impl Cache {
@@ -38,6 +72,7 @@ impl Cache {
             capacity,
             hits: AtomicUsize::new(0),
             misses: AtomicUsize::new(0),
+            clock: AtomicU64::new(0),
         }
     }
 
@@ -49,7 +84,12 @@ impl Cache {
     #[inline(always)]
     pub fn with<R>(&self, key: &str, callback: impl FnOnce(&str) -> R) -> Option<R> {
         let inner = self.inner.read().unwrap();
-        let result = inner.data.get(key).map(|value| callback(value));
+        let result = inner.data.get(key).map(|entry| {
+            entry
+                .last_used
+                .store(self.tick(), std::sync::atomic::Ordering::Relaxed);
+            callback(&entry.value)
+        });
         if result.is_some() {
             self.hits.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         } else {
@@ -60,27 +100,43 @@ impl Cache {
         result
     }
 
+    fn tick(&self) -> u64 {
+        self.clock
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+    }
+
     fn print_stats(&self, inner: &CacheInner) {
         if *DUMBCACHE_TRACING_ENABLED {
-            let hits = self.hits.load(std::sync::atomic::Ordering::Relaxed);
-            let misses = self.misses.load(std::sync::atomic::Ordering::Relaxed);
-            let total = hits + misses;
-            let hit_rate = if total > 0 {
-                hits as f64 / total as f64
-            } else {
-                0.0
-            };
+            let stats = self.stats_for(inner);
             trace!(
                 "Cache '{}' stats: Hit rate: {:.2}%, Load: {}/{} ({:.2}% full)",
                 self.name,
-                hit_rate * 100.0,
-                inner.data.len(),
-                self.capacity,
-                (inner.data.len() as f64 / self.capacity as f64) * 100.0
+                stats.hit_rate() * 100.0,
+                stats.len,
+                stats.capacity,
+                (stats.len as f64 / stats.capacity as f64) * 100.0
             );
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.read().unwrap();
+        self.stats_for(&inner)
+    }
+
+    fn stats_for(&self, inner: &CacheInner) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(std::sync::atomic::Ordering::Relaxed),
+            misses: self.misses.load(std::sync::atomic::Ordering::Relaxed),
+            len: inner.data.len(),
+            capacity: self.capacity,
+        }
+    }
+
     pub fn insert(&self, key: String, value: String) {
         let mut inner = self.inner.write().unwrap();
 
@@ -88,14 +144,26 @@ impl Cache {
             if inner.data.contains_key(&key) {
                 // then we're merely replacing
             } else {
-                let key_to_remove = inner.data.keys().cloned().choose(&mut rand::thread_rng());
+                // a linear scan, but caches are small and misses render
+                // something, which costs a lot more
+                let key_to_remove = inner
+                    .data
+                    .iter()
+                    .min_by_key(|(_, entry)| {
+                        entry.last_used.load(std::sync::atomic::Ordering::Relaxed)
+                    })
+                    .map(|(key, _)| key.clone());
                 if let Some(key) = key_to_remove {
                     inner.data.remove(&key);
                 }
             }
         }
 
-        inner.data.insert(key, value);
+        let entry = Entry {
+            value,
+            last_used: AtomicU64::new(self.tick()),
+        };
+        inner.data.insert(key, entry);
     }
 
     pub fn clear(&self) {
@@ -133,37 +201,40 @@ mod tests {
     }
 
     #[test]
-    fn test_eviction_randomness() {
+    fn test_stats() {
         let cache = Cache::new("test", 3);
-        let mut evicted_keys = HashSet::new();
-
-        for _ in 0..100 {
-            cache.insert("key1".to_string(), "value1".to_string());
-            cache.insert("key2".to_string(), "value2".to_string());
-            cache.insert("key3".to_string(), "value3".to_string());
-            cache.insert("key4".to_string(), "value4".to_string());
-
-            let remaining_keys: HashSet<_> = vec!["key1", "key2", "key3", "key4"]
-                .into_iter()
-                .filter_map(|k| cache.get(k).map(|_| k))
-                .collect();
-
-            assert_eq!(
-                remaining_keys.len(),
-                3,
-                "set of remaining keys: {remaining_keys:?}"
-            );
+        assert_eq!(cache.stats().hit_rate(), 0.0);
 
-            for key in ["key1", "key2", "key3", "key4"] {
-                evicted_keys.insert(key);
-            }
-        }
+        cache.insert("key1".to_string(), "value1".to_string());
+        assert_eq!(cache.get("key1"), Some("value1".to_string()));
+        assert_eq!(cache.get("key2"), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.len), (1, 1, 1));
+        assert_eq!(stats.hit_rate(), 0.5);
+    }
+
+    #[test]
+    fn test_eviction_is_lru() {
+        let cache = Cache::new("test", 3);
 
-        // Ensure that each key has been evicted at least once
-        assert_eq!(
-            evicted_keys.len(),
-            4,
-            "set of evicted keys: {evicted_keys:?}"
-        );
+        cache.insert("key1".to_string(), "value1".to_string());
+        cache.insert("key2".to_string(), "value2".to_string());
+        cache.insert("key3".to_string(), "value3".to_string());
+
+        // reading key1 makes key2 the least recently used
+        assert_eq!(cache.get("key1"), Some("value1".to_string()));
+        cache.insert("key4".to_string(), "value4".to_string());
+        assert_eq!(cache.get("key2"), None);
+
+        // replacing doesn't evict, but counts as a use
+        cache.insert("key3".to_string(), "value3bis".to_string());
+        cache.insert("key5".to_string(), "value5".to_string());
+        let remaining_keys: HashSet<_> = ["key1", "key3", "key4", "key5"]
+            .into_iter()
+            .filter_map(|k| cache.get(k).map(|_| k))
+            .collect();
+        assert_eq!(remaining_keys, HashSet::from(["key3", "key4", "key5"]));
+        assert_eq!(cache.get("key3"), Some("value3bis".to_string()));
     }
 }
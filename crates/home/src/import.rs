@@ -0,0 +1,623 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use eyre::{Context, eyre};
+use libclap::{ImportArgs, ImportFrom};
+use yaml_rust2::YamlLoader;
+
+mod frontmatter;
+mod shortcodes;
+
+use shortcodes::ShortcodeMapping;
+
+/// Extensions home does something with in `content`, besides markdown
+const SUPPORTED_ASSETS: &[&str] = &[
+    "jxl", "png", "jpg", "jpeg", "webp", "avif", "svg", "drawio", "mp4", "woff2", "scss",
+];
+
+/// Extensions home reads in `data`
+const SUPPORTED_DATA: &[&str] = &["json", "yaml", "yml", "toml"];
+
+/// The sections home has without any `sections` in `home.json`
+const DEFAULT_SECTIONS: &[&str] = &["articles", "episodes", "series", "tests"];
+
+/// Everything needed to convert a page
+pub(crate) struct ImportContext {
+    pub(crate) from: ImportFrom,
+
+    /// top-level content folders to rename, e.g. `blog` => `articles`
+    pub(crate) sections: HashMap<String, String>,
+
+    /// shortcode name in the imported site => how to call it in home
+    pub(crate) shortcodes: HashMap<String, ShortcodeMapping>,
+
+    /// the imported site's default language, if its config names one
+    pub(crate) default_lang: Option<String>,
+
+    /// languages the imported site's config lists besides the default one.
+    /// Those are the suffixes of translations, e.g. `_index.fr.md`.
+    pub(crate) languages: Vec<String>,
+}
+
+impl ImportContext {
+    /// Where a file from the imported `content` folder goes, relative to ours
+    fn dest_path(&self, rel: &Utf8Path) -> Utf8PathBuf {
+        let mut components: Vec<&str> = rel.components().map(|c| c.as_str()).collect();
+        // files at the root of `content` aren't in a section
+        if components.len() > 1 {
+            if let Some(to) = self.sections.get(components[0]) {
+                components[0] = to;
+            }
+        }
+        // page bundles: home calls those `_index.md` too, translated or not
+        let mut path = Utf8PathBuf::from(components.join("/"));
+        let bundle = match path.file_stem() {
+            Some("index") => true,
+            Some(stem) => stem
+                .strip_prefix("index.")
+                .is_some_and(|lang| self.is_translation_lang(lang)),
+            None => false,
+        };
+        if bundle && path.extension() == Some("md") {
+            let renamed = format!("_{}", path.file_name().unwrap_or_default());
+            path.set_file_name(renamed);
+        }
+        path
+    }
+
+    fn is_translation_lang(&self, lang: &str) -> bool {
+        self.languages.iter().any(|l| l == lang)
+    }
+
+    /// The language suffix of a translated page, e.g. `fr` for `foo.fr.md`.
+    /// Like home, only languages from the config count, so `node.js.md`
+    /// isn't a translation.
+    fn lang_of<'a>(&self, rel: &'a Utf8Path) -> Option<&'a str> {
+        let (_, lang) = rel.file_stem()?.rsplit_once('.')?;
+        self.is_translation_lang(lang).then_some(lang)
+    }
+
+    /// The route a page is served at, from its path relative to `content`,
+    /// e.g. `blog/foo/_index.md` => `/blog/foo`. Translations get prefixed
+    /// with their language, e.g. `blog/foo.fr.md` => `/fr/blog/foo`, both in
+    /// Zola and Hugo and in home.
+    pub(crate) fn route_of(&self, rel: &Utf8Path) -> String {
+        let dir = rel.parent().map(|p| p.as_str()).unwrap_or_default();
+        let lang = self.lang_of(rel);
+        let stem = rel.file_stem().unwrap_or_default();
+        let stem = match lang {
+            Some(lang) => &stem[..stem.len() - lang.len() - 1],
+            None => stem,
+        };
+        let route = match stem {
+            "_index" | "index" | "" => format!("/{dir}"),
+            stem if dir.is_empty() => format!("/{stem}"),
+            stem => format!("/{dir}/{stem}"),
+        };
+        match lang {
+            Some(lang) if route == "/" => format!("/{lang}"),
+            Some(lang) => format!("/{lang}{route}"),
+            None => route,
+        }
+    }
+}
+
+/// What couldn't be converted, and what the site needs in `home.json`
+#[derive(Default)]
+pub(crate) struct Report {
+    /// file (relative to the new site) => notes
+    notes: BTreeMap<String, Vec<String>>,
+
+    /// taxonomies pages use, besides tags
+    pub(crate) taxonomies: BTreeSet<String>,
+
+    /// custom frontmatter field => its kind
+    pub(crate) extra_fields: BTreeMap<String, &'static str>,
+
+    /// top-level content folders pages ended up in
+    sections: BTreeSet<String>,
+
+    /// languages pages are translated into
+    languages: BTreeSet<String>,
+}
+
+impl Report {
+    pub(crate) fn note(&mut self, file: &str, note: impl Into<String>) {
+        self.notes
+            .entry(file.to_string())
+            .or_default()
+            .push(note.into());
+    }
+}
+
+enum Output {
+    Page(String),
+    Copy(Utf8PathBuf),
+}
+
+/// Converts a Zola or Hugo site into a home site: pages (frontmatter and
+/// shortcodes), their assets, `static` and `data`. Writes what it couldn't
+/// convert to `import-report.md`.
+pub(crate) async fn import_site(args: ImportArgs) -> eyre::Result<()> {
+    let source = &args.source;
+    let content_dir = source.join("content");
+    if tokio::fs::metadata(&content_dir).await.is_err() {
+        return Err(eyre!(
+            "😕 No \x1b[33m{content_dir}\x1b[0m folder, is \x1b[33m{source}\x1b[0m a Zola or Hugo site?"
+        ));
+    }
+
+    let from = match args.from {
+        Some(from) => from,
+        None => detect_generator(source).await?,
+    };
+    let sections = args
+        .sections
+        .iter()
+        .map(|arg| {
+            let (from, to) = arg.split_once('=').ok_or_else(|| {
+                eyre!("😕 \x1b[36m--section {arg}\x1b[0m should look like \x1b[36m--section blog=articles\x1b[0m")
+            })?;
+            Ok((
+                from.trim_matches('/').to_string(),
+                to.trim_matches('/').to_string(),
+            ))
+        })
+        .collect::<eyre::Result<_>>()?;
+    let shortcodes = match &args.shortcodes {
+        Some(path) => serde_json::from_str(&tokio::fs::read_to_string(path).await?)
+            .wrap_err_with(|| format!("reading shortcode mappings from {path}"))?,
+        None => HashMap::new(),
+    };
+    let (default_lang, languages) = site_languages(source).await?;
+    let cx = ImportContext {
+        from,
+        sections,
+        shortcodes,
+        default_lang,
+        languages,
+    };
+    eprintln!("📥 Importing \x1b[33m{source}\x1b[0m as a {from:?} site");
+
+    let mut report = Report::default();
+    let mut outputs: BTreeMap<Utf8PathBuf, Output> = BTreeMap::new();
+    let mut pages = 0;
+
+    let content_files = list_files(&content_dir).await?;
+    for file in &content_files {
+        let rel = file.strip_prefix(&content_dir)?;
+        let dest_rel = cx.dest_path(rel);
+        let dest = Utf8Path::new("content").join(&dest_rel);
+        if rel.extension() != Some("md") {
+            note_unsupported(&mut report, &dest, SUPPORTED_ASSETS);
+            outputs.insert(dest, Output::Copy(file.clone()));
+            continue;
+        }
+        match cx.lang_of(rel) {
+            Some(lang) => {
+                report.languages.insert(lang.to_string());
+            }
+            None => note_unknown_lang(&mut report, &content_files, file, &dest),
+        }
+
+        let markup = tokio::fs::read_to_string(file).await?;
+        let page = match frontmatter::convert_page(
+            &cx,
+            rel,
+            &dest_rel,
+            dest.as_str(),
+            &markup,
+            &mut report,
+        ) {
+            Ok(page) => page,
+            Err(e) => {
+                report.note(dest.as_str(), format!("copied as is: {e}"));
+                markup
+            }
+        };
+        if let Some((section, _)) = dest_rel.as_str().split_once('/') {
+            report.sections.insert(section.to_string());
+        }
+        outputs.insert(dest, Output::Page(page));
+        pages += 1;
+    }
+
+    // `static` is served from the root, home serves everything from `content`
+    let static_dir = source.join("static");
+    if tokio::fs::metadata(&static_dir).await.is_ok() {
+        for file in list_files(&static_dir).await? {
+            let dest = Utf8Path::new("content").join(file.strip_prefix(&static_dir)?);
+            if outputs.contains_key(&dest) {
+                report.note(
+                    dest.as_str(),
+                    "also in `static`, kept the one from `content`",
+                );
+                continue;
+            }
+            note_unsupported(&mut report, &dest, SUPPORTED_ASSETS);
+            outputs.insert(dest, Output::Copy(file));
+        }
+    }
+
+    // Hugo data files work as they are, see `get_data`
+    let data_dir = source.join("data");
+    if tokio::fs::metadata(&data_dir).await.is_ok() {
+        for file in list_files(&data_dir).await? {
+            let dest = Utf8Path::new("data").join(file.strip_prefix(&data_dir)?);
+            note_unsupported(&mut report, &dest, SUPPORTED_DATA);
+            outputs.insert(dest, Output::Copy(file));
+        }
+    }
+
+    if !args.force {
+        for dest in outputs.keys() {
+            let path = args.dest.join(dest);
+            if tokio::fs::metadata(&path).await.is_ok() {
+                return Err(eyre!(
+                    "😕 \x1b[33m{path}\x1b[0m already exists, pass \x1b[36m--force\x1b[0m to overwrite it"
+                ));
+            }
+        }
+    }
+
+    let files = outputs.len() - pages;
+    for (dest, output) in outputs {
+        let path = args.dest.join(&dest);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        match output {
+            Output::Page(page) => tokio::fs::write(&path, page).await?,
+            Output::Copy(from) => {
+                tokio::fs::copy(&from, &path).await?;
+            }
+        }
+    }
+
+    let report_path = args.dest.join("import-report.md");
+    tokio::fs::write(
+        &report_path,
+        report_markdown(&cx, source, &report, pages, files).await?,
+    )
+    .await?;
+
+    eprintln!("✅ Imported {pages} page(s) and {files} other file(s)");
+    if report.notes.is_empty() {
+        eprintln!("📝 Suggested settings are in \x1b[33m{report_path}\x1b[0m");
+    } else {
+        eprintln!(
+            "📝 {} file(s) need a look, see \x1b[33m{report_path}\x1b[0m",
+            report.notes.len()
+        );
+    }
+    Ok(())
+}
+
+/// Zola only reads `config.toml`, with `base_url`. Hugo has `hugo.*` files,
+/// or `baseURL` in its `config.*`.
+async fn detect_generator(source: &Utf8Path) -> eyre::Result<ImportFrom> {
+    for name in [
+        "hugo.toml",
+        "hugo.yaml",
+        "hugo.json",
+        "config.yaml",
+        "config.json",
+    ] {
+        if tokio::fs::metadata(source.join(name)).await.is_ok() {
+            return Ok(ImportFrom::Hugo);
+        }
+    }
+    if let Ok(config) = tokio::fs::read_to_string(source.join("config.toml")).await {
+        if config.contains("base_url") {
+            return Ok(ImportFrom::Zola);
+        }
+        if config.to_lowercase().contains("baseurl") {
+            return Ok(ImportFrom::Hugo);
+        }
+    }
+    Err(eyre!(
+        "😕 Can't tell whether \x1b[33m{source}\x1b[0m is a Zola or a Hugo site, pass \x1b[36m--from zola\x1b[0m or \x1b[36m--from hugo\x1b[0m"
+    ))
+}
+
+/// The default language and the other languages from the imported site's
+/// config. Zola has `default_language` and a `[languages.fr]` table per
+/// other language, Hugo has `defaultContentLanguage` and a `languages` entry
+/// for every language, the default one included.
+async fn site_languages(source: &Utf8Path) -> eyre::Result<(Option<String>, Vec<String>)> {
+    for name in [
+        "config.toml",
+        "hugo.toml",
+        "hugo.yaml",
+        "hugo.json",
+        "config.yaml",
+        "config.json",
+    ] {
+        let Ok(config) = tokio::fs::read_to_string(source.join(name)).await else {
+            continue;
+        };
+        return parse_site_languages(name, &config)
+            .wrap_err_with(|| format!("reading languages from {name}"));
+    }
+    Ok((None, Vec::new()))
+}
+
+/// Like [`site_languages`], for the contents of the config file `name`
+fn parse_site_languages(name: &str, config: &str) -> eyre::Result<(Option<String>, Vec<String>)> {
+    let mut default_lang = None;
+    let mut languages = Vec::new();
+    if name.ends_with(".toml") {
+        let config = toml::from_str::<toml::Value>(config)?;
+        // Hugo's keys are case-insensitive
+        for (key, value) in config.as_table().into_iter().flatten() {
+            match key.to_lowercase().as_str() {
+                "default_language" | "defaultcontentlanguage" => {
+                    default_lang = value.as_str().map(str::to_string);
+                }
+                "languages" => {
+                    languages.extend(value.as_table().into_iter().flat_map(|t| t.keys().cloned()));
+                }
+                _ => {}
+            }
+        }
+    } else if let Some(config) = YamlLoader::load_from_str(config)?.into_iter().next() {
+        default_lang = config["defaultContentLanguage"]
+            .as_str()
+            .map(str::to_string);
+        for (lang, _) in config["languages"].as_hash().into_iter().flatten() {
+            languages.extend(lang.as_str().map(str::to_string));
+        }
+    }
+    // home's `languages` only lists the ones pages get translated into
+    let default = default_lang.as_deref().unwrap_or("en");
+    languages.retain(|lang| lang != default);
+    languages.sort();
+    languages.dedup();
+    Ok((default_lang, languages))
+}
+
+/// `foo.fr.md` next to a `foo.md` looks like a translation, but without
+/// `fr` in the site's config it becomes a page of its own, at `/foo.fr`
+fn note_unknown_lang(report: &mut Report, files: &[Utf8PathBuf], file: &Utf8Path, dest: &Utf8Path) {
+    let Some((base, suffix)) = file.file_stem().and_then(|stem| stem.rsplit_once('.')) else {
+        return;
+    };
+    if files.contains(&file.with_file_name(format!("{base}.md"))) {
+        report.note(
+            dest.as_str(),
+            format!(
+                "looks like a translation into `{suffix}`, but the site's config doesn't list \
+                 that language, imported as a page of its own"
+            ),
+        );
+    }
+}
+
+/// Every file below `dir`, sorted, skipping hidden ones
+async fn list_files(dir: &Utf8Path) -> eyre::Result<Vec<Utf8PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = Utf8PathBuf::try_from(entry.path())?;
+            if path.file_name().is_some_and(|name| name.starts_with('.')) {
+                continue;
+            }
+            if entry.file_type().await?.is_dir() {
+                dirs.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn note_unsupported(report: &mut Report, dest: &Utf8Path, supported: &[&str]) {
+    let ext = dest.extension().unwrap_or_default().to_lowercase();
+    if !supported.contains(&ext.as_str()) {
+        report.note(
+            dest.as_str(),
+            "copied, but home ignores files of this type here",
+        );
+    }
+}
+
+async fn report_markdown(
+    cx: &ImportContext,
+    source: &Utf8Path,
+    report: &Report,
+    pages: usize,
+    files: usize,
+) -> eyre::Result<String> {
+    let mut out = String::from("# Import report\n\n");
+    out.push_str(&format!(
+        "Imported {pages} page(s) and {files} other file(s) from the {:?} site at `{source}`.\n",
+        cx.from
+    ));
+
+    let mut settings = serde_json::Map::new();
+    let sections: Vec<_> = report
+        .sections
+        .iter()
+        .filter(|name| !DEFAULT_SECTIONS.contains(&name.as_str()))
+        .map(|name| {
+            serde_json::json!({
+                "name": name,
+                "route": format!("/{name}"),
+                "kind": "articles",
+                "indexed": true,
+            })
+        })
+        .collect();
+    if !sections.is_empty() {
+        settings.insert("sections".into(), sections.into());
+    }
+    if !report.languages.is_empty() {
+        if let Some(default_lang) = &cx.default_lang {
+            settings.insert("default_lang".into(), default_lang.as_str().into());
+        }
+        let languages: Vec<_> = report.languages.iter().map(String::as_str).collect();
+        settings.insert("languages".into(), languages.into());
+    }
+    if !report.taxonomies.is_empty() {
+        let taxonomies: Vec<_> = report
+            .taxonomies
+            .iter()
+            .map(|name| serde_json::json!({ "name": name }))
+            .collect();
+        settings.insert("taxonomies".into(), taxonomies.into());
+    }
+    if !report.extra_fields.is_empty() {
+        let fields: Vec<_> = report
+            .extra_fields
+            .iter()
+            .map(|(name, kind)| serde_json::json!({ "name": name, "kind": kind }))
+            .collect();
+        settings.insert("frontmatter_fields".into(), fields.into());
+    }
+    if !settings.is_empty() {
+        out.push_str("\n## Suggested `home.json` settings\n\n");
+        out.push_str(
+            "Pages use these sections, languages, taxonomies and custom fields, they won't load until \
+             `home.json` declares them. Adjust kinds and sort orders to taste.\n\n",
+        );
+        out.push_str("```json\n");
+        out.push_str(&serde_json::to_string_pretty(&settings)?);
+        out.push_str("\n```\n");
+    }
+
+    let templates = match cx.from {
+        ImportFrom::Zola => ["templates", "sass"],
+        ImportFrom::Hugo => ["layouts", "assets"],
+    };
+    let mut skipped = Vec::new();
+    for dir in templates.into_iter().chain(["themes"]) {
+        if tokio::fs::metadata(source.join(dir)).await.is_ok() {
+            skipped.push(format!("`{dir}`"));
+        }
+    }
+    if !skipped.is_empty() {
+        out.push_str("\n## Not imported\n\n");
+        out.push_str(&format!(
+            "{} weren't imported: home templates are minijinja, in `templates`, and shortcodes \
+             live in `templates/shortcodes`. Those have to be ported by hand.\n",
+            skipped.join(", ")
+        ));
+    }
+
+    if !report.notes.is_empty() {
+        out.push_str("\n## Files\n");
+        for (file, notes) in &report.notes {
+            out.push_str(&format!("\n### `{file}`\n\n"));
+            for note in notes {
+                out.push_str(&format!("- {note}\n"));
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(languages: &[&str]) -> ImportContext {
+        ImportContext {
+            from: ImportFrom::Zola,
+            sections: HashMap::from([("blog".to_string(), "articles".to_string())]),
+            shortcodes: HashMap::new(),
+            default_lang: None,
+            languages: languages.iter().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn dest_paths() {
+        let cx = context(&["fr"]);
+        for (rel, dest) in [
+            ("blog/foo.md", "articles/foo.md"),
+            ("blog/foo.fr.md", "articles/foo.fr.md"),
+            ("blog/foo/index.md", "articles/foo/_index.md"),
+            ("blog/foo/index.fr.md", "articles/foo/_index.fr.md"),
+            ("blog/foo/index.de.md", "articles/foo/index.de.md"),
+            ("blog/_index.fr.md", "articles/_index.fr.md"),
+            ("blog.md", "blog.md"),
+        ] {
+            assert_eq!(cx.dest_path(Utf8Path::new(rel)), dest, "{rel}");
+        }
+    }
+
+    #[test]
+    fn routes() {
+        let cx = context(&["fr"]);
+        for (rel, route) in [
+            ("_index.md", "/"),
+            ("_index.fr.md", "/fr"),
+            ("about.md", "/about"),
+            ("blog/foo/_index.md", "/blog/foo"),
+            ("blog/foo/index.fr.md", "/fr/blog/foo"),
+            ("blog/foo.fr.md", "/fr/blog/foo"),
+            ("blog/node.js.md", "/blog/node.js"),
+        ] {
+            assert_eq!(cx.route_of(Utf8Path::new(rel)), route, "{rel}");
+        }
+    }
+
+    #[test]
+    fn languages_from_toml() {
+        let zola = r#"
+            base_url = "https://example.org"
+            # default_language = "de"
+            default_language = "en" # the default
+
+            [languages.fr]
+            title = "Mon site"
+
+            [languages."pt-BR"]
+            title = "Meu site"
+
+            [extra]
+            languages = "not a table"
+        "#;
+        let (default, languages) = parse_site_languages("config.toml", zola).unwrap();
+        assert_eq!(default.as_deref(), Some("en"));
+        assert_eq!(languages, ["fr", "pt-BR"]);
+
+        let hugo = r#"
+            defaultcontentlanguage = 'fr'
+            languages.en.weight = 2
+            languages.fr.weight = 1
+            languages.de = { weight = 3 }
+        "#;
+        let (default, languages) = parse_site_languages("hugo.toml", hugo).unwrap();
+        assert_eq!(default.as_deref(), Some("fr"));
+        assert_eq!(languages, ["de", "en"]);
+
+        let (default, languages) = parse_site_languages("config.toml", "title = 'x'").unwrap();
+        assert_eq!(default, None);
+        assert!(languages.is_empty());
+    }
+
+    #[test]
+    fn translations_without_a_language() {
+        let files: Vec<Utf8PathBuf> = [
+            "content/blog/foo.md",
+            "content/blog/foo.fr.md",
+            "content/blog/node.js.md",
+        ]
+        .into_iter()
+        .map(Utf8PathBuf::from)
+        .collect();
+        let mut report = Report::default();
+        for file in &files {
+            note_unknown_lang(&mut report, &files, file, file);
+        }
+        assert_eq!(
+            report.notes.keys().collect::<Vec<_>>(),
+            ["content/blog/foo.fr.md"]
+        );
+    }
+}
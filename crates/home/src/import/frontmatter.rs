@@ -0,0 +1,306 @@
+use std::collections::btree_map::Entry;
+
+use camino::Utf8Path;
+use eyre::bail;
+use libclap::ImportFrom;
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use yaml_rust2::{Yaml, YamlEmitter, YamlLoader, yaml::Hash};
+
+use super::{ImportContext, Report, shortcodes};
+
+/// Hugo keys we have nothing for. Any other key Hugo doesn't know about is a
+/// custom parameter, and becomes an `extra` field.
+const HUGO_DROPPED: &[&str] = &[
+    "weight",
+    "type",
+    "publishdate",
+    "expirydate",
+    "keywords",
+    "linktitle",
+    "menu",
+    "menus",
+    "outputs",
+    "build",
+    "cascade",
+    "headless",
+    "iscjklanguage",
+    "markup",
+    "resources",
+    "translationkey",
+    "summary",
+    "images",
+    "sitemap",
+    "lang",
+];
+
+/// Converts a page's frontmatter to home's, and its shortcodes along the way.
+/// `rel` is where the page was in the imported `content` folder, `dest` where
+/// it goes in ours. `file` is what the report calls it.
+pub(super) fn convert_page(
+    cx: &ImportContext,
+    rel: &Utf8Path,
+    dest: &Utf8Path,
+    file: &str,
+    markup: &str,
+    report: &mut Report,
+) -> eyre::Result<String> {
+    // TOML frontmatter gets the same treatment as with `home migrate-frontmatter`
+    let migrated = librevision::load().migrate_frontmatter(rel.as_str(), markup)?;
+    let markup_yaml = migrated.as_deref().unwrap_or(markup);
+    // notes about shortcodes point at lines of the page as it was written, so
+    // the body and where it starts both come from the original markup
+    let (Some((frontmatter, _)), Some((_, body))) =
+        (split_frontmatter(markup_yaml), split_frontmatter(markup))
+    else {
+        bail!("no `---` or `+++` frontmatter (home doesn't read JSON frontmatter)");
+    };
+    let first_body_line = markup.lines().count() - body.lines().count() + 1;
+
+    let docs = YamlLoader::load_from_str(frontmatter)?;
+    let Some(Yaml::Hash(fields)) = docs.into_iter().next() else {
+        bail!("frontmatter should be a map");
+    };
+
+    let mut out = Hash::new();
+    let mut extra = Hash::new();
+    let mut tags: Vec<Yaml> = Vec::new();
+    let mut taxonomies = Hash::new();
+    let mut aliases: Vec<String> = Vec::new();
+    let mut slug: Option<String> = None;
+    let mut explicit_route: Option<String> = None;
+
+    for (key, value) in fields {
+        let Some(key) = key.as_str().map(str::to_string) else {
+            report.note(file, format!("dropped frontmatter key {key:?}"));
+            continue;
+        };
+        // Hugo doesn't care about case
+        let lower = key.to_lowercase();
+        match (cx.from, lower.as_str()) {
+            (_, "title" | "draft") => {
+                out.insert(Yaml::String(lower), value);
+            }
+            (_, "date") | (ImportFrom::Zola, "updated") | (ImportFrom::Hugo, "lastmod") => {
+                let home_key = if lower == "date" { "date" } else { "updated_at" };
+                match convert_date(&value) {
+                    Some(date) => {
+                        out.insert(Yaml::String(home_key.into()), Yaml::String(date));
+                    }
+                    None => report.note(
+                        file,
+                        format!(
+                            "dropped `{key}`: can't read {value:?} as a date like `2023-10-01T12:00:00Z`"
+                        ),
+                    ),
+                }
+            }
+            (_, "aliases") => {
+                for alias in value.as_vec().into_iter().flatten() {
+                    match alias.as_str() {
+                        Some(alias) => aliases.push(normalize_route(alias)),
+                        None => report.note(file, format!("dropped alias {alias:?}")),
+                    }
+                }
+            }
+            (ImportFrom::Hugo, "tags") => tags.extend(strings(value)),
+            (ImportFrom::Hugo, "categories") => {
+                report.taxonomies.insert(lower.clone());
+                taxonomies.insert(Yaml::String(lower), Yaml::Array(strings(value)));
+            }
+            (ImportFrom::Zola, "taxonomies") => {
+                for (name, terms) in value.into_hash().into_iter().flatten() {
+                    let Some(name) = name.as_str() else {
+                        continue;
+                    };
+                    if name == "tags" {
+                        tags.extend(strings(terms));
+                    } else {
+                        report.taxonomies.insert(name.to_string());
+                        taxonomies.insert(Yaml::String(name.into()), Yaml::Array(strings(terms)));
+                    }
+                }
+            }
+            (ImportFrom::Zola, "extra") | (ImportFrom::Hugo, "params") => {
+                for (name, value) in value.into_hash().into_iter().flatten() {
+                    if let Some(name) = name.as_str() {
+                        add_extra(&mut extra, report, file, name, value);
+                    }
+                }
+            }
+            (_, "description") => add_extra(&mut extra, report, file, &key, value),
+            (_, "slug") => slug = value.as_str().map(str::to_string),
+            (ImportFrom::Zola, "path") | (ImportFrom::Hugo, "url") => {
+                explicit_route = value.as_str().map(str::to_string)
+            }
+            (_, "template") | (ImportFrom::Hugo, "layout") => report.note(
+                file,
+                format!(
+                    "dropped `{key}`: templates have to be ported by hand, pages use their section's template"
+                ),
+            ),
+            (ImportFrom::Hugo, lower) if !HUGO_DROPPED.contains(&lower) => {
+                add_extra(&mut extra, report, file, &key, value)
+            }
+            _ => report.note(file, format!("dropped `{key}`, home has no equivalent")),
+        }
+    }
+
+    for required in ["title", "date"] {
+        if !out.contains_key(&Yaml::String(required.into())) {
+            report.note(file, format!("no `{required}`, home needs one"));
+        }
+    }
+
+    // keep old links working, wherever the page ends up
+    let old_route = match (explicit_route, slug) {
+        (Some(route), _) => normalize_route(&route),
+        (None, Some(slug)) => {
+            let route = cx.route_of(rel);
+            let parent = route.rsplit_once('/').map(|(p, _)| p).unwrap_or_default();
+            format!("{parent}/{slug}")
+        }
+        (None, None) => cx.route_of(rel),
+    };
+    if old_route != cx.route_of(dest) && !aliases.contains(&old_route) {
+        aliases.push(old_route);
+    }
+
+    if !aliases.is_empty() {
+        let aliases = aliases.into_iter().map(Yaml::String).collect();
+        out.insert(Yaml::String("aliases".into()), Yaml::Array(aliases));
+    }
+    if !tags.is_empty() {
+        out.insert(Yaml::String("tags".into()), Yaml::Array(tags));
+    }
+    if !taxonomies.is_empty() {
+        out.insert(Yaml::String("taxonomies".into()), Yaml::Hash(taxonomies));
+    }
+    if !extra.is_empty() {
+        out.insert(Yaml::String("extra".into()), Yaml::Hash(extra));
+    }
+
+    let mut yaml = String::new();
+    YamlEmitter::new(&mut yaml).dump(&Yaml::Hash(out))?;
+    let body = shortcodes::convert(cx, file, body, first_body_line, report);
+    // the emitter starts the document with `---` for us
+    Ok(format!("{yaml}\n---\n{body}"))
+}
+
+/// Splits frontmatter from the rest of the page, be it YAML (between `---`
+/// lines) or TOML (between `+++` lines)
+fn split_frontmatter(markup: &str) -> Option<(&str, &str)> {
+    let delimiter = ["---", "+++"]
+        .into_iter()
+        .find(|delimiter| markup.starts_with(delimiter))?;
+    let rest = &markup[delimiter.len()..];
+    let rest = rest
+        .strip_prefix('\n')
+        .or_else(|| rest.strip_prefix("\r\n"))?;
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == delimiter {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Dates in Zola and Hugo can leave out the time or the offset, home's can't
+fn convert_date(value: &Yaml) -> Option<String> {
+    let date = value.as_str()?.trim();
+    let with_t = date.replacen(' ', "T", 1);
+    [
+        date.to_string(),
+        with_t.clone(),
+        format!("{with_t}Z"),
+        format!("{date}T00:00:00Z"),
+    ]
+    .into_iter()
+    .find(|candidate| OffsetDateTime::parse(candidate, &Rfc3339).is_ok())
+}
+
+/// Routes are absolute, without a trailing slash, e.g. `/blog/foo`
+fn normalize_route(route: &str) -> String {
+    format!("/{}", route.trim_matches('/'))
+}
+
+fn strings(value: Yaml) -> Vec<Yaml> {
+    value
+        .into_vec()
+        .into_iter()
+        .flatten()
+        .filter(|v| v.as_str().is_some())
+        .collect()
+}
+
+/// Adds a custom field, and remembers its kind for `frontmatter_fields`
+fn add_extra(extra: &mut Hash, report: &mut Report, file: &str, name: &str, value: Yaml) {
+    let kind = match &value {
+        Yaml::String(_) => "string",
+        Yaml::Integer(_) => "integer",
+        Yaml::Real(_) => "float",
+        Yaml::Boolean(_) => "boolean",
+        Yaml::Array(items) if items.iter().all(|item| item.as_str().is_some()) => "strings",
+        _ => {
+            report.note(
+                file,
+                format!("dropped `{name}`: custom fields can't hold maps or mixed lists"),
+            );
+            return;
+        }
+    };
+    match report.extra_fields.entry(name.to_string()) {
+        Entry::Vacant(entry) => {
+            entry.insert(kind);
+        }
+        Entry::Occupied(mut entry) => match (*entry.get(), kind) {
+            (existing, kind) if existing == kind => {}
+            ("integer", "float") | ("float", "integer") => {
+                entry.insert("float");
+            }
+            (existing, kind) => report.note(
+                file,
+                format!("`{name}` is a {kind} here but a {existing} elsewhere, pick one"),
+            ),
+        },
+    }
+    extra.insert(Yaml::String(name.into()), value);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn toml_pages_report_lines_as_written() {
+        let cx = ImportContext {
+            from: ImportFrom::Zola,
+            sections: HashMap::new(),
+            shortcodes: HashMap::new(),
+            default_lang: None,
+            languages: vec![],
+        };
+        let mut report = Report::default();
+        let markup = "+++\ntitle = \"Hello\"\ndate = 2023-10-01\n\n[taxonomies]\ntags = [\"rust\"]\n+++\nIntro\n\nBye {{ gist(id=1) }}\n";
+        let page = convert_page(
+            &cx,
+            Utf8Path::new("blog/hello.md"),
+            Utf8Path::new("blog/hello.md"),
+            "content/blog/hello.md",
+            markup,
+            &mut report,
+        )
+        .unwrap();
+        assert!(
+            page.ends_with("---\nIntro\n\nBye {{ gist(id=1) }}\n"),
+            "{page}"
+        );
+        assert_eq!(
+            report.notes["content/blog/hello.md"],
+            ["line 10: shortcode in the middle of a line, left as is"]
+        );
+    }
+}
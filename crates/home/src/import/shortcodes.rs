@@ -0,0 +1,481 @@
+use libclap::ImportFrom;
+use serde::Deserialize;
+
+use super::{ImportContext, Report};
+
+/// How to call one of the imported site's shortcodes in home, from the JSON
+/// file passed as `--shortcodes`, e.g. `{ "youtube": { "name": "youtube",
+/// "positional": ["id"] } }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ShortcodeMapping {
+    /// the home shortcode, e.g. `youtube` for `templates/shortcodes/youtube.html.jinja`
+    name: String,
+
+    /// names for positional arguments, in order (Hugo only)
+    #[serde(default)]
+    positional: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum ArgValue {
+    String(String),
+    Number(String),
+    Boolean(bool),
+}
+
+impl ArgValue {
+    /// Works both in YAML and in home's `*:name(key=value)*`, since JSON
+    /// strings are valid in both
+    fn markup(&self) -> String {
+        match self {
+            ArgValue::String(s) => serde_json::Value::from(s.as_str()).to_string(),
+            ArgValue::Number(n) => n.clone(),
+            ArgValue::Boolean(b) => b.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Call {
+    name: String,
+    positional: Vec<ArgValue>,
+    named: Vec<(String, ArgValue)>,
+
+    /// Zola's `{% name() %}`, which always has a body
+    block: bool,
+
+    /// Hugo's `{{< name />}}`, which never has one
+    self_closing: bool,
+}
+
+/// Rewrites the shortcode calls of a page that have a mapping: calls without
+/// a body become `+++` blocks, calls with one become `> *:name*` blockquotes.
+/// Everything else is left as is, and noted in the report. `first_line` is
+/// the line `body` starts at in the page.
+pub(super) fn convert(
+    cx: &ImportContext,
+    file: &str,
+    body: &str,
+    first_line: usize,
+    report: &mut Report,
+) -> String {
+    let lines: Vec<&str> = body.lines().collect();
+    let mut out = String::with_capacity(body.len());
+    convert_lines(cx, file, &lines, first_line, report, &mut out);
+    out
+}
+
+fn convert_lines(
+    cx: &ImportContext,
+    file: &str,
+    lines: &[&str],
+    first_line: usize,
+    report: &mut Report,
+    out: &mut String,
+) {
+    let push_line = |out: &mut String, line: &str| {
+        out.push_str(line);
+        out.push('\n');
+    };
+
+    let mut in_fence = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let line_number = first_line + i;
+        i += 1;
+
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if in_fence || trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            push_line(out, line);
+            continue;
+        }
+
+        let call = match parse_call(cx.from, trimmed) {
+            Some(Ok(call)) => call,
+            Some(Err(e)) => {
+                report.note(file, format!("line {line_number}: {e}, left as is"));
+                push_line(out, line);
+                continue;
+            }
+            None => {
+                if mentions_shortcode(cx.from, line) && !is_closing(cx.from, trimmed) {
+                    report.note(
+                        file,
+                        format!(
+                            "line {line_number}: shortcode in the middle of a line, left as is"
+                        ),
+                    );
+                }
+                push_line(out, line);
+                continue;
+            }
+        };
+
+        let end = if call.block || (cx.from == ImportFrom::Hugo && !call.self_closing) {
+            find_end(cx.from, &call.name, &lines[i..]).map(|n| i + n)
+        } else {
+            None
+        };
+        if call.block && end.is_none() {
+            report.note(
+                file,
+                format!(
+                    "line {line_number}: `{}` is never closed with `{{% end %}}`, left as is",
+                    call.name
+                ),
+            );
+            push_line(out, line);
+            continue;
+        }
+
+        let Some(mapping) = cx.shortcodes.get(&call.name) else {
+            report.note(
+                file,
+                format!(
+                    "line {line_number}: no mapping for shortcode `{}`, left as is",
+                    call.name
+                ),
+            );
+            push_line(out, line);
+            continue;
+        };
+        if call.positional.len() > mapping.positional.len() {
+            report.note(
+                file,
+                format!(
+                    "line {line_number}: `{}` has {} positional argument(s), name them with `positional` in the shortcode mappings",
+                    call.name,
+                    call.positional.len()
+                ),
+            );
+            push_line(out, line);
+            continue;
+        }
+        let args: Vec<(&str, &ArgValue)> = mapping
+            .positional
+            .iter()
+            .map(String::as_str)
+            .zip(&call.positional)
+            .chain(call.named.iter().map(|(k, v)| (k.as_str(), v)))
+            .collect();
+
+        // shortcodes are blocks in home, they need blank lines around them
+        if !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+        match end {
+            Some(end) => {
+                let mut inner = String::new();
+                convert_lines(cx, file, &lines[i..end], first_line + i, report, &mut inner);
+                out.push_str(&block_shortcode(&mapping.name, &args, &inner));
+                i = end + 1;
+            }
+            None => out.push_str(&inline_shortcode(&mapping.name, &args)),
+        }
+        out.push('\n');
+    }
+}
+
+/// Parses a line that's nothing but a shortcode call, `None` if it isn't one
+fn parse_call(from: ImportFrom, line: &str) -> Option<Result<Call, String>> {
+    match from {
+        ImportFrom::Zola => {
+            let (inner, block) = match line.strip_prefix("{{").and_then(|l| l.strip_suffix("}}")) {
+                Some(inner) => (inner, false),
+                None => (line.strip_prefix("{%")?.strip_suffix("%}")?, true),
+            };
+            let (name, args) = inner.trim().split_once('(')?;
+            let args = args.strip_suffix(')')?;
+            let name = name.trim();
+            if !is_ident(name) {
+                return None;
+            }
+            Some(parse_zola_args(args).map(|named| Call {
+                name: name.to_string(),
+                positional: Vec::new(),
+                named,
+                block,
+                self_closing: false,
+            }))
+        }
+        ImportFrom::Hugo => {
+            let inner = line
+                .strip_prefix("{{<")
+                .and_then(|l| l.strip_suffix(">}}"))
+                .or_else(|| line.strip_prefix("{{%")?.strip_suffix("%}}"))?
+                .trim();
+            // closing tags, and `{{</* name */>}}` which shows a call without running it
+            if inner.starts_with('/') {
+                return None;
+            }
+            let (inner, self_closing) = match inner.strip_suffix('/') {
+                Some(inner) => (inner.trim(), true),
+                None => (inner, false),
+            };
+            let (name, args) = inner.split_once(char::is_whitespace).unwrap_or((inner, ""));
+            if !is_ident(name) {
+                return None;
+            }
+            Some(parse_hugo_args(args).map(|(positional, named)| Call {
+                name: name.to_string(),
+                positional,
+                named,
+                block: false,
+                self_closing,
+            }))
+        }
+    }
+}
+
+/// Finds the line that closes a call: `{% end %}` for Zola, `{{< /name >}}`
+/// for Hugo. Skips over nested calls.
+fn find_end(from: ImportFrom, name: &str, lines: &[&str]) -> Option<usize> {
+    let closing = format!("/{name}");
+    let mut depth = 0;
+    for (n, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        let (opens, closes) = match from {
+            ImportFrom::Zola => (
+                matches!(parse_call(from, line), Some(Ok(Call { block: true, .. }))),
+                is_tag(line, "{%", "%}", "end"),
+            ),
+            ImportFrom::Hugo => (
+                matches!(parse_call(from, line), Some(Ok(call)) if call.name == name && !call.self_closing),
+                is_tag(line, "{{<", ">}}", &closing) || is_tag(line, "{{%", "%}}", &closing),
+            ),
+        };
+        if closes {
+            if depth == 0 {
+                return Some(n);
+            }
+            depth -= 1;
+        } else if opens {
+            depth += 1;
+        }
+    }
+    None
+}
+
+fn is_tag(line: &str, open: &str, close: &str, inner: &str) -> bool {
+    line.strip_prefix(open)
+        .and_then(|l| l.strip_suffix(close))
+        .is_some_and(|l| l.trim() == inner)
+}
+
+/// Closing tags of calls we left alone don't need another note
+fn is_closing(from: ImportFrom, line: &str) -> bool {
+    match from {
+        ImportFrom::Zola => is_tag(line, "{%", "%}", "end"),
+        ImportFrom::Hugo => ["{{<", "{{%"].iter().any(|open| {
+            line.strip_prefix(open)
+                .is_some_and(|rest| rest.trim_start().starts_with('/'))
+        }),
+    }
+}
+
+fn mentions_shortcode(from: ImportFrom, line: &str) -> bool {
+    match from {
+        ImportFrom::Zola => line.contains("{{") || line.contains("{%"),
+        ImportFrom::Hugo => {
+            (line.contains("{{<") || line.contains("{{%"))
+                && !(line.contains("{{</*") || line.contains("{{%/*"))
+        }
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Zola arguments: `key="value", other=3`
+fn parse_zola_args(input: &str) -> Result<Vec<(String, ArgValue)>, String> {
+    let mut args = Vec::new();
+    let mut rest = input.trim();
+    while !rest.is_empty() {
+        let (key, after) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("expected `key=value`, got `{rest}`"))?;
+        let key = key.trim();
+        if !is_ident(key) {
+            return Err(format!("invalid argument name `{key}`"));
+        }
+        let (value, after) = parse_value(ImportFrom::Zola, after.trim_start())?;
+        args.push((key.to_string(), value));
+        rest = after.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+    Ok(args)
+}
+
+/// Hugo arguments: either positional (`abc "d e"`) or named (`key="value"`)
+fn parse_hugo_args(input: &str) -> Result<(Vec<ArgValue>, Vec<(String, ArgValue)>), String> {
+    let mut positional = Vec::new();
+    let mut named = Vec::new();
+    let mut rest = input.trim();
+    while !rest.is_empty() {
+        let key_len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(rest.len());
+        if key_len > 0 && rest[key_len..].starts_with('=') {
+            let (value, after) = parse_value(ImportFrom::Hugo, &rest[key_len + 1..])?;
+            named.push((rest[..key_len].to_string(), value));
+            rest = after.trim_start();
+        } else {
+            let (value, after) = parse_value(ImportFrom::Hugo, rest)?;
+            positional.push(value);
+            rest = after.trim_start();
+        }
+    }
+    Ok((positional, named))
+}
+
+/// Reads a quoted string, a boolean or a number at the start of `input`, and
+/// returns what's left. Hugo also takes bare words as strings.
+fn parse_value(from: ImportFrom, input: &str) -> Result<(ArgValue, &str), String> {
+    let mut chars = input.char_indices();
+    match chars.next() {
+        Some((_, quote @ ('"' | '\'' | '`'))) => {
+            let mut value = String::new();
+            let mut escaped = false;
+            for (i, c) in chars {
+                if escaped {
+                    value.push(match c {
+                        'n' => '\n',
+                        't' => '\t',
+                        c => c,
+                    });
+                    escaped = false;
+                } else if c == '\\' && quote != '`' {
+                    escaped = true;
+                } else if c == quote {
+                    return Ok((ArgValue::String(value), &input[i + 1..]));
+                } else {
+                    value.push(c);
+                }
+            }
+            Err(format!("unterminated string in `{input}`"))
+        }
+        Some(_) => {
+            let end = input
+                .find(|c: char| c.is_whitespace() || (from == ImportFrom::Zola && c == ','))
+                .unwrap_or(input.len());
+            let (word, rest) = input.split_at(end);
+            let value = match word {
+                "true" => ArgValue::Boolean(true),
+                "false" => ArgValue::Boolean(false),
+                _ if word.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+                    && word.parse::<f64>().is_ok() =>
+                {
+                    ArgValue::Number(word.to_string())
+                }
+                _ if from == ImportFrom::Hugo => ArgValue::String(word.to_string()),
+                _ => {
+                    return Err(format!(
+                        "can't convert `{word}`, only strings, numbers and booleans are supported"
+                    ));
+                }
+            };
+            Ok((value, rest))
+        }
+        None => Err("missing argument value".to_string()),
+    }
+}
+
+/// A call without a body
+fn inline_shortcode(name: &str, args: &[(&str, &ArgValue)]) -> String {
+    let mut out = format!("+++\n:{name}:");
+    if args.is_empty() {
+        out.push_str(" {}\n");
+    } else {
+        out.push('\n');
+        for (key, value) in args {
+            out.push_str(&format!("    {key}: {}\n", value.markup()));
+        }
+    }
+    out.push_str("+++\n");
+    out
+}
+
+/// A call with a body
+fn block_shortcode(name: &str, args: &[(&str, &ArgValue)], body: &str) -> String {
+    let mut out = format!("> *:{name}");
+    if !args.is_empty() {
+        let args: Vec<String> = args
+            .iter()
+            .map(|(key, value)| format!("{key}={}", value.markup()))
+            .collect();
+        out.push_str(&format!("({})", args.join(", ")));
+    }
+    out.push_str("*\n>\n");
+    for line in body.trim_matches('\n').lines() {
+        if line.is_empty() {
+            out.push_str(">\n");
+        } else {
+            out.push_str(&format!("> {line}\n"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn context(from: ImportFrom) -> ImportContext {
+        let mapping = |name: &str, positional: &[&str]| ShortcodeMapping {
+            name: name.to_string(),
+            positional: positional.iter().map(|p| p.to_string()).collect(),
+        };
+        ImportContext {
+            from,
+            sections: HashMap::new(),
+            shortcodes: HashMap::from([
+                ("youtube".to_string(), mapping("youtube", &["id"])),
+                ("quote".to_string(), mapping("bearsays", &[])),
+            ]),
+            default_lang: None,
+            languages: vec![],
+        }
+    }
+
+    #[test]
+    fn zola_calls() {
+        let cx = context(ImportFrom::Zola);
+        let mut report = Report::default();
+        let body = "Intro\n{{ youtube(id=\"abc\", autoplay=true) }}\n{% quote(mood='happy') %}\nHi *there*\n{% end %}\nBye {{ gist(id=1) }}\n";
+        let out = convert(&cx, "content/a.md", body, 5, &mut report);
+        assert_eq!(
+            out,
+            "Intro\n\n+++\n:youtube:\n    id: \"abc\"\n    autoplay: true\n+++\n\n> *:bearsays(mood=\"happy\")*\n>\n> Hi *there*\n\nBye {{ gist(id=1) }}\n"
+        );
+        assert_eq!(
+            report.notes["content/a.md"],
+            ["line 10: shortcode in the middle of a line, left as is"]
+        );
+    }
+
+    #[test]
+    fn hugo_calls() {
+        let cx = context(ImportFrom::Hugo);
+        let mut report = Report::default();
+        let body = "{{< youtube abc >}}\n{{% quote %}}\nHi\n{{% /quote %}}\n{{< figure src=\"a.png\" >}}\n";
+        let out = convert(&cx, "content/a.md", body, 1, &mut report);
+        assert_eq!(
+            out,
+            "+++\n:youtube:\n    id: \"abc\"\n+++\n\n> *:bearsays*\n>\n> Hi\n\n{{< figure src=\"a.png\" >}}\n"
+        );
+        assert_eq!(
+            report.notes["content/a.md"],
+            ["line 5: no mapping for shortcode `figure`, left as is"]
+        );
+    }
+}
@@ -16,6 +16,9 @@ use tokio::net::TcpListener;
 use tracing::info;
 
 mod dev_setup;
+mod import;
+mod migrate_frontmatter;
+mod test_templates;
 
 pub(crate) fn print_error(e: &eyre::Report) {
     print_error_to_writer(e, &mut std::io::stderr());
@@ -50,6 +53,11 @@ async fn real_main() -> eyre::Result<()> {
         Cmd::Init(args) => dev_setup::init_project(&args.dir, args.force)
             .await
             .map_err(|err| eyre::eyre!(err.to_string())),
+        Cmd::Import(args) => import::import_site(args).await,
+        Cmd::MigrateFrontmatter(args) => {
+            migrate_frontmatter::migrate_frontmatter(&args.dir, args.dry_run).await
+        }
+        Cmd::TestTemplates(args) => test_templates::test_templates(&args.dir, args.update).await,
         Cmd::Serve(args) => {
             let CubConfigBundle { mut cc, tenants } = libconfig::load()
                 .load_cub_config(args.config.as_ref().map(|p| p.as_path()), args.roots)
@@ -120,6 +128,7 @@ async fn real_main() -> eyre::Result<()> {
                     secrets: MomSecrets {
                         readonly_api_key: MOM_DEV_API_KEY.to_owned(),
                         scoped_api_keys: Default::default(),
+                        smtp: None,
                     },
                 };
 
@@ -0,0 +1,48 @@
+use camino::{Utf8Path, Utf8PathBuf};
+
+/// Rewrites TOML frontmatter as YAML in every markdown page under `content`.
+/// Pages that already use YAML are left alone.
+pub(crate) async fn migrate_frontmatter(base_dir: &Utf8Path, dry_run: bool) -> eyre::Result<()> {
+    let content_dir = base_dir.join("content");
+    if tokio::fs::metadata(&content_dir).await.is_err() {
+        return Err(eyre::eyre!(
+            "😕 No \x1b[33m{content_dir}\x1b[0m folder, is \x1b[33m{base_dir}\x1b[0m a home site?"
+        ));
+    }
+
+    let revision = librevision::load();
+    let mut migrated = 0;
+    let mut dirs: Vec<Utf8PathBuf> = vec![content_dir];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = Utf8PathBuf::try_from(entry.path())?;
+            if entry.file_type().await?.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            if path.extension() != Some("md") {
+                continue;
+            }
+
+            let markup = tokio::fs::read_to_string(&path).await?;
+            let Some(rewritten) = revision.migrate_frontmatter(path.as_str(), &markup)? else {
+                continue;
+            };
+            if dry_run {
+                eprintln!("🔍 Would rewrite \x1b[33m{path}\x1b[0m");
+            } else {
+                tokio::fs::write(&path, rewritten).await?;
+                eprintln!("🔄 Rewrote \x1b[33m{path}\x1b[0m");
+            }
+            migrated += 1;
+        }
+    }
+
+    match (migrated, dry_run) {
+        (0, _) => eprintln!("✅ No TOML frontmatter left, nothing to do"),
+        (n, true) => eprintln!("📝 {n} page(s) have TOML frontmatter"),
+        (n, false) => eprintln!("✅ Moved {n} page(s) over to YAML frontmatter"),
+    }
+    Ok(())
+}
@@ -0,0 +1,596 @@
+use std::sync::Arc;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use closest::{GetOrHelp, ResourceKind};
+use config_types::{CubConfigBundle, Environment, RevisionConfig, TenantInfo, WebConfig};
+use conflux::{AccessOverride, InputPath, LoadedPage, PathMappings, Route, Viewer};
+use credentials::{Profile, Tier, UserInfo};
+use cub_types::IndexedRevision;
+use eyre::{Context, bail, eyre};
+use libcub::ViewedPage;
+use librevision::{RevisionKind, RevisionSpec};
+use mom_types::{GlobalStateView, Sponsors, Webmentions};
+use template_types::{DataObject, DataValue, RenderTemplateArgs, Shortcode};
+use yaml_rust2::{Yaml, YamlLoader};
+
+/// One rendering to check, as declared in `tests/*.yaml`
+struct Fixture {
+    name: String,
+    kind: FixtureKind,
+    /// "anon", "admin", or a tier name
+    viewer: String,
+    /// e.g. `page=2&lang=fr`
+    query: String,
+}
+
+enum FixtureKind {
+    Template {
+        /// defaults to the page's template
+        template: Option<String>,
+        /// a route like `/articles/foo`, or an input path like `/content/articles/foo.md`
+        page: Option<String>,
+        /// defaults to the page's route
+        route: Option<String>,
+    },
+    Shortcode {
+        name: String,
+        args: DataObject,
+        body: Option<String>,
+    },
+}
+
+/// What templates see of mom: nobody sponsors, nobody mentions
+struct FixtureState {
+    ti: Arc<TenantInfo>,
+}
+
+impl GlobalStateView for FixtureState {
+    fn gsv_ti(&self) -> Arc<TenantInfo> {
+        self.ti.clone()
+    }
+
+    fn gsv_sponsors(&self) -> Arc<Sponsors> {
+        Arc::new(Sponsors {
+            sponsors: Vec::new(),
+        })
+    }
+
+    fn gsv_webmentions(&self) -> Arc<Webmentions> {
+        Default::default()
+    }
+}
+
+enum Outcome {
+    Passed,
+    Written,
+    Failed,
+}
+
+/// Builds a revision from the site on disk, renders every fixture in
+/// `tests/*.yaml` and compares the output with `tests/snapshots`.
+pub(crate) async fn test_templates(base_dir: &Utf8Path, update: bool) -> eyre::Result<()> {
+    let tests_dir = base_dir.join("tests");
+    let mut fixture_files = Vec::new();
+    if let Ok(mut entries) = tokio::fs::read_dir(&tests_dir).await {
+        while let Some(entry) = entries.next_entry().await? {
+            let path = Utf8PathBuf::try_from(entry.path())?;
+            if matches!(path.extension(), Some("yaml" | "yml")) {
+                fixture_files.push(path);
+            }
+        }
+    }
+    fixture_files.sort();
+    if fixture_files.is_empty() {
+        bail!(
+            "😕 No fixtures in \x1b[33m{tests_dir}\x1b[0m, add a \x1b[33m.yaml\x1b[0m file there"
+        );
+    }
+
+    let CubConfigBundle { cc, tenants } = libconfig::load()
+        .load_cub_config(None, vec![base_dir.to_owned()])
+        .wrap_err("while reading the site config")?;
+    let ti = Arc::new(
+        tenants
+            .into_values()
+            .next()
+            .ok_or_else(|| eyre!("no tenant in {base_dir}"))?,
+    );
+    let web = WebConfig {
+        env: Environment::default(),
+        port: cc.address.port(),
+    };
+
+    eprintln!("🏗️  Building a revision from \x1b[33m{base_dir}\x1b[0m");
+    let spec = RevisionSpec {
+        kind: RevisionKind::FromScratch,
+        mappings: PathMappings::from_ti(&ti),
+    };
+    let irev = librevision::load()
+        .make_revision(ti.clone(), spec, web)
+        .await
+        .wrap_err("while building the revision")?;
+    let gv: Arc<dyn GlobalStateView> = Arc::new(FixtureState { ti });
+
+    let snapshots_dir = tests_dir.join("snapshots");
+    let (mut passed, mut written, mut failed) = (0, 0, 0);
+    for file in &fixture_files {
+        let fixtures = tokio::fs::read_to_string(file)
+            .await
+            .map_err(eyre::Report::from)
+            .and_then(|yaml| parse_fixtures(&yaml))
+            .wrap_err_with(|| format!("while reading fixtures from {file}"))?;
+        let stem = file.file_stem().unwrap_or_default();
+
+        for fixture in fixtures {
+            let label = format!("{stem} › {}", fixture.name);
+            let snapshot = snapshots_dir
+                .join(stem)
+                .join(format!("{}.html", slugify(&fixture.name)));
+            let rendered = match render_fixture(fixture, &irev, &gv, web) {
+                Ok(rendered) => rendered,
+                Err(e) => {
+                    eprintln!("❌ \x1b[31m{label}\x1b[0m failed to render:");
+                    crate::print_error(&e);
+                    failed += 1;
+                    continue;
+                }
+            };
+
+            match check_snapshot(&snapshot, &rendered, update).await? {
+                Outcome::Passed => passed += 1,
+                Outcome::Written => {
+                    eprintln!("📸 Wrote \x1b[33m{snapshot}\x1b[0m");
+                    written += 1;
+                }
+                Outcome::Failed => {
+                    eprintln!("❌ \x1b[31m{label}\x1b[0m doesn't match \x1b[33m{snapshot}\x1b[0m");
+                    print_first_difference(&snapshot, &rendered).await;
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    eprintln!("🧪 {passed} passed, {written} snapshot(s) written, {failed} failed");
+    if failed > 0 {
+        bail!(
+            "{failed} template test(s) failed, run with \x1b[33m--update\x1b[0m if the changes are expected"
+        );
+    }
+    eprintln!("✅ All templates render as expected");
+    Ok(())
+}
+
+/// Missing snapshots are a failure too, unless we're updating: CI should
+/// never make up its own expectations.
+async fn check_snapshot(
+    snapshot: &Utf8Path,
+    rendered: &str,
+    update: bool,
+) -> eyre::Result<Outcome> {
+    let pending = Utf8PathBuf::from(format!("{snapshot}.new"));
+    let expected = tokio::fs::read_to_string(snapshot).await.ok();
+    if expected.as_deref() == Some(rendered) {
+        let _ = tokio::fs::remove_file(&pending).await;
+        return Ok(Outcome::Passed);
+    }
+
+    if let Some(parent) = snapshot.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    if update {
+        tokio::fs::write(snapshot, rendered).await?;
+        let _ = tokio::fs::remove_file(&pending).await;
+        return Ok(Outcome::Written);
+    }
+    // left next to the snapshot so it can be diffed or copied over
+    tokio::fs::write(&pending, rendered).await?;
+    Ok(Outcome::Failed)
+}
+
+async fn print_first_difference(snapshot: &Utf8Path, rendered: &str) {
+    let Ok(expected) = tokio::fs::read_to_string(snapshot).await else {
+        eprintln!("   no snapshot yet, the output is in \x1b[33m{snapshot}.new\x1b[0m");
+        return;
+    };
+    let mut expected_lines = expected.lines();
+    let mut rendered_lines = rendered.lines();
+    for line in 1.. {
+        match (expected_lines.next(), rendered_lines.next()) {
+            (None, None) => break,
+            (e, r) if e == r => continue,
+            (e, r) => {
+                eprintln!("   first difference on line {line}:");
+                eprintln!("   \x1b[31m- {}\x1b[0m", e.unwrap_or("<end of snapshot>"));
+                eprintln!("   \x1b[32m+ {}\x1b[0m", r.unwrap_or("<end of output>"));
+                break;
+            }
+        }
+    }
+    eprintln!("   the full output is in \x1b[33m{snapshot}.new\x1b[0m");
+}
+
+fn render_fixture(
+    fixture: Fixture,
+    irev: &IndexedRevision,
+    gv: &Arc<dyn GlobalStateView>,
+    web: WebConfig,
+) -> eyre::Result<String> {
+    let mut buffer: Vec<u8> = Vec::new();
+    match fixture.kind {
+        FixtureKind::Shortcode { name, args, body } => {
+            // shortcodes only ever see what anonymous viewers see
+            if fixture.viewer != "anon" || !fixture.query.is_empty() {
+                bail!("shortcodes don't know about the viewer or the query, leave those out");
+            }
+            irev.templates.render_shortcode_to(
+                &mut buffer,
+                Shortcode {
+                    name: &name,
+                    body: body.as_deref(),
+                    args,
+                },
+                irev.rev.clone(),
+                web,
+            )?;
+        }
+        FixtureKind::Template {
+            template,
+            page,
+            route,
+        } => {
+            let rc = &irev.rev.pak.rc;
+            let user_info = fixture_user_info(&fixture.viewer, rc)?;
+            let viewer = Viewer::new(
+                rc.clone(),
+                user_info.as_ref(),
+                AccessOverride::from_raw_query(&fixture.query),
+            );
+
+            let mut additional_globals = DataObject::new();
+            let (page, template) = match page {
+                Some(page) => {
+                    let page = find_page(irev, &page)?;
+                    let title = page.title.clone();
+                    match libcub::load().page_for_viewer(page, &viewer, rc, &fixture.query) {
+                        Some(ViewedPage { page, globals }) => {
+                            additional_globals.insert("title".into(), title.into());
+                            additional_globals.extend(globals);
+                            (Some(page), template)
+                        }
+                        // like when serving: drafts without the right `draft_code`
+                        None => (None, Some("404.html".to_string())),
+                    }
+                }
+                None => (None, template),
+            };
+
+            let template_name = match (template, &page) {
+                (Some(template), _) => template,
+                (None, Some(page)) => page.template.clone(),
+                (None, None) => bail!("needs a `template`, a `page` or a `shortcode`"),
+            };
+            let route = match (route, &page) {
+                (Some(route), _) => Route::new(route),
+                (None, Some(page)) => page.route.clone(),
+                (None, None) => Route::new("/".to_string()),
+            };
+
+            irev.templates.render_template_to(
+                &mut buffer,
+                RenderTemplateArgs {
+                    template_name: &template_name,
+                    path: &route,
+                    raw_query: &fixture.query,
+                    rv: irev.rev.clone(),
+                    gv: gv.clone(),
+                    index: irev.index.clone(),
+                    page,
+                    user_info,
+                    preferences: Default::default(),
+                    web,
+                    additional_globals,
+                },
+            )?;
+        }
+    }
+    Ok(String::from_utf8(buffer)?)
+}
+
+fn find_page(irev: &IndexedRevision, page: &str) -> eyre::Result<Arc<LoadedPage>> {
+    let path = if page.starts_with("/content/") {
+        InputPath::new(page.to_string())
+    } else {
+        let route = Route::new(page.to_string()).trim_trailing_slash();
+        irev.rev
+            .page_routes
+            .get_or_help(ResourceKind::Route, &route)?
+            .clone()
+    };
+    Ok(irev
+        .rev
+        .pages
+        .get_or_help(ResourceKind::Page, &path)?
+        .clone())
+}
+
+/// A made-up signed-in user for `viewer: admin` or `viewer: <tier>`
+fn fixture_user_info(viewer: &str, rc: &RevisionConfig) -> eyre::Result<Option<UserInfo>> {
+    let mut profile = Profile {
+        patreon_id: None,
+        github_id: None,
+        email: None,
+        full_name: "Fixture Viewer".into(),
+        thumb_url: "".into(),
+    };
+    let tier = match viewer {
+        "anon" => return Ok(None),
+        "admin" => {
+            if let Some(id) = rc.admin_github_ids.first() {
+                profile.github_id = Some(id.clone());
+            } else if let Some(id) = rc.admin_patreon_ids.first() {
+                profile.patreon_id = Some(id.clone());
+            } else {
+                bail!(
+                    "`viewer: admin` needs `admin_github_ids` or `admin_patreon_ids` in home.json"
+                );
+            }
+            None
+        }
+        tier => match rc.tier_by_title(tier) {
+            Some(tier) => Some(Tier {
+                title: tier.name.clone(),
+            }),
+            None => bail!(
+                "unknown viewer {tier:?}, expected \"anon\", \"admin\" or one of the tiers: {}",
+                rc.tiers()
+                    .iter()
+                    .map(|t| t.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        },
+    };
+    Ok(Some(UserInfo { profile, tier }))
+}
+
+fn parse_fixtures(yaml: &str) -> eyre::Result<Vec<Fixture>> {
+    let docs = YamlLoader::load_from_str(yaml)?;
+    let Some(Yaml::Array(items)) = docs.into_iter().next() else {
+        bail!("expected a list of fixtures");
+    };
+
+    let mut fixtures: Vec<Fixture> = Vec::new();
+    for (i, item) in items.iter().enumerate() {
+        let fixture = parse_fixture(item).wrap_err_with(|| format!("fixture #{}", i + 1))?;
+        if slugify(&fixture.name).is_empty() {
+            bail!(
+                "fixture #{} is named {:?}, its snapshot needs a name with letters or digits",
+                i + 1,
+                fixture.name
+            );
+        }
+        if fixtures
+            .iter()
+            .any(|f| slugify(&f.name) == slugify(&fixture.name))
+        {
+            bail!(
+                "two fixtures are named {:?}, they'd share a snapshot",
+                fixture.name
+            );
+        }
+        fixtures.push(fixture);
+    }
+    Ok(fixtures)
+}
+
+fn parse_fixture(item: &Yaml) -> eyre::Result<Fixture> {
+    let Yaml::Hash(fields) = item else {
+        bail!("expected a map");
+    };
+    const KNOWN: &[&str] = &[
+        "name",
+        "template",
+        "page",
+        "route",
+        "viewer",
+        "query",
+        "shortcode",
+        "args",
+        "body",
+    ];
+    for key in fields.keys() {
+        match key.as_str() {
+            Some(key) if KNOWN.contains(&key) => {}
+            _ => bail!(
+                "unknown field {key:?}, expected one of: {}",
+                KNOWN.join(", ")
+            ),
+        }
+    }
+
+    let string = |key: &str| -> eyre::Result<Option<String>> {
+        match &item[key] {
+            Yaml::BadValue | Yaml::Null => Ok(None),
+            Yaml::String(s) => Ok(Some(s.clone())),
+            other => bail!("`{key}` should be a string, got {other:?}"),
+        }
+    };
+
+    let name = string("name")?.ok_or_else(|| eyre!("every fixture needs a `name`"))?;
+    let kind = match string("shortcode")? {
+        Some(shortcode) => {
+            if ["template", "page", "route"]
+                .iter()
+                .any(|k| !item[*k].is_badvalue())
+            {
+                bail!("a fixture renders either a `shortcode` or a `template`/`page`, not both");
+            }
+            let mut args = DataObject::new();
+            if let Yaml::Hash(hash) = &item["args"] {
+                for (key, value) in hash {
+                    let key = key
+                        .as_str()
+                        .ok_or_else(|| eyre!("argument names should be strings, got {key:?}"))?;
+                    let value = match value {
+                        Yaml::String(s) => DataValue::String(s.clone()),
+                        Yaml::Boolean(b) => DataValue::Boolean(*b),
+                        Yaml::Integer(n) => DataValue::Number(i32::try_from(*n)?),
+                        other => bail!(
+                            "argument `{key}` should be a string, number or boolean, got {other:?}"
+                        ),
+                    };
+                    args.insert(key.to_string(), value);
+                }
+            } else if !item["args"].is_badvalue() {
+                bail!("`args` should be a map");
+            }
+            FixtureKind::Shortcode {
+                name: shortcode,
+                args,
+                body: string("body")?,
+            }
+        }
+        None => FixtureKind::Template {
+            template: string("template")?,
+            page: string("page")?,
+            route: string("route")?,
+        },
+    };
+
+    Ok(Fixture {
+        name,
+        kind,
+        viewer: string("viewer")?.unwrap_or_else(|| "anon".into()),
+        query: string("query")?.unwrap_or_default(),
+    })
+}
+
+/// `Article, signed out` => `article-signed-out`
+fn slugify(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Article, signed out"), "article-signed-out");
+        assert_eq!(slugify("  Gold -- /fr/ page 2 "), "gold-fr-page-2");
+        assert_eq!(slugify("Café crème"), "café-crème");
+        assert_eq!(slugify("🦀 !!"), "");
+    }
+
+    #[test]
+    fn test_parse_fixtures() {
+        let fixtures = parse_fixtures(
+            r#"
+- name: Article, signed out
+  page: /articles/foo
+- name: Listing for gold
+  template: index.html
+  route: /articles
+  viewer: Gold
+  query: page=2
+- name: YouTube embed
+  shortcode: youtube
+  args: { id: abc, start: 12, autoplay: true }
+  body: Hi
+"#,
+        )
+        .unwrap();
+        assert_eq!(fixtures.len(), 3);
+
+        assert_eq!(fixtures[0].name, "Article, signed out");
+        assert_eq!(fixtures[0].viewer, "anon");
+        assert_eq!(fixtures[0].query, "");
+        assert!(matches!(
+            &fixtures[0].kind,
+            FixtureKind::Template { template: None, page: Some(page), route: None } if page == "/articles/foo"
+        ));
+
+        assert_eq!(fixtures[1].viewer, "Gold");
+        assert_eq!(fixtures[1].query, "page=2");
+        assert!(matches!(
+            &fixtures[1].kind,
+            FixtureKind::Template { template: Some(t), page: None, route: Some(r) } if t == "index.html" && r == "/articles"
+        ));
+
+        let FixtureKind::Shortcode { name, args, body } = &fixtures[2].kind else {
+            panic!("expected a shortcode fixture");
+        };
+        assert_eq!(name, "youtube");
+        assert_eq!(body.as_deref(), Some("Hi"));
+        assert!(matches!(&args["id"], DataValue::String(id) if id == "abc"));
+        assert!(matches!(args["start"], DataValue::Number(12)));
+        assert!(matches!(args["autoplay"], DataValue::Boolean(true)));
+    }
+
+    #[test]
+    fn test_parse_fixtures_errors() {
+        let error = |yaml: &str| format!("{:#}", parse_fixtures(yaml).err().unwrap());
+
+        assert!(error("name: not a list").contains("expected a list of fixtures"));
+        assert!(error("- page: /articles/foo").contains("every fixture needs a `name`"));
+        assert!(error("- { name: a, colour: red }").contains("unknown field"));
+        assert!(error("- { name: a, shortcode: b, page: /c }").contains("not both"));
+        assert!(error("- { name: a, shortcode: b, args: [1] }").contains("`args` should be a map"));
+        assert!(
+            error("- { name: Foo bar, page: /a }\n- { name: foo-bar, page: /b }")
+                .contains("they'd share a snapshot")
+        );
+        assert!(error("- { name: '!!', page: /a }").contains("letters or digits"));
+    }
+
+    #[tokio::test]
+    async fn test_check_snapshot() {
+        let dir = Utf8PathBuf::try_from(std::env::temp_dir())
+            .unwrap()
+            .join(format!("home-test-templates-{}", fastrand::u64(..)));
+        let snapshot = dir.join("articles").join("foo.html");
+        let pending = Utf8PathBuf::from(format!("{snapshot}.new"));
+
+        // no snapshot yet: a failure, unless updating
+        assert!(matches!(
+            check_snapshot(&snapshot, "<p>one</p>", false)
+                .await
+                .unwrap(),
+            Outcome::Failed
+        ));
+        assert!(!snapshot.exists());
+        assert_eq!(std::fs::read_to_string(&pending).unwrap(), "<p>one</p>");
+
+        assert!(matches!(
+            check_snapshot(&snapshot, "<p>one</p>", true).await.unwrap(),
+            Outcome::Written
+        ));
+        assert_eq!(std::fs::read_to_string(&snapshot).unwrap(), "<p>one</p>");
+        assert!(!pending.exists());
+
+        assert!(matches!(
+            check_snapshot(&snapshot, "<p>two</p>", false)
+                .await
+                .unwrap(),
+            Outcome::Failed
+        ));
+        assert_eq!(std::fs::read_to_string(&snapshot).unwrap(), "<p>one</p>");
+        assert_eq!(std::fs::read_to_string(&pending).unwrap(), "<p>two</p>");
+
+        // matching again cleans up the leftover output
+        assert!(matches!(
+            check_snapshot(&snapshot, "<p>one</p>", false)
+                .await
+                .unwrap(),
+            Outcome::Passed
+        ));
+        assert!(!pending.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -32,10 +32,9 @@ pub(crate) async fn serve_asset(rcx: Box<dyn CubReq>, headers: HeaderMap) -> HRe
 
     let lrev = tenant.rev().map_err(to_herror)?;
     let rev = &lrev.rev;
-    let asset = rev
-        .assets
-        .get(route)
-        .ok_or_else(|| HError::with_status(StatusCode::NOT_FOUND, "no such asset"))?;
+    let Some(asset) = rev.assets.get(route) else {
+        return serve_sponsor_avatar(rcx.as_ref(), route).await;
+    };
 
     match asset {
         Asset::Inline {
@@ -144,6 +143,28 @@ pub(crate) async fn serve_asset(rcx: Box<dyn CubReq>, headers: HeaderMap) -> HRe
     }
 }
 
+/// Sponsor avatars aren't part of any revision: mom copies them to the object
+/// store and tells us about them along with the sponsors.
+async fn serve_sponsor_avatar(rcx: &dyn CubReq, route: &Route) -> HReply {
+    let tenant = rcx.tenant_ref();
+    let sponsors = tenant.sponsors();
+    let avatar = sponsors
+        .sponsors
+        .iter()
+        .filter_map(|sponsor| sponsor.avatar.as_ref())
+        .find(|avatar| avatar.route == *route)
+        .ok_or_else(|| HError::with_status(StatusCode::NOT_FOUND, "no such asset"))?;
+    trace!("Found sponsor avatar route");
+
+    let di = DerivationInfo::new(&avatar.params.input, &avatar.params.derivation);
+    let content_type = di.content_type();
+    let bytes = derive(rcx, di).await.map_err(to_herror)?;
+    asset_response_builder(tenant.tc(), rcx.web(), content_type)
+        .status(StatusCode::OK)
+        .body(HBody::from(bytes))
+        .into_reply()
+}
+
 fn asset_response_builder(
     tc: &TenantConfig,
     web: WebConfig,
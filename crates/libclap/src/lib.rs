@@ -37,6 +37,9 @@ pub enum Cmd {
     Mom(MomArgs),
     Term(TermArgs),
     Init(InitArgs),
+    MigrateFrontmatter(MigrateFrontmatterArgs),
+    Import(ImportArgs),
+    TestTemplates(TestTemplatesArgs),
 }
 
 /// Records a terminal session with colors, ready to paste into markdown
@@ -95,6 +98,65 @@ pub struct InitArgs {
     pub force: bool,
 }
 
+#[derive(Parser, PartialEq, Eq, Debug)]
+/// Rewrites TOML frontmatter (`+++`) as YAML (`---`) in every page
+pub struct MigrateFrontmatterArgs {
+    #[clap(default_value = ".")]
+    /// site directory, pages are looked up in its `content` folder
+    pub dir: Utf8PathBuf,
+
+    #[clap(long)]
+    /// only list the pages that would be rewritten
+    pub dry_run: bool,
+}
+
+#[derive(Parser, PartialEq, Eq, Debug)]
+/// Converts a Zola or Hugo site into a home site
+pub struct ImportArgs {
+    /// the Zola or Hugo site to import
+    pub source: Utf8PathBuf,
+
+    #[clap(default_value = ".")]
+    /// where to write the home site
+    pub dest: Utf8PathBuf,
+
+    #[clap(long, value_enum)]
+    /// which generator the site was made for, guessed from its files by default
+    pub from: Option<ImportFrom>,
+
+    #[clap(long = "section", value_name = "FROM=TO")]
+    /// moves a section, e.g. `--section blog=articles`, can be repeated
+    pub sections: Vec<String>,
+
+    #[clap(long, value_name = "FILE")]
+    /// JSON file mapping shortcode names to home shortcodes
+    pub shortcodes: Option<Utf8PathBuf>,
+
+    #[clap(long)]
+    /// overwrite existing files without asking
+    pub force: bool,
+}
+
+#[derive(Parser, PartialEq, Eq, Debug)]
+/// Renders templates and shortcodes against the fixtures in `tests/*.yaml`,
+/// and compares them with their snapshots
+pub struct TestTemplatesArgs {
+    #[clap(default_value = ".")]
+    /// site directory
+    pub dir: Utf8PathBuf,
+
+    #[clap(long)]
+    /// write new and changed snapshots instead of failing
+    pub update: bool,
+}
+
+/// Static site generators `home import` understands
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImportFrom {
+    Zola,
+    Hugo,
+}
+
 #[derive(Parser, PartialEq, Eq, Debug)]
 /// Verifies that home is packaged correctly
 pub struct DoctorArgs {}
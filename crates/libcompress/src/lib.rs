@@ -39,62 +39,72 @@ impl From<std::io::Error> for Error {
 #[autotrait]
 impl Mod for ModImpl {
     fn compress(&self, input: bytes::Bytes, accept_encoding: &str) -> Result<CompressResult> {
-        use encodings::*;
-        use std::io::Write;
-
-        let parsed_encodings = encodings::parse_accept_encoding(accept_encoding);
-        let chosen_encoding =
-            parsed_encodings
-                .into_iter()
-                .find_map(|(_group_weight, group_encodings)| {
-                    for (enc, supported_enc) in SUPPORTED_ENCODING {
-                        if group_encodings.contains(&enc) {
-                            return Some(supported_enc);
-                        }
-                    }
-                    None
-                });
-        let chosen_encoding = match chosen_encoding {
-            Some(encoding) => encoding,
-            None => {
-                return Ok(CompressResult {
-                    content_encoding: None,
-                    payload: input,
-                });
-            }
-        };
-        match chosen_encoding {
-            SupportedEncoding::Gzip => {
-                let mut encoder =
-                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
-                encoder.write_all(&input)?;
-                let inner = encoder.finish()?;
-                Ok(CompressResult {
-                    content_encoding: Some("gzip"),
-                    payload: inner.into(),
-                })
-            }
-            SupportedEncoding::Deflate => {
-                let mut encoder =
-                    flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::fast());
-                encoder.write_all(&input)?;
-                let inner = encoder.finish()?;
-                Ok(CompressResult {
-                    content_encoding: Some("deflate"),
-                    payload: inner.into(),
-                })
-            }
-            SupportedEncoding::Brotli => {
-                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 0, 20);
-                encoder.write_all(&input)?;
-                encoder.flush()?;
-                Ok(CompressResult {
-                    content_encoding: Some("br"),
-                    payload: encoder.into_inner().into(),
-                })
-            }
+        match negotiate(accept_encoding) {
+            Some(encoding) => encode(input, encoding),
+            None => Ok(CompressResult {
+                content_encoding: None,
+                payload: input,
+            }),
         }
     }
+
+    /// The `Content-Encoding` that `compress` would pick for this
+    /// `Accept-Encoding`, if any
+    fn negotiate(&self, accept_encoding: &str) -> Option<&'static str> {
+        negotiate(accept_encoding).map(|encoding| encoding.content_encoding())
+    }
+
+    /// Compresses `input` with every encoding we support, for responses that
+    /// are served many times over.
+    fn compress_all(&self, input: bytes::Bytes) -> Result<Vec<CompressResult>> {
+        encodings::SUPPORTED_ENCODING
+            .iter()
+            .map(|(_, encoding)| encode(input.clone(), *encoding))
+            .collect()
+    }
+}
+
+fn negotiate(accept_encoding: &str) -> Option<encodings::SupportedEncoding> {
+    encodings::parse_accept_encoding(accept_encoding)
+        .into_iter()
+        .find_map(|(_group_weight, group_encodings)| {
+            for (enc, supported_enc) in encodings::SUPPORTED_ENCODING {
+                if group_encodings.contains(&enc) {
+                    return Some(supported_enc);
+                }
+            }
+            None
+        })
+}
+
+fn encode(input: bytes::Bytes, encoding: encodings::SupportedEncoding) -> Result<CompressResult> {
+    use encodings::SupportedEncoding;
+    use std::io::Write;
+
+    let payload: bytes::Bytes = match encoding {
+        SupportedEncoding::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+            encoder.write_all(&input)?;
+            encoder.finish()?.into()
+        }
+        SupportedEncoding::Deflate => {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::fast());
+            encoder.write_all(&input)?;
+            encoder.finish()?.into()
+        }
+        SupportedEncoding::Brotli => {
+            let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 0, 20);
+            encoder.write_all(&input)?;
+            encoder.flush()?;
+            encoder.into_inner().into()
+        }
+    };
+    Ok(CompressResult {
+        content_encoding: Some(encoding.content_encoding()),
+        payload,
+    })
 }
 
 pub(crate) mod encodings {
@@ -111,6 +121,16 @@ pub(crate) mod encodings {
         Brotli,
     }
 
+    impl SupportedEncoding {
+        pub(crate) fn content_encoding(self) -> &'static str {
+            match self {
+                SupportedEncoding::Gzip => "gzip",
+                SupportedEncoding::Deflate => "deflate",
+                SupportedEncoding::Brotli => "br",
+            }
+        }
+    }
+
     #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
     pub(crate) enum Encoding {
         Gzip,
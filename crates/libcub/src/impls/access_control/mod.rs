@@ -1,7 +1,13 @@
-use conflux::LoadedPage;
+use std::sync::Arc;
+
+use config_types::RevisionConfig;
+use conflux::{LoadedPage, PAYWALL_EXCERPT_LENGTH, Viewer};
 use cub_types::CubReq;
+use template_types::DataObject;
+use url::form_urlencoded;
 
 use super::cub_req::CubReqImpl;
+use crate::ViewedPage;
 
 #[derive(Debug, Clone, Copy)]
 #[allow(dead_code)]
@@ -22,41 +28,118 @@ pub enum AccessDeniedReason {
     PageIsDraftAndDoesNotHaveDraftCode,
     PageIsDraftAndQueryDoesNotHaveDraftCode,
     PageIsDraftAndQueryDoesNotMatchDraftCode,
+    /// `min_tier` without an end date, the viewer's tier isn't high enough
+    PageRequiresHigherTier {
+        required_rank: u32,
+    },
+    /// The page is exclusive for now, the viewer's tier isn't high enough
+    PageIsExclusiveForNow {
+        required_rank: u32,
+    },
+}
+
+impl AccessDeniedReason {
+    /// Whether the viewer still gets an excerpt of the page (as opposed to a 404)
+    pub fn is_paywall(&self) -> bool {
+        matches!(
+            self,
+            Self::PageRequiresHigherTier { .. } | Self::PageIsExclusiveForNow { .. }
+        )
+    }
 }
 
 /// Determines if the current user can access a page based on its draft status,
-/// draft code, and publication date.
+/// draft code, publication date, and exclusivity.
 pub(crate) fn can_access(rx: &CubReqImpl, page: &LoadedPage) -> eyre::Result<CanAccess> {
-    if rx.viewer()?.is_admin {
-        return Ok(CanAccess::Yes(AccessGrantedReason::IsAdmin));
+    let url_params = rx.url_params_map();
+    Ok(access(
+        page,
+        &rx.viewer()?,
+        &rx.tenant.rc()?,
+        url_params.get("draft_code").map(String::as_str),
+    ))
+}
+
+/// Like [`can_access`], for a given viewer and `?draft_code=`
+fn access(
+    page: &LoadedPage,
+    viewer: &Viewer,
+    rc: &RevisionConfig,
+    query_draft_code: Option<&str>,
+) -> CanAccess {
+    if viewer.is_admin {
+        return CanAccess::Yes(AccessGrantedReason::IsAdmin);
     }
 
     if page.draft {
         let draft_code = match page.draft_code.as_deref() {
             Some(code) => code,
             None => {
-                return Ok(CanAccess::No(
-                    AccessDeniedReason::PageIsDraftAndDoesNotHaveDraftCode,
-                ));
+                return CanAccess::No(AccessDeniedReason::PageIsDraftAndDoesNotHaveDraftCode);
             }
         };
 
-        let url_params = rx.url_params_map();
-        let query_draft_code = match url_params.get("draft_code") {
+        let query_draft_code = match query_draft_code {
             Some(code) => code,
             None => {
-                return Ok(CanAccess::No(
-                    AccessDeniedReason::PageIsDraftAndQueryDoesNotHaveDraftCode,
-                ));
+                return CanAccess::No(AccessDeniedReason::PageIsDraftAndQueryDoesNotHaveDraftCode);
             }
         };
 
         if query_draft_code != draft_code {
-            return Ok(CanAccess::No(
-                AccessDeniedReason::PageIsDraftAndQueryDoesNotMatchDraftCode,
-            ));
+            return CanAccess::No(AccessDeniedReason::PageIsDraftAndQueryDoesNotMatchDraftCode);
+        }
+    }
+
+    if let Some(required_rank) = page.required_rank(rc) {
+        if !viewer.has_rank(required_rank) {
+            return CanAccess::No(if page.exclusive_until.is_some() {
+                AccessDeniedReason::PageIsExclusiveForNow { required_rank }
+            } else {
+                AccessDeniedReason::PageRequiresHigherTier { required_rank }
+            });
         }
     }
 
-    Ok(CanAccess::Yes(AccessGrantedReason::NotDenied))
+    CanAccess::Yes(AccessGrantedReason::NotDenied)
+}
+
+/// What a viewer gets of a page: members-only sections filled in or teased,
+/// and only the first few paragraphs if it's paywalled, with `paywalled` and
+/// `required_tier` globals so templates can show a call to action. `None` if
+/// it's a 404 for them (drafts without the right `?draft_code=`).
+pub(crate) fn page_for_viewer(
+    page: Arc<LoadedPage>,
+    viewer: &Viewer,
+    rc: &RevisionConfig,
+    raw_query: &str,
+) -> Option<ViewedPage> {
+    let draft_code = form_urlencoded::parse(raw_query.as_bytes())
+        .find(|(name, _)| name == "draft_code")
+        .map(|(_, code)| code);
+    let access = access(&page, viewer, rc, draft_code.as_deref());
+    tracing::debug!("\x1b[1;32m{}\x1b[0m {access:?}", page.route);
+
+    let mut page = page;
+    if !page.members_sections.is_empty() {
+        page = Arc::new(LoadedPage {
+            html: page.html_for(viewer, rc),
+            ..(*page).clone()
+        });
+    }
+
+    let mut globals = DataObject::new();
+    match access {
+        CanAccess::No(reason) if reason.is_paywall() => {
+            let excerpt = libhtmlrewrite::load().truncate_html(&page.html, PAYWALL_EXCERPT_LENGTH);
+            globals.insert("paywalled".into(), true.into());
+            if let Some(min_tier) = page.min_tier.clone() {
+                globals.insert("required_tier".into(), min_tier.into());
+            }
+            page = Arc::new(page.to_paywalled(excerpt));
+        }
+        CanAccess::No(_) => return None,
+        CanAccess::Yes(_) => {}
+    }
+    Some(ViewedPage { page, globals })
 }
@@ -1,7 +1,9 @@
 use config_types::Environment;
 use http::Uri;
+use libmomclient::MomTenantClient;
 use libpatreon::{PatreonRefreshCredentials, PatreonRefreshCredentialsArgs};
 use merde::IntoStatic;
+use mom_types::MagicLinkRefreshCredentialsArgs;
 use time::OffsetDateTime;
 use tower_cookies::{Cookie, PrivateCookies, cookie::SameSite};
 use tracing::{debug, warn};
@@ -30,14 +32,19 @@ pub fn auth_bundle_remove_cookie() -> Cookie<'static> {
 
 fn auth_bundle_configure_cookie(cookie: &mut Cookie) {
     if Environment::default().is_prod() {
-        cookie.set_same_site(Some(SameSite::None));
+        // sent on top-level navigations (e.g. coming back from OAuth), but
+        // not on requests other sites make, cf. `web::api::check_same_origin`
+        cookie.set_same_site(Some(SameSite::Lax));
         cookie.set_secure(true);
         cookie.set_http_only(true);
     }
     cookie.set_path("/");
 }
 
-pub async fn authbundle_load_from_cookies(cookies: &PrivateCookies<'_>) -> Option<AuthBundle> {
+pub async fn authbundle_load_from_cookies(
+    cookies: &PrivateCookies<'_>,
+    tcli: &dyn MomTenantClient,
+) -> Option<AuthBundle> {
     let cookie = cookies.get(COOKIE_NAME)?;
 
     let creds: AuthBundle = match merde::json::from_str(cookie.value()) {
@@ -56,7 +63,7 @@ pub async fn authbundle_load_from_cookies(cookies: &PrivateCookies<'_>) -> Optio
     }
 
     debug!("Refreshing cookies");
-    let creds = match refresh_credentials(&creds).await {
+    let creds = match refresh_credentials(&creds, tcli).await {
         Err(e) => {
             warn!("Refreshing credentials failed, will log out: {:?}", e);
             cookies.remove(cookie.clone().into_owned());
@@ -69,14 +76,25 @@ pub async fn authbundle_load_from_cookies(cookies: &PrivateCookies<'_>) -> Optio
     Some(creds.into_static())
 }
 
-async fn refresh_credentials(creds: &AuthBundle) -> eyre::Result<AuthBundle> {
-    let patreon_id = creds
-        .user_info
-        .profile
-        .patreon_id
-        .as_deref()
-        .ok_or_else(|| eyre::eyre!("Can only refresh patreon credentials"))?;
-    refresh_patreon_credentials(patreon_id.to_string()).await
+async fn refresh_credentials(
+    creds: &AuthBundle,
+    tcli: &dyn MomTenantClient,
+) -> eyre::Result<AuthBundle> {
+    let profile = &creds.user_info.profile;
+    if let Some(patreon_id) = profile.patreon_id.as_deref() {
+        return refresh_patreon_credentials(patreon_id.to_string()).await;
+    }
+    if let Some(email) = profile.email.as_deref() {
+        let res = tcli
+            .magic_link_refresh_credentials(&MagicLinkRefreshCredentialsArgs {
+                email: email.to_owned(),
+            })
+            .await?;
+        return Ok(res.auth_bundle.into_static());
+    }
+    Err(eyre::eyre!(
+        "Can only refresh patreon and email credentials"
+    ))
 }
 
 async fn refresh_patreon_credentials(patreon_id: String) -> eyre::Result<AuthBundle> {
@@ -1,6 +1,8 @@
+use crate::ViewedPage;
 use crate::impls::{
-    access_control::{CanAccess, can_access},
+    access_control::page_for_viewer,
     credentials::AuthBundle,
+    page_cache::{CachedPage, PageCacheKey, page_expiry},
     reply::{IntoLegacyReply, LegacyReply},
     types::DomainResolution,
 };
@@ -13,14 +15,16 @@ use axum::{
 use config_types::{Environment, WebConfig};
 use conflux::{AccessOverride, CacheBuster, InputPathRef, LoadedPage, Route, Viewer};
 use content_type::ContentType;
-use cub_types::{CubReq, CubTenant};
+use credentials::UserPreferences;
+use cub_types::{CubReq, CubTenant, IndexedRevision};
 use eyre::Result;
 use futures_core::future::BoxFuture;
 use hattip::{HBody, HError, HReply};
-use http::{Uri, request};
+use http::{Method, Uri, request};
 use libwebsock::WebSocketStream;
 use std::{sync::Arc, time::Instant};
 use template_types::{DataObject, DataValue, RenderTemplateArgs};
+use time::OffsetDateTime;
 use tower_cookies::{Cookies, PrivateCookies};
 use url::form_urlencoded;
 
@@ -37,6 +41,7 @@ pub struct CubReqImpl {
     pub path: Route,
     pub cookies: PrivateCookies<'static>,
     pub auth_bundle: Option<AuthBundle>,
+    pub preferences: Arc<UserPreferences>,
     pub parts: request::Parts,
 }
 
@@ -137,13 +142,15 @@ where
             .await
             .map_err(|e| e.into_legacy_reply())?;
         let cookies = tenant.private_cookies(cookies);
-        let auth_bundle = authbundle_load_from_cookies(&cookies).await;
+        let auth_bundle = authbundle_load_from_cookies(&cookies, tenant.tcli().as_ref()).await;
+        let preferences = tenant.user_preferences(auth_bundle.as_ref()).await;
 
         let tr = Self {
             tenant,
             path,
             cookies,
             auth_bundle,
+            preferences,
             parts: parts.clone(),
         };
 
@@ -156,6 +163,8 @@ pub struct RenderArgs {
     pub(crate) page: Option<Arc<LoadedPage>>,
     pub(crate) additional_globals: DataObject,
     pub(crate) content_type: ContentType,
+    /// whether the result can go in the page cache, see `cacheable`
+    pub(crate) cacheable: bool,
 }
 
 impl RenderArgs {
@@ -168,9 +177,18 @@ impl RenderArgs {
             page: None,
             additional_globals: Default::default(),
             content_type,
+            cacheable: false,
         }
     }
 
+    /// Lets anonymous viewers get this from the page cache, for pages and
+    /// listings that were found. Error pages, login forms etc. are rendered
+    /// every time.
+    pub fn cacheable(mut self) -> Self {
+        self.cacheable = true;
+        self
+    }
+
     pub fn with_page(mut self, page: Arc<LoadedPage>) -> Self {
         self = self.with_global("title", page.title.clone());
         self.page = Some(page);
@@ -201,25 +219,52 @@ impl CubReqImpl {
         ))
     }
 
-    pub fn render(&self, args: RenderArgs) -> LegacyReply {
-        if let Some(page) = args.page.as_ref() {
-            let access = can_access(self, page)?;
-            tracing::debug!("\x1b[1;32m{}\x1b[0m {access:?}", page.route);
-
-            if matches!(access, CanAccess::No(_)) {
+    pub fn render(&self, mut args: RenderArgs) -> LegacyReply {
+        if let Some(page) = args.page.take() {
+            let viewer = self.viewer()?;
+            let rc = self.tenant.rc()?;
+            let Some(ViewedPage { page, globals }) =
+                page_for_viewer(page, &viewer, &rc, self.raw_query())
+            else {
                 return self.render_inner(RenderArgs::new("404.html"));
-            }
+            };
+            args.page = Some(page);
+            args.additional_globals.extend(globals);
         }
 
         self.render_inner(args)
     }
 
+    /// Where a rendered page goes in the tenant's page cache, if it can be
+    /// shared: signed-in viewers see their own name, avatar and preferences.
+    fn page_cache_key(&self, irev: &IndexedRevision, args: &RenderArgs) -> Option<PageCacheKey> {
+        if !args.cacheable
+            || self.auth_bundle.is_some()
+            || !matches!(self.parts.method, Method::GET | Method::HEAD)
+        {
+            return None;
+        }
+        PageCacheKey::new(irev.rev.id().clone(), self.path.clone(), self.url_params())
+    }
+
     fn render_inner(&self, args: RenderArgs) -> LegacyReply {
         let start = Instant::now();
         let template_name = &args.template_name;
 
         let auth_bundle = &self.auth_bundle;
         let irev = self.tenant.rev()?;
+
+        let cache_key = self.page_cache_key(&irev, &args);
+        if let Some(page) = cache_key
+            .as_ref()
+            .and_then(|key| self.tenant.page_cache.get(key))
+        {
+            tracing::debug!(?template_name, route = %self.path, "Served from page cache");
+            return Ok(page.reply(&self.parts.headers));
+        }
+        // before rendering, so a page published mid-render still shows up
+        let expires_at = page_expiry(&irev.rev, OffsetDateTime::now_utc());
+
         let templates = self.tenant.templates()?;
 
         let mut buffer: Vec<u8> = Default::default();
@@ -230,6 +275,7 @@ impl CubReqImpl {
                 path: &self.path,
                 raw_query: self.raw_query(),
                 user_info: auth_bundle.as_ref().map(|creds| creds.user_info.clone()),
+                preferences: self.preferences.clone(),
                 page: args.page.clone(),
                 additional_globals: args.additional_globals,
                 rv: irev.rev.clone(),
@@ -289,6 +335,14 @@ impl CubReqImpl {
         tracing::debug!(?template_name, elapsed = ?start.elapsed(), "Done rendering");
 
         let body = Bytes::from(rendered);
+        if let Some(key) = cache_key {
+            let page = self
+                .tenant
+                .page_cache
+                .insert(key, CachedPage::new(body, args.content_type, expires_at));
+            return Ok(page.reply(&self.parts.headers));
+        }
+
         let response = (
             StatusCode::OK,
             [
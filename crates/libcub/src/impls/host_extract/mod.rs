@@ -1,3 +1,5 @@
+use std::net::{IpAddr, SocketAddr};
+
 use axum::http::{
     Uri,
     header::{self, FORWARDED, HeaderMap},
@@ -7,6 +9,7 @@ use super::{DomainResolution, global_state};
 use config_types::TenantDomain;
 
 const X_FORWARDED_HOST_HEADER_KEY: &str = "X-Forwarded-Host";
+const X_FORWARDED_FOR_HEADER_KEY: &str = "X-Forwarded-For";
 
 /// Extractor that resolves the hostname of the request.
 ///
@@ -23,7 +26,7 @@ pub struct ExtractedHost(pub String);
 
 impl ExtractedHost {
     pub fn from_headers(uri: &Uri, headers: &HeaderMap) -> Option<Self> {
-        if let Some(host) = parse_forwarded(headers) {
+        if let Some(host) = parse_forwarded(headers, "host") {
             return Some(ExtractedHost(host.to_owned()));
         }
 
@@ -64,19 +67,74 @@ impl ExtractedHost {
     }
 }
 
+/// Best guess at the address of whoever made the request, for throttling.
+///
+/// Resolved through the `for` field of the last `Forwarded` element, then the
+/// last `X-Forwarded-For` entry, then the peer address. Proxies append to
+/// those headers, so only the last entry is the one our proxy wrote: earlier
+/// ones come from the client and can be anything.
+pub fn client_ip(headers: &HeaderMap, peer: Option<SocketAddr>) -> Option<IpAddr> {
+    if let Some(ip) = last_forwarded_for(headers).and_then(parse_node) {
+        return Some(ip);
+    }
+
+    if let Some(ip) = headers
+        .get_all(X_FORWARDED_FOR_HEADER_KEY)
+        .iter()
+        .next_back()
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit(',').next())
+        .and_then(parse_node)
+    {
+        return Some(ip);
+    }
+
+    peer.map(|peer| peer.ip())
+}
+
+/// The `for` field of the last element of the last `Forwarded` header
+fn last_forwarded_for(headers: &HeaderMap) -> Option<&str> {
+    let forwarded_values = headers
+        .get_all(FORWARDED)
+        .iter()
+        .next_back()?
+        .to_str()
+        .ok()?;
+    let last_value = forwarded_values.rsplit(',').next()?;
+    last_value.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case("for")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// Parses `192.0.2.1`, `192.0.2.1:4711`, `2001:db8::1` or `[2001:db8::1]:4711`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(rest) = node.strip_prefix('[') {
+        let (ip, _) = rest.split_once(']')?;
+        return ip.parse().ok();
+    }
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    node.parse::<SocketAddr>().ok().map(|addr| addr.ip())
+}
+
 #[allow(warnings)]
-fn parse_forwarded(headers: &HeaderMap) -> Option<&str> {
+fn parse_forwarded<'a>(headers: &'a HeaderMap, field: &str) -> Option<&'a str> {
     // if there are multiple `Forwarded` `HeaderMap::get` will return the first one
     let forwarded_values = headers.get(FORWARDED)?.to_str().ok()?;
 
     // get the first set of values
     let first_value = forwarded_values.split(',').nth(0)?;
 
-    // find the value of the `host` field
+    // find the value of the requested field
     first_value.split(';').find_map(|pair| {
         let (key, value) = pair.split_once('=')?;
         key.trim()
-            .eq_ignore_ascii_case("host")
+            .eq_ignore_ascii_case(field)
             .then(|| value.trim().trim_matches('"'))
     })
 }
@@ -102,4 +160,84 @@ mod tests {
         let empty_host = ExtractedHost("".to_string());
         assert_eq!(empty_host.domain(), "");
     }
+
+    #[test]
+    fn test_client_ip() {
+        let peer: SocketAddr = "10.0.0.1:1234".parse().unwrap();
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, value.parse().unwrap());
+            }
+            headers
+        };
+
+        assert_eq!(
+            client_ip(&headers(&[]), Some(peer)),
+            Some("10.0.0.1".parse().unwrap())
+        );
+        assert_eq!(client_ip(&headers(&[]), None), None);
+        assert_eq!(
+            client_ip(
+                &headers(&[("forwarded", "for=192.0.2.60;proto=https;host=example.com")]),
+                Some(peer)
+            ),
+            Some("192.0.2.60".parse().unwrap())
+        );
+        assert_eq!(
+            client_ip(
+                &headers(&[("forwarded", "for=\"[2001:db8::1]:4711\", for=192.0.2.1")]),
+                Some(peer)
+            ),
+            Some("192.0.2.1".parse().unwrap())
+        );
+        assert_eq!(
+            client_ip(
+                &headers(&[("forwarded", "for=\"[2001:db8::1]:4711\"")]),
+                Some(peer)
+            ),
+            Some("2001:db8::1".parse().unwrap())
+        );
+        assert_eq!(
+            client_ip(
+                &headers(&[("x-forwarded-for", "10.0.0.2, 203.0.113.7")]),
+                Some(peer)
+            ),
+            Some("203.0.113.7".parse().unwrap())
+        );
+        // obfuscated identifiers fall back to the next source
+        assert_eq!(
+            client_ip(&headers(&[("forwarded", "for=_hidden")]), Some(peer)),
+            Some("10.0.0.1".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_client_ip_ignores_spoofed_entries() {
+        let peer: SocketAddr = "10.0.0.1:1234".parse().unwrap();
+
+        // a client sending its own header, which our proxy then appends to:
+        // each request would otherwise count against a different address
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            X_FORWARDED_FOR_HEADER_KEY,
+            "198.51.100.99, 203.0.113.7".parse().unwrap(),
+        );
+        assert_eq!(
+            client_ip(&headers, Some(peer)),
+            Some("203.0.113.7".parse().unwrap())
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            FORWARDED,
+            "for=198.51.100.99, for=203.0.113.7;proto=https"
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(
+            client_ip(&headers, Some(peer)),
+            Some("203.0.113.7".parse().unwrap())
+        );
+    }
 }
@@ -19,7 +19,7 @@ use layers::{
 use libmomclient::{MomClient, MomClientConfig, MomEventListener};
 use librevision::{RevisionKind, RevisionSpec};
 use mom_event_handler::spawn_mom_event_handler;
-use mom_types::{MomEvent, Sponsors};
+use mom_types::{MomEvent, Sponsors, Webmentions};
 use node_metadata::{NodeMetadata, load_node_metadata};
 use parking_lot::RwLock;
 use reply::{LegacyHttpError, LegacyReply};
@@ -44,7 +44,9 @@ mod graceful_shutdown;
 pub mod host_extract;
 pub mod layers;
 mod node_metadata;
+pub mod page_cache;
 pub mod path_metadata;
+pub mod preferences_cache;
 pub mod reply;
 pub mod types;
 pub mod vite;
@@ -72,7 +74,7 @@ pub(crate) async fn serve(
     };
     let (mom_client, mut mev_rx) = setup_mom_client(mom_client_config).await?;
 
-    let (tenant_infos, mut revs_per_ts, mut sponsors_per_ts) =
+    let (tenant_infos, mut revs_per_ts, mut sponsors_per_ts, mut webmentions_per_ts) =
         process_mom_good_morning(&cc, &mut mev_rx, web).await?;
 
     let deploy_mom_client = if web.env.is_prod() {
@@ -102,6 +104,7 @@ pub(crate) async fn serve(
         &tenant_infos,
         &mut revs_per_ts,
         &mut sponsors_per_ts,
+        &mut webmentions_per_ts,
     )
     .await?;
     global_state::set_global_state(Box::leak(Box::new(gs)))
@@ -178,10 +181,12 @@ async fn process_mom_good_morning(
     HashMap<TenantDomain, Arc<TenantInfo>>,
     HashMap<TenantDomain, CubRevisionState>,
     HashMap<TenantDomain, Sponsors>,
+    HashMap<TenantDomain, Webmentions>,
 )> {
     let mod_revision = librevision::load();
     let mut revs_per_ts: HashMap<TenantDomain, CubRevisionState> = Default::default();
     let mut sponsors_per_ts: HashMap<TenantDomain, Sponsors> = Default::default();
+    let mut webmentions_per_ts: HashMap<TenantDomain, Webmentions> = Default::default();
 
     info!(
         "Waiting for mom's good morning message to initialize tenants and start serving content..."
@@ -218,6 +223,9 @@ async fn process_mom_good_morning(
         if let Some(sponsors) = tis.sponsors {
             sponsors_per_ts.insert(tn.clone(), sponsors);
         }
+        if let Some(webmentions) = tis.webmentions {
+            webmentions_per_ts.insert(tn.clone(), webmentions);
+        }
         let mappings = PathMappings::from_ti(&ti);
 
         let rs = 'load: {
@@ -276,7 +284,12 @@ async fn process_mom_good_morning(
         tenant_infos.insert(tn, ti);
     }
 
-    Ok((tenant_infos, revs_per_ts, sponsors_per_ts))
+    Ok((
+        tenant_infos,
+        revs_per_ts,
+        sponsors_per_ts,
+        webmentions_per_ts,
+    ))
 }
 
 /// This function builds the global state for the application, which includes initializing
@@ -295,6 +308,7 @@ async fn build_global_state(
     tenant_infos: &HashMap<TenantDomain, Arc<TenantInfo>>,
     revs_per_ts: &mut HashMap<TenantDomain, CubRevisionState>,
     sponsors_per_ts: &mut HashMap<TenantDomain, Sponsors>,
+    webmentions_per_ts: &mut HashMap<TenantDomain, Webmentions>,
 ) -> eyre::Result<CubGlobalState> {
     let mut gs = CubGlobalState {
         config,
@@ -332,7 +346,11 @@ async fn build_global_state(
             store: object_store,
             cookie_key: Box::leak(Box::new(cookie_key)),
             sponsors: RwLock::new(Arc::new(sponsors)),
+            comment_threads: Default::default(),
+            user_preferences: Default::default(),
+            webmentions: RwLock::new(Arc::new(webmentions_per_ts.remove(tn).unwrap_or_default())),
             vite_port: Default::default(),
+            page_cache: Default::default(),
         };
         let ts = Arc::new(ts);
         gs.dynamic
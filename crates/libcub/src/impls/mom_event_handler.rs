@@ -1,9 +1,11 @@
 use std::sync::Arc;
 
 use config_types::{WebConfig, is_development};
-use conflux::{Pak, PathMappings};
-use cub_types::CubTenant;
-use mom_types::{MomEvent, Sponsors, TenantEventPayload};
+use conflux::{Pak, PathMappings, Viewer};
+use cub_types::{CubTenant, IndexedRevision};
+use mom_types::{
+    CommentsUpdated, MomEvent, OutgoingLinks, SendWebmentionsArgs, Sponsors, TenantEventPayload,
+};
 use tokio::sync::mpsc;
 
 use super::{global_state, types::CubTenantImpl};
@@ -53,11 +55,33 @@ async fn handle_tenant_event(
         TenantEventPayload::RevisionChanged(pak) => {
             handle_revision_changed(ts, pak, web).await;
         }
+        TenantEventPayload::CommentsUpdated(ev) => {
+            handle_comments_updated(ts, ev);
+        }
+        TenantEventPayload::UserPreferencesUpdated(ev) => {
+            ts.user_preferences
+                .insert(ev.global_id, Arc::new(ev.preferences));
+        }
+        TenantEventPayload::WebmentionsUpdated(webmentions) => {
+            *ts.webmentions.write() = Arc::new(webmentions);
+            ts.page_cache.clear(&ts.ti.tc.name, "webmentions updated");
+        }
     }
 }
 
 fn handle_sponsors_updated(ts: Arc<CubTenantImpl>, sponsors: Sponsors) {
     *ts.sponsors.write() = Arc::new(sponsors);
+    ts.page_cache.clear(&ts.ti.tc.name, "sponsors updated");
+    if let Ok(templates) = ts.templates() {
+        templates.clear_fragment_cache("sponsors updated");
+    }
+}
+
+fn handle_comments_updated(ts: Arc<CubTenantImpl>, ev: CommentsUpdated) {
+    let mut threads = ts.comment_threads.write();
+    for route in &ev.routes {
+        threads.remove(route);
+    }
 }
 
 async fn handle_revision_changed(ts: Arc<CubTenantImpl>, pak: Box<Pak>, web: WebConfig) {
@@ -82,10 +106,59 @@ async fn handle_revision_changed(ts: Arc<CubTenantImpl>, pak: Box<Pak>, web: Web
         {
             Ok(lrev) => lrev,
             Err(e) => {
-                tracing::error!("Failed to load revision: {e}");
+                tracing::error!("Failed to load revision, not switching to it: {e}");
+                let e = conflux::RevisionError(format!("Failed to load revision from mom: {e:?}"));
+                ts.write_to_revstate(&mut |state| {
+                    state.err = Some(e.clone());
+                });
+                ts.broadcast_error(e);
                 return;
             }
         }
     };
+    let outgoing = outgoing_webmentions(&ts, &rev, web);
     ts.switch_to(rev);
+
+    // mom remembers which ones it already sent, so it's fine to ask every time
+    if let Err(e) = ts.tcli().send_webmentions(&outgoing).await {
+        tracing::warn!("Failed to ask mom to send webmentions: {e}");
+    }
+}
+
+/// Collects external links from every public page, for webmention sending.
+/// Exclusive pages are skipped until they're public.
+fn outgoing_webmentions(
+    ts: &CubTenantImpl,
+    rev: &IndexedRevision,
+    web: WebConfig,
+) -> SendWebmentionsArgs {
+    let viewer = Viewer::anon();
+    let rc = &rev.rev.pak.rc;
+    let own_base_url = ts.tc().web_base_url(web);
+
+    let pages = rev
+        .rev
+        .pages
+        .values()
+        .filter(|page| page.is_listed(&viewer) && page.required_rank(rc).is_none())
+        .filter_map(|page| {
+            let mut targets: Vec<String> = page
+                .links
+                .iter()
+                .map(|href| href.as_str())
+                .filter(|href| href.starts_with("https://") || href.starts_with("http://"))
+                .filter(|href| !href.starts_with(&own_base_url))
+                .map(|href| href.to_string())
+                .collect();
+            targets.sort();
+            targets.dedup();
+
+            (!targets.is_empty()).then(|| OutgoingLinks {
+                source: page.canonical_url(web).to_string(),
+                targets,
+            })
+        })
+        .collect();
+
+    SendWebmentionsArgs { pages }
 }
@@ -0,0 +1,348 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use axum::{
+    body::Bytes,
+    response::{IntoResponse as _, Response},
+};
+use conflux::{Revision, RevisionId, Route};
+use content_type::ContentType;
+use http::{HeaderMap, HeaderValue, StatusCode, header};
+use parking_lot::RwLock;
+use rand::seq::IteratorRandom;
+use time::{Duration, OffsetDateTime};
+
+/// How many rendered pages we keep per tenant. Eviction is random, like
+/// `dumbcache`.
+const PAGE_CACHE_CAPACITY: usize = 512;
+
+/// How long a rendered page is served at most. Templates can show things
+/// that change with time on their own ("posted 3 days ago").
+const PAGE_MAX_AGE: Duration = Duration::minutes(10);
+
+/// Query parameters templates render differently for, with numeric values:
+/// `?page=2` on listings. Requests with any other parameter (`?q=` on a
+/// search page, draft codes…) aren't cached, there would be no end to them.
+const CACHEABLE_PARAMS: &[&str] = &["page"];
+
+/// What a rendered page depends on, besides the request not being signed in
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PageCacheKey {
+    rev_id: RevisionId,
+    route: Route,
+    /// sorted, without tracking parameters
+    query: Vec<(String, String)>,
+}
+
+impl PageCacheKey {
+    /// `None` if the query string has parameters that aren't worth caching
+    /// (see `CACHEABLE_PARAMS`)
+    pub fn new(
+        rev_id: RevisionId,
+        route: Route,
+        url_params: Vec<(String, String)>,
+    ) -> Option<Self> {
+        let mut query = Vec::new();
+        for (name, value) in url_params {
+            if is_tracking_param(&name) {
+                continue;
+            }
+            if !CACHEABLE_PARAMS.contains(&name.as_str()) || value.parse::<u32>().is_err() {
+                return None;
+            }
+            query.push((name, value));
+        }
+        query.sort();
+        Some(Self {
+            rev_id,
+            route,
+            query,
+        })
+    }
+}
+
+/// When a page rendered `now` has to be rendered again: after
+/// `PAGE_MAX_AGE`, or sooner if a page gets published or stops being
+/// exclusive in the meantime, since that changes listings and paywalls.
+pub fn page_expiry(rev: &Revision, now: OffsetDateTime) -> OffsetDateTime {
+    let max_age = now + PAGE_MAX_AGE;
+    rev.next_time_boundary(now)
+        .map_or(max_age, |boundary| boundary.min(max_age))
+}
+
+/// Query parameters only analytics care about, templates render the same
+/// page with or without them.
+fn is_tracking_param(name: &str) -> bool {
+    name.starts_with("utm_") || matches!(name, "fbclid" | "gclid" | "mc_cid" | "mc_eid")
+}
+
+/// Rendered pages, ready to be sent in every encoding `libcompress` supports.
+/// Each one expires on its own (see `page_expiry`), and the whole thing is
+/// cleared whenever the tenant switches revisions, and when mom tells us
+/// about new sponsors or webmentions, since templates show those.
+#[derive(Default)]
+pub struct PageCache {
+    pages: RwLock<HashMap<PageCacheKey, Arc<CachedPage>>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl PageCache {
+    pub fn get(&self, key: &PageCacheKey) -> Option<Arc<CachedPage>> {
+        let now = OffsetDateTime::now_utc();
+        let page = self
+            .pages
+            .read()
+            .get(key)
+            .filter(|page| page.expires_at > now)
+            .cloned();
+        if page.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        page
+    }
+
+    pub fn insert(&self, key: PageCacheKey, page: CachedPage) -> Arc<CachedPage> {
+        let page = Arc::new(page);
+        let mut pages = self.pages.write();
+        if pages.len() >= PAGE_CACHE_CAPACITY && !pages.contains_key(&key) {
+            let evicted = pages.keys().choose(&mut rand::thread_rng()).cloned();
+            if let Some(evicted) = evicted {
+                pages.remove(&evicted);
+            }
+        }
+        pages.insert(key, page.clone());
+        page
+    }
+
+    /// Drops every page, logging how useful they were
+    pub fn clear(&self, tenant_name: &impl Display, reason: &str) {
+        let mut pages = self.pages.write();
+        let hits = self.hits.swap(0, Ordering::Relaxed);
+        let misses = self.misses.swap(0, Ordering::Relaxed);
+        if hits + misses > 0 {
+            tracing::info!(
+                "[{tenant_name}] Clearing page cache ({reason}): {} pages, hit rate {:.2}% ({hits} hits, {misses} misses)",
+                pages.len(),
+                hits as f64 / (hits + misses) as f64 * 100.0,
+            );
+        }
+        pages.clear();
+    }
+}
+
+pub struct CachedPage {
+    content_type: ContentType,
+    etag: HeaderValue,
+    identity: Bytes,
+    /// by `Content-Encoding`
+    compressed: Vec<(&'static str, Bytes)>,
+    expires_at: OffsetDateTime,
+}
+
+impl CachedPage {
+    pub fn new(body: Bytes, content_type: ContentType, expires_at: OffsetDateTime) -> Self {
+        let etag = HeaderValue::from_str(&format!("\"{:016x}\"", seahash::hash(&body)))
+            .expect("hex digits make a valid header value");
+        let compressed = match libcompress::load().compress_all(body.clone()) {
+            Ok(results) => results
+                .into_iter()
+                .filter_map(|res| Some((res.content_encoding?, res.payload)))
+                .collect(),
+            Err(e) => {
+                // we can still serve it uncompressed
+                tracing::warn!("Failed to compress page for the page cache: {e}");
+                Vec::new()
+            }
+        };
+        Self {
+            content_type,
+            etag,
+            identity: body,
+            compressed,
+            expires_at,
+        }
+    }
+
+    /// 304 if the client already has this exact page, otherwise the body in
+    /// the best encoding it accepts.
+    pub fn reply(&self, req_headers: &HeaderMap) -> Response {
+        if self.matches_if_none_match(req_headers) {
+            let mut res = StatusCode::NOT_MODIFIED.into_response();
+            let headers = res.headers_mut();
+            headers.insert(header::ETAG, self.etag.clone());
+            headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+            return res;
+        }
+
+        let encoding = req_headers
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|ae| libcompress::load().negotiate(ae));
+        let (content_encoding, body) = encoding
+            .and_then(|encoding| {
+                self.compressed
+                    .iter()
+                    .find(|(ce, _)| *ce == encoding)
+                    .map(|(ce, body)| (Some(*ce), body.clone()))
+            })
+            .unwrap_or((None, self.identity.clone()));
+
+        let mut res = (
+            StatusCode::OK,
+            [
+                (header::CACHE_CONTROL, "no-cache"),
+                (header::CONTENT_TYPE, self.content_type.as_str()),
+                (header::VARY, "accept-encoding"),
+            ],
+            body,
+        )
+            .into_response();
+        let headers = res.headers_mut();
+        headers.insert(header::ETAG, self.etag.clone());
+        if let Some(content_encoding) = content_encoding {
+            // the compression layer leaves responses that have one alone
+            headers.insert(
+                header::CONTENT_ENCODING,
+                HeaderValue::from_static(content_encoding),
+            );
+        }
+        res
+    }
+
+    fn matches_if_none_match(&self, req_headers: &HeaderMap) -> bool {
+        let Some(etag) = self.etag.to_str().ok() else {
+            return false;
+        };
+        req_headers
+            .get_all(header::IF_NONE_MATCH)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|candidate| candidate.trim())
+            .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use conflux::testing;
+
+    fn key(query: &[(&str, &str)]) -> Option<PageCacheKey> {
+        PageCacheKey::new(
+            RevisionId::new("rev_testing".to_string()),
+            Route::new("/articles".to_string()),
+            query
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_page_cache_key() {
+        assert_eq!(key(&[("utm_source", "mastodon")]), key(&[]));
+        assert_eq!(
+            key(&[("page", "2"), ("fbclid", "abc")]),
+            key(&[("page", "2")])
+        );
+        assert_ne!(key(&[("page", "2")]), key(&[("page", "3")]));
+        assert!(key(&[("page", "2")]).is_some());
+        assert!(key(&[("page", "two")]).is_none());
+        assert!(key(&[("q", "rust")]).is_none());
+        assert!(key(&[("draft_code", "secret")]).is_none());
+    }
+
+    #[test]
+    fn test_page_expiry() {
+        let rc = config_types::RevisionConfig::default();
+        let now = OffsetDateTime::now_utc();
+        let mut scheduled = testing::page(&rc, "/articles/scheduled");
+        scheduled.date.0 = now + Duration::minutes(3);
+
+        let rev = testing::revision(rc.clone(), []);
+        assert_eq!(page_expiry(&rev, now), now + PAGE_MAX_AGE);
+        let rev = testing::revision(rc, [scheduled]);
+        assert_eq!(page_expiry(&rev, now), now + Duration::minutes(3));
+    }
+
+    #[test]
+    fn test_expired_pages_are_misses() {
+        let cache = PageCache::default();
+        let now = OffsetDateTime::now_utc();
+        let page =
+            |expires_at| CachedPage::new(Bytes::from_static(b"hi"), ContentType::HTML, expires_at);
+
+        cache.insert(key(&[]).unwrap(), page(now - Duration::seconds(1)));
+        assert!(cache.get(&key(&[]).unwrap()).is_none());
+        cache.insert(key(&[]).unwrap(), page(now + Duration::minutes(1)));
+        assert!(cache.get(&key(&[]).unwrap()).is_some());
+    }
+
+    #[test]
+    fn test_etag_and_if_none_match() {
+        let expires_at = OffsetDateTime::now_utc() + PAGE_MAX_AGE;
+        let page = CachedPage::new(
+            Bytes::from_static(b"<p>hi</p>"),
+            ContentType::HTML,
+            expires_at,
+        );
+        let etag = page.etag.to_str().unwrap().to_string();
+        assert!(etag.starts_with('"') && etag.ends_with('"'), "{etag}");
+
+        let res = page.reply(&HeaderMap::new());
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::ETAG], etag.as_str());
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "no-cache");
+
+        let same = CachedPage::new(
+            Bytes::from_static(b"<p>hi</p>"),
+            ContentType::HTML,
+            expires_at,
+        );
+        assert_eq!(same.etag, page.etag, "same body, same ETag");
+        let other = CachedPage::new(
+            Bytes::from_static(b"<p>bye</p>"),
+            ContentType::HTML,
+            expires_at,
+        );
+        assert_ne!(other.etag, page.etag);
+
+        let if_none_match = |value: String| {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                header::IF_NONE_MATCH,
+                HeaderValue::from_str(&value).unwrap(),
+            );
+            page.reply(&headers)
+        };
+        let res = if_none_match(etag.clone());
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()[header::ETAG], etag.as_str());
+        assert_eq!(
+            if_none_match(format!("W/{etag}")).status(),
+            StatusCode::NOT_MODIFIED
+        );
+        assert_eq!(
+            if_none_match(format!("\"0000000000000000\", {etag}")).status(),
+            StatusCode::NOT_MODIFIED
+        );
+        assert_eq!(
+            if_none_match("*".to_string()).status(),
+            StatusCode::NOT_MODIFIED
+        );
+        assert_eq!(
+            if_none_match("\"0000000000000000\"".to_string()).status(),
+            StatusCode::OK
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use credentials::UserPreferences;
+use parking_lot::RwLock;
+use rand::seq::IteratorRandom;
+
+/// How many viewers' preferences we keep per tenant. Eviction is random, like
+/// the page cache.
+const PREFERENCES_CACHE_CAPACITY: usize = 4096;
+
+/// How long we stick to the defaults after failing to fetch someone's
+/// preferences, before asking mom again
+const FAILURE_TTL: Duration = Duration::from_secs(30);
+
+struct CachedPreferences {
+    preferences: Arc<UserPreferences>,
+    /// only set for failed fetches: preferences we did get are kept up to date
+    /// by mom's `UserPreferencesUpdated` events
+    expires_at: Option<Instant>,
+}
+
+/// Preferences of logged-in viewers, by `Profile::global_id`
+#[derive(Default)]
+pub struct PreferencesCache {
+    entries: RwLock<HashMap<String, CachedPreferences>>,
+}
+
+impl PreferencesCache {
+    pub fn get(&self, global_id: &str) -> Option<Arc<UserPreferences>> {
+        self.get_at(global_id, Instant::now())
+    }
+
+    fn get_at(&self, global_id: &str, now: Instant) -> Option<Arc<UserPreferences>> {
+        let entries = self.entries.read();
+        let entry = entries.get(global_id)?;
+        if entry.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return None;
+        }
+        Some(entry.preferences.clone())
+    }
+
+    pub fn insert(&self, global_id: String, preferences: Arc<UserPreferences>) {
+        self.insert_entry(
+            global_id,
+            CachedPreferences {
+                preferences,
+                expires_at: None,
+            },
+        );
+    }
+
+    /// Remembers that fetching someone's preferences failed, so they get the
+    /// defaults for a little while instead of mom getting asked again on
+    /// every request.
+    pub fn insert_failure(&self, global_id: String) -> Arc<UserPreferences> {
+        self.insert_failure_at(global_id, Instant::now())
+    }
+
+    fn insert_failure_at(&self, global_id: String, now: Instant) -> Arc<UserPreferences> {
+        let preferences = Arc::new(UserPreferences::default());
+        self.insert_entry(
+            global_id,
+            CachedPreferences {
+                preferences: preferences.clone(),
+                expires_at: Some(now + FAILURE_TTL),
+            },
+        );
+        preferences
+    }
+
+    fn insert_entry(&self, global_id: String, entry: CachedPreferences) {
+        let mut entries = self.entries.write();
+        if entries.len() >= PREFERENCES_CACHE_CAPACITY && !entries.contains_key(&global_id) {
+            let evicted = entries.keys().choose(&mut rand::thread_rng()).cloned();
+            if let Some(evicted) = evicted {
+                entries.remove(&evicted);
+            }
+        }
+        entries.insert(global_id, entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use credentials::Theme;
+
+    fn dark() -> Arc<UserPreferences> {
+        Arc::new(UserPreferences {
+            theme: Theme::Dark,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_failures_expire() {
+        let cache = PreferencesCache::default();
+        let now = Instant::now();
+
+        let prefs = cache.insert_failure_at("patreon:1".into(), now);
+        assert_eq!(prefs.theme, Theme::System);
+        assert!(cache.get_at("patreon:1", now).is_some());
+        assert!(cache.get_at("patreon:1", now + FAILURE_TTL).is_none());
+
+        // an update from mom replaces the failure for good
+        cache.insert("patreon:1".into(), dark());
+        let prefs = cache.get_at("patreon:1", now + FAILURE_TTL * 10).unwrap();
+        assert_eq!(prefs.theme, Theme::Dark);
+    }
+
+    #[test]
+    fn test_capacity_is_bounded() {
+        let cache = PreferencesCache::default();
+        for i in 0..PREFERENCES_CACHE_CAPACITY + 100 {
+            cache.insert(format!("github:{i}"), dark());
+        }
+        assert_eq!(cache.entries.read().len(), PREFERENCES_CACHE_CAPACITY);
+
+        // replacing an entry doesn't evict another one
+        let some_id = cache.entries.read().keys().next().unwrap().clone();
+        cache.insert(some_id.clone(), dark());
+        assert_eq!(cache.entries.read().len(), PREFERENCES_CACHE_CAPACITY);
+        assert!(cache.get(&some_id).is_some());
+    }
+}
@@ -1,19 +1,22 @@
 use config_types::{
     CubConfig, TenantConfig, TenantDomain, TenantInfo, WebConfig, is_development, is_production,
 };
-use conflux::{RevisionError, RevisionId};
+use conflux::{RevisionError, RevisionId, Route};
+use credentials::{AuthBundle, UserPreferences};
 use cub_types::{CubRevisionState, CubTenant, IndexedRevision};
 use hattip::prelude::BoxFuture;
 use libmomclient::{MomClient, MomTenantClient};
 use libobjectstore::ObjectStore;
-use mom_types::{GlobalStateView, Sponsors};
+use mom_types::{CommentThread, GetUserPreferencesArgs, GlobalStateView, Sponsors, Webmentions};
 use parking_lot::RwLock;
 use std::{collections::HashMap, sync::Arc};
 use template_types::TemplateCollection;
 use tokio::sync::broadcast;
 use tower_cookies::Key;
 
-use super::{global_state, vite::start_vite};
+use super::{
+    global_state, page_cache::PageCache, preferences_cache::PreferencesCache, vite::start_vite,
+};
 
 #[derive(Clone)]
 pub enum RevisionBroadcastEvent {
@@ -104,11 +107,19 @@ pub struct CubTenantImpl {
     // FIXME: should not be static — that means we leak it
     pub cookie_key: &'static Key,
     pub sponsors: RwLock<Arc<Sponsors>>,
+    /// comment threads fetched from mom, dropped when mom says they changed
+    pub comment_threads: RwLock<HashMap<Route, Arc<CommentThread>>>,
+    /// verified webmentions, pushed by mom whenever they change
+    pub webmentions: RwLock<Arc<Webmentions>>,
+    /// preferences of logged-in users
+    pub user_preferences: PreferencesCache,
     pub ti: Arc<TenantInfo>,
     pub store: Arc<dyn ObjectStore>,
     pub bx_rev: broadcast::Sender<RevisionBroadcastEvent>,
     pub rev_state: RwLock<CubRevisionState>,
     pub vite_port: tokio::sync::OnceCell<Result<u16, String>>,
+    /// rendered pages for anonymous visitors, see `CubReqImpl::render_inner`
+    pub page_cache: PageCache,
 }
 
 impl CubTenant for CubTenantImpl {
@@ -145,6 +156,8 @@ impl CubTenant for CubTenantImpl {
             rev: Some(rev.clone()),
             err: None,
         };
+        self.page_cache
+            .clear(&self.tc().name, &format!("switched to {rev_id}"));
 
         match self
             .bx_rev
@@ -233,6 +246,10 @@ impl GlobalStateView for CubTenantImpl {
     fn gsv_sponsors(&self) -> Arc<Sponsors> {
         CubTenant::sponsors(self)
     }
+
+    fn gsv_webmentions(&self) -> Arc<Webmentions> {
+        self.webmentions.read().clone()
+    }
 }
 
 impl CubTenantImpl {
@@ -243,4 +260,36 @@ impl CubTenantImpl {
     ) -> tower_cookies::PrivateCookies<'static> {
         cookies.private(self.cookie_key)
     }
+
+    /// Returns the viewer's preferences: from the cache if we have them,
+    /// otherwise from mom. Logged-out viewers get the defaults, and so do
+    /// viewers whose preferences mom couldn't give us, for a little while.
+    pub async fn user_preferences(&self, auth_bundle: Option<&AuthBundle>) -> Arc<UserPreferences> {
+        let Some(global_id) = auth_bundle.and_then(|ab| ab.user_info.profile.global_id().ok())
+        else {
+            return Default::default();
+        };
+
+        if let Some(prefs) = self.user_preferences.get(&global_id) {
+            return prefs;
+        }
+
+        match self
+            .tcli()
+            .get_user_preferences(&GetUserPreferencesArgs {
+                global_id: global_id.clone(),
+            })
+            .await
+        {
+            Ok(prefs) => {
+                let prefs = Arc::new(prefs);
+                self.user_preferences.insert(global_id, prefs.clone());
+                prefs
+            }
+            Err(e) => {
+                tracing::warn!("Failed to fetch preferences for {global_id}: {e}");
+                self.user_preferences.insert_failure(global_id)
+            }
+        }
+    }
 }
@@ -20,7 +20,15 @@ pub(crate) async fn serve_autocomplete(
     let irev = tr.tenant.rev()?;
     let index = tr.tenant.index()?;
 
-    let results = index.autocomplete(irev.rev.as_ref(), &tr.viewer()?, q, global_state().web);
+    let lang = query.get("lang").map(|s| s.as_str());
+
+    let results = index.autocomplete(
+        irev.rev.as_ref(),
+        &tr.viewer()?,
+        q,
+        lang,
+        global_state().web,
+    );
 
     MerdeJson(results).into_legacy_reply()
 }
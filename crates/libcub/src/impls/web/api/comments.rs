@@ -1,5 +1,212 @@
-use crate::impls::{cub_req::CubReqImpl, h_to_axum, reply::LegacyReply};
+use std::sync::Arc;
 
+use conflux::{Route, Viewer};
+use cub_types::{CubReq, CubTenant};
+use http::StatusCode;
+use mom_types::{
+    CommentThread, DeleteCommentArgs, EditCommentArgs, ListCommentsArgs, ModerateCommentArgs,
+    PostCommentArgs,
+};
+
+use super::{check_json_post, require_auth_bundle};
+use crate::impls::{
+    cub_req::CubReqImpl,
+    h_to_axum,
+    reply::{IntoLegacyReply, LegacyHttpError, LegacyReply, MerdeJson},
+};
+
+/// Legacy endpoint: redirects to a Reddit submission for the given page.
 pub(crate) async fn serve_comments(rcx: CubReqImpl) -> LegacyReply {
     h_to_axum(libapi::load().serve_comments(Box::new(rcx)).await)
 }
+
+/// A comment thread, as seen by the current viewer
+struct ThreadForViewer {
+    thread: CommentThread,
+
+    /// The viewer's `Profile::global_id`, if logged in — lets the frontend
+    /// show edit/delete buttons on their own comments.
+    viewer_id: Option<String>,
+
+    /// Whether the viewer may hide comments and ban people
+    can_moderate: bool,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct ThreadForViewer { thread, viewer_id, can_moderate }
+}
+
+struct PostCommentBody {
+    route: Route,
+    parent_id: Option<i64>,
+    markdown: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct PostCommentBody { route, parent_id, markdown }
+}
+
+struct EditCommentBody {
+    comment_id: i64,
+    markdown: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct EditCommentBody { comment_id, markdown }
+}
+
+struct DeleteCommentBody {
+    comment_id: i64,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct DeleteCommentBody { comment_id }
+}
+
+/// Returns the comment thread for `?route=`, hiding moderated comments
+/// from non-admins.
+pub(crate) async fn serve_comments_thread(tr: CubReqImpl) -> LegacyReply {
+    let route = match tr.url_params_map().remove("route") {
+        Some(route) => Route::new(route),
+        None => {
+            return LegacyHttpError::with_status(StatusCode::BAD_REQUEST, "Missing route")
+                .into_legacy_reply();
+        }
+    };
+    let viewer = tr.viewer()?;
+    check_commentable(&tr, &route, &viewer)?;
+
+    let mut thread = (*get_thread(&tr, &route).await?).clone();
+    if !viewer.is_admin {
+        for comment in thread.comments.iter_mut().filter(|c| c.hidden) {
+            comment.markdown.clear();
+            comment.html.clear();
+        }
+    }
+
+    let viewer_id = match tr.auth_bundle.as_ref() {
+        Some(ab) => Some(ab.user_info.profile.global_id()?),
+        None => None,
+    };
+    MerdeJson(ThreadForViewer {
+        thread,
+        viewer_id,
+        can_moderate: viewer.is_admin,
+    })
+    .into_legacy_reply()
+}
+
+pub(crate) async fn serve_post_comment(tr: CubReqImpl, body: axum::body::Bytes) -> LegacyReply {
+    check_json_post(&tr)?;
+    let auth_bundle = require_auth_bundle(&tr)?;
+    let body: PostCommentBody = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+    check_commentable(&tr, &body.route, &tr.viewer()?)?;
+
+    let comment = tr
+        .tenant
+        .tcli()
+        .post_comment(&PostCommentArgs {
+            route: body.route,
+            parent_id: body.parent_id,
+            markdown: body.markdown,
+            author: auth_bundle.user_info.profile.clone(),
+        })
+        .await?;
+    MerdeJson(comment).into_legacy_reply()
+}
+
+pub(crate) async fn serve_edit_comment(tr: CubReqImpl, body: axum::body::Bytes) -> LegacyReply {
+    check_json_post(&tr)?;
+    let auth_bundle = require_auth_bundle(&tr)?;
+    let body: EditCommentBody = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+
+    let comment = tr
+        .tenant
+        .tcli()
+        .edit_comment(&EditCommentArgs {
+            comment_id: body.comment_id,
+            markdown: body.markdown,
+            author_id: auth_bundle.user_info.profile.global_id()?,
+        })
+        .await?;
+    MerdeJson(comment).into_legacy_reply()
+}
+
+pub(crate) async fn serve_delete_comment(tr: CubReqImpl, body: axum::body::Bytes) -> LegacyReply {
+    check_json_post(&tr)?;
+    let auth_bundle = require_auth_bundle(&tr)?;
+    let body: DeleteCommentBody = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+
+    tr.tenant
+        .tcli()
+        .delete_comment(&DeleteCommentArgs {
+            comment_id: body.comment_id,
+            author_id: auth_bundle.user_info.profile.global_id()?,
+        })
+        .await?;
+    StatusCode::OK.into_legacy_reply()
+}
+
+/// Hide/unhide comments, ban/unban commenters. Admins only.
+pub(crate) async fn serve_moderate_comment(tr: CubReqImpl, body: axum::body::Bytes) -> LegacyReply {
+    check_json_post(&tr)?;
+    if !tr.viewer()?.is_admin {
+        return LegacyHttpError::with_status(StatusCode::FORBIDDEN, "Admins only")
+            .into_legacy_reply();
+    }
+    let args: ModerateCommentArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+
+    tr.tenant.tcli().moderate_comment(&args).await?;
+    StatusCode::OK.into_legacy_reply()
+}
+
+/// Comments are only available on pages that exist, haven't opted out of
+/// comments, and that the viewer is allowed to read in full.
+fn check_commentable(
+    tr: &CubReqImpl,
+    route: &Route,
+    viewer: &Viewer,
+) -> Result<(), LegacyHttpError> {
+    let irev = tr.tenant.rev()?;
+    let page = irev
+        .rev
+        .page_routes
+        .get(route)
+        .and_then(|path| irev.rev.pages.get(path))
+        .ok_or_else(|| LegacyHttpError::with_status(StatusCode::NOT_FOUND, "Page not found"))?;
+
+    if page.hide_comments {
+        return Err(LegacyHttpError::with_status(
+            StatusCode::NOT_FOUND,
+            "Comments are disabled for this page",
+        ));
+    }
+    if page.is_paywalled_for(viewer, &irev.rev.pak.rc) {
+        return Err(LegacyHttpError::with_status(
+            StatusCode::FORBIDDEN,
+            "Comments on this page are for members only",
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the thread from the tenant's cache, asking mom on a miss.
+async fn get_thread(tr: &CubReqImpl, route: &Route) -> eyre::Result<Arc<CommentThread>> {
+    if let Some(thread) = tr.tenant.comment_threads.read().get(route) {
+        return Ok(thread.clone());
+    }
+
+    let thread = tr
+        .tenant
+        .tcli()
+        .list_comments(&ListCommentsArgs {
+            route: route.clone(),
+        })
+        .await?;
+    let thread = Arc::new(thread);
+    tr.tenant
+        .comment_threads
+        .write()
+        .insert(route.clone(), thread.clone());
+    Ok(thread)
+}
@@ -0,0 +1,52 @@
+use cub_types::CubTenant;
+use http::StatusCode;
+use mom_types::SetMagicLinkNameArgs;
+
+use super::{check_json_post, require_auth_bundle};
+use crate::impls::{
+    credentials::auth_bundle_as_cookie,
+    cub_req::CubReqImpl,
+    reply::{IntoLegacyReply, LegacyHttpError, LegacyReply, MerdeJson},
+};
+
+struct SetDisplayNameBody {
+    full_name: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct SetDisplayNameBody { full_name }
+}
+
+/// Sets the name the viewer goes by, then refreshes their session so it
+/// shows up right away. Only for accounts that signed in with email: others
+/// get their name from Patreon or GitHub.
+pub(crate) async fn serve_set_display_name(tr: CubReqImpl, body: axum::body::Bytes) -> LegacyReply {
+    check_json_post(&tr)?;
+    let auth_bundle = require_auth_bundle(&tr)?;
+    let body: SetDisplayNameBody = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+
+    let profile = &auth_bundle.user_info.profile;
+    let Some(email) = profile
+        .email
+        .as_ref()
+        .filter(|_| profile.patreon_id.is_none() && profile.github_id.is_none())
+    else {
+        return LegacyHttpError::with_status(
+            StatusCode::BAD_REQUEST,
+            "This account's name comes from Patreon or GitHub",
+        )
+        .into_legacy_reply();
+    };
+
+    let tcli = tr.tenant.tcli();
+    tcli.set_magic_link_name(&SetMagicLinkNameArgs {
+        email: email.clone(),
+        full_name: body.full_name,
+    })
+    .await?;
+
+    let new_auth_bundle = tcli.update_auth_bundle(auth_bundle).await?;
+    tr.cookies.add(auth_bundle_as_cookie(&new_auth_bundle));
+
+    MerdeJson(new_auth_bundle.user_info).into_legacy_reply()
+}
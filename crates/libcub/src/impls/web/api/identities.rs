@@ -0,0 +1,66 @@
+use cub_types::CubTenant;
+use http::StatusCode;
+use mom_types::{ListIdentitiesArgs, UnlinkIdentityArgs};
+
+use super::{check_json_post, require_auth_bundle};
+use crate::impls::{
+    credentials::auth_bundle_as_cookie,
+    cub_req::CubReqImpl,
+    reply::{IntoLegacyReply, LegacyHttpError, LegacyReply, MerdeJson},
+};
+
+struct UnlinkIdentityBody {
+    identity: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct UnlinkIdentityBody { identity }
+}
+
+/// Lists the identities linked to the viewer's account. Link more with
+/// `/login/{patreon,github}?link=1`.
+pub(crate) async fn serve_list_identities(tr: CubReqImpl) -> LegacyReply {
+    let auth_bundle = require_auth_bundle(&tr)?;
+    let identities = tr
+        .tenant
+        .tcli()
+        .list_identities(&ListIdentitiesArgs {
+            global_id: auth_bundle.user_info.profile.global_id()?,
+        })
+        .await?;
+    MerdeJson(identities).into_legacy_reply()
+}
+
+/// Unlinks one of the viewer's identities, then refreshes their session so
+/// it doesn't include it anymore.
+pub(crate) async fn serve_unlink_identity(tr: CubReqImpl, body: axum::body::Bytes) -> LegacyReply {
+    check_json_post(&tr)?;
+    let mut auth_bundle = require_auth_bundle(&tr)?.clone();
+    let body: UnlinkIdentityBody = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+
+    let tcli = tr.tenant.tcli();
+    tcli.unlink_identity(&UnlinkIdentityArgs {
+        global_id: auth_bundle.user_info.profile.global_id()?,
+        identity: body.identity.clone(),
+    })
+    .await?;
+
+    auth_bundle
+        .user_info
+        .profile
+        .remove_identity(&body.identity);
+    let new_auth_bundle = tcli.update_auth_bundle(&auth_bundle).await?;
+    tr.cookies.add(auth_bundle_as_cookie(&new_auth_bundle));
+
+    MerdeJson(new_auth_bundle.user_info).into_legacy_reply()
+}
+
+/// Every account with linked identities. Admins only.
+pub(crate) async fn serve_all_linked_accounts(tr: CubReqImpl) -> LegacyReply {
+    if !tr.viewer()?.is_admin {
+        return LegacyHttpError::with_status(StatusCode::FORBIDDEN, "Admins only")
+            .into_legacy_reply();
+    }
+    let accounts = tr.tenant.tcli().list_linked_accounts().await?;
+    MerdeJson(accounts).into_legacy_reply()
+}
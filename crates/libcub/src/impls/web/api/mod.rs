@@ -1,25 +1,59 @@
-use crate::impls::reply::{IntoLegacyReply, LegacyHttpError, LegacyReply};
+use crate::impls::{
+    cub_req::CubReqImpl,
+    host_extract::ExtractedHost,
+    reply::{IntoLegacyReply, LegacyHttpError, LegacyReply},
+    types::DomainResolution,
+};
 use axum::{
     Router, http,
     routing::{get, post},
 };
-use http::StatusCode;
+use credentials::AuthBundle;
+use cub_types::CubTenant;
+use http::{StatusCode, header};
 
 mod autocomplete;
 mod comments;
+mod display_name;
+mod identities;
 mod link_preview;
+mod preferences;
+mod sponsors;
 mod update_userinfo;
+mod webmention;
 
 /// Returns routes that are available in both development and production
 pub(crate) fn public_api_routes() -> Router {
     Router::new()
         .route("/comments", get(comments::serve_comments))
+        .route("/comments/thread", get(comments::serve_comments_thread))
+        .route("/comments/post", post(comments::serve_post_comment))
+        .route("/comments/edit", post(comments::serve_edit_comment))
+        .route("/comments/delete", post(comments::serve_delete_comment))
+        .route("/comments/moderate", post(comments::serve_moderate_comment))
         .route("/autocomplete", get(autocomplete::serve_autocomplete))
+        .route("/downloads", get(super::downloads::serve_download_counts))
+        .route("/display-name", post(display_name::serve_set_display_name))
+        .route("/identities", get(identities::serve_list_identities))
+        .route(
+            "/identities/unlink",
+            post(identities::serve_unlink_identity),
+        )
+        .route(
+            "/identities/all",
+            get(identities::serve_all_linked_accounts),
+        )
         .route(
             "/update-userinfo",
             post(update_userinfo::serve_update_userinfo),
         )
         .route("/link-preview", get(link_preview::serve_link_preview))
+        .route(
+            "/preferences",
+            get(preferences::serve_get_preferences).post(preferences::serve_update_preferences),
+        )
+        .route("/sponsors/opt-out", post(sponsors::serve_sponsor_opt_out))
+        .route("/webmention", post(webmention::serve_webmention))
         .route("/{*splat}", get(serve_api_not_found))
 }
 
@@ -27,3 +61,117 @@ async fn serve_api_not_found() -> LegacyReply {
     LegacyHttpError::with_status(StatusCode::NOT_FOUND, "API endpoint not found")
         .into_legacy_reply()
 }
+
+/// For endpoints that act on the viewer's behalf, on top of
+/// [`check_same_origin`]: the body must be JSON. Other sites can't send that
+/// with a plain form, only with a `fetch` that CORS preflights (and we don't
+/// answer those).
+pub(crate) fn check_json_post(tr: &CubReqImpl) -> Result<(), LegacyHttpError> {
+    check_same_origin(tr)?;
+
+    let content_type = tr
+        .parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if !is_json_content_type(content_type) {
+        return Err(LegacyHttpError::with_status(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Expected Content-Type: application/json",
+        ));
+    }
+    Ok(())
+}
+
+/// Rejects requests that another site made with the viewer's cookie: the
+/// `Origin` (or, failing that, the `Referer`) must be one of this tenant's
+/// domains. Browsers send one of them with every cross-site `POST`, so
+/// requests without either aren't coming from another site.
+pub(crate) fn check_same_origin(tr: &CubReqImpl) -> Result<(), LegacyHttpError> {
+    let headers = &tr.parts.headers;
+    let Some(origin) = headers
+        .get(header::ORIGIN)
+        .or_else(|| headers.get(header::REFERER))
+    else {
+        return Ok(());
+    };
+
+    let tenant = origin
+        .to_str()
+        .ok()
+        .and_then(origin_host)
+        .and_then(|host| ExtractedHost(host.to_string()).resolve_domain())
+        .map(|resolution| match resolution {
+            DomainResolution::Tenant(tenant) => tenant,
+            DomainResolution::Redirect { tenant, .. } => tenant,
+        });
+    match tenant {
+        Some(tenant) if tenant.tc().name == tr.tenant.tc().name => Ok(()),
+        _ => {
+            tracing::warn!("Rejecting cross-origin {} from {origin:?}", tr.parts.uri);
+            Err(LegacyHttpError::with_status(
+                StatusCode::FORBIDDEN,
+                "Cross-origin requests aren't allowed",
+            ))
+        }
+    }
+}
+
+/// For endpoints that only make sense for a logged-in viewer.
+pub(crate) fn require_auth_bundle(tr: &CubReqImpl) -> Result<&AuthBundle, LegacyHttpError> {
+    tr.auth_bundle
+        .as_ref()
+        .ok_or_else(|| LegacyHttpError::with_status(StatusCode::UNAUTHORIZED, "Not logged in"))
+}
+
+/// `application/json`, with or without parameters like `charset`
+fn is_json_content_type(value: &str) -> bool {
+    value
+        .split(';')
+        .next()
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+}
+
+/// The `host[:port]` of an `Origin` (`https://example.org`) or `Referer`
+/// (`https://example.org/articles/foo`). `None` for opaque origins (`null`).
+fn origin_host(origin: &str) -> Option<&str> {
+    let (scheme, rest) = origin.split_once("://")?;
+    if !matches!(scheme, "http" | "https") {
+        return None;
+    }
+    let host = rest.split(['/', '?', '#']).next()?;
+    (!host.is_empty()).then_some(host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_json_content_type() {
+        assert!(is_json_content_type("application/json"));
+        assert!(is_json_content_type("application/json; charset=utf-8"));
+        assert!(is_json_content_type("Application/JSON"));
+        assert!(!is_json_content_type(""));
+        assert!(!is_json_content_type("text/plain"));
+        assert!(!is_json_content_type("application/x-www-form-urlencoded"));
+        assert!(!is_json_content_type("multipart/form-data; boundary=x"));
+    }
+
+    #[test]
+    fn test_origin_host() {
+        assert_eq!(origin_host("https://example.org"), Some("example.org"));
+        assert_eq!(
+            origin_host("http://example.org.snug.blog:1111"),
+            Some("example.org.snug.blog:1111")
+        );
+        assert_eq!(
+            origin_host("https://example.org/articles/foo?bar#baz"),
+            Some("example.org")
+        );
+        assert_eq!(origin_host("null"), None);
+        assert_eq!(origin_host("file:///etc/passwd"), None);
+        assert_eq!(origin_host("https://"), None);
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use credentials::UserPreferencesPatch;
+use cub_types::CubTenant;
+use http::StatusCode;
+use mom_types::UpdateUserPreferencesArgs;
+
+use super::check_json_post;
+use crate::impls::{
+    cub_req::CubReqImpl,
+    reply::{IntoLegacyReply, LegacyHttpError, LegacyReply, MerdeJson},
+};
+
+/// Returns the viewer's preferences
+pub(crate) async fn serve_get_preferences(tr: CubReqImpl) -> LegacyReply {
+    if tr.auth_bundle.is_none() {
+        return LegacyHttpError::with_status(StatusCode::UNAUTHORIZED, "Not logged in")
+            .into_legacy_reply();
+    }
+    MerdeJson(tr.preferences.as_ref().clone()).into_legacy_reply()
+}
+
+/// Updates the viewer's preferences with the fields set in the JSON body,
+/// returns the result.
+pub(crate) async fn serve_update_preferences(
+    tr: CubReqImpl,
+    body: axum::body::Bytes,
+) -> LegacyReply {
+    check_json_post(&tr)?;
+    let global_id = match tr.auth_bundle.as_ref() {
+        Some(ab) => ab.user_info.profile.global_id()?,
+        None => {
+            return LegacyHttpError::with_status(StatusCode::UNAUTHORIZED, "Not logged in")
+                .into_legacy_reply();
+        }
+    };
+    let patch: UserPreferencesPatch = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+
+    // mom validates the patch — but a 400 from mom would surface as a 500
+    // here, so check it first.
+    if let Err(e) = tr.preferences.as_ref().clone().apply(patch.clone()) {
+        return LegacyHttpError::with_status(StatusCode::BAD_REQUEST, e.to_string())
+            .into_legacy_reply();
+    }
+
+    let preferences = tr
+        .tenant
+        .tcli()
+        .update_user_preferences(&UpdateUserPreferencesArgs {
+            global_id: global_id.clone(),
+            patch,
+        })
+        .await?;
+
+    // mom broadcasts the change too, but this request's follow-up page load
+    // might beat the event here.
+    let preferences = Arc::new(preferences);
+    tr.tenant
+        .user_preferences
+        .insert(global_id, preferences.clone());
+    MerdeJson(preferences.as_ref().clone()).into_legacy_reply()
+}
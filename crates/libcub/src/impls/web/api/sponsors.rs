@@ -0,0 +1,37 @@
+use cub_types::CubTenant;
+use http::StatusCode;
+use mom_types::SponsorOptOutArgs;
+
+use super::check_json_post;
+use crate::impls::{
+    cub_req::CubReqImpl,
+    reply::{IntoLegacyReply, LegacyHttpError, LegacyReply},
+};
+
+struct SponsorOptOutBody {
+    opt_out: bool,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct SponsorOptOutBody { opt_out }
+}
+
+/// Lets sponsors hide themselves from (or show themselves again on) the
+/// sponsor credits, for every identity linked to their account.
+pub(crate) async fn serve_sponsor_opt_out(tr: CubReqImpl, body: axum::body::Bytes) -> LegacyReply {
+    check_json_post(&tr)?;
+    let Some(auth_bundle) = tr.auth_bundle.as_ref() else {
+        return LegacyHttpError::with_status(StatusCode::UNAUTHORIZED, "Not logged in")
+            .into_legacy_reply();
+    };
+    let body: SponsorOptOutBody = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+
+    tr.tenant
+        .tcli()
+        .sponsor_opt_out(&SponsorOptOutArgs {
+            identities: auth_bundle.user_info.profile.identities(),
+            opt_out: body.opt_out,
+        })
+        .await?;
+    StatusCode::OK.into_legacy_reply()
+}
@@ -3,6 +3,7 @@ use credentials::UserInfo;
 use cub_types::CubTenant;
 use http::StatusCode;
 
+use super::check_same_origin;
 use crate::impls::{
     credentials::auth_bundle_as_cookie,
     cub_req::CubReqImpl,
@@ -21,6 +22,9 @@ merde::derive!(
 
 /// Does another GitHub/Patreon API call to re-check someone's tier.
 pub(crate) async fn serve_update_userinfo(mut tr: CubReqImpl) -> LegacyReply {
+    // no body, so no content type to check
+    check_same_origin(&tr)?;
+
     let auth_bundle = match tr.auth_bundle.as_ref() {
         Some(creds) => creds,
         None => {
@@ -0,0 +1,66 @@
+use conflux::Route;
+use cub_types::CubTenant;
+use http::StatusCode;
+use mom_types::ReceiveWebmentionArgs;
+
+use crate::impls::{
+    cub_req::CubReqImpl,
+    global_state,
+    reply::{IntoLegacyReply, LegacyHttpError, LegacyReply},
+};
+
+/// Receives a webmention (<https://www.w3.org/TR/webmention/>): checks that
+/// `target` is one of our pages, then hands it to mom, which verifies that
+/// `source` actually links to it.
+pub(crate) async fn serve_webmention(tr: CubReqImpl, body: axum::body::Bytes) -> LegacyReply {
+    let mut source = None;
+    let mut target = None;
+    for (k, v) in url::form_urlencoded::parse(&body[..]) {
+        match k.as_ref() {
+            "source" => source = Some(v.into_owned()),
+            "target" => target = Some(v.into_owned()),
+            _ => {}
+        }
+    }
+    let (Some(source), Some(target)) = (source, target) else {
+        return bad_request("Expected `source` and `target` form parameters");
+    };
+
+    let source_url = match url::Url::parse(&source) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => url,
+        _ => return bad_request("`source` must be an http(s) URL"),
+    };
+    let target_url = match url::Url::parse(&target) {
+        Ok(url) => url,
+        Err(_) => return bad_request("`target` must be a URL"),
+    };
+    if source_url == target_url {
+        return bad_request("`source` and `target` must differ");
+    }
+
+    let web_domain = tr.tenant.tc().web_domain(global_state().web.env);
+    if target_url.host_str() != Some(web_domain.as_str()) {
+        return bad_request("`target` is not on this site");
+    }
+
+    let route = Route::new(target_url.path().to_string()).trim_trailing_slash();
+    let irev = tr.tenant.rev()?;
+    if !irev.rev.page_routes.contains_key(&route) {
+        return bad_request("`target` is not a page on this site");
+    }
+
+    tr.tenant
+        .tcli()
+        .receive_webmention(&ReceiveWebmentionArgs {
+            source,
+            target,
+            route,
+        })
+        .await?;
+
+    StatusCode::ACCEPTED.into_legacy_reply()
+}
+
+fn bad_request(msg: &'static str) -> LegacyReply {
+    LegacyHttpError::with_status(StatusCode::BAD_REQUEST, msg).into_legacy_reply()
+}
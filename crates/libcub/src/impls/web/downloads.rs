@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use axum::{
+    Router,
+    extract::Path,
+    response::{IntoResponse, Redirect},
+    routing::get,
+};
+use conflux::{Download, LoadedPage};
+use content_type::ContentType;
+use cub_types::{CubTenant, IndexedRevision};
+use http::{
+    StatusCode,
+    header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE, X_CONTENT_TYPE_OPTIONS},
+};
+use mom_types::RecordDownloadArgs;
+use std::sync::Arc;
+
+use crate::impls::{
+    access_control::{CanAccess, can_access},
+    cub_req::CubReqImpl,
+    reply::{IntoLegacyReply, LegacyHttpError, LegacyReply, MerdeJson},
+};
+
+/// How long the URLs we redirect to keep working. Long enough to start the
+/// download, short enough that they're not worth sharing.
+const SIGNED_URL_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+pub(crate) fn download_routes() -> Router {
+    Router::new().route("/{*path}", get(serve_download))
+}
+
+/// Redirects to a short-lived signed URL for a file declared in some page's
+/// `downloads` frontmatter, if the viewer is allowed to have it.
+async fn serve_download(Path(path): Path<String>, tr: CubReqImpl) -> LegacyReply {
+    let irev = tr.tenant.rev()?;
+    let Some((page, download)) = find_download(&irev, &path) else {
+        return LegacyHttpError::with_status(StatusCode::NOT_FOUND, "no such download")
+            .into_legacy_reply();
+    };
+
+    let viewer = tr.viewer()?;
+    match can_access(&tr, page)? {
+        CanAccess::Yes(_) => {}
+        // a download with its own tier doesn't care whether the page is exclusive
+        CanAccess::No(reason) if reason.is_paywall() && download.tier.is_some() => {}
+        CanAccess::No(reason) if reason.is_paywall() => {
+            return LegacyHttpError::with_status(
+                StatusCode::FORBIDDEN,
+                "this download is for members only",
+            )
+            .into_legacy_reply();
+        }
+        CanAccess::No(reason) => {
+            tracing::info!("Denying download {path} from draft page: {reason:?}");
+            return LegacyHttpError::with_status(StatusCode::NOT_FOUND, "no such download")
+                .into_legacy_reply();
+        }
+    }
+    if let Some(tier) = &download.tier {
+        if !(viewer.is_admin || viewer.has_tier(&irev.rev.pak.rc, tier)) {
+            return LegacyHttpError::with_status(
+                StatusCode::FORBIDDEN,
+                format!("this download is for {tier} members and above"),
+            )
+            .into_legacy_reply();
+        }
+    }
+
+    let tcli = tr.tenant.tcli();
+    let args = RecordDownloadArgs { path: path.clone() };
+    tokio::spawn(async move {
+        if let Err(e) = tcli.record_download(&args).await {
+            tracing::warn!("Failed to record download of {}: {e}", args.path);
+        }
+    });
+
+    let store = tr.tenant.store();
+    let key = download.key();
+    if let Some(url) = store.signed_get_url(&key, SIGNED_URL_LIFETIME).await? {
+        tracing::info!("Handing out signed URL for download \x1b[33m{path}\x1b[0m");
+        let mut response = Redirect::to(&url).into_response();
+        response
+            .headers_mut()
+            .insert(CACHE_CONTROL, "private, no-store".parse().unwrap());
+        return Ok(response);
+    }
+
+    // local stores (in development) can't sign URLs, so we serve it ourselves
+    tracing::info!(
+        "Serving download \x1b[33m{path}\x1b[0m from {}",
+        store.desc()
+    );
+    let body = store.get(&key).await?.bytes().await?;
+    let content_type = ContentType::guess_from_path(&path).unwrap_or(ContentType::OctetStream);
+    let file_name = path.rsplit_once('/').map(|(_, name)| name).unwrap_or(&path);
+    Ok((
+        StatusCode::OK,
+        [
+            (CONTENT_TYPE, content_type.as_str().to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name.replace('"', "")),
+            ),
+            (X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+            (CACHE_CONTROL, "private, no-store".to_string()),
+        ],
+        axum::body::Body::from(body),
+    )
+        .into_response())
+}
+
+/// Download counts for every attachment. Admins only.
+pub(crate) async fn serve_download_counts(tr: CubReqImpl) -> LegacyReply {
+    if !tr.viewer()?.is_admin {
+        return LegacyHttpError::with_status(StatusCode::FORBIDDEN, "Admins only")
+            .into_legacy_reply();
+    }
+    let counts = tr.tenant.tcli().list_download_counts().await?;
+    MerdeJson(counts).into_legacy_reply()
+}
+
+/// The page declaring the download, and the download itself. Paths are
+/// unique, revisions with duplicates don't load.
+fn find_download<'a>(
+    irev: &'a IndexedRevision,
+    path: &str,
+) -> Option<(&'a Arc<LoadedPage>, &'a Download)> {
+    irev.rev.pages.values().find_map(|page| {
+        page.downloads
+            .iter()
+            .find(|download| download.path == path)
+            .map(|download| (page, download))
+    })
+}
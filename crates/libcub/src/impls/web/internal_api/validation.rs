@@ -18,6 +18,7 @@ enum ValidationMessage {
     RouteResult(RouteResult),
     BadLink(BadLink),
     MathError(MathError),
+    RedirectProblem(RedirectProblem),
     ValidationComplete(ValidationComplete),
 }
 
@@ -29,6 +30,7 @@ merde::derive! {
         "validationComplete" => ValidationComplete,
         "badLink" => BadLink,
         "mathError" => MathError,
+        "redirectProblem" => RedirectProblem,
     }
 }
 
@@ -46,6 +48,7 @@ impl_from!(RouteResult);
 impl_from!(ValidationComplete);
 impl_from!(BadLink);
 impl_from!(MathError);
+impl_from!(RedirectProblem);
 
 #[derive(Debug)]
 struct RouteResult {
@@ -96,6 +99,18 @@ merde::derive! {
     }
 }
 
+/// A redirect loop, a redirect shadowed by a page, a source redirected twice, etc.
+#[derive(Debug)]
+struct RedirectProblem {
+    message: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct RedirectProblem {
+        message
+    }
+}
+
 pub(crate) async fn serve(
     ws: axum::extract::WebSocketUpgrade,
     tr: CubReqImpl,
@@ -159,6 +174,13 @@ async fn handle_validation(mut sock: ws::WebSocket, ts: Arc<CubTenantImpl>) {
         }
     };
 
+    ms.info("Validating redirects...").await;
+    let redirect_problems = irev.rev.redirects.problems(&irev.rev.page_routes);
+    let num_redirect_problems = redirect_problems.len() as u32;
+    for message in redirect_problems {
+        ms.send(RedirectProblem { message }).await;
+    }
+
     ms.info("Validating routes...").await;
 
     enum Task {
@@ -321,7 +343,7 @@ async fn handle_validation(mut sock: ws::WebSocket, ts: Arc<CubTenantImpl>) {
     ))
     .await;
 
-    let num_errors = num_bad_routes;
+    let num_errors = num_bad_routes + num_redirect_problems;
 
     ms.send(ValidationComplete { num_errors }).await;
 
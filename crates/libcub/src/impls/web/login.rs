@@ -1,14 +1,24 @@
+use std::net::SocketAddr;
+
 use crate::impls::{
     credentials::{AuthBundle, auth_bundle_as_cookie, auth_bundle_remove_cookie},
     cub_req::{CubReqImpl, RenderArgs},
+    host_extract::client_ip,
     reply::{IntoLegacyReply, LegacyReply},
+    web::api::check_same_origin,
+};
+use axum::{
+    Form, Router,
+    extract::ConnectInfo,
+    response::Redirect,
+    routing::{get, post},
 };
-use axum::{Form, Router, response::Redirect, routing::get};
 use config_types::is_development;
-use credentials::UserInfo;
+use credentials::{UserInfo, normalize_email};
 use cub_types::{CubReq, CubTenant};
 use libgithub::GitHubLoginPurpose;
 use libpatreon::PatreonCallbackArgs;
+use mom_types::{LinkIdentitiesArgs, MagicLinkCallbackArgs, RequestMagicLinkArgs};
 use serde::Deserialize;
 use time::OffsetDateTime;
 use tower_cookies::{Cookie, PrivateCookies};
@@ -22,6 +32,11 @@ pub(crate) fn login_routes() -> Router {
         .route("/patreon/callback", get(serve_patreon_callback))
         .route("/github", get(serve_login_with_github))
         .route("/github/callback", get(serve_github_callback))
+        .route("/email", post(serve_login_with_email))
+        .route(
+            "/email/callback",
+            get(serve_email_confirm).post(serve_email_callback),
+        )
         .route("/debug-credentials", get(serve_debug_credentials))
         .route("/logout", get(serve_logout))
 }
@@ -33,6 +48,10 @@ struct LoginParams {
 
     #[serde(default)]
     admin_login: bool,
+
+    /// Link the account they sign in with to the one they're logged in as
+    #[serde(default)]
+    link: bool,
 }
 
 async fn serve_login(tr: CubReqImpl, params: Form<LoginParams>) -> LegacyReply {
@@ -45,8 +64,8 @@ async fn serve_login(tr: CubReqImpl, params: Form<LoginParams>) -> LegacyReply {
     tr.render(args)
 }
 
-fn set_return_to_cookie(cookies: &PrivateCookies<'_>, params: &Form<LoginParams>) {
-    if let Some(return_to) = params.return_to.as_deref() {
+fn set_return_to_cookie(cookies: &PrivateCookies<'_>, return_to: Option<&str>) {
+    if let Some(return_to) = return_to {
         let mut cookie = Cookie::new("return_to", return_to.to_owned());
         cookie.set_path("/");
         cookie.set_expires(time::OffsetDateTime::now_utc() + time::Duration::minutes(30));
@@ -54,9 +73,20 @@ fn set_return_to_cookie(cookies: &PrivateCookies<'_>, params: &Form<LoginParams>
     }
 }
 
+/// Remembers to link accounts once the login flow comes back to us
+fn set_link_accounts_cookie(tr: &CubReqImpl, link: bool) {
+    if link && tr.auth_bundle.is_some() {
+        let mut cookie = Cookie::new("link_accounts", "1");
+        cookie.set_path("/");
+        cookie.set_expires(time::OffsetDateTime::now_utc() + time::Duration::minutes(30));
+        tr.cookies.add(cookie);
+    }
+}
+
 async fn serve_login_with_patreon(tr: CubReqImpl, params: Form<LoginParams>) -> LegacyReply {
     tracing::info!("Initiating login with Patreon");
-    set_return_to_cookie(&tr.cookies, &params);
+    set_return_to_cookie(&tr.cookies, params.return_to.as_deref());
+    set_link_accounts_cookie(&tr, params.link);
 
     let patreon = libpatreon::load();
     let location = patreon.make_login_url(tr.web(), tr.tenant.tc())?;
@@ -65,7 +95,8 @@ async fn serve_login_with_patreon(tr: CubReqImpl, params: Form<LoginParams>) ->
 
 async fn serve_login_with_github(tr: CubReqImpl, params: Form<LoginParams>) -> LegacyReply {
     tracing::info!("Initiating login with GitHub");
-    set_return_to_cookie(&tr.cookies, &params);
+    set_return_to_cookie(&tr.cookies, params.return_to.as_deref());
+    set_link_accounts_cookie(&tr, params.link);
 
     let purpose = if params.admin_login {
         GitHubLoginPurpose::Admin
@@ -76,13 +107,118 @@ async fn serve_login_with_github(tr: CubReqImpl, params: Form<LoginParams>) -> L
     Redirect::to(&location).into_legacy_reply()
 }
 
+#[derive(Deserialize)]
+struct EmailLoginParams {
+    email: String,
+
+    #[serde(default)]
+    return_to: Option<String>,
+
+    #[serde(default)]
+    link: bool,
+}
+
+/// Asks mom to email a sign-in link, then shows the login page again with
+/// `magic_link_sent` set (or `magic_link_error` if the address looks wrong).
+async fn serve_login_with_email(tr: CubReqImpl, params: Form<EmailLoginParams>) -> LegacyReply {
+    let return_to = params.return_to.as_deref().unwrap_or("");
+    let args = RenderArgs::new("login.html").with_global("return_to", return_to);
+
+    let Some(email) = normalize_email(&params.email) else {
+        return tr.render(args.with_global(
+            "magic_link_error",
+            "That doesn't look like an email address",
+        ));
+    };
+
+    tracing::info!("Initiating login with email");
+    set_return_to_cookie(&tr.cookies, params.return_to.as_deref());
+    set_link_accounts_cookie(&tr, params.link);
+    let peer = tr
+        .parts
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    tr.tenant
+        .tcli()
+        .request_magic_link(&RequestMagicLinkArgs {
+            email: email.clone(),
+            client_ip: client_ip(&tr.parts.headers, peer).map(|ip| ip.to_string()),
+        })
+        .await?;
+    tr.render(args.with_global("magic_link_sent", email))
+}
+
+/// Shows the login page with `magic_link_token` set, for the reader to
+/// confirm signing in. Following the link doesn't use it up by itself, since
+/// mail scanners and link previews fetch links too.
+async fn serve_email_confirm(tr: CubReqImpl) -> LegacyReply {
+    let token = tr.url_params_map().remove("token").unwrap_or_default();
+    tr.render(RenderArgs::new("login.html").with_global("magic_link_token", token))
+}
+
+#[derive(Deserialize)]
+struct EmailCallbackParams {
+    token: String,
+}
+
+/// Uses up the token: only ever from our own confirm page, so other sites
+/// can't sign visitors into an account of their choosing.
+async fn serve_email_callback(tr: CubReqImpl, params: Form<EmailCallbackParams>) -> LegacyReply {
+    check_same_origin(&tr)?;
+    let token = params.0.token;
+    let res = tr
+        .tenant
+        .tcli()
+        .magic_link_callback(&MagicLinkCallbackArgs { token })
+        .await?;
+
+    match res {
+        Some(res) => finish_login_callback(&tr, Some(res.auth_bundle)).await,
+        None => tr.render(RenderArgs::new("login.html").with_global(
+            "magic_link_error",
+            "This sign-in link has expired or was already used. You can ask for a new one below.",
+        )),
+    }
+}
+
 async fn serve_patreon_callback(tr: CubReqImpl) -> LegacyReply {
     finish_login_callback(&tr, serve_patreon_callback_inner(&tr).await?).await
 }
 
 async fn finish_login_callback(tr: &CubReqImpl, auth_bundle: Option<AuthBundle>) -> LegacyReply {
+    let link_accounts = match tr.cookies.get("link_accounts") {
+        Some(cookie) => {
+            tr.cookies.remove(cookie);
+            true
+        }
+        None => false,
+    };
+
     // if None, the oauth flow was cancelled
-    if let Some(auth_bundle) = auth_bundle {
+    if let Some(mut auth_bundle) = auth_bundle {
+        if let (true, Some(current)) = (link_accounts, tr.auth_bundle.as_ref()) {
+            tracing::info!("Linking the account they just logged in with to their current one");
+            let res = tr
+                .tenant
+                .tcli()
+                .link_identities(&LinkIdentitiesArgs {
+                    current: current.user_info.profile.clone(),
+                    new: auth_bundle.user_info.profile,
+                })
+                .await;
+            auth_bundle = match res {
+                Ok(auth_bundle) => auth_bundle,
+                Err(e) => {
+                    tracing::warn!("Could not link accounts: {e}");
+                    return tr.render(RenderArgs::new("login.html").with_global(
+                        "link_error",
+                        "Those accounts couldn't be linked. Is another account from the same provider already linked?",
+                    ));
+                }
+            };
+        }
+
         let session_cookie = auth_bundle_as_cookie(&auth_bundle);
         tr.cookies.add(session_cookie);
         {
@@ -205,6 +341,7 @@ async fn serve_login_for_dev(tr: CubReqImpl) -> LegacyReply {
     let profile = credentials::Profile {
         patreon_id,
         github_id,
+        email: None,
         full_name: "Admin (Dev)".to_string(),
         thumb_url: "https://placehold.co/32".to_string(), // Placeholder URL
     };
@@ -1,7 +1,9 @@
 mod api;
+pub(crate) mod downloads;
 mod internal_api;
 mod login;
 mod tags;
+mod taxonomies;
 
 use std::net::SocketAddr;
 
@@ -19,12 +21,12 @@ use axum::{
 use camino::Utf8PathBuf;
 use closest::{GetOrHelp, ResourceKind};
 use config_types::is_development;
-use conflux::{CacheBuster, InputPathRef};
+use conflux::{CacheBuster, InputPathRef, ResolvedRedirect};
 use content_type::ContentType;
 use cub_types::{CubReq, CubTenant};
 use http::{
     StatusCode,
-    header::{ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE, X_CONTENT_TYPE_OPTIONS},
+    header::{ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE, LOCATION, X_CONTENT_TYPE_OPTIONS},
 };
 use objectstore_types::ObjectStoreKey;
 
@@ -37,6 +39,7 @@ pub(crate) fn web_routes() -> Router {
         .route("/robots.txt", get(robots_txt))
         .route("/whoami", get(whoami))
         .route("/index.xml", get(atom_feed))
+        .nest("/downloads", downloads::download_routes())
         .route("/extra-files/{*path}", get(extra_files))
         .route("/favicon.ico", get(favicon))
         .route("/", get(serve_page_route))
@@ -49,13 +52,20 @@ async fn robots_txt() -> &'static str {
 }
 
 async fn atom_feed(tr: CubReqImpl) -> LegacyReply {
-    tr.render(RenderArgs::new("index.xml").with_content_type(ContentType::Atom))
+    tr.render(
+        RenderArgs::new("index.xml")
+            .with_content_type(ContentType::Atom)
+            .cacheable(),
+    )
 }
 
 /// Render a 404 page using the template
 pub(crate) fn render_404(tr: CubReqImpl) -> LegacyReply {
     let mut response = tr.render(RenderArgs::new("404.html"))?;
-    *response.status_mut() = StatusCode::NOT_FOUND;
+    // leave 304s from the page cache alone
+    if response.status() == StatusCode::OK {
+        *response.status_mut() = StatusCode::NOT_FOUND;
+    }
     Ok(response)
 }
 
@@ -97,6 +107,14 @@ async fn serve_page_route(rx: CubReqImpl) -> LegacyReply {
     {
         Ok(path) => path,
         Err(e) => {
+            if let Some(args) = taxonomies::taxonomy_render_args(&irev.rev, page_route.as_str()) {
+                return rx.render(args.cacheable());
+            }
+
+            if let Some(redirect) = irev.rev.redirects.resolve(page_route) {
+                return serve_redirect(&rx, redirect);
+            }
+
             if rx.path.as_str().ends_with(".png") {
                 let cdn_base_url = &rx.tenant.tc().cdn_base_url(rx.web());
                 let cdn_url = format!("{}{}", cdn_base_url, rx.path);
@@ -141,18 +159,32 @@ async fn serve_page_route(rx: CubReqImpl) -> LegacyReply {
         CanAccess::No(_) => { /* Access denied for non-admins, no redirect */ }
     }
 
-    if &page.route != page_route {
-        let redirect_target = if rx.raw_query().is_empty() {
-            page.route.to_string()
-        } else {
-            format!("{}?{}", page.route, rx.raw_query())
-        };
-        tracing::info!("Redirecting to {redirect_target}");
-        return Redirect::temporary(&redirect_target).into_legacy_reply();
-    }
-
     let template_name = page.template.as_str();
-    rx.render(RenderArgs::new(template_name).with_page(page))
+    rx.render(RenderArgs::new(template_name).with_page(page).cacheable())
+}
+
+/// Answers with the status code the redirect rule asked for (axum's `Redirect`
+/// only does 303/307/308), keeping the query string if the target has none.
+fn serve_redirect(rx: &CubReqImpl, redirect: ResolvedRedirect) -> LegacyReply {
+    let Some(location) = redirect.location else {
+        tracing::info!("{} is gone", rx.path);
+        let mut response = rx.render(RenderArgs::new("404.html"))?;
+        if response.status() == StatusCode::OK {
+            *response.status_mut() = StatusCode::GONE;
+        }
+        return Ok(response);
+    };
+
+    let location = if rx.raw_query().is_empty() || location.contains('?') {
+        location
+    } else {
+        format!("{location}?{}", rx.raw_query())
+    };
+    let status = StatusCode::from_u16(redirect.status.as_u16())
+        .expect("redirect statuses are valid status codes");
+
+    tracing::info!("Redirecting {} to {location} ({status})", rx.path);
+    Ok((status, [(LOCATION, location)]).into_response())
 }
 
 async fn whoami(ConnectInfo(addr): ConnectInfo<SocketAddr>, req: Request) -> LegacyReply {
@@ -171,11 +203,11 @@ async fn extra_files(
     tr: CubReqImpl,
 ) -> LegacyReply {
     let viewer = tr.viewer()?;
-    if !(viewer.has_bronze || viewer.is_admin) {
+    if !(viewer.is_member() || viewer.is_admin) {
         tracing::warn!("Unauthorized access attempt to extra files");
         return Err(LegacyHttpError::with_status(
             StatusCode::FORBIDDEN,
-            "extra files are only available to Bronze sponsors and above",
+            "extra files are only available to sponsors",
         ));
     }
 
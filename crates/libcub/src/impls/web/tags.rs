@@ -4,6 +4,7 @@ use crate::impls::{
 };
 use axum::{extract::Path, response::Redirect};
 use content_type::ContentType;
+use cub_types::CubTenant;
 
 use axum::{Router, routing::get};
 
@@ -15,15 +16,31 @@ pub(crate) fn tag_routes() -> Router<()> {
 }
 
 async fn serve_list(tr: CubReqImpl) -> LegacyReply {
-    tr.render(RenderArgs::new("tags.html"))
+    tr.render(RenderArgs::new("tags.html").cacheable())
 }
 
 async fn serve_single(tr: CubReqImpl, Path(tag): Path<String>) -> LegacyReply {
-    tr.render(
-        RenderArgs::new("tag.html")
-            .with_global("tag", tag)
-            .with_content_type(ContentType::HTML),
-    )
+    let irev = tr.tenant.rev()?;
+    let canonical = irev.rev.pak.rc.canonical_tag(&tag);
+    if canonical != tag {
+        return Redirect::permanent(&format!("/tags/{canonical}")).into_legacy_reply();
+    }
+
+    // `?page=2`, for `get_tag_listing(tag=tag, page_number=page_number)`
+    let page_number = tr
+        .url_params_map()
+        .get("page")
+        .and_then(|page| page.parse::<i32>().ok())
+        .filter(|&page| page >= 1)
+        .unwrap_or(1);
+    let mut args = RenderArgs::new("tag.html")
+        .with_global("page_number", page_number)
+        .with_content_type(ContentType::HTML);
+    // there's no end to tags nobody uses, those aren't worth keeping
+    if irev.rev.tags.contains_key(&tag) {
+        args = args.cacheable();
+    }
+    tr.render(args.with_global("tag", tag))
 }
 
 async fn redirect_to_slashless(Path(tag): Path<String>) -> LegacyReply {
@@ -0,0 +1,89 @@
+use conflux::Revision;
+use content_type::ContentType;
+
+use crate::impls::cub_req::RenderArgs;
+
+/// Pages generated for the taxonomies configured in `home.json`: `/{taxonomy}`
+/// lists its terms, `/{taxonomy}/{term}` lists the pages that have that term,
+/// and `/{taxonomy}/{term}/index.xml` is a feed of them, if enabled. Returns
+/// `None` for routes that aren't any of those, including terms no page has,
+/// so they 404 like any other missing page.
+pub(crate) fn taxonomy_render_args(rev: &Revision, route: &str) -> Option<RenderArgs> {
+    let mut segments = route.strip_prefix('/')?.split('/');
+    let taxonomy = rev.pak.rc.taxonomy(segments.next()?)?;
+    let is_term = |term: &str| {
+        rev.taxonomies
+            .get(&taxonomy.name)
+            .is_some_and(|terms| terms.contains_key(term))
+    };
+
+    match (segments.next(), segments.next(), segments.next()) {
+        (None, _, _) => {
+            Some(RenderArgs::new("taxonomy.html").with_global("taxonomy", taxonomy.name.clone()))
+        }
+        (Some(term), None, _) if is_term(term) => Some(
+            RenderArgs::new("taxonomy_term.html")
+                .with_global("taxonomy", taxonomy.name.clone())
+                .with_global("term", term.to_string()),
+        ),
+        (Some(term), Some("index.xml"), None) if taxonomy.feed && is_term(term) => Some(
+            RenderArgs::new("taxonomy_term.xml")
+                .with_global("taxonomy", taxonomy.name.clone())
+                .with_global("term", term.to_string())
+                .with_content_type(ContentType::Atom),
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config_types::{RevisionConfig, TaxonomyConfig};
+    use conflux::testing;
+
+    #[test]
+    fn test_taxonomy_render_args() {
+        let mut rc = RevisionConfig::default();
+        rc.taxonomies = vec![
+            TaxonomyConfig {
+                name: "categories".into(),
+                feed: true,
+            },
+            TaxonomyConfig {
+                name: "series-tags".into(),
+                feed: false,
+            },
+        ];
+        let page = testing::page(&rc, "/articles/foo");
+        let mut rev = testing::revision(rc, [page.clone()]);
+        rev.taxonomies.insert(
+            "categories".into(),
+            [("rust".to_string(), vec![page.path.clone()])].into(),
+        );
+        rev.taxonomies.insert(
+            "series-tags".into(),
+            [("rust".to_string(), vec![page.path.clone()])].into(),
+        );
+
+        let template =
+            |route: &str| taxonomy_render_args(&rev, route).map(|args| args.template_name);
+        assert_eq!(template("/categories").as_deref(), Some("taxonomy.html"));
+        assert_eq!(
+            template("/categories/rust").as_deref(),
+            Some("taxonomy_term.html")
+        );
+        assert_eq!(
+            template("/categories/rust/index.xml").as_deref(),
+            Some("taxonomy_term.xml")
+        );
+
+        // unknown taxonomies and terms are left to the 404 page
+        assert!(template("/authors/amos").is_none());
+        assert!(template("/categories/go").is_none());
+        assert!(template("/categories/").is_none());
+        assert!(template("/categories/go/index.xml").is_none());
+        assert!(template("/categories/rust/extra").is_none());
+        assert!(template("/series-tags/rust/index.xml").is_none());
+    }
+}
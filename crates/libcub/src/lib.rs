@@ -1,8 +1,12 @@
+use std::sync::Arc;
+
 use autotrait::autotrait;
 use tokio::net::TcpListener;
 
-use config_types::CubConfig;
+use config_types::{CubConfig, RevisionConfig};
+use conflux::{LoadedPage, Viewer};
 use futures_core::future::BoxFuture;
+use template_types::DataObject;
 
 struct ModImpl;
 
@@ -16,6 +20,14 @@ pub enum OpenBehavior {
     DontOpen,
 }
 
+/// A page as a given viewer gets it, see [`Mod::page_for_viewer`]
+pub struct ViewedPage {
+    pub page: Arc<LoadedPage>,
+
+    /// `paywalled` and `required_tier`, for paywalled pages
+    pub globals: DataObject,
+}
+
 #[autotrait]
 impl Mod for ModImpl {
     fn serve(
@@ -30,6 +42,19 @@ impl Mod for ModImpl {
                 .map_err(|e| eyre::eyre!("{}", e))
         })
     }
+
+    /// What a viewer gets of a page when it's served (`raw_query` may have a
+    /// `draft_code`), `None` if it's a 404 for them. For `home test-templates`,
+    /// so fixtures render pages like cub does.
+    fn page_for_viewer(
+        &self,
+        page: Arc<LoadedPage>,
+        viewer: &Viewer,
+        rc: &RevisionConfig,
+        raw_query: &str,
+    ) -> Option<ViewedPage> {
+        impls::access_control::page_for_viewer(page, viewer, rc, raw_query)
+    }
 }
 
 mod impls;
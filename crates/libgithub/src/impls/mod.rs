@@ -1,10 +1,8 @@
-use std::collections::HashSet;
-
 use config_types::{RevisionConfig, TenantConfig, WebConfig};
-use credentials::{AuthBundle, Profile, Tier, UserInfo};
+use credentials::{AuthBundle, CreditedSponsor, Profile, Tier, UserInfo};
 use eyre::Result;
 use libhttpclient::{HeaderValue, HttpClient, Uri, header};
-use merde::{IntoStatic, Map};
+use merde::{IntoStatic, Map, time::Rfc3339};
 use time::OffsetDateTime;
 use tracing::debug;
 
@@ -55,11 +53,11 @@ impl ModImpl {
 
     pub(crate) async fn list_sponsors_unboxed(
         &self,
-        _tc: &TenantConfig,
+        rc: &RevisionConfig,
         client: &dyn HttpClient,
         github_creds: &GitHubCredentials,
-    ) -> eyre::Result<HashSet<String>> {
-        let mut credited_patrons: HashSet<String> = Default::default();
+    ) -> eyre::Result<Vec<CreditedSponsor>> {
+        let mut credited_patrons: Vec<CreditedSponsor> = Default::default();
         let query = include_str!("github_sponsors.graphql");
 
         struct GraphqlQuery {
@@ -120,21 +118,25 @@ impl ModImpl {
 
         #[allow(non_snake_case)]
         struct Node {
+            databaseId: i64,
             login: String,
             name: Option<String>,
+            avatarUrl: Option<String>,
+            url: Option<String>,
             sponsorshipForViewerAsSponsorable: Option<SponsorshipForViewerAsSponsorable>,
         }
         merde::derive!(
-            impl (Deserialize) for struct Node { login, name, sponsorshipForViewerAsSponsorable }
+            impl (Deserialize) for struct Node { databaseId, login, name, avatarUrl, url, sponsorshipForViewerAsSponsorable }
         );
 
         #[allow(non_snake_case)]
         struct SponsorshipForViewerAsSponsorable {
+            createdAt: Option<Rfc3339<OffsetDateTime>>,
             privacyLevel: String,
             tier: GitHubTier,
         }
         merde::derive!(
-            impl (Deserialize) for struct SponsorshipForViewerAsSponsorable { privacyLevel, tier }
+            impl (Deserialize) for struct SponsorshipForViewerAsSponsorable { createdAt, privacyLevel, tier }
         );
 
         #[allow(non_snake_case)]
@@ -237,14 +239,23 @@ impl ModImpl {
                         continue;
                     }
 
+                    let mut tier_title = None;
                     if let Some(price) = sponsorship.tier.monthlyPriceInDollars {
-                        if price < 10 {
-                            continue;
+                        match rc.tier_for_github_monthly_dollars(price) {
+                            Some(tier) if tier.credited => tier_title = Some(tier.name.clone()),
+                            _ => continue,
                         }
                     }
 
                     let name = sponsor.name.as_ref().unwrap_or(&sponsor.login);
-                    credited_patrons.insert(name.trim().to_string());
+                    credited_patrons.push(CreditedSponsor {
+                        identity: format!("github:{}", sponsor.databaseId),
+                        name: name.trim().to_string(),
+                        tier_title,
+                        avatar_source_url: sponsor.avatarUrl.clone(),
+                        profile_url: sponsor.url.clone(),
+                        joined_at: sponsorship.createdAt.as_ref().map(|d| d.0),
+                    });
                 }
             }
 
@@ -373,20 +384,18 @@ impl ModImpl {
                     return None;
                 }
 
-                match s.tier.monthlyPriceInDollars {
-                    _ if web.env.is_dev() => Some(Tier {
-                        title: "Silver".into(),
-                    }),
-                    0..10 => Some(Tier {
-                        title: "Bronze".into(),
-                    }),
-                    10..50 => Some(Tier {
-                        title: "Silver".into(),
-                    }),
-                    50.. => Some(Tier {
-                        title: "Gold".into(),
-                    }),
-                }
+                let tier = if web.env.is_dev() {
+                    // in dev, pretend to be the lowest credited tier
+                    rc.tiers()
+                        .iter()
+                        .filter(|t| t.credited)
+                        .min_by_key(|t| t.rank)
+                } else {
+                    rc.tier_for_github_monthly_dollars(s.tier.monthlyPriceInDollars)
+                };
+                tier.map(|t| Tier {
+                    title: t.name.clone(),
+                })
             })
             .or_else(|| {
                 eprintln!("admin github ids: {:?}", rc.admin_github_ids);
@@ -418,6 +427,7 @@ impl ModImpl {
                     full_name: full_name.to_owned(),
                     patreon_id: None,
                     github_id: Some(viewer_github_user_id.clone()),
+                    email: None,
                     thumb_url: viewer.avatarUrl.to_owned(),
                 },
                 tier,
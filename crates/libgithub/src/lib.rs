@@ -3,10 +3,9 @@
 use autotrait::autotrait;
 use futures_core::future::BoxFuture;
 use libhttpclient::HttpClient;
-use std::collections::HashSet;
 
 use config_types::{RevisionConfig, TenantConfig, WebConfig};
-use credentials::AuthBundle;
+use credentials::{AuthBundle, CreditedSponsor};
 use eyre::Result;
 
 mod impls;
@@ -64,11 +63,11 @@ impl Mod for ModImpl {
 
     fn list_sponsors<'fut>(
         &'fut self,
-        tc: &'fut TenantConfig,
+        rc: &'fut RevisionConfig,
         client: &'fut dyn HttpClient,
         github_creds: &'fut GitHubCredentials,
-    ) -> BoxFuture<'fut, Result<HashSet<String>>> {
-        Box::pin(async move { self.list_sponsors_unboxed(tc, client, github_creds).await })
+    ) -> BoxFuture<'fut, Result<Vec<CreditedSponsor>>> {
+        Box::pin(async move { self.list_sponsors_unboxed(rc, client, github_creds).await })
     }
 }
 
@@ -3,6 +3,19 @@ use autotrait::autotrait;
 #[derive(Default)]
 struct ModImpl;
 
+/// A link found in an HTML document, see [Mod::extract_links]
+#[derive(Debug, Clone)]
+pub struct HtmlLink {
+    /// `a` or `link`
+    pub tag: String,
+
+    /// The `href` attribute, as-is (may be relative)
+    pub href: String,
+
+    /// The `rel` attribute, empty if absent
+    pub rel: String,
+}
+
 pub fn load() -> &'static dyn Mod {
     static MOD: ModImpl = ModImpl;
     &MOD
@@ -84,4 +97,139 @@ impl Mod for ModImpl {
         }
         String::from_utf8(output).unwrap()
     }
+
+    /// Sanitize user-provided HTML (comments, etc.): only keeps a small set
+    /// of formatting tags, drops every attribute except safe link targets,
+    /// and removes scripts, styles, embeds and HTML comments entirely.
+    fn sanitize_html(&self, input: &str) -> String {
+        use lol_html::{HtmlRewriter, Settings, doc_comments, element};
+
+        let mut output: Vec<u8> = Vec::new();
+        let output_sink = |c: &[u8]| {
+            output.extend_from_slice(c);
+        };
+
+        fn is_allowed_tag(tag_name: &str) -> bool {
+            #![allow(clippy::match_like_matches_macro)]
+
+            match tag_name {
+                "p" | "br" | "hr" | "em" | "strong" | "b" | "i" | "u" | "s" | "del" | "ins"
+                | "sub" | "sup" | "a" | "code" | "pre" | "blockquote" | "ul" | "ol" | "li"
+                | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "table" | "thead" | "tbody" | "tr"
+                | "th" | "td" => true,
+                _ => false,
+            }
+        }
+
+        fn is_dropped_with_content(tag_name: &str) -> bool {
+            #![allow(clippy::match_like_matches_macro)]
+
+            match tag_name {
+                "script" | "style" | "iframe" | "object" | "embed" | "noscript" | "template"
+                | "textarea" | "title" | "svg" | "math" | "select" | "frame" | "frameset" => true,
+                _ => false,
+            }
+        }
+
+        fn is_safe_href(href: &str) -> bool {
+            let href = href.trim().to_ascii_lowercase();
+            href.starts_with("https://")
+                || href.starts_with("http://")
+                || href.starts_with("mailto:")
+                || href.starts_with('/')
+                || href.starts_with('#')
+        }
+
+        let mut rewriter = HtmlRewriter::new(
+            Settings {
+                element_content_handlers: vec![element!("*", |el| {
+                    let tag_name = el.tag_name();
+                    if is_dropped_with_content(&tag_name) {
+                        el.remove();
+                        return Ok(());
+                    }
+                    if !is_allowed_tag(&tag_name) {
+                        el.remove_and_keep_content();
+                        return Ok(());
+                    }
+
+                    let href = el.get_attribute("href");
+                    let names: Vec<String> = el.attributes().iter().map(|a| a.name()).collect();
+                    for name in names {
+                        el.remove_attribute(&name);
+                    }
+                    if tag_name == "a" {
+                        if let Some(href) = href.filter(|h| is_safe_href(h)) {
+                            el.set_attribute("href", &href)?;
+                        }
+                        el.set_attribute("rel", "nofollow ugc noopener")?;
+                    }
+                    Ok(())
+                })],
+                document_content_handlers: vec![doc_comments!(|c| {
+                    c.remove();
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+            output_sink,
+        );
+
+        let max_write_size: usize = 4096;
+        let mut remaining = input.as_bytes();
+        while !remaining.is_empty() {
+            let chunk_size = remaining.len().min(max_write_size);
+            rewriter.write(&remaining[..chunk_size]).unwrap();
+            remaining = &remaining[chunk_size..];
+        }
+        rewriter.end().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    /// Returns all `a` and `link` elements that have an `href`, in document order
+    fn extract_links(&self, input: &str) -> Vec<HtmlLink> {
+        use lol_html::{HtmlRewriter, Settings, element};
+
+        let mut links: Vec<HtmlLink> = Vec::new();
+        let mut rewriter = HtmlRewriter::new(
+            Settings {
+                element_content_handlers: vec![element!("a[href], link[href]", |el| {
+                    links.push(HtmlLink {
+                        tag: el.tag_name(),
+                        href: el.get_attribute("href").unwrap_or_default(),
+                        rel: el.get_attribute("rel").unwrap_or_default(),
+                    });
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+            |_: &[u8]| {},
+        );
+        rewriter.write(input.as_bytes()).unwrap();
+        rewriter.end().unwrap();
+        links
+    }
+
+    /// Returns the (entity-decoded, trimmed) contents of the document's
+    /// `<title>`, if it has a non-empty one.
+    fn extract_title(&self, input: &str) -> Option<String> {
+        use lol_html::{HtmlRewriter, Settings, text};
+
+        let mut title = String::new();
+        let mut rewriter = HtmlRewriter::new(
+            Settings {
+                element_content_handlers: vec![text!("title", |txt| {
+                    title.push_str(txt.as_str());
+                    Ok(())
+                })],
+                ..Settings::default()
+            },
+            |_: &[u8]| {},
+        );
+        rewriter.write(input.as_bytes()).unwrap();
+        rewriter.end().unwrap();
+
+        let title = html_escape::decode_html_entities(title.trim()).into_owned();
+        (!title.is_empty()).then_some(title)
+    }
 }
@@ -0,0 +1,177 @@
+use autotrait::autotrait;
+use camino::{Utf8Path, Utf8PathBuf};
+use config_types::SmtpSecrets;
+use futures_core::future::BoxFuture;
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{Mailbox, header::ContentType},
+    transport::smtp::authentication::Credentials,
+};
+use std::sync::Arc;
+
+pub use eyre::Result;
+
+/// A plain-text email
+#[derive(Debug, Clone)]
+pub struct Email {
+    /// Display name for the sender, e.g. the site's name. The address itself
+    /// is up to the mailer.
+    pub from_name: Option<String>,
+
+    /// Recipient address
+    pub to: String,
+
+    pub subject: String,
+
+    pub text: String,
+}
+
+struct ModImpl;
+
+pub fn load() -> &'static dyn Mod {
+    static MOD: ModImpl = ModImpl;
+    &MOD
+}
+
+#[autotrait]
+impl Mod for ModImpl {
+    /// Sends emails through an SMTP relay, over STARTTLS
+    fn smtp(&self, secrets: &SmtpSecrets) -> Result<Arc<dyn Mailer>> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&secrets.host)?
+            .port(secrets.port.unwrap_or(587))
+            .credentials(Credentials::new(
+                secrets.username.clone(),
+                secrets.password.clone(),
+            ))
+            .build();
+        Ok(Arc::new(SmtpMailer {
+            transport,
+            from: secrets.from.clone(),
+        }))
+    }
+
+    /// Writes emails as `.eml` files in `dir` instead of sending them —
+    /// for development and tests.
+    fn file_drop(&self, dir: &Utf8Path) -> Arc<dyn Mailer> {
+        Arc::new(FileDropMailer {
+            dir: dir.to_owned(),
+        })
+    }
+
+    /// Fails to send anything, with the given reason — for production setups
+    /// that forgot to configure SMTP, so sign-in links don't silently end up
+    /// on disk.
+    fn unavailable(&self, reason: &str) -> Arc<dyn Mailer> {
+        Arc::new(UnavailableMailer {
+            reason: reason.to_owned(),
+        })
+    }
+}
+
+struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: String,
+}
+
+#[autotrait]
+impl Mailer for SmtpMailer {
+    fn send(&self, email: Email) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let message = build_message(&self.from, email)?;
+            self.transport.send(message).await?;
+            Ok(())
+        })
+    }
+}
+
+struct FileDropMailer {
+    dir: Utf8PathBuf,
+}
+
+impl Mailer for FileDropMailer {
+    fn send(&self, email: Email) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let to = email.to.clone();
+            let message = build_message("noreply@localhost", email)?;
+
+            tokio::fs::create_dir_all(&self.dir).await?;
+            let now = time::OffsetDateTime::now_utc();
+            let file_name = format!(
+                "{}-{}.eml",
+                now.unix_timestamp_nanos(),
+                to.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+            );
+            let path = self.dir.join(file_name);
+            tokio::fs::write(&path, message.formatted()).await?;
+            tracing::info!("📬 Email to {to} dropped in \x1b[36m{path}\x1b[0m");
+            Ok(())
+        })
+    }
+}
+
+struct UnavailableMailer {
+    reason: String,
+}
+
+impl Mailer for UnavailableMailer {
+    fn send(&self, email: Email) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move { eyre::bail!("Can't send email to {}: {}", email.to, self.reason) })
+    }
+}
+
+fn build_message(from: &str, email: Email) -> Result<Message> {
+    let from = Mailbox::new(email.from_name, from.parse()?);
+    Ok(Message::builder()
+        .from(from)
+        .to(email.to.parse()?)
+        .subject(email.subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(email.text)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn file_drop_writes_eml() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+
+        let mailer = load().file_drop(dir);
+        mailer
+            .send(Email {
+                from_name: Some("example.org".into()),
+                to: "reader@example.com".into(),
+                subject: "Your sign-in link".into(),
+                text: "https://example.org/login/email/callback?token=abc".into(),
+            })
+            .await
+            .unwrap();
+
+        let entries: Vec<_> = std::fs::read_dir(dir).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+        let path = entries[0].as_ref().unwrap().path();
+        assert!(path.to_string_lossy().ends_with("reader_example_com.eml"));
+
+        let contents = std::fs::read_to_string(path).unwrap();
+        assert!(contents.contains("To: reader@example.com"));
+        assert!(contents.contains("Subject: Your sign-in link"));
+        assert!(contents.contains("token=abc"));
+    }
+
+    #[tokio::test]
+    async fn unavailable_fails() {
+        let mailer = load().unavailable("no SMTP configured");
+        let err = mailer
+            .send(Email {
+                from_name: None,
+                to: "reader@example.com".into(),
+                subject: "Your sign-in link".into(),
+                text: "".into(),
+            })
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("no SMTP configured"));
+    }
+}
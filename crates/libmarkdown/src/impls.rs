@@ -6,7 +6,7 @@ use std::{
 };
 
 use bo_inserter::BoInserter;
-use conflux::{Href, Media};
+use conflux::{Href, Media, MembersSection, members_section_placeholder};
 use eyre::{bail, eyre};
 use libmedia::MediaMarkupOpts;
 use pulldown_cmark::{
@@ -17,6 +17,7 @@ use saphyr::{Yaml, yaml::YamlLoader};
 
 use conflux::{InputPath, InputPathRef, Markdown, TocEntry};
 use libmath::MathMode;
+use markdown_types::FrontmatterFormat;
 use slug::slugify;
 use template_types::{DataObject, DataValue};
 
@@ -77,6 +78,10 @@ pub(crate) enum StackItem<'a> {
     ShortBlockEnd {
         after_body: Markdown,
     },
+    MembersSection {
+        tier: Option<String>,
+        html: Vec<u8>,
+    },
     Paragraph,
     Link,
     Emphasis,
@@ -90,6 +95,7 @@ pub(crate) enum StackItem<'a> {
     Image(ImageItem<'a>),
     Frontmatter {
         plain_text: String,
+        format: FrontmatterFormat,
     },
     CodeBlock {
         lang: CowStr<'a>,
@@ -158,6 +164,9 @@ pub(crate) struct Formatter<'a> {
     pub(crate) footnote_counter: usize,
     pub(crate) footnotes: HashMap<String, usize>,
 
+    /// ids for members-only sections, shared with nested formatters
+    pub(crate) members_section_counter: usize,
+
     pub(crate) args: ProcessMarkdownArgs<'a>,
     pub(crate) result: ProcessMarkdownResult,
 
@@ -169,6 +178,10 @@ pub(crate) struct Formatter<'a> {
 
     pub(crate) discard_writer: DiscardWriter,
 
+    /// whether we're rendering markdown that came out of a shortcode, where a
+    /// leading `+++` block is another shortcode, not frontmatter
+    pub(crate) nested: bool,
+
     /// used in reading time estimates
     pub(crate) num_prose_bytes: usize,
     pub(crate) num_code_lines: usize,
@@ -200,6 +213,9 @@ impl<'a> Formatter<'a> {
         for item in self.stack.iter_mut().rev() {
             match item {
                 StackItem::Heading { html, .. } => return Ok(html as &mut dyn std::io::Write),
+                StackItem::MembersSection { html, .. } => {
+                    return Ok(html as &mut dyn std::io::Write);
+                }
                 StackItem::CodeBlock { .. } => bail!("fenced code blocks don't have markup"),
                 _ => continue,
             }
@@ -239,7 +255,8 @@ impl<'a> Formatter<'a> {
     }
 
     fn write_plain_text(&mut self, text: &str) {
-        let mut counts_as_plaintext = true;
+        // members-only text must not end up in search indexes, feeds, etc.
+        let mut counts_as_plaintext = !self.in_members_section();
         let mut is_code_block = false;
 
         for item in self.stack.iter_mut().rev() {
@@ -340,6 +357,33 @@ impl<'a> Formatter<'a> {
         Ok(())
     }
 
+    fn in_members_section(&self) -> bool {
+        self.stack
+            .iter()
+            .any(|item| matches!(item, StackItem::MembersSection { .. }))
+    }
+
+    /// `> *:members(tier="Silver")*` blocks: rendered to their own buffer,
+    /// spliced in at request time depending on who's looking.
+    fn start_members_section(&mut self, args: DataObject) -> eyre::Result<()> {
+        if self.in_members_section() {
+            bail!("members-only sections can't be nested");
+        }
+        if let Some(key) = args.keys().find(|key| key.as_str() != "tier") {
+            bail!("unknown argument for members-only section: {key}");
+        }
+        let tier = match args.get("tier") {
+            Some(DataValue::String(tier)) => Some(tier.clone()),
+            Some(other) => bail!("members-only section tier should be a string, got {other:?}"),
+            None => None,
+        };
+        self.push(StackItem::MembersSection {
+            tier,
+            html: Default::default(),
+        })?;
+        Ok(())
+    }
+
     fn start_code_block(&mut self, lang: CowStr<'a>, byte_offset: usize) -> eyre::Result<()> {
         self.push(StackItem::CodeBlock {
             lang,
@@ -402,6 +446,17 @@ impl<'a> Formatter<'a> {
                 Tag::MetadataBlock(MetadataBlockKind::YamlStyle) => {
                     self.push(StackItem::Frontmatter {
                         plain_text: Default::default(),
+                        format: FrontmatterFormat::Yaml,
+                    })?;
+                }
+                // a `+++` block that opens the page is TOML frontmatter (Zola/Hugo
+                // style), anywhere else it's a shortcode
+                Tag::MetadataBlock(MetadataBlockKind::PlusesStyle)
+                    if range.start == 0 && !self.nested =>
+                {
+                    self.push(StackItem::Frontmatter {
+                        plain_text: Default::default(),
+                        format: FrontmatterFormat::Toml,
                     })?;
                 }
                 Tag::MetadataBlock(MetadataBlockKind::PlusesStyle) => {
@@ -606,14 +661,19 @@ impl<'a> Formatter<'a> {
             },
             Event::End(tag) => match tag {
                 TagEnd::MetadataBlock(MetadataBlockKind::YamlStyle) => match self.pop() {
-                    Some((StackItem::Frontmatter { plain_text }, _)) => {
+                    Some((StackItem::Frontmatter { plain_text, format }, _)) => {
                         self.result.frontmatter.replace(plain_text);
+                        self.result.frontmatter_format = format;
                     }
                     _ => {
                         bail!("Unexpected: wanted to end frontmatter, self={self:?}");
                     }
                 },
                 TagEnd::MetadataBlock(MetadataBlockKind::PlusesStyle) => match self.pop() {
+                    Some((StackItem::Frontmatter { plain_text, format }, _)) => {
+                        self.result.frontmatter.replace(plain_text);
+                        self.result.frontmatter_format = format;
+                    }
                     Some((StackItem::ShortPlus { plain_text }, _)) => {
                         // parse shortcode arguments as YAML
                         trace!("Parsing shortcode arguments as YAML: {plain_text:?}");
@@ -697,7 +757,9 @@ impl<'a> Formatter<'a> {
                         if !self.stack.iter().any(|item| {
                             matches!(
                                 item,
-                                StackItem::Blockquote | StackItem::ShortBlockEnd { .. }
+                                StackItem::Blockquote
+                                    | StackItem::ShortBlockEnd { .. }
+                                    | StackItem::MembersSection { .. }
                             )
                         }) {
                             self.result.toc.push(TocEntry {
@@ -737,6 +799,18 @@ impl<'a> Formatter<'a> {
 
                             let (shortcode_name, mut shortcode_args) =
                                 parse_emphasis_shortcode(plain_text)?;
+                            if shortcode_name == "members" {
+                                // not a template: rendered to its own buffer until the
+                                // blockquote ends, see `start_members_section`
+                                self.start_members_section(shortcode_args)?;
+                                if let Some((Event::End(TagEnd::Paragraph), _)) = ev_buf.front() {
+                                    ev_buf.pop_front();
+                                } else {
+                                    self.writer()?.write_all(b"<p>")?;
+                                    self.push(StackItem::Paragraph)?;
+                                }
+                                return Ok(());
+                            }
                             self.insert_shortcode_globals(&mut shortcode_args);
 
                             let after_body;
@@ -882,6 +956,17 @@ impl<'a> Formatter<'a> {
                                 // now we can write the rest of the body
                                 self.process_nested_markdown(&after_body)?;
                             }
+                            StackItem::MembersSection { tier, html } => {
+                                let id = self.members_section_counter;
+                                self.members_section_counter += 1;
+                                self.result.members_sections.push(MembersSection {
+                                    id,
+                                    tier,
+                                    html: String::from_utf8(html)?,
+                                });
+                                self.writer()?
+                                    .write_all(members_section_placeholder(id).as_bytes())?;
+                            }
                             other => {
                                 bail!(
                                     "At blockquote end, expected blockquote or shortblockend, got {other:#?}"
@@ -1115,15 +1200,27 @@ impl<'a> Formatter<'a> {
 
         let nested_parser = Parser::new_ext(markdown.as_str(), options());
 
+        // shortcodes inside a members-only section render into that section
+        let in_members_section = self.in_members_section();
+        let members_html = self.stack.iter_mut().rev().find_map(|item| match item {
+            StackItem::MembersSection { html, .. } => Some(html),
+            _ => None,
+        });
+        let w: &mut dyn std::io::Write = match members_html {
+            Some(html) => html,
+            None => &mut *self.args.w,
+        };
+
         let mut nested_formatter = Formatter {
             stack: Vec::new(),
             footnote_counter: self.footnote_counter,
             footnotes: self.footnotes.clone(),
+            members_section_counter: self.members_section_counter,
 
             args: ProcessMarkdownArgs {
                 path: self.args.path,
                 markdown,
-                w: self.args.w,
+                w,
                 rv: self.args.rv.clone(),
                 ti: self.args.ti.clone(),
                 templates: self.args.templates,
@@ -1138,6 +1235,7 @@ impl<'a> Formatter<'a> {
             mode: self.mode,
 
             discard_writer: DiscardWriter,
+            nested: true,
 
             num_prose_bytes: 0,
             num_code_lines: 0,
@@ -1147,11 +1245,17 @@ impl<'a> Formatter<'a> {
 
         self.footnote_counter += nested_formatter.footnote_counter;
         self.footnotes.extend(nested_formatter.footnotes);
+        self.members_section_counter = nested_formatter.members_section_counter;
+
+        let mut nested_result = nested_formatter.result;
+        if in_members_section {
+            nested_result.plain_text.clear();
+        }
 
         self.num_prose_bytes += nested_formatter.num_prose_bytes;
         self.num_code_lines += nested_formatter.num_code_lines;
 
-        extend_markdown_result(&mut self.result, nested_formatter.result);
+        extend_markdown_result(&mut self.result, nested_result);
 
         trace!("<<< Nested markdown ends!\n");
 
@@ -1223,6 +1327,7 @@ fn extend_markdown_result(receiver: &mut ProcessMarkdownResult, giver: ProcessMa
     receiver.plain_text.push_str(&giver.plain_text);
     receiver.links.extend(giver.links);
     receiver.deps.extend(giver.deps);
+    receiver.members_sections.extend(giver.members_sections);
 }
 
 impl ModImpl {
@@ -1235,6 +1340,7 @@ impl ModImpl {
             stack: Vec::new(),
             footnote_counter: 0,
             footnotes: HashMap::new(),
+            members_section_counter: 0,
 
             args,
             result: Default::default(),
@@ -1246,6 +1352,7 @@ impl ModImpl {
             mode,
 
             discard_writer: DiscardWriter,
+            nested: false,
 
             num_code_lines: 0,
             num_prose_bytes: 0,
@@ -1397,6 +1504,26 @@ mod tests {
         insta::assert_snapshot!(html);
     }
 
+    #[test]
+    fn toml_frontmatter() {
+        let markdown = indoc! {r#"+++
+        title = "Test Post"
+        date = 2023-01-01T00:00:00Z
+        +++
+
+        Hello World
+        "#};
+
+        let (html, result) = to_html(MarkdownRef::from_str(markdown));
+        assert_eq!(result.frontmatter_format, FrontmatterFormat::Toml);
+        assert_eq!(
+            result.frontmatter.unwrap(),
+            "title = \"Test Post\"\ndate = 2023-01-01T00:00:00Z\n"
+        );
+        assert!(html.contains("Hello World"));
+        assert!(!html.contains("Test Post"));
+    }
+
     #[test]
     fn markdown_with_figure() {
         let markdown = indoc! {r#"
@@ -1558,6 +1685,35 @@ How about a:
         assert_eq!(result.toc[2].text, "Third Main Heading");
     }
 
+    #[test]
+    fn members_section() {
+        let markdown = indoc! {r#"
+        Everyone can read this.
+
+        > *:members(tier="Silver")*
+        >
+        > ## Bonus section
+        >
+        > Only silver members can read this.
+
+        Everyone can read this too.
+        "#};
+
+        let (html, result) = to_html(MarkdownRef::from_str(markdown));
+
+        assert_eq!(result.members_sections.len(), 1);
+        let section = &result.members_sections[0];
+        assert_eq!(section.tier.as_deref(), Some("Silver"));
+        assert!(section.html.contains("Only silver members can read this."));
+
+        assert!(html.contains(&members_section_placeholder(section.id)));
+        assert!(!html.contains("Only silver members"));
+        assert!(!result.plain_text.contains("Only silver members"));
+        assert!(!result.plain_text.contains("Bonus section"));
+        assert!(result.plain_text.contains("Everyone can read this too."));
+        assert!(result.toc.is_empty());
+    }
+
     #[test]
     fn home_is_very_opinionated() {
         let markdown = indoc! {r#"
@@ -43,6 +43,7 @@ impl Mod for ModImpl {
 
         let res = CollectDependenciesResult {
             frontmatter: formatter.result.frontmatter,
+            frontmatter_format: formatter.result.frontmatter_format,
             deps: formatter.result.deps,
         };
         Ok(res)
@@ -7,13 +7,14 @@ use std::{
 
 use axum::extract::ws;
 use config_types::{
-    MomConfig, RevisionConfig, TenantDomain, TenantInfo, WebConfig, is_development,
+    MomConfig, RevisionConfig, TenantDomain, TenantInfo, WebConfig, is_development, is_production,
 };
 use conflux::Pak;
 use credentials::AuthBundle;
 use inflight::InflightSlots;
 use itertools::Itertools;
 use libhttpclient::HttpClient;
+use libmail::Mailer;
 use libobjectstore::ObjectStore;
 use libpatreon::{ForcePatreonRefresh, PatreonCredentials, PatreonStore, test_patreon_renewal};
 use merde::IntoStatic;
@@ -37,6 +38,7 @@ mod ffmpeg;
 mod ffmpeg_stream;
 mod site;
 mod sponsors;
+mod webmentions;
 
 pub(crate) struct MomGlobalState {
     /// shared HTTP client
@@ -53,6 +55,9 @@ pub(crate) struct MomGlobalState {
 
     /// web config (mostly just port)
     pub(crate) web: WebConfig,
+
+    /// sends sign-in links
+    pub(crate) mailer: Arc<dyn Mailer>,
 }
 
 pub(crate) struct MomTenantState {
@@ -299,12 +304,31 @@ pub async fn serve(args: MomServeArgs) -> eyre::Result<()> {
         let (tx_event, rx_event) = broadcast::channel(16);
         drop(rx_event);
 
+        let mailer = match config.secrets.smtp.as_ref() {
+            Some(smtp) => libmail::load().smtp(smtp)?,
+            None if is_production() => {
+                error!(
+                    "No SMTP configured in production: sign-in links can't be sent, set secrets.smtp"
+                );
+                libmail::load().unavailable("no SMTP configured (secrets.smtp)")
+            }
+            None => {
+                let dir = config.tenant_data_dir.join("mail-drop");
+                eprintln!(
+                    "No SMTP configured, emails will be written to {}",
+                    dir.blue()
+                );
+                libmail::load().file_drop(&dir)
+            }
+        };
+
         let mut gs = MomGlobalState {
             client: Arc::from(libhttpclient::load().client()),
             bx_event: tx_event,
             tenants: Default::default(),
             config: Arc::new(config),
             web,
+            mailer,
         };
 
         for (tn, ti) in tenants {
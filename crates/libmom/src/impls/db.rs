@@ -51,3 +51,11 @@ fn mk_sqlite_pool(
 
     Ok(pool)
 }
+
+/// An in-memory database with every migration applied, for tests
+#[cfg(test)]
+pub(crate) fn test_conn() -> rusqlite::Connection {
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    migrations::migrate_all_sqlite(conn.transaction().unwrap(), all_migrations()).unwrap();
+    conn
+}
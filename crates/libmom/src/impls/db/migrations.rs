@@ -1,5 +1,5 @@
 use eyre::Result;
-use rusqlite::{named_params, Connection, Transaction};
+use rusqlite::{Connection, Transaction, named_params};
 use std::collections::HashSet;
 use time::OffsetDateTime;
 use tracing::info;
@@ -24,6 +24,17 @@ include_migrations! {
     m0006_revisions,
     m0007_objectstore_entries,
     m0008_objectstore_entries_rename,
+    m0009_comments,
+    m0010_webmentions,
+    m0011_magic_links,
+    m0012_linked_identities,
+    m0013_download_counts,
+    m0014_sponsor_credits,
+    m0015_webmentions_by_target,
+    m0016_magic_links_by_client,
+    m0017_identity_tiers,
+    m0018_sponsor_avatars_by_identity,
+    m0019_magic_link_default_names,
 }
 
 pub fn migrate_all_sqlite(
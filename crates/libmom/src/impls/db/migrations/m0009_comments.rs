@@ -0,0 +1,70 @@
+use rusqlite::Connection;
+
+pub struct Migration;
+
+impl super::SqlMigration for Migration {
+    fn tag(&self) -> &'static str {
+        "m0009_create_comments_tables"
+    }
+
+    fn up(&self, conn: &Connection) -> eyre::Result<()> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS comment_threads (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                route TEXT NOT NULL UNIQUE,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS comments (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                thread_id INTEGER NOT NULL REFERENCES comment_threads (id),
+                parent_id INTEGER REFERENCES comments (id),
+                author_id TEXT NOT NULL,
+                author_name TEXT NOT NULL,
+                author_thumb_url TEXT NOT NULL,
+                markdown TEXT NOT NULL,
+                html TEXT NOT NULL,
+                created_at TIMESTAMP NOT NULL,
+                edited_at TIMESTAMP,
+                deleted_at TIMESTAMP,
+                hidden BOOLEAN NOT NULL DEFAULT FALSE
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS comments_thread_id ON comments (thread_id)",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS comments_author_id ON comments (author_id)",
+            [],
+        )?;
+
+        // previous versions of a comment, one row per edit
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS comment_edits (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                comment_id INTEGER NOT NULL REFERENCES comments (id),
+                markdown TEXT NOT NULL,
+                edited_at TIMESTAMP NOT NULL
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS comment_bans (
+                global_id TEXT PRIMARY KEY,
+                reason TEXT,
+                banned_at TIMESTAMP NOT NULL
+            )",
+            [],
+        )?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+use rusqlite::Connection;
+
+pub struct Migration;
+
+impl super::SqlMigration for Migration {
+    fn tag(&self) -> &'static str {
+        "m0010_create_webmentions_tables"
+    }
+
+    fn up(&self, conn: &Connection) -> eyre::Result<()> {
+        // mentions of our pages by other sites
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS webmentions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                source TEXT NOT NULL,
+                target TEXT NOT NULL,
+                route TEXT NOT NULL,
+                status TEXT NOT NULL,
+                title TEXT,
+                received_at TIMESTAMP NOT NULL,
+                verified_at TIMESTAMP,
+                UNIQUE (source, target)
+            )",
+            [],
+        )?;
+
+        // mentions of other sites by our pages
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS sent_webmentions (
+                source TEXT NOT NULL,
+                target TEXT NOT NULL,
+                endpoint TEXT,
+                status TEXT NOT NULL,
+                sent_at TIMESTAMP NOT NULL,
+                PRIMARY KEY (source, target)
+            )",
+            [],
+        )?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+use rand::RngCore;
+use rusqlite::Connection;
+
+pub struct Migration;
+
+impl super::SqlMigration for Migration {
+    fn tag(&self) -> &'static str {
+        "m0011_create_magic_links_tables"
+    }
+
+    fn up(&self, conn: &Connection) -> eyre::Result<()> {
+        // people who signed in with an email address. `tier` is granted by
+        // hand (e.g. for folks who paid by other means), NULL means no tier.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS magic_link_users (
+                email TEXT PRIMARY KEY,
+                full_name TEXT NOT NULL,
+                tier TEXT,
+                created_at TIMESTAMP NOT NULL,
+                last_login_at TIMESTAMP
+            )",
+            [],
+        )?;
+
+        // every link we've sent, so each one only works once and so we can
+        // throttle requests per address
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS magic_link_tokens (
+                nonce TEXT PRIMARY KEY,
+                email TEXT NOT NULL,
+                created_at TIMESTAMP NOT NULL,
+                expires_at TIMESTAMP NOT NULL,
+                used_at TIMESTAMP
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_magic_link_tokens_email
+            ON magic_link_tokens (email, created_at)",
+            [],
+        )?;
+
+        // the key tokens are signed with, one per tenant
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS magic_link_keys (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                key BLOB NOT NULL
+            )",
+            [],
+        )?;
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        conn.execute(
+            "INSERT OR IGNORE INTO magic_link_keys (id, key) VALUES (1, ?1)",
+            [&key[..]],
+        )?;
+
+        Ok(())
+    }
+}
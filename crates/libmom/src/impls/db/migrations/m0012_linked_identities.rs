@@ -0,0 +1,30 @@
+use rusqlite::Connection;
+
+pub struct Migration;
+
+impl super::SqlMigration for Migration {
+    fn tag(&self) -> &'static str {
+        "m0012_create_linked_identities_table"
+    }
+
+    fn up(&self, conn: &Connection) -> eyre::Result<()> {
+        // identities (`patreon:1234`, `github:5678`, `email:a@b.c`) that
+        // belong to the same person. Identities that were never linked to
+        // anything don't have a row.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS linked_identities (
+                identity TEXT PRIMARY KEY,
+                account_id INTEGER NOT NULL,
+                linked_at TIMESTAMP NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_linked_identities_account_id
+            ON linked_identities (account_id)",
+            [],
+        )?;
+
+        Ok(())
+    }
+}
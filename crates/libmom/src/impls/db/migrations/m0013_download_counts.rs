@@ -0,0 +1,24 @@
+use rusqlite::Connection;
+
+pub struct Migration;
+
+impl super::SqlMigration for Migration {
+    fn tag(&self) -> &'static str {
+        "m0013_create_download_counts_table"
+    }
+
+    fn up(&self, conn: &Connection) -> eyre::Result<()> {
+        // how many times each page attachment (`extra-files/{path}`) was
+        // handed out
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS download_counts (
+                path TEXT PRIMARY KEY,
+                count INTEGER NOT NULL DEFAULT 0,
+                last_downloaded_at TIMESTAMP NOT NULL
+            )",
+            [],
+        )?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use rusqlite::Connection;
+
+pub struct Migration;
+
+impl super::SqlMigration for Migration {
+    fn tag(&self) -> &'static str {
+        "m0014_create_sponsor_credits_tables"
+    }
+
+    fn up(&self, conn: &Connection) -> eyre::Result<()> {
+        // sponsors who'd rather not be listed on the site, by identity
+        // (`patreon:1234`, `github:5678`)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS sponsor_opt_outs (
+                identity TEXT PRIMARY KEY,
+                opted_out_at TIMESTAMP NOT NULL
+            )",
+            [],
+        )?;
+
+        // avatars we've already copied to the object store, so we only
+        // fetch each one once
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS sponsor_avatars (
+                source_url TEXT PRIMARY KEY,
+                hash TEXT NOT NULL,
+                content_type TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                fetched_at TIMESTAMP NOT NULL
+            )",
+            [],
+        )?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,20 @@
+use rusqlite::Connection;
+
+pub struct Migration;
+
+impl super::SqlMigration for Migration {
+    fn tag(&self) -> &'static str {
+        "m0015_index_webmentions_by_target"
+    }
+
+    fn up(&self, conn: &Connection) -> eyre::Result<()> {
+        // to throttle how many mentions of one page get verified
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_webmentions_target
+            ON webmentions (target, received_at)",
+            [],
+        )?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+use rusqlite::Connection;
+
+pub struct Migration;
+
+impl super::SqlMigration for Migration {
+    fn tag(&self) -> &'static str {
+        "m0016_magic_links_by_client"
+    }
+
+    fn up(&self, conn: &Connection) -> eyre::Result<()> {
+        // to throttle how many links one client can ask for, across addresses
+        conn.execute(
+            "ALTER TABLE magic_link_tokens ADD COLUMN client_ip TEXT",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_magic_link_tokens_client_ip
+            ON magic_link_tokens (client_ip, created_at)",
+            [],
+        )?;
+
+        Ok(())
+    }
+}
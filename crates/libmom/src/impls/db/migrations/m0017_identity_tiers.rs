@@ -0,0 +1,24 @@
+use rusqlite::Connection;
+
+pub struct Migration;
+
+impl super::SqlMigration for Migration {
+    fn tag(&self) -> &'static str {
+        "m0017_identity_tiers"
+    }
+
+    fn up(&self, conn: &Connection) -> eyre::Result<()> {
+        // the last tier each identity's provider told us about, so linked
+        // accounts don't need to ask every provider on every login
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS identity_tiers (
+                identity TEXT PRIMARY KEY,
+                tier TEXT,
+                checked_at TIMESTAMP NOT NULL
+            )",
+            [],
+        )?;
+
+        Ok(())
+    }
+}
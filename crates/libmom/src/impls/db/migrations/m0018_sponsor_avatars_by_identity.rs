@@ -0,0 +1,28 @@
+use rusqlite::Connection;
+
+pub struct Migration;
+
+impl super::SqlMigration for Migration {
+    fn tag(&self) -> &'static str {
+        "m0018_sponsor_avatars_by_identity"
+    }
+
+    fn up(&self, conn: &Connection) -> eyre::Result<()> {
+        // avatar URLs rotate (Patreon signs them), so keying by URL meant
+        // fetching and storing the same picture over and over. The old rows
+        // can't be mapped back to a sponsor, they'll be fetched again.
+        conn.execute("DROP TABLE IF EXISTS sponsor_avatars", [])?;
+        conn.execute(
+            "CREATE TABLE sponsor_avatars (
+                identity TEXT PRIMARY KEY,
+                hash TEXT NOT NULL,
+                content_type TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                fetched_at TIMESTAMP NOT NULL
+            )",
+            [],
+        )?;
+
+        Ok(())
+    }
+}
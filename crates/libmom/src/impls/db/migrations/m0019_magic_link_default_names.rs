@@ -0,0 +1,34 @@
+use rusqlite::Connection;
+
+pub struct Migration;
+
+impl super::SqlMigration for Migration {
+    fn tag(&self) -> &'static str {
+        "m0019_magic_link_default_names"
+    }
+
+    fn up(&self, conn: &Connection) -> eyre::Result<()> {
+        // email users used to be named after the local part of their address,
+        // and shown with a Gravatar of it: both gave the address away, on
+        // comments too. Names they didn't pick go back to the default.
+        conn.execute(
+            "UPDATE magic_link_users SET full_name = 'Reader'
+            WHERE full_name = substr(email, 1, instr(email, '@') - 1)",
+            [],
+        )?;
+        conn.execute(
+            "UPDATE comments SET author_name = 'Reader'
+            WHERE author_id LIKE 'email:%'
+            AND author_name = substr(author_id, 7, instr(author_id, '@') - 7)",
+            [],
+        )?;
+        conn.execute(
+            "UPDATE comments SET author_thumb_url = ''
+            WHERE author_id LIKE 'email:%'
+            AND author_thumb_url LIKE 'https://www.gravatar.com/avatar/%'",
+            [],
+        )?;
+
+        Ok(())
+    }
+}
@@ -11,7 +11,7 @@ use tenant_extractor::TenantExtractor;
 use tokio::signal::unix::SignalKind;
 use tracing::{error, info, warn};
 
-use crate::impls::{MomGlobalState, global_state};
+use crate::impls::{MomGlobalState, global_state, webmentions};
 use mom_types::{GoodMorning, MomEvent, TenantInitialState};
 
 mod tenant;
@@ -174,6 +174,13 @@ async fn handle_socket(mut socket: ws::WebSocket) {
     for (tn, ts) in gs.tenants.iter() {
         let revision = ts.pak.lock().clone();
         let sponsors = ts.sponsors.lock().clone();
+        let webmentions = match webmentions::load_webmentions_from_db(ts) {
+            Ok(webmentions) => Some(webmentions),
+            Err(e) => {
+                tracing::error!("Failed to load webmentions for tenant {tn}: {e}");
+                None
+            }
+        };
         tracing::info!(
             "in good morning, for tenant {}, sending {} sponsors (-1 means None)",
             tn,
@@ -188,6 +195,7 @@ async fn handle_socket(mut socket: ws::WebSocket) {
             TenantInitialState {
                 pak: revision,
                 sponsors,
+                webmentions,
                 tc: ts.ti.tc.clone(),
                 base_dir: if is_development() {
                     // in dev, let mom and cub share a base directory
@@ -4,7 +4,7 @@ use axum::routing::get;
 use config_types::is_development;
 use libhttpclient::Uri;
 
-use crate::impls::{MomTenantState, global_state};
+use crate::impls::{MomTenantState, global_state, sponsors, webmentions};
 use axum::{Extension, Router};
 use axum::{
     body::Bytes,
@@ -19,15 +19,23 @@ use libpatreon::{
     PatreonRefreshCredentials, PatreonRefreshCredentialsArgs, PatreonStore,
 };
 use merde::IntoStatic;
-use mom_types::{ListMissingArgs, ListMissingResponse, TenantEventPayload};
+use mom_types::{
+    ListMissingArgs, ListMissingResponse, ReceiveWebmentionArgs, SendWebmentionsArgs,
+    SponsorOptOutArgs, TenantEventPayload,
+};
 use objectstore_types::{ObjectStoreKey, ObjectStoreKeyRef};
 
 use crate::impls::site::{HttpError, IntoReply, MerdeJson, Reply};
 
 use super::tenant_extractor::TenantExtractor;
 
+mod accounts;
+mod comments;
 mod derive;
+mod downloads;
+mod magic_link;
 mod media;
+mod preferences;
 
 pub fn tenant_routes() -> Router {
     Router::new()
@@ -37,7 +45,30 @@ pub fn tenant_routes() -> Router {
             post(patreon_refresh_credentials),
         )
         .route("/github/callback", post(github_callback))
+        .route("/magic-link/request", post(magic_link::request))
+        .route("/magic-link/callback", post(magic_link::callback))
+        .route(
+            "/magic-link/refresh-credentials",
+            post(magic_link::refresh_credentials),
+        )
+        .route("/magic-link/set-name", post(magic_link::set_name))
         .route("/auth-bundle/update", post(auth_bundle_update))
+        .route("/identities/link", post(accounts::link))
+        .route("/identities/list", post(accounts::list))
+        .route("/identities/unlink", post(accounts::unlink))
+        .route("/identities/all", post(accounts::all))
+        .route("/comments/list", post(comments::list))
+        .route("/comments/post", post(comments::post))
+        .route("/comments/edit", post(comments::edit))
+        .route("/comments/delete", post(comments::delete))
+        .route("/comments/moderate", post(comments::moderate))
+        .route("/preferences/get", post(preferences::get))
+        .route("/preferences/update", post(preferences::update))
+        .route("/downloads/record", post(downloads::record))
+        .route("/downloads/counts", post(downloads::counts))
+        .route("/sponsors/opt-out", post(sponsors_opt_out))
+        .route("/webmentions/receive", post(webmentions_receive))
+        .route("/webmentions/send", post(webmentions_send))
         .route("/objectstore/list-missing", post(objectstore_list_missing))
         .route("/objectstore/put/{*key}", put(objectstore_put_key))
         .route("/media/upload", get(media::upload))
@@ -73,6 +104,7 @@ async fn patreon_callback(
 
             let patreon_id = auth_bundle.user_info.profile.patreon_id.as_deref().unwrap();
             pool.save_patreon_credentials(patreon_id, &pat_creds)?;
+            let auth_bundle = accounts::resolve_linked(&ts, auth_bundle).await?;
             Some(PatreonCallbackResponse { auth_bundle })
         }
         None => None,
@@ -100,10 +132,8 @@ async fn patreon_refresh_credentials(
             )
         })?;
 
-    MerdeJson(PatreonRefreshCredentials {
-        auth_bundle: site_credentials,
-    })
-    .into_reply()
+    let auth_bundle = accounts::resolve_linked(&ts, site_credentials).await?;
+    MerdeJson(PatreonRefreshCredentials { auth_bundle }).into_reply()
 }
 
 async fn github_callback(
@@ -126,14 +156,17 @@ async fn github_callback(
             let (github_creds, site_creds) = mod_github.to_auth_bundle(&rc, web, creds).await?;
 
             // Save GitHub credentials to the database
-            let conn = ts.pool.get()?;
-            conn.execute(
-                "INSERT OR REPLACE INTO github_credentials (github_id, data) VALUES (?1, ?2)",
-                rusqlite::params![
-                    site_creds.user_info.profile.github_id,
-                    merde::json::to_string(&github_creds)?
-                ],
-            )?;
+            {
+                let conn = ts.pool.get()?;
+                conn.execute(
+                    "INSERT OR REPLACE INTO github_credentials (github_id, data) VALUES (?1, ?2)",
+                    rusqlite::params![
+                        site_creds.user_info.profile.github_id,
+                        merde::json::to_string(&github_creds)?
+                    ],
+                )?;
+            }
+            let site_creds = accounts::resolve_linked(&ts, site_creds).await?;
             Some(GitHubCallbackResponse {
                 auth_bundle: site_creds,
                 github_credentials: github_creds,
@@ -205,47 +238,99 @@ async fn auth_bundle_update(
 ) -> Reply {
     let auth_bundle: AuthBundle = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
 
-    let new_auth_bundle: AuthBundle = if let Some(patreon_id) =
-        auth_bundle.user_info.profile.patreon_id
-    {
-        let mod_patreon = libpatreon::load();
-        let pat_creds = {
-            let conn = ts.pool.get()?;
-            get_patreon_credentials(&conn, &patreon_id)?
-        };
-
-        let (_pat_creds, auth_bundle) = mod_patreon
-            .to_auth_bundle(
-                &ts.ti.tc,
-                &ts.rc()?,
-                pat_creds,
-                &ts.pool,
-                ForcePatreonRefresh::DontForceRefresh,
-            )
-            .await?;
-        auth_bundle
-    } else if let Some(github_id) = auth_bundle.user_info.profile.github_id {
-        let mod_github = libgithub::load();
-        let github_creds = {
-            let conn = ts.pool.get()?;
-            get_github_credentials(&conn, &github_id)?
-        };
-
-        let web = global_state().web;
-        let rc = ts.rc()?;
-        let (_gh_creds, auth_bundle) = mod_github.to_auth_bundle(&rc, web, github_creds).await?;
-        auth_bundle
-    } else {
+    let Ok(identity) = auth_bundle.user_info.profile.global_id() else {
         return HttpError::with_status(
             StatusCode::BAD_REQUEST,
-            "AuthBundle must contain either a patreon_id or github_id",
+            "AuthBundle must contain a patreon_id, github_id or email",
         )
         .into_reply();
     };
+    let new_auth_bundle = match fresh_auth_bundle(&ts, &identity).await {
+        Ok(fresh) => accounts::resolve_linked(&ts, fresh).await?,
+        Err(e) => accounts::resolve_linked_stale(&ts, auth_bundle, &identity, e).await?,
+    };
 
     MerdeJson(new_auth_bundle).into_reply()
 }
 
+/// Asks the identity's provider for an up-to-date auth bundle, using the
+/// credentials we stored when they logged in.
+async fn fresh_auth_bundle(ts: &MomTenantState, identity: &str) -> Result<AuthBundle, HttpError> {
+    let auth_bundle = match identity.split_once(':') {
+        Some(("patreon", patreon_id)) => {
+            let mod_patreon = libpatreon::load();
+            let pat_creds = {
+                let conn = ts.pool.get()?;
+                get_patreon_credentials(&conn, patreon_id)?
+            };
+
+            let (_pat_creds, auth_bundle) = mod_patreon
+                .to_auth_bundle(
+                    &ts.ti.tc,
+                    &ts.rc()?,
+                    pat_creds,
+                    &ts.pool,
+                    ForcePatreonRefresh::DontForceRefresh,
+                )
+                .await?;
+            auth_bundle
+        }
+        Some(("github", github_id)) => {
+            let mod_github = libgithub::load();
+            let github_creds = {
+                let conn = ts.pool.get()?;
+                get_github_credentials(&conn, github_id)?
+            };
+
+            let web = global_state().web;
+            let rc = ts.rc()?;
+            let (_gh_creds, auth_bundle) =
+                mod_github.to_auth_bundle(&rc, web, github_creds).await?;
+            auth_bundle
+        }
+        Some(("email", email)) => {
+            let conn = ts.pool.get()?;
+            magic_link::to_auth_bundle(&conn, email)?
+        }
+        _ => {
+            return Err(HttpError::with_status(
+                StatusCode::BAD_REQUEST,
+                format!("Unknown identity {identity:?}"),
+            ));
+        }
+    };
+    Ok(auth_bundle)
+}
+
+async fn sponsors_opt_out(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: SponsorOptOutArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+    sponsors::opt_out(ts, args)?;
+    StatusCode::OK.into_reply()
+}
+
+async fn webmentions_receive(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: ReceiveWebmentionArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+    webmentions::receive(ts, args)?;
+
+    // verification happens in the background
+    StatusCode::ACCEPTED.into_reply()
+}
+
+async fn webmentions_send(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: SendWebmentionsArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+    tokio::spawn(webmentions::send_all(ts, args));
+    StatusCode::ACCEPTED.into_reply()
+}
+
 async fn objectstore_list_missing(
     Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
     body: Bytes,
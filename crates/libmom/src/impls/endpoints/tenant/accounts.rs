@@ -0,0 +1,669 @@
+use std::collections::BTreeMap;
+
+use axum::{Extension, body::Bytes, http::StatusCode};
+use conflux::Route;
+use credentials::{AuthBundle, Profile, Tier};
+use merde::time::Rfc3339;
+use mom_types::{
+    CommentsUpdated, LinkIdentitiesArgs, LinkedAccount, LinkedIdentity, ListIdentitiesArgs,
+    TenantEventPayload, UnlinkIdentityArgs,
+};
+use rusqlite::{Connection, OptionalExtension, params};
+use time::{Duration, OffsetDateTime};
+
+use super::{MomTenantState, TenantExtractor, fresh_auth_bundle};
+use crate::impls::site::{HttpError, IntoReply, MerdeJson, Reply};
+
+/// Links the identities of two profiles (and everything already linked to
+/// either), then returns an auth bundle for the merged profile.
+pub(crate) async fn link(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: LinkIdentitiesArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+
+    let (global_id, routes) = {
+        let mut conn = ts.pool.get()?;
+        link_profiles(
+            &mut conn,
+            &args.current,
+            &args.new,
+            OffsetDateTime::now_utc(),
+        )?
+    };
+
+    tracing::info!(
+        "Linked {:?} and {:?}, now known as {global_id}",
+        args.current.identities(),
+        args.new.identities()
+    );
+    if !routes.is_empty() {
+        ts.broadcast_event(TenantEventPayload::CommentsUpdated(CommentsUpdated {
+            routes,
+        }))?;
+    }
+
+    let auth_bundle = fresh_auth_bundle(&ts, &global_id).await?;
+    MerdeJson(resolve_linked(&ts, auth_bundle).await?).into_reply()
+}
+
+/// Puts every identity of both profiles (and everything already linked to
+/// either) in the same account, and moves what we store about them to the
+/// account's global id. Returns that id, and the routes of comment threads
+/// that changed.
+fn link_profiles(
+    conn: &mut Connection,
+    current: &Profile,
+    new: &Profile,
+    now: OffsetDateTime,
+) -> Result<(String, Vec<Route>), HttpError> {
+    let current_id = current.global_id()?;
+    let new_id = new.global_id()?;
+
+    let mut all: Vec<String> = current.identities();
+    all.extend(new.identities());
+
+    let tx = conn.transaction()?;
+
+    let existing = linked_identities(&tx, &all)?;
+    all.extend(existing.iter().map(|row| row.identity.identity.clone()));
+    all.sort();
+    all.dedup();
+
+    // a profile only has room for one identity per provider
+    let mut providers: Vec<&str> = all
+        .iter()
+        .filter_map(|identity| identity.split_once(':').map(|(provider, _)| provider))
+        .collect();
+    providers.dedup();
+    if providers.len() != all.len() {
+        return Err(HttpError::with_status(
+            StatusCode::CONFLICT,
+            "Another account from the same provider is already linked",
+        ));
+    }
+
+    let account_id: i64 = match existing.first() {
+        Some(row) => row.account_id,
+        None => tx.query_row(
+            "SELECT COALESCE(MAX(account_id), 0) + 1 FROM linked_identities",
+            [],
+            |row| row.get(0),
+        )?,
+    };
+    for identity in &all {
+        tx.execute(
+            "INSERT INTO linked_identities (identity, account_id, linked_at)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT (identity) DO UPDATE SET account_id = excluded.account_id",
+            params![identity, account_id, now],
+        )?;
+    }
+
+    let global_id = global_id_of(&all)?;
+    let mut routes = Vec::new();
+    for old_id in [&current_id, &new_id] {
+        if *old_id != global_id {
+            routes.extend(rekey(&tx, old_id, &global_id)?);
+        }
+    }
+    tx.commit()?;
+    Ok((global_id, routes))
+}
+
+/// Lists the identities linked to someone (empty if they never linked any)
+pub(crate) async fn list(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: ListIdentitiesArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+    let conn = ts.pool.get()?;
+    let identities: Vec<LinkedIdentity> = linked_identities(&conn, &[args.global_id])?
+        .into_iter()
+        .map(|row| row.identity)
+        .collect();
+    MerdeJson(identities).into_reply()
+}
+
+/// Removes an identity from someone's account. Their comments and
+/// preferences stay with the identities that remain.
+pub(crate) async fn unlink(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: UnlinkIdentityArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+
+    let routes = {
+        let mut conn = ts.pool.get()?;
+        unlink_identity(&mut conn, &args.global_id, &args.identity)?
+    };
+
+    tracing::info!(
+        "Unlinked identity {} from {}",
+        args.identity,
+        args.global_id
+    );
+    if !routes.is_empty() {
+        ts.broadcast_event(TenantEventPayload::CommentsUpdated(CommentsUpdated {
+            routes,
+        }))?;
+    }
+    StatusCode::OK.into_reply()
+}
+
+/// Takes `identity` out of the account `global_id` belongs to. Returns the
+/// routes of comment threads that changed.
+fn unlink_identity(
+    conn: &mut Connection,
+    global_id: &str,
+    identity: &str,
+) -> Result<Vec<Route>, HttpError> {
+    let tx = conn.transaction()?;
+
+    let rows = linked_identities(&tx, &[global_id.to_owned()])?;
+    if !rows.iter().any(|row| row.identity.identity == identity) {
+        return Err(HttpError::with_status(
+            StatusCode::NOT_FOUND,
+            "That identity isn't linked to this account",
+        ));
+    }
+    let all: Vec<String> = rows.into_iter().map(|row| row.identity.identity).collect();
+    let remaining: Vec<String> = all
+        .iter()
+        .filter(|other| *other != identity)
+        .cloned()
+        .collect();
+    if remaining.is_empty() {
+        return Err(HttpError::with_status(
+            StatusCode::BAD_REQUEST,
+            "Can't unlink the only identity of an account",
+        ));
+    }
+
+    tx.execute(
+        "DELETE FROM linked_identities WHERE identity = ?1",
+        [identity],
+    )?;
+    if let [last] = &remaining[..] {
+        // nothing left to link it to
+        tx.execute("DELETE FROM linked_identities WHERE identity = ?1", [last])?;
+    }
+
+    let old_id = global_id_of(&all)?;
+    let new_id = global_id_of(&remaining)?;
+    let routes = if old_id != new_id {
+        rekey(&tx, &old_id, &new_id)?
+    } else {
+        Vec::new()
+    };
+    // a ban is for the person, not just for what's left of their account:
+    // the identity they took out stays banned on its own
+    tx.execute(
+        "INSERT OR IGNORE INTO comment_bans (global_id, reason, banned_at)
+        SELECT ?2, reason, banned_at FROM comment_bans WHERE global_id = ?1",
+        [&new_id, identity],
+    )?;
+    tx.commit()?;
+    Ok(routes)
+}
+
+/// Every account with linked identities, for admins
+pub(crate) async fn all(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    _body: Bytes,
+) -> Reply {
+    let conn = ts.pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT identity, account_id, linked_at FROM linked_identities
+        ORDER BY account_id, linked_at",
+    )?;
+    let rows = stmt.query_map([], row_to_linked)?;
+
+    let mut accounts: BTreeMap<i64, Vec<LinkedIdentity>> = Default::default();
+    for row in rows {
+        let row = row?;
+        accounts
+            .entry(row.account_id)
+            .or_default()
+            .push(row.identity);
+    }
+    let accounts: Vec<LinkedAccount> = accounts
+        .into_iter()
+        .map(|(account_id, identities)| LinkedAccount {
+            account_id,
+            identities,
+        })
+        .collect();
+    MerdeJson(accounts).into_reply()
+}
+
+/// Fills in every identity linked to the auth bundle's, and upgrades its tier
+/// to the highest one any of them is entitled to. The auth bundle must come
+/// straight from its provider: we remember its tier for when it's the linked
+/// one.
+pub(crate) async fn resolve_linked(
+    ts: &MomTenantState,
+    auth_bundle: AuthBundle,
+) -> Result<AuthBundle, HttpError> {
+    {
+        let conn = ts.pool.get()?;
+        let now = OffsetDateTime::now_utc();
+        for identity in auth_bundle.user_info.profile.identities() {
+            remember_tier(&conn, &identity, auth_bundle.user_info.tier.as_ref(), now)?;
+        }
+    }
+    add_linked(ts, auth_bundle).await
+}
+
+/// Like [resolve_linked], for when the provider of the auth bundle's identity
+/// couldn't be reached: keeps the profile they already had, with the last tier
+/// we know of, as long as that's recent enough — the same way we'd treat a
+/// linked identity. Returns the provider's error otherwise.
+pub(crate) async fn resolve_linked_stale(
+    ts: &MomTenantState,
+    mut auth_bundle: AuthBundle,
+    identity: &str,
+    err: HttpError,
+) -> Result<AuthBundle, HttpError> {
+    let remembered = {
+        let conn = ts.pool.get()?;
+        remembered_tier(&conn, identity)?
+    };
+    let Some(tier) = remembered_within(remembered, TIER_GRACE, OffsetDateTime::now_utc()) else {
+        return Err(err);
+    };
+    tracing::warn!("Could not refresh {identity}, keeping the tier we last saw: {err:?}");
+
+    let profile = &mut auth_bundle.user_info.profile;
+    for other in profile.identities() {
+        if other != identity {
+            profile.remove_identity(&other);
+        }
+    }
+    auth_bundle.user_info.tier = tier;
+    add_linked(ts, auth_bundle).await
+}
+
+async fn add_linked(
+    ts: &MomTenantState,
+    mut auth_bundle: AuthBundle,
+) -> Result<AuthBundle, HttpError> {
+    let own = auth_bundle.user_info.profile.identities();
+    let linked = {
+        let conn = ts.pool.get()?;
+        linked_identities(&conn, &own)?
+    };
+    if linked.is_empty() {
+        return Ok(auth_bundle);
+    }
+
+    let rc = ts.rc()?;
+    let rank = |tier: &Option<Tier>| {
+        tier.as_ref()
+            .and_then(|tier| rc.tier_by_title(&tier.title))
+            .map(|tier| tier.rank)
+            .unwrap_or_default()
+    };
+
+    let now = OffsetDateTime::now_utc();
+    for row in linked {
+        let identity = row.identity.identity;
+        if own.contains(&identity) {
+            continue;
+        }
+        auth_bundle.user_info.profile.set_identity(&identity)?;
+
+        match linked_tier(ts, &identity, now).await {
+            Ok(tier) => {
+                if rank(&tier) > rank(&auth_bundle.user_info.tier) {
+                    auth_bundle.user_info.tier = tier;
+                }
+            }
+            Err(e) => {
+                // one provider being down shouldn't lock people out
+                tracing::warn!("Could not check tier of linked identity {identity}: {e:?}");
+            }
+        }
+    }
+    Ok(auth_bundle)
+}
+
+/// How long we trust the tier a provider told us about before asking again
+const TIER_TTL: Duration = Duration::days(1);
+
+/// How long we keep using the last tier we know of when the provider can't be
+/// reached
+const TIER_GRACE: Duration = Duration::days(7);
+
+/// The tier of a linked identity: the one we remember if it's fresh enough,
+/// otherwise whatever its provider says now.
+async fn linked_tier(
+    ts: &MomTenantState,
+    identity: &str,
+    now: OffsetDateTime,
+) -> Result<Option<Tier>, HttpError> {
+    let remembered = {
+        let conn = ts.pool.get()?;
+        remembered_tier(&conn, identity)?
+    };
+    if let Some(tier) = remembered_within(remembered.clone(), TIER_TTL, now) {
+        return Ok(tier);
+    }
+
+    match fresh_auth_bundle(ts, identity).await {
+        Ok(fresh) => {
+            let conn = ts.pool.get()?;
+            remember_tier(&conn, identity, fresh.user_info.tier.as_ref(), now)?;
+            Ok(fresh.user_info.tier)
+        }
+        Err(e) => remembered_within(remembered, TIER_GRACE, now).ok_or(e),
+    }
+}
+
+/// The remembered tier, if we got it from the provider less than `max_age` ago
+fn remembered_within(
+    remembered: Option<(Option<Tier>, OffsetDateTime)>,
+    max_age: Duration,
+    now: OffsetDateTime,
+) -> Option<Option<Tier>> {
+    let (tier, checked_at) = remembered?;
+    (now - checked_at < max_age).then_some(tier)
+}
+
+fn remember_tier(
+    conn: &Connection,
+    identity: &str,
+    tier: Option<&Tier>,
+    now: OffsetDateTime,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO identity_tiers (identity, tier, checked_at) VALUES (?1, ?2, ?3)
+        ON CONFLICT (identity) DO UPDATE SET tier = excluded.tier, checked_at = excluded.checked_at",
+        params![identity, tier.map(|tier| &tier.title), now],
+    )?;
+    Ok(())
+}
+
+fn remembered_tier(
+    conn: &Connection,
+    identity: &str,
+) -> rusqlite::Result<Option<(Option<Tier>, OffsetDateTime)>> {
+    conn.query_row(
+        "SELECT tier, checked_at FROM identity_tiers WHERE identity = ?1",
+        [identity],
+        |row| {
+            let tier: Option<String> = row.get(0)?;
+            Ok((tier.map(|title| Tier { title }), row.get(1)?))
+        },
+    )
+    .optional()
+}
+
+struct LinkedRow {
+    account_id: i64,
+    identity: LinkedIdentity,
+}
+
+fn row_to_linked(row: &rusqlite::Row<'_>) -> rusqlite::Result<LinkedRow> {
+    Ok(LinkedRow {
+        account_id: row.get(1)?,
+        identity: LinkedIdentity {
+            identity: row.get(0)?,
+            linked_at: Rfc3339(row.get(2)?),
+        },
+    })
+}
+
+/// Returns every identity in the accounts any of `identities` belongs to
+fn linked_identities(conn: &Connection, identities: &[String]) -> rusqlite::Result<Vec<LinkedRow>> {
+    if identities.is_empty() {
+        return Ok(Vec::new());
+    }
+    let placeholders = (0..identities.len())
+        .map(|_| "?")
+        .collect::<Vec<_>>()
+        .join(",");
+    let query = format!(
+        "SELECT identity, account_id, linked_at FROM linked_identities
+        WHERE account_id IN (
+            SELECT account_id FROM linked_identities WHERE identity IN ({placeholders})
+        )
+        ORDER BY linked_at"
+    );
+    let mut stmt = conn.prepare(&query)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(identities), row_to_linked)?;
+    rows.collect()
+}
+
+/// The global id of a profile made of those identities
+fn global_id_of(identities: &[String]) -> eyre::Result<String> {
+    let mut profile = Profile::default();
+    for identity in identities {
+        profile.set_identity(identity)?;
+    }
+    profile.global_id()
+}
+
+/// Moves everything we store under one global id to another, returns the
+/// routes of comment threads that changed.
+fn rekey(conn: &Connection, from: &str, to: &str) -> rusqlite::Result<Vec<Route>> {
+    let routes = {
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT t.route FROM comments c
+            JOIN comment_threads t ON t.id = c.thread_id
+            WHERE c.author_id = ?1",
+        )?;
+        stmt.query_map([from], |row| row.get::<_, Route>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?
+    };
+    conn.execute(
+        "UPDATE comments SET author_id = ?2 WHERE author_id = ?1",
+        [from, to],
+    )?;
+
+    // if both had some, keep the ones of the identity we're moving to
+    for (table, column) in [("user_preferences", "id"), ("comment_bans", "global_id")] {
+        conn.execute(
+            &format!("UPDATE OR IGNORE {table} SET {column} = ?2 WHERE {column} = ?1"),
+            [from, to],
+        )?;
+        conn.execute(&format!("DELETE FROM {table} WHERE {column} = ?1"), [from])?;
+    }
+    Ok(routes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impls::db::test_conn;
+
+    fn profile(identities: &[&str]) -> Profile {
+        let mut profile = Profile::default();
+        for identity in identities {
+            profile.set_identity(identity).unwrap();
+        }
+        profile
+    }
+
+    fn account_of(conn: &Connection, identity: &str) -> Vec<String> {
+        let mut identities: Vec<String> = linked_identities(conn, &[identity.to_owned()])
+            .unwrap()
+            .into_iter()
+            .map(|row| row.identity.identity)
+            .collect();
+        identities.sort();
+        identities
+    }
+
+    fn author_of_comment(conn: &Connection) -> String {
+        conn.query_row("SELECT author_id FROM comments", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    fn status_of(err: HttpError) -> StatusCode {
+        match err {
+            HttpError::WithStatus { status_code, .. } => status_code,
+            HttpError::Internal { err } => panic!("unexpected internal error: {err}"),
+        }
+    }
+
+    #[test]
+    fn link_and_unlink() {
+        let mut conn = test_conn();
+        let now = OffsetDateTime::now_utc();
+        conn.execute(
+            "INSERT INTO comment_threads (route) VALUES ('/articles/hello')",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO comments (thread_id, author_id, author_name, author_thumb_url, markdown, html, created_at)
+            VALUES (1, 'email:a@example.com', 'a', '', 'hi', '<p>hi</p>', ?1)",
+            [now],
+        )
+        .unwrap();
+
+        // comments follow the account's global id
+        let (global_id, routes) = link_profiles(
+            &mut conn,
+            &profile(&["email:a@example.com"]),
+            &profile(&["patreon:1"]),
+            now,
+        )
+        .unwrap();
+        assert_eq!(global_id, "patreon:1");
+        assert_eq!(routes.len(), 1);
+        assert_eq!(author_of_comment(&conn), "patreon:1");
+
+        // linking to any identity of the account joins the whole account
+        let (global_id, routes) = link_profiles(
+            &mut conn,
+            &profile(&["github:2"]),
+            &profile(&["email:a@example.com"]),
+            now,
+        )
+        .unwrap();
+        assert_eq!(global_id, "patreon:1");
+        assert!(routes.is_empty());
+        assert_eq!(
+            account_of(&conn, "github:2"),
+            ["email:a@example.com", "github:2", "patreon:1"]
+        );
+
+        // one identity per provider
+        let err = link_profiles(
+            &mut conn,
+            &profile(&["patreon:1"]),
+            &profile(&["github:3"]),
+            now,
+        )
+        .unwrap_err();
+        assert_eq!(status_of(err), StatusCode::CONFLICT);
+        assert!(account_of(&conn, "github:3").is_empty());
+
+        // unlinking the main identity hands everything to the next one
+        let routes = unlink_identity(&mut conn, "patreon:1", "patreon:1").unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(author_of_comment(&conn), "github:2");
+        assert_eq!(
+            account_of(&conn, "github:2"),
+            ["email:a@example.com", "github:2"]
+        );
+        assert!(account_of(&conn, "patreon:1").is_empty());
+
+        let err = unlink_identity(&mut conn, "github:2", "patreon:1").unwrap_err();
+        assert_eq!(status_of(err), StatusCode::NOT_FOUND);
+
+        // an account of one isn't an account anymore
+        unlink_identity(&mut conn, "github:2", "email:a@example.com").unwrap();
+        assert!(account_of(&conn, "github:2").is_empty());
+        assert_eq!(author_of_comment(&conn), "github:2");
+    }
+
+    #[test]
+    fn bans_survive_unlinking() {
+        use super::super::comments::is_banned;
+
+        let mut conn = test_conn();
+        let now = OffsetDateTime::now_utc();
+        link_profiles(
+            &mut conn,
+            &profile(&["email:a@example.com"]),
+            &profile(&["github:2"]),
+            now,
+        )
+        .unwrap();
+        link_profiles(
+            &mut conn,
+            &profile(&["github:2"]),
+            &profile(&["patreon:1"]),
+            now,
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO comment_bans (global_id, reason, banned_at) VALUES ('patreon:1', 'spam', ?1)",
+            [now],
+        )
+        .unwrap();
+
+        // whichever identity they comment with next, alone or not: taking
+        // out the main one...
+        unlink_identity(&mut conn, "patreon:1", "patreon:1").unwrap();
+        assert!(is_banned(&conn, "patreon:1").unwrap());
+        assert!(is_banned(&conn, "github:2").unwrap());
+
+        // ...or another one
+        unlink_identity(&mut conn, "github:2", "email:a@example.com").unwrap();
+        assert!(is_banned(&conn, "email:a@example.com").unwrap());
+        assert!(is_banned(&conn, "github:2").unwrap());
+    }
+
+    #[test]
+    fn remembered_tiers() {
+        let conn = test_conn();
+        let now = OffsetDateTime::now_utc();
+        assert!(remembered_tier(&conn, "patreon:1").unwrap().is_none());
+
+        let silver = Tier {
+            title: "Silver".into(),
+        };
+        remember_tier(&conn, "patreon:1", Some(&silver), now).unwrap();
+        let remembered = remembered_tier(&conn, "patreon:1").unwrap();
+
+        let title = |tier: Option<Option<Tier>>| tier.map(|tier| tier.map(|tier| tier.title));
+        // trusted for a while, then we ask the provider again...
+        assert_eq!(
+            title(remembered_within(remembered.clone(), TIER_TTL, now)),
+            Some(Some("Silver".into()))
+        );
+        assert_eq!(
+            title(remembered_within(
+                remembered.clone(),
+                TIER_TTL,
+                now + TIER_TTL
+            )),
+            None
+        );
+        // ...but good enough if it can't be reached, for a while longer
+        assert_eq!(
+            title(remembered_within(
+                remembered.clone(),
+                TIER_GRACE,
+                now + TIER_TTL
+            )),
+            Some(Some("Silver".into()))
+        );
+        assert_eq!(
+            title(remembered_within(remembered, TIER_GRACE, now + TIER_GRACE)),
+            None
+        );
+
+        // not having a tier anymore is remembered too
+        remember_tier(&conn, "patreon:1", None, now).unwrap();
+        let remembered = remembered_tier(&conn, "patreon:1").unwrap();
+        assert_eq!(
+            title(remembered_within(remembered, TIER_TTL, now)),
+            Some(None)
+        );
+    }
+}
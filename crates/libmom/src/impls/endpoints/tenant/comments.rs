@@ -0,0 +1,320 @@
+use axum::{Extension, body::Bytes, http::StatusCode};
+use conflux::{Route, RouteRef};
+use merde::time::Rfc3339;
+use mom_types::{
+    Comment, CommentThread, CommentsUpdated, DeleteCommentArgs, EditCommentArgs, ListCommentsArgs,
+    ModerateCommentArgs, ModerationAction, PostCommentArgs, TenantEventPayload,
+};
+use rusqlite::{Connection, OptionalExtension, params};
+use time::OffsetDateTime;
+
+use super::{MomTenantState, TenantExtractor};
+use crate::impls::site::{HttpError, IntoReply, MerdeJson, Reply};
+
+/// Comments longer than this (in bytes of markdown) are rejected
+const MAX_COMMENT_LENGTH: usize = 10_000;
+
+const COMMENT_COLUMNS: &str = "c.id, c.parent_id, c.author_id, c.author_name, c.author_thumb_url, c.markdown, c.html, c.created_at, c.edited_at, c.deleted_at, c.hidden";
+
+pub(crate) async fn list(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: ListCommentsArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+    let conn = ts.pool.get()?;
+    MerdeJson(get_thread(&conn, &args.route)?).into_reply()
+}
+
+pub(crate) async fn post(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: PostCommentArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+    let author_id = args.author.global_id()?;
+    let html = render_comment(&args.markdown)?;
+
+    let comment = {
+        let mut conn = ts.pool.get()?;
+        if is_banned(&conn, &author_id)? {
+            return HttpError::with_status(StatusCode::FORBIDDEN, "You cannot comment anymore")
+                .into_reply();
+        }
+
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT OR IGNORE INTO comment_threads (route) VALUES (?1)",
+            [&args.route],
+        )?;
+        let thread_id: i64 = tx.query_row(
+            "SELECT id FROM comment_threads WHERE route = ?1",
+            [&args.route],
+            |row| row.get(0),
+        )?;
+
+        if let Some(parent_id) = args.parent_id {
+            let parent_thread_id: Option<i64> = tx
+                .query_row(
+                    "SELECT thread_id FROM comments WHERE id = ?1",
+                    [parent_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if parent_thread_id != Some(thread_id) {
+                return HttpError::with_status(
+                    StatusCode::BAD_REQUEST,
+                    "Parent comment not found in this thread",
+                )
+                .into_reply();
+            }
+        }
+
+        tx.execute(
+            "INSERT INTO comments
+            (thread_id, parent_id, author_id, author_name, author_thumb_url, markdown, html, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                thread_id,
+                args.parent_id,
+                author_id,
+                args.author.full_name,
+                args.author.thumb_url,
+                args.markdown,
+                html,
+                OffsetDateTime::now_utc(),
+            ],
+        )?;
+        let comment = get_comment(&tx, tx.last_insert_rowid())?;
+        tx.commit()?;
+        comment
+    };
+
+    notify_updated(&ts, vec![args.route])?;
+    MerdeJson(comment).into_reply()
+}
+
+pub(crate) async fn edit(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: EditCommentArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+    let html = render_comment(&args.markdown)?;
+
+    let (route, comment) = {
+        let mut conn = ts.pool.get()?;
+        if is_banned(&conn, &args.author_id)? {
+            return HttpError::with_status(StatusCode::FORBIDDEN, "You cannot comment anymore")
+                .into_reply();
+        }
+        check_author(&conn, args.comment_id, &args.author_id)?;
+
+        let now = OffsetDateTime::now_utc();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO comment_edits (comment_id, markdown, edited_at)
+            SELECT id, markdown, ?2 FROM comments WHERE id = ?1",
+            params![args.comment_id, now],
+        )?;
+        tx.execute(
+            "UPDATE comments SET markdown = ?2, html = ?3, edited_at = ?4 WHERE id = ?1",
+            params![args.comment_id, args.markdown, html, now],
+        )?;
+        let route = route_for_comment(&tx, args.comment_id)?;
+        let comment = get_comment(&tx, args.comment_id)?;
+        tx.commit()?;
+        (route, comment)
+    };
+
+    notify_updated(&ts, vec![route])?;
+    MerdeJson(comment).into_reply()
+}
+
+pub(crate) async fn delete(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: DeleteCommentArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+
+    let route = {
+        let conn = ts.pool.get()?;
+        check_author(&conn, args.comment_id, &args.author_id)?;
+        conn.execute(
+            "UPDATE comments SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+            params![args.comment_id, OffsetDateTime::now_utc()],
+        )?;
+        route_for_comment(&conn, args.comment_id)?
+    };
+
+    notify_updated(&ts, vec![route])?;
+    StatusCode::OK.into_reply()
+}
+
+pub(crate) async fn moderate(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: ModerateCommentArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+
+    let routes = {
+        let conn = ts.pool.get()?;
+        match args.action {
+            ModerationAction::Hide | ModerationAction::Unhide => {
+                let comment_id = args.comment_id.ok_or_else(|| {
+                    HttpError::with_status(StatusCode::BAD_REQUEST, "Missing comment_id")
+                })?;
+                conn.execute(
+                    "UPDATE comments SET hidden = ?2 WHERE id = ?1",
+                    params![comment_id, args.action == ModerationAction::Hide],
+                )?;
+                vec![route_for_comment(&conn, comment_id)?]
+            }
+            ModerationAction::Ban | ModerationAction::Unban => {
+                let global_id = match (args.global_id, args.comment_id) {
+                    (Some(global_id), _) => global_id,
+                    (None, Some(comment_id)) => author_of(&conn, comment_id)?,
+                    (None, None) => {
+                        return HttpError::with_status(
+                            StatusCode::BAD_REQUEST,
+                            "Missing global_id or comment_id",
+                        )
+                        .into_reply();
+                    }
+                };
+
+                if args.action == ModerationAction::Unban {
+                    conn.execute(
+                        "DELETE FROM comment_bans WHERE global_id = ?1",
+                        [&global_id],
+                    )?;
+                    vec![]
+                } else {
+                    tracing::info!("Banning {global_id} from commenting: {:?}", args.reason);
+                    conn.execute(
+                        "INSERT OR REPLACE INTO comment_bans (global_id, reason, banned_at) VALUES (?1, ?2, ?3)",
+                        params![global_id, args.reason, OffsetDateTime::now_utc()],
+                    )?;
+                    conn.execute(
+                        "UPDATE comments SET hidden = TRUE WHERE author_id = ?1",
+                        [&global_id],
+                    )?;
+
+                    let mut stmt = conn.prepare(
+                        "SELECT DISTINCT t.route FROM comments c
+                        JOIN comment_threads t ON t.id = c.thread_id
+                        WHERE c.author_id = ?1",
+                    )?;
+                    stmt.query_map([&global_id], |row| row.get::<_, Route>(0))?
+                        .collect::<Result<Vec<_>, _>>()?
+                }
+            }
+        }
+    };
+
+    if !routes.is_empty() {
+        notify_updated(&ts, routes)?;
+    }
+    StatusCode::OK.into_reply()
+}
+
+/// Renders a comment's markdown to HTML, keeping only harmless markup
+fn render_comment(markdown: &str) -> Result<String, HttpError> {
+    if markdown.trim().is_empty() {
+        return Err(HttpError::with_status(
+            StatusCode::BAD_REQUEST,
+            "Comment is empty",
+        ));
+    }
+    if markdown.len() > MAX_COMMENT_LENGTH {
+        return Err(HttpError::with_status(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Comments are limited to {MAX_COMMENT_LENGTH} bytes"),
+        ));
+    }
+
+    let html = libmarkdown::load().basic_markdown(markdown)?;
+    Ok(libhtmlrewrite::load().sanitize_html(&html))
+}
+
+fn notify_updated(ts: &MomTenantState, routes: Vec<Route>) -> eyre::Result<()> {
+    ts.broadcast_event(TenantEventPayload::CommentsUpdated(CommentsUpdated {
+        routes,
+    }))
+}
+
+fn get_thread(conn: &Connection, route: &RouteRef) -> rusqlite::Result<CommentThread> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {COMMENT_COLUMNS} FROM comments c
+        JOIN comment_threads t ON t.id = c.thread_id
+        WHERE t.route = ?1
+        ORDER BY c.created_at ASC, c.id ASC"
+    ))?;
+    let comments = stmt
+        .query_map([route], comment_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(CommentThread {
+        route: route.to_owned(),
+        comments,
+    })
+}
+
+fn get_comment(conn: &Connection, comment_id: i64) -> rusqlite::Result<Comment> {
+    conn.query_row(
+        &format!("SELECT {COMMENT_COLUMNS} FROM comments c WHERE c.id = ?1"),
+        [comment_id],
+        comment_from_row,
+    )
+}
+
+fn comment_from_row(row: &rusqlite::Row) -> rusqlite::Result<Comment> {
+    let deleted = row.get::<_, Option<OffsetDateTime>>(9)?.is_some();
+    Ok(Comment {
+        id: row.get(0)?,
+        parent_id: row.get(1)?,
+        author_id: row.get(2)?,
+        author_name: row.get(3)?,
+        author_thumb_url: row.get(4)?,
+        markdown: if deleted { String::new() } else { row.get(5)? },
+        html: if deleted { String::new() } else { row.get(6)? },
+        created_at: Rfc3339(row.get(7)?),
+        edited_at: row.get::<_, Option<OffsetDateTime>>(8)?.map(Rfc3339),
+        deleted,
+        hidden: row.get(10)?,
+    })
+}
+
+pub(super) fn is_banned(conn: &Connection, global_id: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM comment_bans WHERE global_id = ?1)",
+        [global_id],
+        |row| row.get(0),
+    )
+}
+
+fn author_of(conn: &Connection, comment_id: i64) -> Result<String, HttpError> {
+    conn.query_row(
+        "SELECT author_id FROM comments WHERE id = ?1",
+        [comment_id],
+        |row| row.get(0),
+    )
+    .optional()?
+    .ok_or_else(|| HttpError::with_status(StatusCode::NOT_FOUND, "Comment not found"))
+}
+
+fn check_author(conn: &Connection, comment_id: i64, author_id: &str) -> Result<(), HttpError> {
+    if author_of(conn, comment_id)? != author_id {
+        return Err(HttpError::with_status(
+            StatusCode::FORBIDDEN,
+            "You can only change your own comments",
+        ));
+    }
+    Ok(())
+}
+
+fn route_for_comment(conn: &Connection, comment_id: i64) -> rusqlite::Result<Route> {
+    conn.query_row(
+        "SELECT t.route FROM comments c
+        JOIN comment_threads t ON t.id = c.thread_id
+        WHERE c.id = ?1",
+        [comment_id],
+        |row| row.get(0),
+    )
+}
@@ -0,0 +1,49 @@
+use axum::{Extension, body::Bytes, http::StatusCode};
+use merde::time::Rfc3339;
+use mom_types::{DownloadCount, RecordDownloadArgs};
+use rusqlite::params;
+use time::OffsetDateTime;
+
+use super::TenantExtractor;
+use crate::impls::site::{IntoReply, MerdeJson, Reply};
+
+/// Counts one download of a page attachment. cub calls this every time it
+/// hands out a signed URL for one.
+pub(crate) async fn record(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: RecordDownloadArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+    let conn = ts.pool.get()?;
+    conn.execute(
+        "INSERT INTO download_counts (path, count, last_downloaded_at)
+        VALUES (?1, 1, ?2)
+        ON CONFLICT (path) DO UPDATE SET
+            count = count + 1,
+            last_downloaded_at = excluded.last_downloaded_at",
+        params![args.path, OffsetDateTime::now_utc()],
+    )?;
+    StatusCode::OK.into_reply()
+}
+
+/// Download counts for every attachment, most downloaded first, for admins
+pub(crate) async fn counts(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    _body: Bytes,
+) -> Reply {
+    let conn = ts.pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT path, count, last_downloaded_at FROM download_counts
+        ORDER BY count DESC, path",
+    )?;
+    let counts = stmt
+        .query_map([], |row| {
+            Ok(DownloadCount {
+                path: row.get(0)?,
+                count: row.get(1)?,
+                last_downloaded_at: Rfc3339(row.get(2)?),
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    MerdeJson(counts).into_reply()
+}
@@ -0,0 +1,475 @@
+use axum::{Extension, body::Bytes, http::StatusCode};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use credentials::{AuthBundle, Profile, Tier, UserInfo, normalize_email};
+use hmac::{Hmac, Mac};
+use libmail::Email;
+use mom_types::{
+    MagicLinkCallbackArgs, MagicLinkCallbackResponse, MagicLinkRefreshCredentialsArgs,
+    RequestMagicLinkArgs, SetMagicLinkNameArgs,
+};
+use rand::RngCore;
+use rusqlite::{Connection, OptionalExtension, params};
+use sha2::Sha256;
+use time::{Duration, OffsetDateTime};
+
+use super::TenantExtractor;
+use crate::impls::{
+    global_state,
+    site::{HttpError, IntoReply, MerdeJson, Reply},
+};
+
+/// How long a sign-in link works for
+const TOKEN_LIFETIME: Duration = Duration::minutes(15);
+
+/// Someone can only ask for one link per address this often
+const REQUEST_COOLDOWN: Duration = Duration::minutes(1);
+
+/// How many links one client can ask for in an hour, across addresses
+const MAX_REQUESTS_PER_CLIENT_PER_HOUR: i64 = 10;
+
+/// How long an auth bundle is good for before cub asks for a fresh one, the
+/// same as for Patreon logins.
+const SESSION_LIFETIME: Duration = Duration::days(1);
+
+/// What email users are called until they pick a name: anything we'd derive
+/// from their address would give it away.
+const DEFAULT_FULL_NAME: &str = "Reader";
+
+/// Longest name email users can pick, in characters
+const MAX_FULL_NAME_LENGTH: usize = 64;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Emails a sign-in link to the given address. Always answers 202 for valid
+/// addresses, even when throttled, so this can't be used to probe for accounts.
+pub(crate) async fn request(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: RequestMagicLinkArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+    let Some(email) = normalize_email(&args.email) else {
+        return HttpError::with_status(StatusCode::BAD_REQUEST, "Invalid email address")
+            .into_reply();
+    };
+
+    let token = {
+        let conn = ts.pool.get()?;
+        issue_token(
+            &conn,
+            &email,
+            args.client_ip.as_deref(),
+            OffsetDateTime::now_utc(),
+        )?
+    };
+    let Some(token) = token else {
+        tracing::info!("Not sending another sign-in link to {email} so soon");
+        return StatusCode::ACCEPTED.into_reply();
+    };
+
+    let gs = global_state();
+    let site = ts.ti.tc.name.to_string();
+    let link = format!(
+        "{}/login/email/callback?token={token}",
+        ts.ti.tc.web_base_url(gs.web)
+    );
+    gs.mailer
+        .send(Email {
+            from_name: Some(site.clone()),
+            to: email,
+            subject: format!("Sign in to {site}"),
+            text: format!(
+                "Hi!\n\nHere's your link to sign in to {site}:\n\n{link}\n\n\
+                It works once, for the next {} minutes. If you didn't ask for it, \
+                you can ignore this email.\n",
+                TOKEN_LIFETIME.whole_minutes()
+            ),
+        })
+        .await?;
+
+    StatusCode::ACCEPTED.into_reply()
+}
+
+/// Exchanges a token for an auth bundle. Returns `null` if the token is
+/// invalid, expired or was already used.
+pub(crate) async fn callback(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: MagicLinkCallbackArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+    let now = OffsetDateTime::now_utc();
+    let conn = ts.pool.get()?;
+
+    let claims = verify_token(&signing_key(&conn)?, &args.token, now);
+    let Some(claims) = claims else {
+        tracing::info!("Rejecting invalid or expired sign-in link");
+        return MerdeJson(None::<MagicLinkCallbackResponse>).into_reply();
+    };
+
+    let claimed = conn.execute(
+        "UPDATE magic_link_tokens SET used_at = ?3
+        WHERE nonce = ?1 AND email = ?2 AND used_at IS NULL",
+        params![claims.nonce, claims.email, now],
+    )?;
+    if claimed != 1 {
+        tracing::info!("Rejecting already-used sign-in link for {}", claims.email);
+        return MerdeJson(None::<MagicLinkCallbackResponse>).into_reply();
+    }
+
+    conn.execute(
+        "INSERT INTO magic_link_users (email, full_name, created_at, last_login_at)
+        VALUES (?1, ?2, ?3, ?3)
+        ON CONFLICT (email) DO UPDATE SET last_login_at = excluded.last_login_at",
+        params![claims.email, DEFAULT_FULL_NAME, now],
+    )?;
+
+    let auth_bundle = to_auth_bundle(&conn, &claims.email)?;
+    drop(conn);
+    let auth_bundle = super::accounts::resolve_linked(&ts, auth_bundle).await?;
+    tracing::info!(
+        "Email user \x1b[33m{}\x1b[0m (tier: \x1b[35m{:?}\x1b[0m) logged in",
+        claims.email,
+        auth_bundle.user_info.tier
+    );
+    MerdeJson(Some(MagicLinkCallbackResponse { auth_bundle })).into_reply()
+}
+
+/// Builds a new auth bundle for someone whose previous one expired, so tier
+/// changes (and accounts linked since) get picked up.
+pub(crate) async fn refresh_credentials(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: MagicLinkRefreshCredentialsArgs =
+        merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+    let auth_bundle = {
+        let conn = ts.pool.get()?;
+        to_auth_bundle(&conn, &args.email)?
+    };
+    let auth_bundle = super::accounts::resolve_linked(&ts, auth_bundle).await?;
+    MerdeJson(MagicLinkCallbackResponse { auth_bundle }).into_reply()
+}
+
+/// Sets the name someone who signed in with an email address goes by.
+pub(crate) async fn set_name(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: SetMagicLinkNameArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+    let Some(full_name) = normalize_full_name(&args.full_name) else {
+        return HttpError::with_status(StatusCode::BAD_REQUEST, "Invalid name").into_reply();
+    };
+
+    let updated = {
+        let conn = ts.pool.get()?;
+        conn.execute(
+            "UPDATE magic_link_users SET full_name = ?2 WHERE email = ?1",
+            params![args.email, full_name],
+        )?
+    };
+    if updated != 1 {
+        return HttpError::with_status(
+            StatusCode::NOT_FOUND,
+            format!("No email user found for {}", args.email),
+        )
+        .into_reply();
+    }
+    StatusCode::OK.into_reply()
+}
+
+/// Trims the name, and rejects empty ones, overly long ones, and ones with
+/// control characters.
+fn normalize_full_name(full_name: &str) -> Option<&str> {
+    let full_name = full_name.trim();
+    let valid = !full_name.is_empty()
+        && full_name.chars().count() <= MAX_FULL_NAME_LENGTH
+        && !full_name.chars().any(char::is_control);
+    valid.then_some(full_name)
+}
+
+/// Builds a fresh auth bundle for someone who signed in with an email
+/// address, picking up any tier they've been granted since.
+pub(crate) fn to_auth_bundle(conn: &Connection, email: &str) -> Result<AuthBundle, HttpError> {
+    let (full_name, tier): (String, Option<String>) = conn
+        .query_row(
+            "SELECT full_name, tier FROM magic_link_users WHERE email = ?1",
+            [email],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| {
+            HttpError::with_status(
+                StatusCode::UNAUTHORIZED,
+                format!("No email user found for {email}"),
+            )
+        })?;
+
+    Ok(AuthBundle {
+        expires_at: (OffsetDateTime::now_utc() + SESSION_LIFETIME).into(),
+        user_info: UserInfo {
+            profile: Profile {
+                patreon_id: None,
+                github_id: None,
+                email: Some(email.to_owned()),
+                full_name,
+                // no Gravatar: it's a hash of the address, which anyone can
+                // check guesses against
+                thumb_url: String::new(),
+            },
+            tier: tier.map(|title| Tier { title }),
+        },
+    })
+}
+
+/// Records a new token for `email` and returns it, unless one was issued
+/// very recently, or the client asked for too many of them.
+fn issue_token(
+    conn: &Connection,
+    email: &str,
+    client_ip: Option<&str>,
+    now: OffsetDateTime,
+) -> Result<Option<String>, HttpError> {
+    conn.execute(
+        "DELETE FROM magic_link_tokens WHERE expires_at < ?1",
+        [now - Duration::days(1)],
+    )?;
+
+    if let Some(client_ip) = client_ip {
+        let from_client: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM magic_link_tokens WHERE client_ip = ?1 AND created_at > ?2",
+            params![client_ip, now - Duration::hours(1)],
+            |row| row.get(0),
+        )?;
+        if from_client >= MAX_REQUESTS_PER_CLIENT_PER_HOUR {
+            tracing::warn!("Too many sign-in links requested from {client_ip}");
+            return Ok(None);
+        }
+    }
+
+    let recent: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM magic_link_tokens WHERE email = ?1 AND created_at > ?2",
+        params![email, now - REQUEST_COOLDOWN],
+        |row| row.get(0),
+    )?;
+    if recent > 0 {
+        return Ok(None);
+    }
+
+    let mut nonce = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let claims = TokenClaims {
+        nonce: URL_SAFE_NO_PAD.encode(nonce),
+        email: email.to_owned(),
+        expires_at: now + TOKEN_LIFETIME,
+    };
+    conn.execute(
+        "INSERT INTO magic_link_tokens (nonce, email, client_ip, created_at, expires_at)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            claims.nonce,
+            claims.email,
+            client_ip,
+            now,
+            claims.expires_at
+        ],
+    )?;
+
+    Ok(Some(sign_token(&signing_key(conn)?, &claims)))
+}
+
+fn signing_key(conn: &Connection) -> Result<Vec<u8>, HttpError> {
+    Ok(
+        conn.query_row("SELECT key FROM magic_link_keys WHERE id = 1", [], |row| {
+            row.get(0)
+        })?,
+    )
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct TokenClaims {
+    nonce: String,
+    email: String,
+    expires_at: OffsetDateTime,
+}
+
+impl TokenClaims {
+    fn payload(&self) -> String {
+        format!(
+            "{}\n{}\n{}",
+            self.nonce,
+            self.expires_at.unix_timestamp(),
+            self.email
+        )
+    }
+}
+
+/// Tokens look like `base64(payload).base64(hmac(payload))`
+fn sign_token(key: &[u8], claims: &TokenClaims) -> String {
+    let payload = claims.payload();
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(payload.as_bytes());
+    format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(payload),
+        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    )
+}
+
+/// Checks the token's signature and expiry (but not whether it was used)
+fn verify_token(key: &[u8], token: &str, now: OffsetDateTime) -> Option<TokenClaims> {
+    let (payload, signature) = token.split_once('.')?;
+    let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+    let mut mac = HmacSha256::new_from_slice(key).ok()?;
+    mac.update(&payload);
+    mac.verify_slice(&signature).ok()?;
+
+    let payload = String::from_utf8(payload).ok()?;
+    let mut parts = payload.splitn(3, '\n');
+    let nonce = parts.next()?.to_owned();
+    let expires_at = OffsetDateTime::from_unix_timestamp(parts.next()?.parse().ok()?).ok()?;
+    let email = parts.next()?.to_owned();
+    if expires_at < now {
+        return None;
+    }
+
+    Some(TokenClaims {
+        nonce,
+        email,
+        expires_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(now: OffsetDateTime) -> TokenClaims {
+        TokenClaims {
+            nonce: "abc".into(),
+            email: "reader@example.com".into(),
+            expires_at: (now + TOKEN_LIFETIME).replace_nanosecond(0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn token_round_trip() {
+        let key = b"key";
+        let now = OffsetDateTime::now_utc();
+        let token = sign_token(key, &claims(now));
+
+        assert_eq!(verify_token(key, &token, now), Some(claims(now)));
+        assert_eq!(verify_token(b"other key", &token, now), None);
+        assert_eq!(
+            verify_token(key, &token, now + TOKEN_LIFETIME + Duration::seconds(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn tampered_token() {
+        let key = b"key";
+        let now = OffsetDateTime::now_utc();
+        let token = sign_token(key, &claims(now));
+        let (_, signature) = token.split_once('.').unwrap();
+
+        let forged = TokenClaims {
+            email: "admin@example.com".into(),
+            ..claims(now)
+        };
+        let forged = format!("{}.{signature}", URL_SAFE_NO_PAD.encode(forged.payload()));
+        assert_eq!(verify_token(key, &forged, now), None);
+        assert_eq!(verify_token(key, "garbage", now), None);
+    }
+
+    #[test]
+    fn full_names() {
+        assert_eq!(normalize_full_name("  Amos  "), Some("Amos"));
+        assert_eq!(
+            normalize_full_name("Émilie Du Châtelet"),
+            Some("Émilie Du Châtelet")
+        );
+        assert_eq!(normalize_full_name("   "), None);
+        assert_eq!(normalize_full_name("Amos\nAdmin"), None);
+        assert_eq!(
+            normalize_full_name(&"a".repeat(MAX_FULL_NAME_LENGTH + 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn profiles_dont_give_the_address_away() {
+        let conn = crate::impls::db::test_conn();
+        conn.execute(
+            "INSERT INTO magic_link_users (email, full_name, created_at) VALUES (?1, ?2, ?3)",
+            params![
+                "amos@example.com",
+                DEFAULT_FULL_NAME,
+                OffsetDateTime::now_utc()
+            ],
+        )
+        .unwrap();
+
+        let profile = to_auth_bundle(&conn, "amos@example.com")
+            .unwrap()
+            .user_info
+            .profile;
+        assert_eq!(profile.full_name, DEFAULT_FULL_NAME);
+        assert_eq!(profile.thumb_url, "");
+    }
+
+    #[test]
+    fn throttles_per_address_and_client() {
+        let conn = crate::impls::db::test_conn();
+        let now = OffsetDateTime::now_utc();
+
+        assert!(
+            issue_token(&conn, "a@example.com", Some("192.0.2.1"), now)
+                .unwrap()
+                .is_some()
+        );
+        // same address, too soon
+        assert!(
+            issue_token(&conn, "a@example.com", Some("192.0.2.2"), now)
+                .unwrap()
+                .is_none()
+        );
+        let later = now + REQUEST_COOLDOWN + Duration::seconds(1);
+        assert!(
+            issue_token(&conn, "a@example.com", None, later)
+                .unwrap()
+                .is_some()
+        );
+
+        // one client going through many addresses
+        for i in 1..MAX_REQUESTS_PER_CLIENT_PER_HOUR {
+            let email = format!("spray{i}@example.com");
+            assert!(
+                issue_token(&conn, &email, Some("192.0.2.1"), now)
+                    .unwrap()
+                    .is_some()
+            );
+        }
+        assert!(
+            issue_token(&conn, "victim@example.com", Some("192.0.2.1"), now)
+                .unwrap()
+                .is_none()
+        );
+        // ...doesn't affect other clients, and wears off
+        assert!(
+            issue_token(&conn, "victim@example.com", Some("192.0.2.3"), now)
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            issue_token(
+                &conn,
+                "other@example.com",
+                Some("192.0.2.1"),
+                now + Duration::hours(1)
+            )
+            .unwrap()
+            .is_some()
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use axum::{Extension, body::Bytes};
+use credentials::{UserPreferences, UserPreferencesPatch};
+use mom_types::{
+    GetUserPreferencesArgs, TenantEventPayload, UpdateUserPreferencesArgs, UserPreferencesUpdated,
+};
+use rusqlite::{Connection, OptionalExtension};
+
+use super::TenantExtractor;
+use crate::impls::site::{HttpError, IntoReply, MerdeJson, Reply};
+
+pub(crate) async fn get(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: GetUserPreferencesArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+    let conn = ts.pool.get()?;
+    MerdeJson(load_preferences(&conn, &args.global_id)?).into_reply()
+}
+
+pub(crate) async fn update(
+    Extension(TenantExtractor(ts)): Extension<TenantExtractor>,
+    body: Bytes,
+) -> Reply {
+    let args: UpdateUserPreferencesArgs = merde::json::from_str(std::str::from_utf8(&body[..])?)?;
+
+    let preferences = {
+        let conn = ts.pool.get()?;
+        let mut preferences = load_preferences(&conn, &args.global_id)?;
+        preferences.apply(args.patch).map_err(|e| {
+            HttpError::with_status(axum::http::StatusCode::BAD_REQUEST, e.to_string())
+        })?;
+        conn.execute(
+            "INSERT OR REPLACE INTO user_preferences (id, data) VALUES (?1, ?2)",
+            [&args.global_id, &merde::json::to_string(&preferences)?],
+        )?;
+        preferences
+    };
+
+    ts.broadcast_event(TenantEventPayload::UserPreferencesUpdated(
+        UserPreferencesUpdated {
+            global_id: args.global_id,
+            preferences: preferences.clone(),
+        },
+    ))?;
+    MerdeJson(preferences).into_reply()
+}
+
+/// Returns someone's preferences, or the defaults if they never saved any
+fn load_preferences(conn: &Connection, global_id: &str) -> Result<UserPreferences, HttpError> {
+    let data: Option<String> = conn
+        .query_row(
+            "SELECT data FROM user_preferences WHERE id = ?1",
+            [global_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    let mut preferences = UserPreferences::default();
+    if let Some(data) = data {
+        let stored: UserPreferencesPatch = merde::json::from_str(&data)?;
+        preferences.apply(stored)?;
+    }
+    Ok(preferences)
+}
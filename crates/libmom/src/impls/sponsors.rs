@@ -1,12 +1,31 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, sync::Arc};
 
-use futures_util::TryFutureExt;
+use conflux::{Derivation, DerivationBitmap, DerivationKind, Input, InputHash, InputPath};
+use content_type::ContentType;
+use credentials::CreditedSponsor;
+use derivations::DerivationInfo;
+use futures_util::{StreamExt, TryFutureExt};
+use image_types::{ICodec, IntrinsicPixels};
 use libgithub::GitHubCredentials;
-use libhttpclient::HttpClient;
-use merde::IntoStatic;
+use libhttpclient::{HttpClient, Uri};
+use merde::{IntoStatic, time::Rfc3339};
+use rusqlite::{Connection, OptionalExtension, params};
+use time::{Duration, OffsetDateTime};
 
-use crate::impls::{MomTenantState, global_state};
-use mom_types::Sponsors;
+use crate::impls::{MomTenantState, global_state, save_sponsors_to_db};
+use mom_types::{
+    DeriveParams, Sponsor, SponsorAvatar, SponsorOptOutArgs, Sponsors, TenantEventPayload,
+};
+
+/// Avatars are shown small, no need to keep them any bigger than this
+const AVATAR_WIDTH: u32 = 128;
+
+/// Avatar URLs rotate (Patreon signs them), so we go by how old our copy is
+/// rather than by which URL it came from
+const AVATAR_MAX_AGE: Duration = Duration::days(7);
+
+/// How many avatars we fetch at once
+const AVATAR_CONCURRENCY: usize = 8;
 
 pub(crate) async fn get_sponsors(ts: &MomTenantState) -> eyre::Result<Sponsors> {
     let client = global_state().client.clone();
@@ -17,18 +36,299 @@ pub(crate) async fn get_sponsors(ts: &MomTenantState) -> eyre::Result<Sponsors>
     )
     .await?;
 
-    let sponsors = gh_sponsors
+    let opted_out = opted_out_identities(ts)?;
+    let rc = ts.rc()?;
+    let rank = |sponsor: &Sponsor| {
+        sponsor
+            .tier
+            .as_deref()
+            .and_then(|title| rc.tier_by_title(title))
+            .map(|tier| tier.rank)
+            .unwrap_or_default()
+    };
+
+    let mut seen: HashSet<String> = Default::default();
+    let credited: Vec<CreditedSponsor> = gh_sponsors
         .into_iter()
-        .chain(patreon_sponsors.into_iter())
+        .chain(patreon_sponsors)
+        .filter(|credited| {
+            !opted_out.contains(&credited.identity) && seen.insert(credited.identity.clone())
+        })
         .collect();
 
+    let client = client.as_ref();
+    let now = OffsetDateTime::now_utc();
+    let avatars: Vec<Option<SponsorAvatar>> = futures_util::stream::iter(&credited)
+        .map(|credited| async move {
+            let url = credited.avatar_source_url.as_deref()?;
+            match sponsor_avatar(ts, client, &credited.identity, url, now).await {
+                Ok(avatar) => avatar,
+                Err(e) => {
+                    tracing::warn!("Could not fetch avatar for {}: {e}", credited.name);
+                    None
+                }
+            }
+        })
+        .buffered(AVATAR_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut sponsors: Vec<Sponsor> = credited
+        .into_iter()
+        .zip(avatars)
+        .map(|(credited, avatar)| to_sponsor(&rc, credited, avatar))
+        .collect();
+
+    sponsors.sort_by(|a, b| {
+        rank(b)
+            .cmp(&rank(a))
+            .then_with(|| joined_key(a).cmp(&joined_key(b)))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
     Ok(Sponsors { sponsors })
 }
 
+/// Longest-standing first, then those we don't know about
+fn joined_key(sponsor: &Sponsor) -> (bool, Option<OffsetDateTime>) {
+    let joined_at = sponsor.joined_at.as_ref().map(|d| d.0);
+    (joined_at.is_none(), joined_at)
+}
+
+fn to_sponsor(
+    rc: &config_types::RevisionConfig,
+    credited: CreditedSponsor,
+    avatar: Option<SponsorAvatar>,
+) -> Sponsor {
+    let provider = credited
+        .identity
+        .split_once(':')
+        .map(|(provider, _)| provider.to_string())
+        .unwrap_or_default();
+    Sponsor {
+        name: credited.name,
+        // Patreon gives us its own tier titles, show the canonical name instead
+        tier: credited
+            .tier_title
+            .map(|title| match rc.tier_by_title(&title) {
+                Some(tier) => tier.name.clone(),
+                None => title,
+            }),
+        provider,
+        identity: credited.identity,
+        avatar,
+        profile_url: credited.profile_url,
+        joined_at: credited.joined_at.map(Rfc3339),
+    }
+}
+
+/// Copies a sponsor's avatar to the object store, and returns the derivation
+/// that resizes it. Returns `None` for formats we can't resize.
+///
+/// Avatars are stored by content hash, so fetching the same picture again
+/// (say, from a freshly signed URL) doesn't store another copy.
+async fn sponsor_avatar(
+    ts: &MomTenantState,
+    client: &dyn HttpClient,
+    identity: &str,
+    source_url: &str,
+    now: OffsetDateTime,
+) -> eyre::Result<Option<SponsorAvatar>> {
+    let cached = {
+        let conn = ts.pool.get()?;
+        cached_avatar(&conn, identity, now)?
+    };
+
+    let input = match cached {
+        Some(input) => input,
+        None => {
+            let res = client
+                .get(source_url.parse::<Uri>()?)
+                .polite_user_agent()
+                .send_and_expect_200()
+                .await?;
+            let bytes = res.bytes().await?;
+            let Some(content_type) = sniff_image(&bytes) else {
+                tracing::debug!("Avatar at {source_url} isn't a PNG, JPEG or WebP, skipping");
+                return Ok(None);
+            };
+
+            let h = seahash::hash(&bytes);
+            let hash = InputHash::new(format!("{h:016x}"));
+            let input = avatar_input(hash, content_type, bytes.len() as u64, now);
+
+            let stored = {
+                let conn = ts.pool.get()?;
+                conn.query_row(
+                    "SELECT 1 FROM objectstore_entries WHERE key = ?1",
+                    [&input.key()],
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_some()
+            };
+            if !stored {
+                ts.object_store.put(&input.key(), bytes.into()).await?;
+            }
+
+            let conn = ts.pool.get()?;
+            conn.execute(
+                "INSERT OR REPLACE INTO objectstore_entries (key) VALUES (?1)",
+                [&input.key()],
+            )?;
+            remember_avatar(&conn, identity, &input)?;
+            input
+        }
+    };
+
+    let derivation = Derivation {
+        input: input.path.clone(),
+        kind: DerivationKind::Bitmap(DerivationBitmap {
+            ic: ICodec::WEBP,
+            width: Some(IntrinsicPixels::from(AVATAR_WIDTH)),
+        }),
+    };
+    let route = DerivationInfo::new(&input, &derivation).route();
+    Ok(Some(SponsorAvatar {
+        params: DeriveParams { input, derivation },
+        route,
+    }))
+}
+
+/// The avatar we last copied for this identity, unless it's older than
+/// `AVATAR_MAX_AGE`
+fn cached_avatar(
+    conn: &Connection,
+    identity: &str,
+    now: OffsetDateTime,
+) -> eyre::Result<Option<Input>> {
+    let cached: Option<(String, String, u64, OffsetDateTime)> = conn
+        .query_row(
+            "SELECT hash, content_type, size, fetched_at FROM sponsor_avatars WHERE identity = ?1",
+            [identity],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .optional()?;
+    let Some((hash, content_type, size, fetched_at)) = cached else {
+        return Ok(None);
+    };
+    if now - fetched_at >= AVATAR_MAX_AGE {
+        return Ok(None);
+    }
+    Ok(ContentType::guess_from_path(&format!(".{content_type}"))
+        .map(|content_type| avatar_input(InputHash::new(hash), content_type, size, fetched_at)))
+}
+
+fn remember_avatar(conn: &Connection, identity: &str, input: &Input) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO sponsor_avatars (identity, hash, content_type, size, fetched_at)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            identity,
+            input.hash.as_str(),
+            input.content_type.ext(),
+            input.size,
+            input.mtime.0
+        ],
+    )?;
+    Ok(())
+}
+
+fn avatar_input(
+    hash: InputHash,
+    content_type: ContentType,
+    size: u64,
+    mtime: OffsetDateTime,
+) -> Input {
+    Input {
+        path: InputPath::new(format!("/sponsor-avatars/{hash}.{}", content_type.ext())),
+        hash,
+        mtime: Rfc3339(mtime),
+        size,
+        content_type,
+    }
+}
+
+/// Avatar URLs don't always have an extension, so look at the bytes
+fn sniff_image(bytes: &[u8]) -> Option<ContentType> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(ContentType::PNG)
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        Some(ContentType::JPG)
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some(ContentType::WEBP)
+    } else {
+        None
+    }
+}
+
+fn opted_out_identities(ts: &MomTenantState) -> eyre::Result<HashSet<String>> {
+    let conn = ts.pool.get()?;
+    let mut stmt = conn.prepare("SELECT identity FROM sponsor_opt_outs")?;
+    let identities = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<HashSet<String>>>()?;
+    Ok(identities)
+}
+
+/// Records whether sponsors want to be credited on the site. Applies to
+/// every identity they have, since they may sponsor through several providers.
+pub(crate) fn opt_out(ts: Arc<MomTenantState>, args: SponsorOptOutArgs) -> eyre::Result<()> {
+    {
+        let mut conn = ts.pool.get()?;
+        let tx = conn.transaction()?;
+        let now = OffsetDateTime::now_utc();
+        for identity in &args.identities {
+            if args.opt_out {
+                tx.execute(
+                    "INSERT OR IGNORE INTO sponsor_opt_outs (identity, opted_out_at) VALUES (?1, ?2)",
+                    params![identity, now],
+                )?;
+            } else {
+                tx.execute(
+                    "DELETE FROM sponsor_opt_outs WHERE identity = ?1",
+                    [identity],
+                )?;
+            }
+        }
+        tx.commit()?;
+    }
+    tracing::info!(
+        "Identities {:?} opted {} of sponsor credits",
+        args.identities,
+        if args.opt_out { "out" } else { "back in" }
+    );
+
+    if args.opt_out {
+        // no need to ask the providers again to hide someone
+        let sponsors = {
+            let mut cached = ts.sponsors.lock();
+            cached.as_mut().map(|sponsors| {
+                sponsors
+                    .sponsors
+                    .retain(|s| !args.identities.contains(&s.identity));
+                sponsors.clone()
+            })
+        };
+        if let Some(sponsors) = sponsors {
+            save_sponsors_to_db(&ts, sponsors.clone())?;
+            ts.broadcast_event(TenantEventPayload::SponsorsUpdated(sponsors))?;
+        }
+    } else {
+        // we dropped their details when they opted out, so fetch everything again
+        tokio::spawn(async move {
+            if let Err(e) = ts.sponsors_inflight.query(()).await {
+                tracing::warn!("Failed to refresh sponsors after opt-in: {e}");
+            }
+        });
+    }
+    Ok(())
+}
+
 async fn patreon_list_sponsors(
     ts: &MomTenantState,
     client: &dyn HttpClient,
-) -> eyre::Result<HashSet<String>> {
+) -> eyre::Result<Vec<CreditedSponsor>> {
     let patreon = libpatreon::load();
     let rc = ts.rc()?;
     patreon.list_sponsors(&rc, client, &ts.pool).await
@@ -37,7 +337,7 @@ async fn patreon_list_sponsors(
 async fn github_list_sponsors(
     ts: &MomTenantState,
     client: &dyn HttpClient,
-) -> eyre::Result<HashSet<String>> {
+) -> eyre::Result<Vec<CreditedSponsor>> {
     let github_credentials: String = {
         let conn = ts.pool.get()?;
         let mut stmt = conn.prepare(
@@ -60,8 +360,67 @@ async fn github_list_sponsors(
     };
     let github_credentials = merde::json::from_str_owned::<GitHubCredentials>(&github_credentials)
         .map_err(|e| e.into_static())?;
+    let rc = ts.rc()?;
     let github = libgithub::load();
-    github
-        .list_sponsors(&ts.ti.tc, client, &github_credentials)
-        .await
+    github.list_sponsors(&rc, client, &github_credentials).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impls::db::test_conn;
+
+    #[test]
+    fn sniffs_avatar_formats() {
+        assert_eq!(sniff_image(b"\x89PNG\r\n\x1a\n..."), Some(ContentType::PNG));
+        assert_eq!(sniff_image(b"\xff\xd8\xff\xe0..."), Some(ContentType::JPG));
+        assert_eq!(
+            sniff_image(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some(ContentType::WEBP)
+        );
+        assert!(sniff_image(b"RIFF").is_none());
+        assert!(sniff_image(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>").is_none());
+    }
+
+    #[test]
+    fn avatars_are_remembered_per_identity() {
+        let conn = test_conn();
+        let now = OffsetDateTime::now_utc();
+        assert!(cached_avatar(&conn, "patreon:1", now).unwrap().is_none());
+
+        let first = avatar_input(InputHash::new("aaaa".into()), ContentType::PNG, 10, now);
+        remember_avatar(&conn, "patreon:1", &first).unwrap();
+        let cached = cached_avatar(&conn, "patreon:1", now).unwrap().unwrap();
+        assert_eq!(cached.key(), first.key());
+        assert_eq!(cached.size, 10);
+        assert!(cached_avatar(&conn, "github:1", now).unwrap().is_none());
+
+        // a new picture replaces the old one rather than piling up
+        let second = avatar_input(InputHash::new("bbbb".into()), ContentType::JPG, 20, now);
+        remember_avatar(&conn, "patreon:1", &second).unwrap();
+        let cached = cached_avatar(&conn, "patreon:1", now).unwrap().unwrap();
+        assert_eq!(cached.key(), second.key());
+        let rows: u64 = conn
+            .query_row("SELECT COUNT(*) FROM sponsor_avatars", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 1);
+    }
+
+    #[test]
+    fn stale_avatars_are_fetched_again() {
+        let conn = test_conn();
+        let fetched_at = OffsetDateTime::now_utc() - AVATAR_MAX_AGE;
+        let input = avatar_input(
+            InputHash::new("aaaa".into()),
+            ContentType::PNG,
+            10,
+            fetched_at,
+        );
+        remember_avatar(&conn, "patreon:1", &input).unwrap();
+
+        let soon = fetched_at + Duration::hours(1);
+        assert!(cached_avatar(&conn, "patreon:1", soon).unwrap().is_some());
+        let later = fetched_at + AVATAR_MAX_AGE;
+        assert!(cached_avatar(&conn, "patreon:1", later).unwrap().is_none());
+    }
 }
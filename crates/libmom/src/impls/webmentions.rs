@@ -0,0 +1,739 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+
+use conflux::Route;
+use futures_util::StreamExt;
+use libhttpclient::{ClientOpts, HttpClient, Response, StatusCode, Uri};
+use merde::time::Rfc3339;
+use mom_types::{
+    ReceiveWebmentionArgs, SendWebmentionsArgs, TenantEventPayload, Webmention, Webmentions,
+};
+use rusqlite::{Connection, params};
+use time::{Duration, OffsetDateTime};
+use url::{Host, Url};
+
+use crate::impls::MomTenantState;
+
+/// A source is fetched at most this often, whatever it claims to mention
+const SOURCE_COOLDOWN: Duration = Duration::minutes(1);
+
+/// At most this many mentions of one target get verified per hour
+const MAX_MENTIONS_PER_TARGET_PER_HOUR: u32 = 30;
+
+/// Where and how much we're willing to fetch when checking someone else's
+/// page. Anyone can send a webmention, so by default that's only public
+/// addresses, for a bounded amount of time and bytes.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FetchLimits {
+    /// Whether loopback, private, link-local etc. addresses are fine (only
+    /// for tests)
+    pub(crate) allow_private: bool,
+
+    /// Most bytes of a body we read, the rest is ignored
+    pub(crate) max_body_size: usize,
+
+    /// Most redirects we follow
+    pub(crate) max_redirects: usize,
+
+    /// How long the whole fetch can take, redirects included
+    pub(crate) timeout: std::time::Duration,
+}
+
+impl Default for FetchLimits {
+    fn default() -> Self {
+        Self {
+            allow_private: false,
+            max_body_size: 1024 * 1024,
+            max_redirects: 5,
+            timeout: std::time::Duration::from_secs(10),
+        }
+    }
+}
+
+/// Records an incoming mention as pending, then verifies it in the background.
+/// Receiving the same source/target pair again re-verifies it (that's how
+/// senders signal updates and deletions), unless the source was fetched very
+/// recently or the target gets too many mentions.
+pub(crate) fn receive(ts: Arc<MomTenantState>, args: ReceiveWebmentionArgs) -> eyre::Result<()> {
+    {
+        let conn = ts.pool.get()?;
+        let now = OffsetDateTime::now_utc();
+        if is_throttled(&conn, &args.source, &args.target, now)? {
+            tracing::info!(
+                "Not verifying webmention from {} to {} again so soon",
+                args.source,
+                args.target
+            );
+            return Ok(());
+        }
+        conn.execute(
+            "INSERT INTO webmentions (source, target, route, status, received_at)
+            VALUES (?1, ?2, ?3, 'pending', ?4)
+            ON CONFLICT (source, target) DO UPDATE SET
+                route = excluded.route,
+                status = 'pending',
+                received_at = excluded.received_at",
+            params![args.source, args.target, args.route, now],
+        )?;
+    }
+
+    tokio::spawn(async move {
+        if let Err(e) = verify_and_publish(&ts, &args).await {
+            tracing::warn!(
+                "Failed to verify webmention from {} to {}: {e}",
+                args.source,
+                args.target
+            );
+        }
+    });
+    Ok(())
+}
+
+/// Whether `source` was received in the last [`SOURCE_COOLDOWN`], or `target`
+/// got [`MAX_MENTIONS_PER_TARGET_PER_HOUR`] mentions in the last hour
+fn is_throttled(
+    conn: &Connection,
+    source: &str,
+    target: &str,
+    now: OffsetDateTime,
+) -> rusqlite::Result<bool> {
+    let recent_for_source: u32 = conn.query_row(
+        "SELECT COUNT(*) FROM webmentions WHERE source = ?1 AND received_at > ?2",
+        params![source, now - SOURCE_COOLDOWN],
+        |row| row.get(0),
+    )?;
+    if recent_for_source > 0 {
+        return Ok(true);
+    }
+
+    let recent_for_target: u32 = conn.query_row(
+        "SELECT COUNT(*) FROM webmentions WHERE target = ?1 AND received_at > ?2",
+        params![target, now - Duration::hours(1)],
+        |row| row.get(0),
+    )?;
+    Ok(recent_for_target >= MAX_MENTIONS_PER_TARGET_PER_HOUR)
+}
+
+async fn verify_and_publish(ts: &MomTenantState, args: &ReceiveWebmentionArgs) -> eyre::Result<()> {
+    let verification = verify_source(&args.source, &args.target, FetchLimits::default()).await?;
+
+    {
+        let conn = ts.pool.get()?;
+        match &verification {
+            Verification::Verified { title } => conn.execute(
+                "UPDATE webmentions SET status = 'verified', title = ?3, verified_at = ?4
+                WHERE source = ?1 AND target = ?2",
+                params![args.source, args.target, title, OffsetDateTime::now_utc()],
+            )?,
+            Verification::Rejected(reason) => {
+                tracing::info!(
+                    "Rejecting webmention from {} to {}: {reason}",
+                    args.source,
+                    args.target
+                );
+                conn.execute(
+                    "UPDATE webmentions SET status = 'rejected'
+                    WHERE source = ?1 AND target = ?2",
+                    params![args.source, args.target],
+                )?
+            }
+        };
+    }
+
+    let webmentions = load_webmentions_from_db(ts)?;
+    ts.broadcast_event(TenantEventPayload::WebmentionsUpdated(webmentions))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Verification {
+    Verified { title: Option<String> },
+    Rejected(String),
+}
+
+/// Fetches `source` and checks that it links to `target`
+pub(crate) async fn verify_source(
+    source: &str,
+    target: &str,
+    limits: FetchLimits,
+) -> eyre::Result<Verification> {
+    let (source_url, res) = match fetch(Url::parse(source)?, limits).await? {
+        Fetched::Response { url, res } => (url, res),
+        Fetched::Status(status) => {
+            // this includes 410 Gone, for deleted posts
+            return Ok(Verification::Rejected(format!("source returned {status}")));
+        }
+    };
+    let body = read_body(res, limits).await?;
+
+    let mod_htmlrewrite = libhtmlrewrite::load();
+    let links_to_target = mod_htmlrewrite
+        .extract_links(&body)
+        .iter()
+        .filter_map(|link| source_url.join(&link.href).ok())
+        .any(|url| same_url(url.as_str(), target));
+    if !links_to_target {
+        return Ok(Verification::Rejected(
+            "source doesn't link to target".into(),
+        ));
+    }
+
+    Ok(Verification::Verified {
+        title: mod_htmlrewrite.extract_title(&body),
+    })
+}
+
+fn same_url(a: &str, b: &str) -> bool {
+    a.trim_end_matches('/') == b.trim_end_matches('/')
+}
+
+enum Fetched {
+    /// A successful response, from `url` (after redirects)
+    Response { url: Url, res: Box<dyn Response> },
+
+    /// Anything but a success or a redirect
+    Status(StatusCode),
+}
+
+/// GETs `url` within `limits`
+async fn fetch(url: Url, limits: FetchLimits) -> eyre::Result<Fetched> {
+    tokio::time::timeout(limits.timeout, follow_redirects(url, limits))
+        .await
+        .map_err(|_| eyre::eyre!("timed out after {:?}", limits.timeout))?
+}
+
+/// Follows redirects by hand, so every hop's address gets checked
+async fn follow_redirects(mut url: Url, limits: FetchLimits) -> eyre::Result<Fetched> {
+    for _ in 0..=limits.max_redirects {
+        let client = pinned_client(&url, limits).await?;
+        let res = client
+            .get(url.as_str().parse::<Uri>()?)
+            .polite_user_agent()
+            .send()
+            .await?;
+        let status = res.status();
+        if status.is_redirection() {
+            let location = res
+                .headers_only_string_safe()
+                .remove("location")
+                .ok_or_else(|| eyre::eyre!("{url} redirects without a location"))?;
+            url = url.join(&location)?;
+            continue;
+        }
+        if !status.is_success() {
+            return Ok(Fetched::Status(status));
+        }
+        return Ok(Fetched::Response { url, res });
+    }
+    eyre::bail!(
+        "more than {} redirects, gave up at {url}",
+        limits.max_redirects
+    )
+}
+
+/// A client that can only reach `url`'s host at the addresses we resolved
+/// (and checked) here, so a second DNS lookup can't point it somewhere
+/// else. It doesn't follow redirects, see [`fetch`].
+async fn pinned_client(url: &Url, limits: FetchLimits) -> eyre::Result<Box<dyn HttpClient>> {
+    if !matches!(url.scheme(), "http" | "https") {
+        eyre::bail!("refusing to fetch {url}: not http(s)");
+    }
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| eyre::eyre!("no port for {url}"))?;
+    let (domain, addrs): (Option<&str>, Vec<SocketAddr>) = match url.host() {
+        Some(Host::Domain(domain)) => (
+            Some(domain),
+            tokio::net::lookup_host((domain, port)).await?.collect(),
+        ),
+        Some(Host::Ipv4(ip)) => (None, vec![SocketAddr::new(ip.into(), port)]),
+        Some(Host::Ipv6(ip)) => (None, vec![SocketAddr::new(ip.into(), port)]),
+        None => eyre::bail!("no host in {url}"),
+    };
+    if addrs.is_empty() {
+        eyre::bail!("{url} doesn't resolve to anything");
+    }
+    let non_public = addrs
+        .iter()
+        .find(|addr| !limits.allow_private && !is_public(addr.ip()));
+    if let Some(addr) = non_public {
+        eyre::bail!("refusing to fetch {url}: resolves to non-public address {addr}");
+    }
+
+    Ok(libhttpclient::load().client_with_opts(ClientOpts {
+        resolve_to_addrs: domain
+            .map(|domain| (domain.to_string(), addrs))
+            .into_iter()
+            .collect(),
+        follow_redirects: false,
+    }))
+}
+
+/// Reads at most `limits.max_body_size` bytes of a body, as (lossy) UTF-8
+async fn read_body(res: Box<dyn Response>, limits: FetchLimits) -> eyre::Result<String> {
+    let mut body = Vec::new();
+    let mut stream = res.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        let room = limits.max_body_size - body.len();
+        if chunk.len() >= room {
+            body.extend_from_slice(&chunk[..room]);
+            break;
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Whether an address is on the public internet, as opposed to loopback,
+/// private networks, link-local (cloud metadata lives there), etc.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "this network"
+        || a == 0
+        // carrier-grade NAT
+        || (a == 100 && (64..128).contains(&b))
+        // benchmarking
+        || (a == 198 && (18..20).contains(&b))
+        // reserved
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let [first, second, ..] = ip.segments();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // unique local
+        || (first & 0xfe00) == 0xfc00
+        // link-local
+        || (first & 0xffc0) == 0xfe80
+        // documentation
+        || (first == 0x2001 && second == 0x0db8)
+        // NAT64, could reach anything in IPv4
+        || (first == 0x0064 && second == 0xff9b))
+}
+
+pub(crate) fn load_webmentions_from_db(ts: &MomTenantState) -> eyre::Result<Webmentions> {
+    let conn = ts.pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT route, source, title, verified_at
+        FROM webmentions
+        WHERE status = 'verified'
+        ORDER BY verified_at DESC",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, Route>(0)?,
+            Webmention {
+                source: row.get(1)?,
+                title: row.get(2)?,
+                verified_at: Rfc3339(row.get(3)?),
+            },
+        ))
+    })?;
+
+    let mut webmentions = Webmentions::default();
+    for row in rows {
+        let (route, mention) = row?;
+        webmentions.by_route.entry(route).or_default().push(mention);
+    }
+    Ok(webmentions)
+}
+
+/// Notifies every target that hasn't been notified for its source yet.
+/// Failed attempts are forgotten, so the next deploy retries them.
+pub(crate) async fn send_all(ts: Arc<MomTenantState>, args: SendWebmentionsArgs) {
+    for page in args.pages {
+        for target in page.targets {
+            // claim this pair first, in case several cubs asked at once
+            let claimed = match ts.pool.get() {
+                Ok(conn) => conn.execute(
+                    "INSERT OR IGNORE INTO sent_webmentions (source, target, status, sent_at)
+                    VALUES (?1, ?2, 'pending', ?3)",
+                    params![page.source, target, OffsetDateTime::now_utc()],
+                ),
+                Err(e) => {
+                    tracing::warn!("Could not get connection to send webmentions: {e}");
+                    return;
+                }
+            };
+            if !matches!(claimed, Ok(1)) {
+                continue;
+            }
+
+            let res = send_one(&page.source, &target, FetchLimits::default()).await;
+            let conn = match ts.pool.get() {
+                Ok(conn) => conn,
+                Err(e) => {
+                    tracing::warn!("Could not get connection to record webmention: {e}");
+                    return;
+                }
+            };
+            let res = match res {
+                Ok(endpoint) => {
+                    let status = if endpoint.is_some() {
+                        "sent"
+                    } else {
+                        "no-endpoint"
+                    };
+                    tracing::debug!("Webmention from {} to {target}: {status}", page.source);
+                    conn.execute(
+                        "UPDATE sent_webmentions SET endpoint = ?3, status = ?4
+                        WHERE source = ?1 AND target = ?2",
+                        params![page.source, target, endpoint.map(String::from), status],
+                    )
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Failed to send webmention from {} to {target}: {e}",
+                        page.source
+                    );
+                    conn.execute(
+                        "DELETE FROM sent_webmentions WHERE source = ?1 AND target = ?2",
+                        params![page.source, target],
+                    )
+                }
+            };
+            if let Err(e) = res {
+                tracing::warn!("Could not record webmention: {e}");
+            }
+        }
+    }
+}
+
+/// Discovers `target`'s webmention endpoint and, if it has one, notifies it.
+/// Returns the endpoint that was notified. The endpoint comes from someone
+/// else's page, so it's held to the same `limits` as fetches.
+pub(crate) async fn send_one(
+    source: &str,
+    target: &str,
+    limits: FetchLimits,
+) -> eyre::Result<Option<Url>> {
+    let Some(endpoint) = discover_endpoint(target, limits).await? else {
+        return Ok(None);
+    };
+
+    let form = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("source", source)
+        .append_pair("target", target)
+        .finish();
+    let client = pinned_client(&endpoint, limits).await?;
+    tokio::time::timeout(
+        limits.timeout,
+        client
+            .post(endpoint.as_str().parse::<Uri>()?)
+            .polite_user_agent()
+            .form(form)
+            .send_and_expect_200(),
+    )
+    .await
+    .map_err(|_| eyre::eyre!("timed out after {:?}", limits.timeout))??;
+    Ok(Some(endpoint))
+}
+
+/// Looks for a `rel="webmention"` endpoint in `target`'s `Link` header, then
+/// in its `<link>` and `<a>` elements.
+pub(crate) async fn discover_endpoint(
+    target: &str,
+    limits: FetchLimits,
+) -> eyre::Result<Option<Url>> {
+    let (target_url, res) = match fetch(Url::parse(target)?, limits).await? {
+        Fetched::Response { url, res } => (url, res),
+        Fetched::Status(_) => return Ok(None),
+    };
+
+    let headers = res.headers_only_string_safe();
+    if let Some(href) = headers
+        .get("link")
+        .and_then(|link| webmention_from_link_header(link.as_str()))
+    {
+        return Ok(Some(target_url.join(&href)?));
+    }
+
+    let is_html = headers
+        .get("content-type")
+        .is_some_and(|ct| ct.contains("html"));
+    if !is_html {
+        return Ok(None);
+    }
+
+    let body = read_body(res, limits).await?;
+    let endpoint = libhtmlrewrite::load()
+        .extract_links(&body)
+        .into_iter()
+        .find(|link| has_webmention_rel(&link.rel));
+    match endpoint {
+        Some(link) => Ok(Some(target_url.join(&link.href)?)),
+        None => Ok(None),
+    }
+}
+
+fn has_webmention_rel(rel: &str) -> bool {
+    rel.split_ascii_whitespace()
+        .any(|r| r.eq_ignore_ascii_case("webmention"))
+}
+
+/// Parses a `Link` header like `<https://example.org/wm>; rel="webmention"`
+fn webmention_from_link_header(header: &str) -> Option<String> {
+    for part in header.split(',') {
+        let mut segments = part.split(';');
+        let Some(url) = segments
+            .next()
+            .map(str::trim)
+            .and_then(|url| url.strip_prefix('<'))
+            .and_then(|url| url.strip_suffix('>'))
+        else {
+            continue;
+        };
+
+        let is_webmention = segments.any(|param| match param.split_once('=') {
+            Some((key, value)) if key.trim().eq_ignore_ascii_case("rel") => {
+                has_webmention_rel(value.trim().trim_matches('"'))
+            }
+            _ => false,
+        });
+        if is_webmention {
+            return Some(url.to_string());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Router,
+        response::{Html, Redirect},
+        routing::get,
+    };
+
+    #[test]
+    fn link_header() {
+        assert_eq!(
+            webmention_from_link_header(r#"<https://example.org/wm>; rel="webmention""#),
+            Some("https://example.org/wm".to_string())
+        );
+        assert_eq!(
+            webmention_from_link_header(
+                r#"<https://example.org/style.css>; rel=stylesheet, </wm>; rel="other webmention""#
+            ),
+            Some("/wm".to_string())
+        );
+        assert_eq!(
+            webmention_from_link_header(r#"<https://example.org/>; rel="canonical""#),
+            None
+        );
+    }
+
+    #[test]
+    fn public_addresses() {
+        for ip in ["93.184.216.34", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{ip} should be public");
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fe80::1",
+            "fd00::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip} should not be public");
+        }
+    }
+
+    #[test]
+    fn throttling() {
+        let conn = crate::impls::db::test_conn();
+        let now = OffsetDateTime::now_utc();
+        let target = "https://example.org/articles/foo";
+        let receive = |source: &str, at: OffsetDateTime| {
+            conn.execute(
+                "INSERT INTO webmentions (source, target, route, status, received_at)
+                VALUES (?1, ?2, '/articles/foo', 'pending', ?3)",
+                params![source, target, at],
+            )
+            .unwrap();
+        };
+
+        assert!(!is_throttled(&conn, "https://a.example/", target, now).unwrap());
+
+        // the same source, just now
+        receive("https://a.example/", now - Duration::seconds(10));
+        assert!(is_throttled(&conn, "https://a.example/", target, now).unwrap());
+        assert!(!is_throttled(&conn, "https://a.example/", target, now + SOURCE_COOLDOWN).unwrap());
+
+        // lots of sources for the same target
+        for i in 1..MAX_MENTIONS_PER_TARGET_PER_HOUR {
+            receive(
+                &format!("https://{i}.example/"),
+                now - Duration::minutes(30),
+            );
+        }
+        assert!(is_throttled(&conn, "https://b.example/", target, now).unwrap());
+        assert!(
+            !is_throttled(
+                &conn,
+                "https://b.example/",
+                "https://example.org/articles/bar",
+                now
+            )
+            .unwrap()
+        );
+        assert!(
+            !is_throttled(
+                &conn,
+                "https://b.example/",
+                target,
+                now + Duration::hours(1)
+            )
+            .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_and_discover_against_local_server() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let target = "https://example.org/articles/foo";
+
+        let app = Router::new()
+            .route(
+                "/links",
+                get(move || async move {
+                    Html(format!(
+                        r#"<html><head><title>Tom &amp; Jerry</title><link rel="webmention" href="/wm"></head>
+                        <body><a href="{target}/">a great article</a></body></html>"#
+                    ))
+                }),
+            )
+            .route(
+                "/no-links",
+                get(|| async { Html("<html><body><p>nothing to see</p></body></html>") }),
+            )
+            .route("/moved", get(|| async { Redirect::to("/links") }))
+            .route("/loop", get(|| async { Redirect::to("/loop") }))
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                    Html("<html></html>")
+                }),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let limits = FetchLimits {
+            allow_private: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            verify_source(&format!("{base}/links"), target, limits)
+                .await
+                .unwrap(),
+            Verification::Verified {
+                title: Some("Tom & Jerry".to_string())
+            }
+        );
+        assert!(matches!(
+            verify_source(&format!("{base}/no-links"), target, limits)
+                .await
+                .unwrap(),
+            Verification::Rejected(_)
+        ));
+        assert!(matches!(
+            verify_source(&format!("{base}/missing"), target, limits)
+                .await
+                .unwrap(),
+            Verification::Rejected(_)
+        ));
+
+        assert_eq!(
+            discover_endpoint(&format!("{base}/links"), limits)
+                .await
+                .unwrap()
+                .map(String::from),
+            Some(format!("{base}/wm"))
+        );
+        assert_eq!(
+            discover_endpoint(&format!("{base}/no-links"), limits)
+                .await
+                .unwrap(),
+            None
+        );
+
+        // redirects are followed, within reason
+        assert!(matches!(
+            verify_source(&format!("{base}/moved"), target, limits)
+                .await
+                .unwrap(),
+            Verification::Verified { .. }
+        ));
+        assert!(
+            verify_source(&format!("{base}/loop"), target, limits)
+                .await
+                .is_err()
+        );
+
+        // only so much of the body is read, for only so long
+        let small = FetchLimits {
+            max_body_size: 64,
+            ..limits
+        };
+        assert!(matches!(
+            verify_source(&format!("{base}/links"), target, small)
+                .await
+                .unwrap(),
+            Verification::Rejected(_)
+        ));
+        let impatient = FetchLimits {
+            timeout: std::time::Duration::from_millis(100),
+            ..limits
+        };
+        assert!(
+            verify_source(&format!("{base}/slow"), target, impatient)
+                .await
+                .is_err()
+        );
+
+        // and none of it is reachable by default
+        let err = verify_source(&format!("{base}/links"), target, FetchLimits::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("non-public"), "{err}");
+        assert!(
+            discover_endpoint(&format!("{base}/links"), FetchLimits::default())
+                .await
+                .is_err()
+        );
+    }
+}
@@ -3,8 +3,13 @@ use config_types::{MOM_DEV_API_KEY, MomApiKey, production_mom_url};
 use eyre::bail;
 use futures_core::future::BoxFuture;
 use mom_types::{
-    DeriveParams, DeriveResponse, ListMissingArgs, ListMissingResponse, MomEvent, TranscodeParams,
-    TranscodeResponse,
+    Comment, CommentThread, DeleteCommentArgs, DeriveParams, DeriveResponse, DownloadCount,
+    EditCommentArgs, GetUserPreferencesArgs, LinkIdentitiesArgs, LinkedAccount, LinkedIdentity,
+    ListCommentsArgs, ListIdentitiesArgs, ListMissingArgs, ListMissingResponse,
+    MagicLinkCallbackArgs, MagicLinkCallbackResponse, MagicLinkRefreshCredentialsArgs,
+    ModerateCommentArgs, MomEvent, PostCommentArgs, ReceiveWebmentionArgs, RecordDownloadArgs,
+    RequestMagicLinkArgs, SendWebmentionsArgs, SetMagicLinkNameArgs, SponsorOptOutArgs,
+    TranscodeParams, TranscodeResponse, UnlinkIdentityArgs, UpdateUserPreferencesArgs,
     media_types::{HeadersMessage, TranscodeEvent, UploadDoneMessage, WebSocketMessage},
 };
 use std::str::FromStr;
@@ -19,7 +24,7 @@ use tracing::info;
 
 use bytes::Bytes;
 use conflux::RevisionIdRef;
-use credentials::AuthBundle;
+use credentials::{AuthBundle, UserPreferences};
 use libgithub::{GitHubCallbackArgs, GitHubCallbackResponse};
 use libhttpclient::{HttpClient, RequestBuilder};
 use libpatreon::{
@@ -276,6 +281,98 @@ impl MomTenantClient for MomTenantClientImpl {
         })
     }
 
+    fn link_identities<'fut>(
+        &'fut self,
+        body: &'fut LinkIdentitiesArgs,
+    ) -> BoxFuture<'fut, Result<AuthBundle>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("identities/link");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                let res = req.send_and_expect_200().await?;
+                Ok(res.json::<AuthBundle>().await?)
+            }
+        })
+    }
+
+    fn list_identities<'fut>(
+        &'fut self,
+        body: &'fut ListIdentitiesArgs,
+    ) -> BoxFuture<'fut, Result<Vec<LinkedIdentity>>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("identities/list");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                let res = req.send_and_expect_200().await?;
+                Ok(res.json::<Vec<LinkedIdentity>>().await?)
+            }
+        })
+    }
+
+    fn unlink_identity<'fut>(
+        &'fut self,
+        body: &'fut UnlinkIdentityArgs,
+    ) -> BoxFuture<'fut, Result<()>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("identities/unlink");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                req.send_and_expect_200().await?;
+                Ok(())
+            }
+        })
+    }
+
+    fn list_linked_accounts<'fut>(&'fut self) -> BoxFuture<'fut, Result<Vec<LinkedAccount>>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("identities/all");
+                let req = self.hclient.post(uri).with_auth(&self.mcc);
+                let res = req.send_and_expect_200().await?;
+                Ok(res.json::<Vec<LinkedAccount>>().await?)
+            }
+        })
+    }
+
+    fn record_download<'fut>(
+        &'fut self,
+        body: &'fut RecordDownloadArgs,
+    ) -> BoxFuture<'fut, Result<()>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("downloads/record");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                req.send_and_expect_200().await?;
+                Ok(())
+            }
+        })
+    }
+
+    fn list_download_counts<'fut>(&'fut self) -> BoxFuture<'fut, Result<Vec<DownloadCount>>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("downloads/counts");
+                let req = self.hclient.post(uri).with_auth(&self.mcc);
+                let res = req.send_and_expect_200().await?;
+                Ok(res.json::<Vec<DownloadCount>>().await?)
+            }
+        })
+    }
+
+    fn sponsor_opt_out<'fut>(
+        &'fut self,
+        body: &'fut SponsorOptOutArgs,
+    ) -> BoxFuture<'fut, Result<()>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("sponsors/opt-out");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                req.send_and_expect_200().await?;
+                Ok(())
+            }
+        })
+    }
+
     fn github_callback<'fut>(
         &'fut self,
         body: &'fut GitHubCallbackArgs,
@@ -304,6 +401,62 @@ impl MomTenantClient for MomTenantClientImpl {
         })
     }
 
+    fn request_magic_link<'fut>(
+        &'fut self,
+        body: &'fut RequestMagicLinkArgs,
+    ) -> BoxFuture<'fut, Result<()>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("magic-link/request");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                req.send_and_expect_200().await?;
+                Ok(())
+            }
+        })
+    }
+
+    fn magic_link_callback<'fut>(
+        &'fut self,
+        body: &'fut MagicLinkCallbackArgs,
+    ) -> BoxFuture<'fut, Result<Option<MagicLinkCallbackResponse>>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("magic-link/callback");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                let res = req.send_and_expect_200().await?;
+                Ok(res.json::<Option<MagicLinkCallbackResponse>>().await?)
+            }
+        })
+    }
+
+    fn magic_link_refresh_credentials<'fut>(
+        &'fut self,
+        body: &'fut MagicLinkRefreshCredentialsArgs,
+    ) -> BoxFuture<'fut, Result<MagicLinkCallbackResponse>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("magic-link/refresh-credentials");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                let res = req.send_and_expect_200().await?;
+                Ok(res.json::<MagicLinkCallbackResponse>().await?)
+            }
+        })
+    }
+
+    fn set_magic_link_name<'fut>(
+        &'fut self,
+        body: &'fut SetMagicLinkNameArgs,
+    ) -> BoxFuture<'fut, Result<()>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("magic-link/set-name");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                req.send_and_expect_200().await?;
+                Ok(())
+            }
+        })
+    }
+
     fn patreon_refresh_credentials<'fut>(
         &'fut self,
         body: &'fut PatreonRefreshCredentialsArgs,
@@ -318,6 +471,132 @@ impl MomTenantClient for MomTenantClientImpl {
         })
     }
 
+    fn list_comments<'fut>(
+        &'fut self,
+        body: &'fut ListCommentsArgs,
+    ) -> BoxFuture<'fut, Result<CommentThread>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("comments/list");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                let res = req.send_and_expect_200().await?;
+                Ok(res.json::<CommentThread>().await?)
+            }
+        })
+    }
+
+    fn post_comment<'fut>(
+        &'fut self,
+        body: &'fut PostCommentArgs,
+    ) -> BoxFuture<'fut, Result<Comment>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("comments/post");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                let res = req.send_and_expect_200().await?;
+                Ok(res.json::<Comment>().await?)
+            }
+        })
+    }
+
+    fn edit_comment<'fut>(
+        &'fut self,
+        body: &'fut EditCommentArgs,
+    ) -> BoxFuture<'fut, Result<Comment>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("comments/edit");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                let res = req.send_and_expect_200().await?;
+                Ok(res.json::<Comment>().await?)
+            }
+        })
+    }
+
+    fn delete_comment<'fut>(
+        &'fut self,
+        body: &'fut DeleteCommentArgs,
+    ) -> BoxFuture<'fut, Result<()>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("comments/delete");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                req.send_and_expect_200().await?;
+                Ok(())
+            }
+        })
+    }
+
+    fn moderate_comment<'fut>(
+        &'fut self,
+        body: &'fut ModerateCommentArgs,
+    ) -> BoxFuture<'fut, Result<()>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("comments/moderate");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                req.send_and_expect_200().await?;
+                Ok(())
+            }
+        })
+    }
+
+    fn get_user_preferences<'fut>(
+        &'fut self,
+        body: &'fut GetUserPreferencesArgs,
+    ) -> BoxFuture<'fut, Result<UserPreferences>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("preferences/get");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                let res = req.send_and_expect_200().await?;
+                Ok(res.json::<UserPreferences>().await?)
+            }
+        })
+    }
+
+    fn update_user_preferences<'fut>(
+        &'fut self,
+        body: &'fut UpdateUserPreferencesArgs,
+    ) -> BoxFuture<'fut, Result<UserPreferences>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("preferences/update");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                let res = req.send_and_expect_200().await?;
+                Ok(res.json::<UserPreferences>().await?)
+            }
+        })
+    }
+
+    fn receive_webmention<'fut>(
+        &'fut self,
+        body: &'fut ReceiveWebmentionArgs,
+    ) -> BoxFuture<'fut, Result<()>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("webmentions/receive");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                req.send_and_expect_200().await?;
+                Ok(())
+            }
+        })
+    }
+
+    fn send_webmentions<'fut>(
+        &'fut self,
+        body: &'fut SendWebmentionsArgs,
+    ) -> BoxFuture<'fut, Result<()>> {
+        Box::pin({
+            async move {
+                let uri = self.config_mom_uri("webmentions/send");
+                let req = self.hclient.post(uri).with_auth(&self.mcc).json(body)?;
+                req.send_and_expect_200().await?;
+                Ok(())
+            }
+        })
+    }
+
     fn objectstore_list_missing<'fut>(
         &'fut self,
         body: &'fut ListMissingArgs,
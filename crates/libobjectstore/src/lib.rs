@@ -4,7 +4,7 @@ pub use bytes::Bytes;
 use futures_core::future::{BoxFuture, LocalBoxFuture};
 use futures_util::stream::BoxStream;
 use objectstore_types::{ObjectStoreKey, ObjectStoreKeyRef};
-use std::{borrow::Cow, ops::Range, sync::Arc};
+use std::{borrow::Cow, ops::Range, sync::Arc, time::Duration};
 
 use config_types::{AwsSecrets, Environment, ObjectStorageConfig};
 
@@ -51,6 +51,13 @@ pub struct GetOptions {
     pub head: bool,
 }
 
+/// What a HEAD request tells us about an object
+#[derive(Debug, Clone)]
+pub struct ObjectMeta {
+    /// Size of the object, in bytes
+    pub size: usize,
+}
+
 #[derive(Debug)]
 pub enum ErrorKind {
     NotFound,
@@ -143,27 +150,30 @@ impl Mod for ModImpl {
             s3_builder = s3_builder.with_endpoint(endpoint.as_str());
         }
 
-        let s3 = s3_builder.build().map_err(to_spec_error)?;
+        let s3 = Arc::new(s3_builder.build().map_err(to_spec_error)?);
         Ok(Arc::new(ObjectStoreWrapper {
             desc: format!("S3 (region: {}, bucket: {})", config.region, config.bucket),
-            inner: Box::new(s3),
+            inner: s3.clone(),
+            signer: Some(s3),
         }))
     }
 
     fn local_disk_with_prefix(&self, prefix: &str) -> Result<Arc<dyn ObjectStore>> {
         Ok(Arc::new(ObjectStoreWrapper {
             desc: format!("Local disk (prefix: {prefix})"),
-            inner: Box::new(
+            inner: Arc::new(
                 object_store::local::LocalFileSystem::new_with_prefix(prefix)
                     .map_err(to_spec_error)?,
             ),
+            signer: None,
         }))
     }
 
     fn in_memory(&self) -> Arc<dyn ObjectStore> {
         Arc::new(ObjectStoreWrapper {
             desc: "In-memory".to_string(),
-            inner: Box::new(object_store::memory::InMemory::new()),
+            inner: Arc::new(object_store::memory::InMemory::new()),
+            signer: None,
         })
     }
 
@@ -235,7 +245,9 @@ fn to_spec_put_result(res: object_store::PutResult) -> PutResult {
 
 struct ObjectStoreWrapper {
     desc: String,
-    inner: Box<dyn object_store::ObjectStore + Send + Sync>,
+    inner: Arc<dyn object_store::ObjectStore + Send + Sync>,
+    // only set for stores that can hand out pre-signed URLs (S3)
+    signer: Option<Arc<dyn object_store::signer::Signer>>,
 }
 
 #[autotrait]
@@ -290,6 +302,35 @@ impl ObjectStore for ObjectStoreWrapper {
         })
     }
 
+    /// Checks that an object exists (and how big it is) without fetching it
+    fn head(&self, key: &ObjectStoreKeyRef) -> BoxFuture<'_, Result<ObjectMeta>> {
+        let path = Path::from(key.as_str());
+        Box::pin(async move {
+            let meta = self.inner.head(&path).await.map_err(to_spec_error)?;
+            Ok(ObjectMeta { size: meta.size })
+        })
+    }
+
+    /// A pre-signed URL anyone can GET the object from until it expires, or
+    /// `None` if the store can't make those (local disk, memory).
+    fn signed_get_url(
+        &self,
+        key: &ObjectStoreKeyRef,
+        expires_in: Duration,
+    ) -> BoxFuture<'_, Result<Option<String>>> {
+        let path = Path::from(key.as_str());
+        Box::pin(async move {
+            let Some(signer) = &self.signer else {
+                return Ok(None);
+            };
+            let url = signer
+                .signed_url(http::Method::GET, &path, expires_in)
+                .await
+                .map_err(to_spec_error)?;
+            Ok(Some(url.to_string()))
+        })
+    }
+
     fn desc(&self) -> String {
         self.desc.clone()
     }
@@ -515,6 +556,41 @@ impl ObjectStore for LayeredStore {
         })
     }
 
+    fn head(&self, key: &ObjectStoreKeyRef) -> BoxFuture<'_, Result<ObjectMeta>> {
+        let key = key.to_owned();
+        Box::pin(async move {
+            // unlike `get_opts`, this doesn't fill in the higher layers
+            for layer in &self.stores {
+                match layer.store.head(&key).await {
+                    Ok(meta) => return Ok(meta),
+                    Err(e) if e.is_not_found() => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(Error {
+                kind: ErrorKind::NotFound,
+                source: Box::new(LayeredNotFound),
+            })
+        })
+    }
+
+    fn signed_get_url(
+        &self,
+        key: &ObjectStoreKeyRef,
+        expires_in: Duration,
+    ) -> BoxFuture<'_, Result<Option<String>>> {
+        let key = key.to_owned();
+        Box::pin(async move {
+            // the first layer that can sign is the one that'll serve it
+            for layer in &self.stores {
+                if let Some(url) = layer.store.signed_get_url(&key, expires_in).await? {
+                    return Ok(Some(url));
+                }
+            }
+            Ok(None)
+        })
+    }
+
     fn desc(&self) -> String {
         format!(
             "LayeredStore({})",
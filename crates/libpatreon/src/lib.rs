@@ -1,13 +1,13 @@
 use autotrait::autotrait;
 use config_types::{RevisionConfig, TenantConfig, WebConfig};
-use credentials::AuthBundle;
+use credentials::{AuthBundle, CreditedSponsor};
 use eyre::Context as _;
 use eyre::Result;
 use futures_core::future::BoxFuture;
 use libhttpclient::{HttpClient, Uri};
 use merde::CowStr;
 use merde::IntoStatic;
-use std::collections::HashSet;
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
 use url::Url;
 
 use std::collections::HashMap;
@@ -168,7 +168,7 @@ impl Mod for ModImpl {
         rc: &'fut RevisionConfig,
         client: &'fut dyn HttpClient,
         store: &'fut dyn PatreonStore,
-    ) -> BoxFuture<'fut, Result<HashSet<String>>> {
+    ) -> BoxFuture<'fut, Result<Vec<CreditedSponsor>>> {
         Box::pin(async move {
             let patreon_campaign_id = rc
                 .patreon_campaign_ids
@@ -182,12 +182,13 @@ impl Mod for ModImpl {
                 .fetch_patreon_credentials(creator_patreon_user_id)?
                 .ok_or_else(|| eyre::eyre!("creator needs to log in with Patreon first"))?;
 
-            let mut credited_patrons: HashSet<String> = Default::default();
+            let mut credited_patrons: Vec<CreditedSponsor> = Default::default();
 
-            let credited_tiers: HashSet<String> = ["Silver", "Gold"]
-                .into_iter()
-                .map(|x| x.to_string())
-                .collect();
+            let credited_rank = |title: &str| {
+                rc.tier_by_title(title)
+                    .filter(|t| t.credited)
+                    .map(|t| t.rank)
+            };
 
             let mut api_uri = Uri::builder()
                 .scheme("https")
@@ -196,9 +197,10 @@ impl Mod for ModImpl {
                     libhttpclient::form_urlencoded::Serializer::new(format!(
                         "/api/oauth2/v2/campaigns/{patreon_campaign_id}/members?"
                     ))
-                    .append_pair("include", "currently_entitled_tiers")
-                    .append_pair("fields[member]", "full_name")
+                    .append_pair("include", "currently_entitled_tiers,user")
+                    .append_pair("fields[member]", "full_name,pledge_relationship_start")
                     .append_pair("fields[tier]", "title")
+                    .append_pair("fields[user]", "thumb_url,url")
                     .append_pair("page[size]", "100")
                     .finish(),
                 )
@@ -233,39 +235,72 @@ impl Mod for ModImpl {
                 let patreon_response: PatreonResponse = serde_json::from_str(&patreon_payload)?;
 
                 let mut tiers_per_id: HashMap<String, Tier> = Default::default();
-                for tier in patreon_response.included {
-                    if let Item::Tier(tier) = tier {
-                        tiers_per_id.insert(tier.common.id.clone(), tier);
+                let mut users_per_id: HashMap<String, User> = Default::default();
+                for item in patreon_response.included {
+                    match item {
+                        Item::Tier(tier) => {
+                            tiers_per_id.insert(tier.common.id.clone(), tier);
+                        }
+                        Item::User(user) => {
+                            users_per_id.insert(user.common.id.clone(), user);
+                        }
+                        Item::Member(_) => {}
                     }
                 }
 
                 for item in patreon_response.data {
-                    if let Item::Member(member) = item {
-                        if let Some(full_name) = member.attributes.full_name.as_deref() {
-                            if let Some(entitled) = member.rel("currently_entitled_tiers") {
-                                for item_ref in entitled.data.iter() {
-                                    let ItemRef::Tier(tier_id) = item_ref;
-                                    if let Some(tier) = tiers_per_id.get(&tier_id.id) {
-                                        if let Some(title) = tier.attributes.title.as_deref() {
-                                            if credited_tiers.contains(title) {
-                                                credited_patrons
-                                                    .insert(full_name.trim().to_string());
-                                            } else {
-                                                tracing::trace!("Tier {title} not credited");
-                                            }
-                                        }
-                                    } else {
-                                        tracing::trace!("Tier for id {} not found", tier_id.id);
-                                    }
-                                }
-                            } else {
-                                tracing::trace!(
-                                    "No currently_entitled_tiers for member: {}",
-                                    full_name
-                                );
-                            }
-                        }
-                    }
+                    let Item::Member(member) = item else {
+                        continue;
+                    };
+                    let Some(full_name) = member.attributes.full_name.as_deref() else {
+                        continue;
+                    };
+                    let Some(entitled) = member.rel("currently_entitled_tiers") else {
+                        tracing::trace!("No currently_entitled_tiers for member: {}", full_name);
+                        continue;
+                    };
+
+                    // if they're entitled to several tiers, credit the highest one
+                    let tier_title = entitled
+                        .refs()
+                        .iter()
+                        .filter_map(|item_ref| match item_ref {
+                            ItemRef::Tier(tier_ref) => tiers_per_id.get(&tier_ref.id),
+                            ItemRef::User(_) => None,
+                        })
+                        .filter_map(|tier| tier.attributes.title.as_deref())
+                        .filter_map(|title| credited_rank(title).map(|rank| (rank, title)))
+                        .max_by_key(|(rank, _)| *rank)
+                        .map(|(_, title)| title.to_string());
+                    let Some(tier_title) = tier_title else {
+                        tracing::trace!("No credited tier for member: {}", full_name);
+                        continue;
+                    };
+
+                    let user = member
+                        .rel("user")
+                        .and_then(|rel| rel.refs().first())
+                        .and_then(|item_ref| match item_ref {
+                            ItemRef::User(user_ref) => users_per_id.get(&user_ref.id),
+                            ItemRef::Tier(_) => None,
+                        });
+                    let Some(user) = user else {
+                        tracing::trace!("No user for member: {}", full_name);
+                        continue;
+                    };
+
+                    credited_patrons.push(CreditedSponsor {
+                        identity: format!("patreon:{}", user.common.id),
+                        name: full_name.trim().to_string(),
+                        tier_title: Some(tier_title),
+                        avatar_source_url: user.attributes.thumb_url.clone(),
+                        profile_url: user.attributes.url.clone(),
+                        joined_at: member
+                            .attributes
+                            .pledge_relationship_start
+                            .as_deref()
+                            .and_then(|date| OffsetDateTime::parse(date, &Rfc3339).ok()),
+                    });
                 }
 
                 match patreon_response.links.and_then(|l| l.next) {
@@ -448,6 +483,7 @@ impl ModImpl {
         let profile = credentials::Profile {
             patreon_id: Some(user.id.clone()),
             github_id: None,
+            email: None,
             full_name: user_attrs.full_name,
             thumb_url: user_attrs.thumb_url,
         };
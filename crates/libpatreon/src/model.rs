@@ -26,6 +26,7 @@ pub struct Links {
 pub enum Item {
     Member(Member),
     Tier(Tier),
+    User(User),
 }
 
 #[derive(Deserialize, Debug)]
@@ -44,6 +45,7 @@ impl Member {
 #[derive(Deserialize, Debug)]
 pub struct MemberAttributes {
     pub full_name: Option<String>,
+    pub pledge_relationship_start: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -58,18 +60,56 @@ pub struct TierAttributes {
     pub title: Option<String>,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct User {
+    #[serde(flatten)]
+    pub common: ItemCommon,
+    pub attributes: UserAttributes,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UserAttributes {
+    pub thumb_url: Option<String>,
+    pub url: Option<String>,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct Relationship {
-    pub data: Vec<ItemRef>,
+    pub data: RelationshipData,
+}
+
+impl Relationship {
+    pub fn refs(&self) -> &[ItemRef] {
+        match &self.data {
+            RelationshipData::Many(refs) => refs,
+            RelationshipData::One(Some(item_ref)) => std::slice::from_ref(item_ref),
+            RelationshipData::One(None) => &[],
+        }
+    }
+}
+
+/// To-many relationships are arrays, to-one relationships are a single
+/// (possibly null) reference.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum RelationshipData {
+    Many(Vec<ItemRef>),
+    One(Option<ItemRef>),
 }
 
 #[derive(Deserialize, Debug, Clone)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum ItemRef {
     Tier(TierRef),
+    User(UserRef),
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct TierRef {
     pub id: String,
 }
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UserRef {
+    pub id: String,
+}
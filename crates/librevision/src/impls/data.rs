@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use conflux::{DataFile, LoadedDataFile, Pak};
+use content_type::ContentType;
+use eyre::{bail, eyre};
+use minijinja::Value;
+use yaml_rust2::{Yaml, YamlLoader};
+
+/// Parses every file in `/data`, keyed by the name templates use to look
+/// them up. Parse errors mention the file, line and column.
+pub(crate) fn load_data_files(pak: &Pak) -> eyre::Result<HashMap<String, LoadedDataFile>> {
+    let mut data: HashMap<String, LoadedDataFile> = HashMap::new();
+    for file in pak.data.iter().flat_map(|data| data.values()) {
+        let value = parse_data_file(file)?;
+        let name = file.name().to_string();
+        if let Some(existing) = data.get(&name) {
+            bail!(
+                "{} and {} would both be available as `{name}`, please rename one",
+                existing.path,
+                file.path
+            );
+        }
+        data.insert(
+            name,
+            LoadedDataFile {
+                path: file.path.clone(),
+                value,
+            },
+        );
+    }
+    Ok(data)
+}
+
+fn parse_data_file(file: &DataFile) -> eyre::Result<Value> {
+    let path = &file.path;
+    match ContentType::guess_from_path(path.as_str()) {
+        Some(ContentType::JSON) => {
+            let value: serde_json::Value =
+                serde_json::from_str(&file.markup).map_err(|e| eyre!("{path}: {e}"))?;
+            Ok(Value::from_serialize(&value))
+        }
+        Some(ContentType::TOML) => {
+            let value: toml::Table = toml::from_str(&file.markup).map_err(|e| {
+                let line = e
+                    .span()
+                    .map(|span| file.markup[..span.start].matches('\n').count() + 1)
+                    .unwrap_or(1);
+                eyre!("{path}:{line}: {}", e.message())
+            })?;
+            Ok(Value::from_serialize(&value))
+        }
+        Some(ContentType::YAML) => {
+            let mut docs = YamlLoader::load_from_str(&file.markup).map_err(|e| {
+                let marker = e.marker();
+                eyre!(
+                    "{path}:{}:{}: {}",
+                    marker.line(),
+                    marker.col() + 1,
+                    e.info()
+                )
+            })?;
+            if docs.len() > 1 {
+                bail!(
+                    "{path}: expected a single YAML document, found {}",
+                    docs.len()
+                );
+            }
+            yaml_to_value(docs.pop().unwrap_or(Yaml::Null)).map_err(|e| eyre!("{path}: {e}"))
+        }
+        _ => bail!("{path}: data files must be .json, .yaml (or .yml) or .toml"),
+    }
+}
+
+fn yaml_to_value(yaml: Yaml) -> eyre::Result<Value> {
+    Ok(match yaml {
+        Yaml::Null => Value::from(()),
+        Yaml::Boolean(b) => Value::from(b),
+        Yaml::Integer(i) => Value::from(i),
+        Yaml::Real(_) => match yaml.as_f64() {
+            Some(f) => Value::from(f),
+            None => bail!("invalid number: {yaml:?}"),
+        },
+        Yaml::String(s) => Value::from(s),
+        Yaml::Array(items) => Value::from(
+            items
+                .into_iter()
+                .map(yaml_to_value)
+                .collect::<eyre::Result<Vec<_>>>()?,
+        ),
+        Yaml::Hash(hash) => {
+            let mut entries = Vec::with_capacity(hash.len());
+            for (k, v) in hash {
+                let key = match k {
+                    Yaml::String(s) => s,
+                    Yaml::Integer(i) => i.to_string(),
+                    Yaml::Boolean(b) => b.to_string(),
+                    other => bail!("unsupported map key: {other:?}"),
+                };
+                entries.push((key, yaml_to_value(v)?));
+            }
+            Value::from_iter(entries)
+        }
+        Yaml::Alias(_) | Yaml::BadValue => bail!("unsupported YAML value: {yaml:?}"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use conflux::InputPath;
+
+    fn parse(path: &str, markup: &str) -> eyre::Result<Value> {
+        parse_data_file(&DataFile {
+            path: InputPath::new(path.to_string()),
+            markup: markup.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_parse_data_files() {
+        for (path, markup) in [
+            (
+                "/data/talks.json",
+                r#"[{"title": "Slow builds", "year": 2024}]"#,
+            ),
+            ("/data/talks.yaml", "- title: Slow builds\n  year: 2024\n"),
+            ("/data/talks.yml", "- title: Slow builds\n  year: 2024\n"),
+            (
+                "/data/talks.toml",
+                "[[talks]]\ntitle = \"Slow builds\"\nyear = 2024\n",
+            ),
+        ] {
+            let value = parse(path, markup).unwrap();
+            let talks = if path.ends_with(".toml") {
+                value.get_attr("talks").unwrap()
+            } else {
+                value
+            };
+            let talk = talks.get_item(&Value::from(0)).unwrap();
+            assert_eq!(
+                talk.get_attr("title").unwrap().as_str(),
+                Some("Slow builds")
+            );
+            assert_eq!(i64::try_from(talk.get_attr("year").unwrap()).unwrap(), 2024);
+        }
+
+        let err = parse("/data/talks.yml", "- title: [")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("/data/talks.yml:"), "got {err}");
+        let err = parse("/data/talks.csv", "").unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "/data/talks.csv: data files must be .json, .yaml (or .yml) or .toml"
+        );
+    }
+}
@@ -1,14 +1,20 @@
-use conflux::Route;
-use merde::{DeserOpinions, time::Rfc3339};
-use time::OffsetDateTime;
+use std::collections::HashMap;
+
+use config_types::{FrontmatterFieldConfig, FrontmatterFieldKind, RevisionConfig};
+use conflux::{ExtraValue, Route};
+use eyre::{bail, eyre};
+use merde::time::Rfc3339;
+use time::{OffsetDateTime, format_description::well_known::Rfc3339 as Rfc3339Format};
+use yaml_rust2::{Yaml, YamlEmitter, YamlLoader};
 
 #[derive(Debug)]
 pub struct Frontmatter {
     /// Title of the page
     pub title: String,
 
-    /// Jinja2 template to use for rendering — defaults to `page.html`
-    pub template: String,
+    /// Jinja2 template to use for rendering — defaults to the section's
+    /// template, then `page.html`
+    pub template: Option<String>,
 
     /// Publication date in RFC3339 format, e.g. `2023-10-01T12:00:00Z` (UTC)
     pub date: Rfc3339<OffsetDateTime>,
@@ -25,16 +31,52 @@ pub struct Frontmatter {
     /// Code used to allow access to a draft
     pub draft_code: Option<String>,
 
+    /// Tier needed to read the whole page, e.g. `Silver`
+    pub min_tier: Option<String>,
+
+    /// Date after which the page is open to everyone
+    pub exclusive_until: Option<Rfc3339<OffsetDateTime>>,
+
+    /// How long after `date` the page stays exclusive, e.g. `6 months` or `30 days`
+    pub exclusive_for: Option<String>,
+
     /// Alternative routes for this page (for redirects)
     pub aliases: Vec<Route>,
 
     /// Tags associated with the page (useful for listings)
     pub tags: Vec<String>,
 
+    /// Terms of the configured taxonomies, e.g. `{ "authors": ["amos"] }`
+    pub taxonomies: HashMap<String, Vec<String>>,
+
+    /// Files attached to the page, see [`FrontmatterDownload`]
+    pub downloads: Vec<FrontmatterDownload>,
+
     /// Additional metadata for the page
     pub extra: FrontmatterExtras,
 }
 
+/// A file readers can download from a page, e.g.
+///
+/// ```yaml
+/// downloads:
+///   - path: rust-101/slides.pdf
+///     title: Slides
+///   - path: rust-101/source.tar.gz
+///     tier: Silver
+/// ```
+#[derive(Debug, Clone)]
+pub struct FrontmatterDownload {
+    /// Path under `extra-files/` in the object store
+    pub path: String,
+
+    /// What to call it, defaults to the file name
+    pub title: Option<String>,
+
+    /// Tier needed to download it, defaults to whatever the page needs
+    pub tier: Option<String>,
+}
+
 #[derive(Default, Debug)]
 pub struct FrontmatterExtras {
     // show patreon credits
@@ -63,137 +105,617 @@ pub struct FrontmatterExtras {
 
     // for a series, marks whether it's still ongoing
     pub ongoing: bool,
-}
 
-pub struct FrontmatterIn {
-    /// Title of the page
-    pub title: String,
+    // fields declared in `frontmatter_fields` in the config
+    pub custom: HashMap<String, ExtraValue>,
+}
 
-    /// Jinja2 template to use for rendering — defaults to `page.html`
-    pub template: Option<String>,
+/// Parses an exclusivity window like `6 months`, `2 weeks` or `30 days`.
+/// A month is 30 days.
+pub fn parse_exclusivity_duration(input: &str) -> eyre::Result<time::Duration> {
+    let (amount, unit) = input
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(|| eyre::eyre!("expected e.g. `6 months`, got {input:?}"))?;
+    let amount: i64 = amount
+        .parse()
+        .map_err(|_| eyre::eyre!("expected a number in {input:?}"))?;
+    let days_per_unit = match unit.trim().trim_end_matches('s') {
+        "day" => 1,
+        "week" => 7,
+        "month" => 30,
+        "year" => 365,
+        other => eyre::bail!(
+            "unknown unit {other:?} in {input:?}, expected days, weeks, months or years"
+        ),
+    };
+    Ok(time::Duration::days(amount * days_per_unit))
+}
 
-    /// Publication date in RFC3339 format, e.g. `2023-10-01T12:00:00Z` (UTC)
-    pub date: Rfc3339<OffsetDateTime>,
+/// Reads a page's frontmatter from its YAML markup. Fields we don't know
+/// about are ignored (other generators have plenty), except under `extra`,
+/// see [`parse_extras`]. Errors name the field that's wrong.
+pub fn parse_frontmatter(markup: &str, rc: &RevisionConfig) -> eyre::Result<Frontmatter> {
+    let docs = YamlLoader::load_from_str(markup).map_err(|e| {
+        let marker = e.marker();
+        eyre!("{}:{}: {}", marker.line(), marker.col() + 1, e.info())
+    })?;
+    let fields = match docs.first() {
+        Some(Yaml::Hash(fields)) => Some(fields),
+        Some(Yaml::Null) | None => None,
+        Some(doc) => bail!("frontmatter should be a map of fields, got {doc:?}"),
+    };
+
+    let mut title = None;
+    let mut template = None;
+    let mut date = None;
+    let mut updated_at = None;
+    let mut draft = false;
+    let mut archive = false;
+    let mut draft_code = None;
+    let mut min_tier = None;
+    let mut exclusive_until = None;
+    let mut exclusive_for = None;
+    let mut aliases = Vec::new();
+    let mut tags = Vec::new();
+    let mut taxonomies = HashMap::new();
+    let mut downloads = Vec::new();
+    let mut extra = &Yaml::Null;
+
+    for (key, value) in fields.into_iter().flatten() {
+        let Some(key) = key.as_str() else {
+            bail!("frontmatter keys should be strings, got {key:?}");
+        };
+        // `draft:` with nothing after it is the same as leaving it out
+        if value.is_null() {
+            continue;
+        }
+        match key {
+            "title" => title = Some(string(key, value)?),
+            "template" => template = Some(string(key, value)?),
+            "date" => date = Some(rfc3339(key, value)?),
+            "updated_at" => updated_at = Some(rfc3339(key, value)?),
+            "draft" => draft = boolean(key, value)?,
+            "archive" => archive = boolean(key, value)?,
+            "draft_code" | "draft-code" => draft_code = Some(string(key, value)?),
+            "min_tier" | "min-tier" => min_tier = Some(string(key, value)?),
+            "exclusive_until" | "exclusive-until" => exclusive_until = Some(rfc3339(key, value)?),
+            "exclusive_for" | "exclusive-for" => exclusive_for = Some(string(key, value)?),
+            "aliases" => aliases = strings(key, value)?.into_iter().map(Route::new).collect(),
+            "tags" => tags = strings(key, value)?,
+            "taxonomies" => taxonomies = parse_taxonomies(value)?,
+            "downloads" => downloads = parse_downloads(value)?,
+            "extra" => extra = value,
+            _ => {}
+        }
+    }
 
-    /// Last update date, if any
-    pub updated_at: Option<Rfc3339<OffsetDateTime>>,
+    Ok(Frontmatter {
+        title: title.ok_or_else(|| eyre!("missing `title`"))?,
+        template,
+        date: date.ok_or_else(|| eyre!("missing `date`"))?,
+        updated_at,
+        draft,
+        archive,
+        draft_code,
+        min_tier,
+        exclusive_until,
+        exclusive_for,
+        aliases,
+        tags,
+        taxonomies,
+        downloads,
+        extra: parse_extras(extra, rc)?,
+    })
+}
 
-    /// If true, page is only visible by admins
-    pub draft: Option<bool>,
+fn parse_taxonomies(value: &Yaml) -> eyre::Result<HashMap<String, Vec<String>>> {
+    let Yaml::Hash(taxonomies) = value else {
+        bail!("`taxonomies` should be a map of taxonomy names to terms, got {value:?}");
+    };
+    let mut parsed = HashMap::with_capacity(taxonomies.len());
+    for (name, terms) in taxonomies {
+        let name = string("taxonomies", name)?;
+        let terms = strings(&format!("taxonomies.{name}"), terms)?;
+        parsed.insert(name, terms);
+    }
+    Ok(parsed)
+}
 
-    /// Whether the page should be excluded from search indexing
-    pub archive: Option<bool>,
+fn parse_downloads(value: &Yaml) -> eyre::Result<Vec<FrontmatterDownload>> {
+    let Yaml::Array(items) = value else {
+        bail!("`downloads` should be a list, got {value:?}");
+    };
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let key = format!("downloads[{i}]");
+            if !matches!(item, Yaml::Hash(_)) {
+                bail!("`{key}` should be a map with a `path`, got {item:?}");
+            }
+            let field = |name: &str| match &item[name] {
+                Yaml::Null | Yaml::BadValue => Ok(None),
+                value => string(&format!("{key}.{name}"), value).map(Some),
+            };
+            Ok(FrontmatterDownload {
+                path: field("path")?.ok_or_else(|| eyre!("`{key}` is missing its `path`"))?,
+                title: field("title")?,
+                tier: field("tier")?,
+            })
+        })
+        .collect()
+}
 
-    /// Code used to allow access to a draft
-    pub draft_code: Option<String>,
+/// Reads `extra`: our own fields, and custom ones, which must be declared in
+/// `frontmatter_fields` in the config. Custom fields pages don't set get
+/// their default, if any.
+fn parse_extras(extra: &Yaml, rc: &RevisionConfig) -> eyre::Result<FrontmatterExtras> {
+    let fields = match extra {
+        Yaml::Hash(fields) => Some(fields),
+        Yaml::Null | Yaml::BadValue => None,
+        _ => bail!("`extra` should be a map"),
+    };
+
+    let mut extras = FrontmatterExtras::default();
+    for (key, value) in fields.into_iter().flatten() {
+        let Some(name) = key.as_str() else {
+            bail!("`extra` keys should be strings, got {key:?}");
+        };
+        if value.is_null() {
+            continue;
+        }
+        let key = format!("extra.{name}");
+        match name {
+            "patreon" => extras.patreon = boolean(&key, value)?,
+            "hide_comments" => extras.hide_comments = boolean(&key, value)?,
+            "hide_patreon" => extras.hide_patreon = boolean(&key, value)?,
+            "hide_metadata" => extras.hide_metadata = boolean(&key, value)?,
+            "tube" => extras.tube = Some(string(&key, value)?),
+            "dual_feature" => extras.dual_feature = boolean(&key, value)?,
+            "youtube" => extras.youtube = Some(string(&key, value)?),
+            "duration" => {
+                let duration = value.as_i64().and_then(|d| u64::try_from(d).ok());
+                extras.duration = Some(duration.ok_or_else(|| {
+                    eyre!("`{key}` should be a number of seconds, got {value:?}")
+                })?);
+            }
+            "ongoing" => extras.ongoing = boolean(&key, value)?,
+            _ => {
+                let field = rc.frontmatter_field(name).ok_or_else(|| {
+                    eyre!("unknown field `{key}`, declare it in `frontmatter_fields` in home.json")
+                })?;
+                extras
+                    .custom
+                    .insert(name.to_string(), custom_field_value(field, value)?);
+            }
+        }
+    }
 
-    /// Alternative routes for this page (for redirects)
-    pub aliases: Option<Vec<Route>>,
+    for field in &rc.frontmatter_fields {
+        if extras.custom.contains_key(&field.name) {
+            continue;
+        }
+        if let Some(default) = &field.default {
+            let docs = YamlLoader::load_from_str(default)
+                .map_err(|e| eyre!("default of `extra.{}`: {e}", field.name))?;
+            let value = docs.first().unwrap_or(&Yaml::Null);
+            extras
+                .custom
+                .insert(field.name.clone(), custom_field_value(field, value)?);
+        } else if field.required {
+            bail!("missing required field `extra.{}`", field.name);
+        }
+    }
+    Ok(extras)
+}
 
-    /// Tags associated with the page (useful for listings)
-    pub tags: Option<Vec<String>>,
+fn string(key: &str, value: &Yaml) -> eyre::Result<String> {
+    match value.as_str() {
+        Some(s) => Ok(s.to_string()),
+        None => bail!("`{key}` should be a string, got {value:?}"),
+    }
+}
 
-    /// Additional metadata for the page
-    pub extra: Option<FrontmatterExtrasIn>,
+fn strings(key: &str, value: &Yaml) -> eyre::Result<Vec<String>> {
+    let Yaml::Array(items) = value else {
+        bail!("`{key}` should be a list of strings, got {value:?}");
+    };
+    items.iter().map(|item| string(key, item)).collect()
 }
 
-// TODO: implement defaults via merde's default mechanism
+fn boolean(key: &str, value: &Yaml) -> eyre::Result<bool> {
+    match value.as_bool() {
+        Some(b) => Ok(b),
+        None => bail!("`{key}` should be `true` or `false`, got {value:?}"),
+    }
+}
 
-struct FrontMatterInOpinions;
+fn rfc3339(key: &str, value: &Yaml) -> eyre::Result<Rfc3339<OffsetDateTime>> {
+    let s = string(key, value)?;
+    let date = OffsetDateTime::parse(&s, &Rfc3339Format)
+        .map_err(|e| eyre!("`{key}` should be an RFC3339 date like `2023-10-01T12:00:00Z`: {e}"))?;
+    Ok(Rfc3339(date))
+}
 
-impl DeserOpinions for FrontMatterInOpinions {
-    fn deny_unknown_fields(&self) -> bool {
-        false
-    }
+fn custom_field_value(field: &FrontmatterFieldConfig, value: &Yaml) -> eyre::Result<ExtraValue> {
+    let name = &field.name;
+    let check_allowed = |s: &str| {
+        if !field.values.is_empty() && !field.values.iter().any(|v| v == s) {
+            bail!(
+                "`extra.{name}` can't be {s:?}, it should be one of {:?}",
+                field.values
+            );
+        }
+        Ok(())
+    };
 
-    fn map_key_name<'s>(&self, key: merde::CowStr<'s>) -> merde::CowStr<'s> {
-        if key == "draft-code" {
-            "draft_code".into()
-        } else {
-            key
+    let value = match (field.kind, value) {
+        (FrontmatterFieldKind::String, Yaml::String(s)) => {
+            check_allowed(s)?;
+            ExtraValue::String(s.clone())
+        }
+        (FrontmatterFieldKind::Integer, Yaml::Integer(i)) => ExtraValue::Integer(*i),
+        (FrontmatterFieldKind::Float, Yaml::Integer(i)) => ExtraValue::Float(*i as f64),
+        (FrontmatterFieldKind::Float, Yaml::Real(_)) => match value.as_f64() {
+            Some(f) => ExtraValue::Float(f),
+            None => bail!("`extra.{name}` should be a number, got {value:?}"),
+        },
+        (FrontmatterFieldKind::Boolean, Yaml::Boolean(b)) => ExtraValue::Boolean(*b),
+        (FrontmatterFieldKind::Date, Yaml::String(_)) => {
+            ExtraValue::Date(rfc3339(&format!("extra.{name}"), value)?)
+        }
+        (FrontmatterFieldKind::Strings, Yaml::Array(items)) => {
+            let mut strings = Vec::with_capacity(items.len());
+            for item in items {
+                let Some(s) = item.as_str() else {
+                    bail!("`extra.{name}` should only contain strings, got {item:?}");
+                };
+                check_allowed(s)?;
+                strings.push(s.to_string());
+            }
+            ExtraValue::Strings(strings)
         }
+        (kind, value) => {
+            let kind = format!("{kind:?}").to_lowercase();
+            bail!("`extra.{name}` should be of kind `{kind}`, got {value:?}")
+        }
+    };
+    Ok(value)
+}
+
+/// Turns TOML frontmatter (from a `+++` block) into YAML, so it goes through
+/// the same deserialization as YAML frontmatter. Syntax errors point at the
+/// line and column in the page, which starts with the opening `+++`.
+pub fn toml_frontmatter_to_yaml(path: &str, markup: &str) -> eyre::Result<String> {
+    let table: toml::Table = toml::from_str(markup).map_err(|e| {
+        let (line, col) = e
+            .span()
+            .map(|span| {
+                let before = &markup[..span.start];
+                let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+                (
+                    before.matches('\n').count() + 1,
+                    before[line_start..].chars().count() + 1,
+                )
+            })
+            .unwrap_or((1, 1));
+        eyre!("{path}:{}:{col}: {}", line + 1, e.message())
+    })?;
+
+    let mut yaml = String::new();
+    YamlEmitter::new(&mut yaml).dump(&toml_to_yaml(toml::Value::Table(table)))?;
+    Ok(yaml)
+}
+
+fn toml_to_yaml(value: toml::Value) -> Yaml {
+    match value {
+        toml::Value::String(s) => Yaml::String(s),
+        toml::Value::Integer(i) => Yaml::Integer(i),
+        toml::Value::Float(f) => Yaml::Real(f.to_string()),
+        toml::Value::Boolean(b) => Yaml::Boolean(b),
+        // YAML frontmatter has dates as strings too
+        toml::Value::Datetime(d) => Yaml::String(toml_datetime_to_rfc3339(d)),
+        toml::Value::Array(items) => Yaml::Array(items.into_iter().map(toml_to_yaml).collect()),
+        toml::Value::Table(table) => Yaml::Hash(
+            table
+                .into_iter()
+                .map(|(k, v)| (Yaml::String(k), toml_to_yaml(v)))
+                .collect(),
+        ),
     }
+}
 
-    fn default_field_value<'borrow>(
-        &self,
-        _key: &'borrow str,
-        _slot: merde::FieldSlot<'_, 'borrow>,
-    ) {
-        // don't fill in
+/// TOML has local dates and datetimes, RFC3339 (which `date` and friends
+/// are) doesn't: those are taken to be UTC, at midnight for bare dates.
+/// Bare times aren't dates at all, so they're left alone.
+fn toml_datetime_to_rfc3339(d: toml::value::Datetime) -> String {
+    match (d.date, d.time, d.offset) {
+        (Some(date), Some(time), None) => format!("{date}T{time}Z"),
+        (Some(date), None, _) => format!("{date}T00:00:00Z"),
+        _ => d.to_string(),
     }
 }
 
-merde::derive! {
-    impl (Deserialize) for struct FrontmatterIn {
-        title,
-        template,
-        date,
-        updated_at,
-        draft,
-        archive,
-        draft_code,
-        aliases,
-        tags,
-        extra
-    } via FrontMatterInOpinions
-}
-
-impl From<FrontmatterIn> for Frontmatter {
-    fn from(frontmatter_in: FrontmatterIn) -> Self {
-        Self {
-            title: frontmatter_in.title,
-            template: frontmatter_in.template.unwrap_or("page.html".into()),
-            date: frontmatter_in.date.0.into(),
-            updated_at: frontmatter_in.updated_at.map(|d| d.0.into()),
-            draft: frontmatter_in.draft.unwrap_or_default(),
-            archive: frontmatter_in.archive.unwrap_or_default(),
-            draft_code: frontmatter_in.draft_code,
-            aliases: frontmatter_in.aliases.unwrap_or_default(),
-            tags: frontmatter_in.tags.unwrap_or_default(),
-            extra: frontmatter_in.extra.unwrap_or_default().into(),
+/// Rewrites a page's TOML frontmatter as YAML, leaving the rest untouched.
+/// Returns `None` if the page doesn't start with TOML frontmatter.
+pub fn migrate_frontmatter(path: &str, markup: &str) -> eyre::Result<Option<String>> {
+    let Some(rest) = markup
+        .strip_prefix("+++\n")
+        .or_else(|| markup.strip_prefix("+++\r\n"))
+    else {
+        return Ok(None);
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "+++" {
+            let yaml = toml_frontmatter_to_yaml(path, &rest[..offset])?;
+            let body = &rest[offset + line.len()..];
+            // the emitter starts the document with `---` for us
+            return Ok(Some(format!("{yaml}\n---\n{body}")));
         }
+        offset += line.len();
     }
+    bail!("{path}: TOML frontmatter is never closed, expected a `+++` line")
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct FrontmatterExtrasIn {
-    pub patreon: Option<bool>,
-    pub hide_comments: Option<bool>,
-    pub hide_patreon: Option<bool>,
-    pub hide_metadata: Option<bool>,
-    pub tube: Option<String>,
-    pub dual_feature: Option<bool>,
-    pub youtube: Option<String>,
-    pub duration: Option<u64>,
-    pub ongoing: Option<bool>,
-}
-
-merde::derive! {
-    impl (Deserialize) for struct FrontmatterExtrasIn {
-        patreon,
-        hide_comments,
-        hide_patreon,
-        hide_metadata,
-        tube,
-        dual_feature,
-        youtube,
-        duration,
-        ongoing
-    }
-}
-
-impl From<FrontmatterExtrasIn> for FrontmatterExtras {
-    fn from(frontmatter_extras_in: FrontmatterExtrasIn) -> Self {
-        Self {
-            patreon: frontmatter_extras_in.patreon.unwrap_or_default(),
-            hide_comments: frontmatter_extras_in.hide_comments.unwrap_or_default(),
-            hide_patreon: frontmatter_extras_in.hide_patreon.unwrap_or_default(),
-            hide_metadata: frontmatter_extras_in.hide_metadata.unwrap_or_default(),
-            tube: frontmatter_extras_in.tube,
-            dual_feature: frontmatter_extras_in.dual_feature.unwrap_or_default(),
-            youtube: frontmatter_extras_in.youtube,
-            duration: frontmatter_extras_in.duration,
-            ongoing: frontmatter_extras_in.ongoing.unwrap_or_default(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, kind: FrontmatterFieldKind) -> FrontmatterFieldConfig {
+        FrontmatterFieldConfig {
+            name: name.into(),
+            kind,
+            required: false,
+            default: None,
+            values: vec![],
         }
     }
+
+    fn yaml(markup: &str) -> Yaml {
+        YamlLoader::load_from_str(markup).unwrap().remove(0)
+    }
+
+    #[test]
+    fn test_parse_frontmatter() {
+        let rc = RevisionConfig::default();
+        let frontmatter = parse_frontmatter(
+            r#"
+title: Hello
+date: 2023-10-01T12:00:00Z
+draft-code: sesame
+tags: [rust, async]
+taxonomies:
+  categories: [news]
+downloads:
+  - path: hello/slides.pdf
+    title: Slides
+description: ignored, it's Zola's
+extra:
+  patreon: true
+  duration: 90
+  youtube:
+"#,
+            &rc,
+        )
+        .unwrap();
+        assert_eq!(frontmatter.title, "Hello");
+        assert_eq!(frontmatter.date.0.unix_timestamp(), 1696161600);
+        assert_eq!(frontmatter.draft_code.as_deref(), Some("sesame"));
+        assert!(!frontmatter.draft);
+        assert_eq!(frontmatter.tags, ["rust", "async"]);
+        assert_eq!(frontmatter.taxonomies["categories"], ["news"]);
+        assert_eq!(frontmatter.downloads[0].path, "hello/slides.pdf");
+        assert_eq!(frontmatter.downloads[0].title.as_deref(), Some("Slides"));
+        assert!(frontmatter.extra.patreon);
+        assert_eq!(frontmatter.extra.duration, Some(90));
+        assert_eq!(frontmatter.extra.youtube, None);
+
+        let err = |markup: &str| parse_frontmatter(markup, &rc).unwrap_err().to_string();
+        assert_eq!(err("date: 2023-10-01T12:00:00Z"), "missing `title`");
+        assert_eq!(
+            err("title: Hello\ndate: 2023-10-01T12:00:00Z\ndraft: maybe"),
+            "`draft` should be `true` or `false`, got String(\"maybe\")"
+        );
+        assert!(
+            err("title: Hello\ndate: yesterday").starts_with("`date` should be an RFC3339 date")
+        );
+        assert_eq!(
+            err("title: Hello\ndate: 2023-10-01T12:00:00Z\ndownloads: [{title: Slides}]"),
+            "`downloads[0]` is missing its `path`"
+        );
+    }
+
+    #[test]
+    fn test_parse_custom_fields() {
+        let mut rc = RevisionConfig::default();
+        rc.frontmatter_fields = vec![
+            FrontmatterFieldConfig {
+                required: true,
+                ..field("subtitle", FrontmatterFieldKind::String)
+            },
+            FrontmatterFieldConfig {
+                default: Some("[beginner]".into()),
+                values: vec!["beginner".into(), "expert".into()],
+                ..field("levels", FrontmatterFieldKind::Strings)
+            },
+            field("rating", FrontmatterFieldKind::Float),
+        ];
+
+        let extras = parse_extras(&yaml("{subtitle: Hi, hide_comments: true}"), &rc).unwrap();
+        assert!(extras.hide_comments);
+        assert!(matches!(&extras.custom["subtitle"], ExtraValue::String(s) if s == "Hi"));
+        assert!(
+            matches!(&extras.custom["levels"], ExtraValue::Strings(levels) if levels == &["beginner"])
+        );
+        assert!(!extras.custom.contains_key("rating"));
+
+        let err = |markup: &str| parse_extras(&yaml(markup), &rc).unwrap_err().to_string();
+        assert_eq!(
+            err("{hide_comments: true}"),
+            "missing required field `extra.subtitle`"
+        );
+        assert_eq!(
+            err("{subtitle: Hi, mood: happy}"),
+            "unknown field `extra.mood`, declare it in `frontmatter_fields` in home.json"
+        );
+        assert_eq!(
+            err("{subtitle: Hi, levels: [wizard]}"),
+            "`extra.levels` can't be \"wizard\", it should be one of [\"beginner\", \"expert\"]"
+        );
+        assert_eq!(err("[subtitle]"), "`extra` should be a map");
+
+        // builtin fields are never mistaken for custom ones
+        parse_extras(
+            &yaml(
+                "{subtitle: Hi, patreon: true, hide_comments: true, hide_patreon: true, \
+                hide_metadata: true, tube: t, dual_feature: true, youtube: y, duration: 1, \
+                ongoing: true}",
+            ),
+            &rc,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_toml_frontmatter_to_yaml() {
+        let toml = r#"
+title = "Hello"
+date = 2023-10-01T12:00:00+02:00
+updated_at = 2023-10-02T08:30:00
+tags = ["rust", "async"]
+
+[extra]
+release = 2023-10-03
+at = 08:30:00
+ratings = [1, 2.5]
+
+[taxonomies]
+categories = ["news"]
+"#;
+        let yaml = toml_frontmatter_to_yaml("/content/hello.md", toml).unwrap();
+        let doc = &YamlLoader::load_from_str(&yaml).unwrap()[0];
+        assert_eq!(doc["title"].as_str(), Some("Hello"));
+        assert_eq!(doc["date"].as_str(), Some("2023-10-01T12:00:00+02:00"));
+        assert_eq!(doc["updated_at"].as_str(), Some("2023-10-02T08:30:00Z"));
+        assert_eq!(
+            doc["extra"]["release"].as_str(),
+            Some("2023-10-03T00:00:00Z")
+        );
+        assert_eq!(doc["extra"]["at"].as_str(), Some("08:30:00"));
+        assert_eq!(doc["extra"]["ratings"][0].as_i64(), Some(1));
+        assert_eq!(doc["extra"]["ratings"][1].as_f64(), Some(2.5));
+        assert_eq!(doc["tags"][1].as_str(), Some("async"));
+        assert_eq!(doc["taxonomies"]["categories"][0].as_str(), Some("news"));
+
+        // and it reads like YAML frontmatter would
+        let frontmatter = parse_frontmatter(&yaml, &RevisionConfig::default()).unwrap();
+        assert_eq!(frontmatter.date.0.unix_timestamp(), 1696154400);
+        assert_eq!(
+            frontmatter.updated_at.unwrap().0.unix_timestamp(),
+            1696235400
+        );
+
+        // errors name the TOML key, or point at the line in the page (the
+        // opening `+++` is line 1)
+        let yaml = toml_frontmatter_to_yaml(
+            "/content/hello.md",
+            "title = \"Hi\"\ndate = 2023-10-01\ndraft = \"yes\"",
+        )
+        .unwrap();
+        assert_eq!(
+            parse_frontmatter(&yaml, &RevisionConfig::default())
+                .unwrap_err()
+                .to_string(),
+            "`draft` should be `true` or `false`, got String(\"yes\")"
+        );
+        let err = toml_frontmatter_to_yaml("/content/hello.md", "title = \"Hi\"\ndate = ")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("/content/hello.md:3:"), "got {err}");
+    }
+
+    #[test]
+    fn test_migrate_frontmatter() {
+        let migrated = migrate_frontmatter(
+            "/content/hello.md",
+            "+++\ntitle = \"Hello\"\n[extra]\npatreon = true\n+++\n\n+++ is fine down here\n",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            migrated,
+            "---\nextra:\n  patreon: true\ntitle: Hello\n---\n\n+++ is fine down here\n"
+        );
+
+        let migrated = migrate_frontmatter(
+            "/content/hello.md",
+            "+++\r\ntitle = \"Hello\"\r\n+++\r\nbody",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(migrated, "---\ntitle: Hello\n---\nbody");
+
+        let yaml = "---\ntitle: Hello\n---\nbody";
+        assert!(
+            migrate_frontmatter("/content/hello.md", yaml)
+                .unwrap()
+                .is_none()
+        );
+
+        let err = migrate_frontmatter("/content/hello.md", "+++\ntitle = \"Hello\"\n")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "/content/hello.md: TOML frontmatter is never closed, expected a `+++` line"
+        );
+    }
+
+    #[test]
+    fn test_custom_field_value() {
+        let value = |kind, markup: &str| custom_field_value(&field("f", kind), &yaml(markup));
+
+        assert!(matches!(
+            value(FrontmatterFieldKind::Integer, "3"),
+            Ok(ExtraValue::Integer(3))
+        ));
+        assert!(matches!(
+            value(FrontmatterFieldKind::Float, "3"),
+            Ok(ExtraValue::Float(f)) if f == 3.0
+        ));
+        assert!(matches!(
+            value(FrontmatterFieldKind::Float, "2.5"),
+            Ok(ExtraValue::Float(f)) if f == 2.5
+        ));
+        assert!(matches!(
+            value(FrontmatterFieldKind::Boolean, "false"),
+            Ok(ExtraValue::Boolean(false))
+        ));
+        assert!(matches!(
+            value(FrontmatterFieldKind::Date, "2023-10-01T12:00:00Z"),
+            Ok(ExtraValue::Date(d)) if d.0.unix_timestamp() == 1696161600
+        ));
+
+        assert_eq!(
+            value(FrontmatterFieldKind::Integer, "three")
+                .unwrap_err()
+                .to_string(),
+            "`extra.f` should be of kind `integer`, got String(\"three\")"
+        );
+        assert_eq!(
+            value(FrontmatterFieldKind::Strings, "[a, 1]")
+                .unwrap_err()
+                .to_string(),
+            "`extra.f` should only contain strings, got Integer(1)"
+        );
+        assert!(
+            value(FrontmatterFieldKind::Date, "2023-10-01")
+                .unwrap_err()
+                .to_string()
+                .starts_with("`extra.f` should be an RFC3339 date")
+        );
+    }
 }
@@ -1,13 +1,13 @@
 use std::{collections::HashMap, sync::Arc, time::Instant};
 
 use closest::{GetOrHelp, ResourceKind};
-use config_types::{TenantInfo, WebConfig};
+use config_types::{Environment, RevisionConfig, TenantInfo, WebConfig};
 use conflux::{
     ACodec, Asset, BitmapVariant, Derivation, DerivationBitmap, DerivationDrawioRender,
     DerivationIdentity, DerivationKind, DerivationPassthrough, DerivationSvgCleanup,
-    DerivationVideo, DerivationVideoThumbnail, InputPathRef, LoadedPage, MarkdownRef, Media,
-    MediaKind, Page, PageKind, Pak, Part, PartNumber, PathMappings, Revision, Route, SeriesLink,
-    VCodec, VContainer, VideoInfo, VideoVariant,
+    DerivationVideo, DerivationVideoThumbnail, Download, InputPathRef, LoadedPage, MarkdownRef,
+    Media, MediaKind, Page, PageKind, Pak, Part, PartNumber, PathMappings, Redirects, Revision,
+    Route, SeriesLink, SeriesStats, VCodec, VContainer, VideoInfo, VideoVariant,
 };
 use content_type::ContentType;
 use cub_types::IndexedRevision;
@@ -16,13 +16,20 @@ use eyre::{Context, eyre};
 use image_types::{ICodec, LogicalPixels, PixelDensity};
 use itertools::Itertools;
 use libsearch::Index;
-use markdown_types::ProcessMarkdownArgs;
-use merde::{DynDeserializerExt, yaml::YamlDeserializer};
+use markdown_types::{FrontmatterFormat, ProcessMarkdownArgs};
+use merde::time::Rfc3339;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use template_types::{CompileArgs, TemplateCollection};
+use time::OffsetDateTime;
 use tracing::{self, debug, warn};
 
-use crate::impls::frontmatter::{Frontmatter, FrontmatterIn};
+use crate::impls::{
+    data::load_data_files,
+    frontmatter::{
+        Frontmatter, parse_exclusivity_duration, parse_frontmatter, toml_frontmatter_to_yaml,
+    },
+    related::compute_related_pages,
+};
 
 pub async fn load_pak(
     pak: Pak,
@@ -36,13 +43,26 @@ pub async fn load_pak(
         ti: ti.clone(),
         pages: Default::default(),
         page_routes: Default::default(),
+        redirects: Default::default(),
         assets: Default::default(),
         asset_routes: Default::default(),
         tags: Default::default(),
+        taxonomies: Default::default(),
         media: Default::default(),
+        related: Default::default(),
+        translations: Default::default(),
+        data: Default::default(),
         mappings,
     };
 
+    let data_start = Instant::now();
+    rev.data = load_data_files(&rev.pak)?;
+    tracing::debug!(
+        "Loaded {} data files in {:?}",
+        rev.data.len(),
+        data_start.elapsed()
+    );
+
     let before_input = Instant::now();
     for (input_path, input) in &rev.pak.inputs {
         let (base, _ext) = input_path.explode();
@@ -371,9 +391,8 @@ pub async fn load_pak(
     let before_calculate_deps = Instant::now();
     for path in input_pages {
         let page = rev.pak.pages.get(path).unwrap();
-        let route_path = path.to_route_path();
         rev.page_routes
-            .insert(route_path.to_owned(), page.path.clone());
+            .insert(path.to_localized_route(&rev.pak.rc), page.path.clone());
 
         let prev_page = {
             if let Some(prev) = prev_rev {
@@ -509,19 +528,139 @@ pub async fn load_pak(
     );
     tracing::debug!("Extended pages in {:?}", extend_start.elapsed());
 
-    let aliases_start = Instant::now();
-    for page in rev.pages.values() {
+    // Group translations with their original
+    let translations_start = Instant::now();
+    for page in rev.pages.values().sorted_by_key(|p| &p.path) {
+        if let Some(original) = &page.translation_of {
+            if !rev.pages.contains_key(original) {
+                warn!(
+                    "{} is a translation of {original}, which doesn't exist",
+                    page.path
+                );
+            }
+            rev.translations
+                .entry(original.clone())
+                .or_default()
+                .push(page.path.clone());
+        }
+    }
+    tracing::debug!("Grouped translations in {:?}", translations_start.elapsed());
+
+    let redirects_start = Instant::now();
+    if let Some(markup) = rev.pak.redirects.as_deref() {
+        for rule in Redirects::parse(markup)? {
+            rev.redirects.add(rule);
+        }
+    }
+    for page in rev.pages.values().sorted_by_key(|p| &p.path) {
         for alias in &page.aliases {
-            rev.page_routes.insert(alias.clone(), page.path.clone());
+            rev.redirects.add_alias(alias, &page.path, &page.route);
         }
     }
+    for problem in rev.redirects.problems(&rev.page_routes) {
+        warn!("Redirect problem: {problem}");
+    }
     tracing::debug!(
-        "Added page aliases to routes in {:?}",
-        aliases_start.elapsed()
+        "Collected {} redirects in {:?}",
+        rev.redirects.rules().len(),
+        redirects_start.elapsed()
     );
 
     // Now collect series parts
-    let series_parts_start = Instant::now();
+    let series_start = Instant::now();
+    link_series_parts(&mut rev)?;
+    tracing::debug!("Linked series parts in {:?}", series_start.elapsed());
+
+    // Collect tags
+    for (page_path, lpage) in &rev.pages {
+        for tag in &lpage.tags {
+            rev.tags
+                .entry(tag.clone())
+                .or_default()
+                .push(page_path.clone());
+        }
+    }
+
+    // Collect taxonomy terms (every configured taxonomy is there, even unused)
+    for taxonomy in &rev.pak.rc.taxonomies {
+        rev.taxonomies.entry(taxonomy.name.clone()).or_default();
+    }
+    for (page_path, lpage) in &rev.pages {
+        for (name, terms) in &lpage.taxonomies {
+            let taxonomy = rev.taxonomies.entry(name.clone()).or_default();
+            for term in terms {
+                taxonomy
+                    .entry(term.clone())
+                    .or_default()
+                    .push(page_path.clone());
+            }
+        }
+    }
+
+    check_sections(&rev)?;
+    check_tag_aliases(&rev.pak.rc)?;
+
+    let downloads_start = Instant::now();
+    check_downloads(&rev).await?;
+    tracing::debug!("Checked downloads in {:?}", downloads_start.elapsed());
+
+    // Collect children
+    {
+        let all_pages = rev
+            .pages
+            .values()
+            .map(|p| (p.path.clone(), p.route.clone()))
+            .collect::<Vec<_>>();
+        for (page_path, page_route) in all_pages {
+            if let Some(parent_route) = page_route.parent() {
+                if let Some(parent_path) = rev.page_routes.get(parent_route) {
+                    // Arc::get_mut is _bad_
+                    if let Some(parent_page) = Arc::get_mut(rev.pages.get_mut(parent_path).unwrap())
+                    {
+                        parent_page.children.push(page_path.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    // Index pages for search
+    let mut indexer = libsearch::load().indexer(rev.pak.rc.default_lang());
+
+    let before_index = Instant::now();
+    for (path, page) in &rev.pages {
+        if page.is_indexed() {
+            indexer.insert(path.clone(), page);
+        }
+    }
+    tracing::debug!(
+        "Indexed {} pages in {:?}",
+        rev.pages.len(),
+        before_index.elapsed()
+    );
+
+    let before_commit = Instant::now();
+    let index = indexer.commit();
+    tracing::debug!("Committed search index in {:?}", before_commit.elapsed());
+
+    let before_related = Instant::now();
+    rev.related = compute_related_pages(&rev, index.as_ref());
+    tracing::debug!(
+        "Computed related pages for {} pages in {:?}",
+        rev.related.len(),
+        before_related.elapsed()
+    );
+
+    Ok(IndexedRevision {
+        rev: Arc::new(rev),
+        index: Arc::<dyn Index>::from(index),
+        templates,
+    })
+}
+
+/// Gives series indexes their parts and totals, and lets each part know
+/// where it stands in its series (cumulative times, previous and next part).
+fn link_series_parts(rev: &mut Revision) -> eyre::Result<()> {
     let mut series_parts: HashMap<Route, HashMap<PartNumber, Arc<LoadedPage>>> = Default::default();
 
     for lpage in rev.pages.values() {
@@ -532,12 +671,14 @@ pub async fn load_pak(
                 .insert(link.part_number, lpage.clone());
         }
     }
-    tracing::debug!(
-        "Collected series parts in {:?}",
-        series_parts_start.elapsed()
-    );
-
     for (index_route, parts) in series_parts {
+        let is_translated = parts.values().any(|p| p.translation_of.is_some());
+        if is_translated && !rev.page_routes.contains_key(&index_route) {
+            // translating a few parts without translating the index is fine
+            warn!("Translated series parts found, but no series index at {index_route}");
+            continue;
+        }
+
         let index_path = rev
             .page_routes
             .get_or_help(ResourceKind::Route, &index_route)
@@ -557,85 +698,75 @@ pub async fn load_pak(
         let num_parts = parts.len();
         let mut parts_vec: Vec<Part> = Vec::with_capacity(parts.len());
 
+        // translated series may skip parts that haven't been translated yet
+        let part_numbers: Vec<PartNumber> = if is_translated {
+            parts.keys().copied().sorted().collect()
+        } else {
+            (1..=num_parts).map(PartNumber::new).collect()
+        };
+
         let mut total_reading_time = 0;
-        for i in 1..=num_parts {
-            let part = parts.get(&PartNumber::new(i)).ok_or_else(|| {
+        let mut total_video_duration = 0;
+        for part_number in part_numbers {
+            let part = parts.get(&part_number).ok_or_else(|| {
                 eyre!(
-                    "Could not find part {i} of series {index_route}. All part numbers: {:?}",
+                    "Could not find part {} of series {index_route}. All part numbers: {:?}",
+                    part_number.as_usize() + 1,
                     parts.keys().join(", ")
                 )
             })?;
             let part_path = part.path.clone();
-            let part_route = part_path.to_route_path().to_owned();
+            let part_route = part.route.clone();
 
             total_reading_time += part.reading_time;
+            total_video_duration += part.video_info.duration.unwrap_or_default();
             parts_vec.push(Part {
                 title: part.title.clone(),
                 path: part_path,
                 route: part_route,
+                reading_time: part.reading_time,
+                video_duration: part.video_info.duration,
+                cumulative_reading_time: total_reading_time,
+                cumulative_video_duration: total_video_duration,
             });
         }
 
+        let stats = SeriesStats {
+            num_parts: parts_vec.len(),
+            total_reading_time,
+            total_video_duration,
+            ongoing: index_page.ongoing,
+        };
+        let series_title = index_page.title.clone();
+
         let mut index_page_cloned = LoadedPage::clone(index_page.as_ref());
         index_page_cloned.reading_time = total_reading_time;
-        index_page_cloned.parts = parts_vec;
+        index_page_cloned.parts = parts_vec.clone();
+        index_page_cloned.series_stats = Some(stats.clone());
 
         // replace the index page with the new one
         rev.pages
             .insert(index_path.clone(), Arc::new(index_page_cloned));
-    }
-
-    // Collect tags
-    for (page_path, lpage) in &rev.pages {
-        for tag in &lpage.tags {
-            rev.tags
-                .entry(tag.clone())
-                .or_default()
-                .push(page_path.clone());
-        }
-    }
 
-    // Collect children
-    {
-        let all_input_paths = rev.pages.keys().cloned().collect::<Vec<_>>();
-        for page_path in all_input_paths {
-            let page_route = page_path.to_route_path();
-            if let Some(parent_route) = page_route.parent() {
-                if let Some(parent_path) = rev.page_routes.get(parent_route) {
-                    // Arc::get_mut is _bad_
-                    if let Some(parent_page) = Arc::get_mut(rev.pages.get_mut(parent_path).unwrap())
-                    {
-                        parent_page.children.push(page_path.clone());
-                    }
-                }
+        // let parts know where they stand in the series. we're holding
+        // references to them in `parts`, so let go of those first.
+        drop(parts);
+        for (i, part) in parts_vec.iter().enumerate() {
+            let Some(lpage) = rev.pages.get_mut(&part.path) else {
+                continue;
+            };
+            let lpage = Arc::make_mut(lpage);
+            if let Some(link) = lpage.series_link.as_mut() {
+                link.series_title = series_title.clone();
+                link.stats = stats.clone();
+                link.part = Some(part.clone());
+                link.prev = i.checked_sub(1).map(|prev| parts_vec[prev].clone());
+                link.next = parts_vec.get(i + 1).cloned();
             }
         }
     }
 
-    // Index pages for search
-    let mut indexer = libsearch::load().indexer();
-
-    let before_index = Instant::now();
-    for (path, page) in &rev.pages {
-        if page.is_indexed() {
-            indexer.insert(path.clone(), page);
-        }
-    }
-    tracing::debug!(
-        "Indexed {} pages in {:?}",
-        rev.pages.len(),
-        before_index.elapsed()
-    );
-
-    let before_commit = Instant::now();
-    let index = indexer.commit();
-    tracing::debug!("Committed search index in {:?}", before_commit.elapsed());
-
-    Ok(IndexedRevision {
-        rev: Arc::new(rev),
-        index: Arc::<dyn Index>::from(index),
-        templates,
-    })
+    Ok(())
 }
 
 fn recompute_asset_routes(rev: &mut Revision) -> eyre::Result<()> {
@@ -656,7 +787,7 @@ fn load_single_page(
     web: WebConfig,
 ) -> eyre::Result<LoadedPage> {
     let path = &page.path;
-    let route_path = path.to_route_path();
+    let route_path = path.to_localized_route(&rev.pak.rc);
 
     tracing::debug!("Building \x1b[32m{path:?}\x1b[0m => route \x1b[36m{route_path:?}\x1b[0m");
     let mut html_buffer = Vec::with_capacity(page.markup.len() * 4);
@@ -675,16 +806,40 @@ fn load_single_page(
     let res = mod_markdown
         .process_markdown_to_writer(args)
         .wrap_err_with(|| format!("processing markdown for {path:?}"))?;
-    let mut deser = YamlDeserializer::new(res.frontmatter.as_deref().unwrap_or_default());
-    let frontmatter: Frontmatter = deser
-        .deserialize::<FrontmatterIn>()
-        .map_err(|e| {
-            eyre!(
-                "yaml deser error for input path {path:?}: {e:?}\nFull YAML markup:\n{}\nError as display: {e}\n",
-                res.frontmatter.as_deref().unwrap_or_default()
-            )
-        })?
-        .into();
+    let frontmatter_markup = match res.frontmatter_format {
+        FrontmatterFormat::Yaml => res.frontmatter.clone().unwrap_or_default(),
+        FrontmatterFormat::Toml => toml_frontmatter_to_yaml(
+            path.as_str(),
+            res.frontmatter.as_deref().unwrap_or_default(),
+        )?,
+    };
+    let frontmatter = parse_frontmatter(&frontmatter_markup, &rev.pak.rc)
+        .wrap_err_with(|| format!("frontmatter of {path:?}"))?;
+
+    let (min_tier, exclusive_until) = page_exclusivity(&frontmatter, &rev.pak.rc)
+        .wrap_err_with(|| format!("exclusivity settings for {path:?}"))?;
+
+    let mut members_sections = res.members_sections;
+    for section in &mut members_sections {
+        if let Some(name) = section.tier.take() {
+            let tier = rev.pak.rc.tier_by_title(&name).ok_or_else(|| {
+                eyre!("unknown tier {name:?} for members-only section in {path:?}")
+            })?;
+            section.tier = Some(tier.name.clone());
+        }
+    }
+
+    let downloads = page_downloads(&frontmatter, &rev.pak.rc)
+        .wrap_err_with(|| format!("downloads for {path:?}"))?;
+    let taxonomies = page_taxonomies(&frontmatter, &rev.pak.rc)
+        .wrap_err_with(|| format!("taxonomies for {path:?}"))?;
+    let mut tags: Vec<String> = Vec::with_capacity(frontmatter.tags.len());
+    for tag in &frontmatter.tags {
+        let tag = rev.pak.rc.canonical_tag(tag);
+        if !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_string());
+        }
+    }
 
     let reading_time = res.reading_time;
 
@@ -696,28 +851,52 @@ fn load_single_page(
         path.canonicalize_relative_path(InputPathRef::from_str("../_thumb.jxl"));
     let parent_thumb = rev.media.get(&parent_thumb_path).cloned();
 
+    let unlocalized_path = path.without_lang(&rev.pak.rc);
+    let unlocalized_route = unlocalized_path.to_route_path();
+    let section = rev
+        .pak
+        .rc
+        .section_for_route(unlocalized_route.as_str())
+        .cloned();
+    let template = frontmatter
+        .template
+        .or_else(|| section.as_ref().and_then(|s| s.template.clone()))
+        .unwrap_or_else(|| "page.html".into());
+
     let lpage = LoadedPage {
         ti: rev.ti.clone(),
         web,
         path: path.to_owned(),
-        route: route_path.to_owned(),
-        kind: route_path.into(),
+        kind: PageKind::from_route(unlocalized_route, &rev.pak.rc),
+        section,
+        route: route_path,
+        lang: path
+            .lang(&rev.pak.rc)
+            .unwrap_or(rev.pak.rc.default_lang())
+            .to_string(),
+        translation_of: path.lang(&rev.pak.rc).map(|_| unlocalized_path.clone()),
 
         html: String::from_utf8(html_buffer)?,
         plain_text: res.plain_text,
         reading_time,
         toc: res.toc,
+        members_sections,
         crates: Default::default(),       // TODO
         github_repos: Default::default(), // TODO
         links: res.links.into_iter().collect(),
+        downloads,
         title: frontmatter.title,
-        template: frontmatter.template,
+        template,
         date: frontmatter.date,
         draft: frontmatter.draft,
         archive: frontmatter.archive,
         draft_code: frontmatter.draft_code,
+        min_tier,
+        exclusive_until,
         aliases: frontmatter.aliases,
-        tags: frontmatter.tags,
+        tags,
+        taxonomies,
+        extra: frontmatter.extra.custom,
         updated_at: frontmatter.updated_at,
 
         show_patreon_credits: frontmatter.extra.patreon,
@@ -731,6 +910,7 @@ fn load_single_page(
 
         series_link: None,
         parts: Default::default(),
+        series_stats: None,
 
         video_info: VideoInfo {
             dual_feature: frontmatter.extra.dual_feature,
@@ -754,28 +934,298 @@ fn load_single_page(
     Ok(lpage)
 }
 
-fn mark_series_links(lpage: &mut LoadedPage) -> eyre::Result<()> {
-    if lpage.kind == PageKind::SeriesPart {
-        let series_index_path = lpage.route.parent().unwrap();
+/// Checks taxonomy terms against the config: taxonomies must be declared, and
+/// terms of `authors` must be in the authors registry, if there is one.
+fn page_taxonomies(
+    frontmatter: &Frontmatter,
+    rc: &RevisionConfig,
+) -> eyre::Result<HashMap<String, Vec<String>>> {
+    let mut taxonomies = HashMap::new();
+    for (name, terms) in &frontmatter.taxonomies {
+        if rc.taxonomy(name).is_none() {
+            return Err(eyre!(
+                "unknown taxonomy {name:?}, configured ones are: {:?}",
+                rc.taxonomies.iter().map(|t| &t.name).collect::<Vec<_>>()
+            ));
+        }
+        let mut checked: Vec<String> = Vec::with_capacity(terms.len());
+        for term in terms {
+            if term.is_empty() || term.contains('/') {
+                return Err(eyre!(
+                    "{name} term {term:?} should be non-empty and have no slashes, it's used in URLs"
+                ));
+            }
+            if name == "authors" && !rc.authors.is_empty() && rc.author(term).is_none() {
+                return Err(eyre!(
+                    "unknown author {term:?}, add them to `authors` in home.json"
+                ));
+            }
+            if !checked.contains(term) {
+                checked.push(term.clone());
+            }
+        }
+        taxonomies.insert(name.clone(), checked);
+    }
+    Ok(taxonomies)
+}
+
+/// Checks the files a page declares for download (whether they actually
+/// exist in the object store is checked once the whole revision is loaded,
+/// see `check_downloads`) and resolves their tiers to canonical names.
+fn page_downloads(frontmatter: &Frontmatter, rc: &RevisionConfig) -> eyre::Result<Vec<Download>> {
+    frontmatter
+        .downloads
+        .iter()
+        .map(|download| {
+            let path = download.path.as_str();
+            if path.is_empty()
+                || path.starts_with('/')
+                || path.ends_with('/')
+                || path.split('/').any(|segment| segment == "..")
+            {
+                return Err(eyre!(
+                    "download path {path:?} should be relative to `extra-files/`, e.g. `my-article/slides.pdf`"
+                ));
+            }
+
+            let tier = match &download.tier {
+                Some(name) => Some(
+                    rc.tier_by_title(name)
+                        .ok_or_else(|| eyre!("unknown tier {name:?} for download {path:?}"))?
+                        .name
+                        .clone(),
+                ),
+                None => None,
+            };
+            let title = download.title.clone().unwrap_or_else(|| {
+                path.rsplit_once('/')
+                    .map(|(_, name)| name)
+                    .unwrap_or(path)
+                    .to_string()
+            });
+
+            Ok(Download {
+                path: path.to_string(),
+                title,
+                tier,
+                route: Route::new(format!("/downloads/{path}")),
+            })
+        })
+        .collect()
+}
+
+/// Makes sure configured sections can be told apart, and that each one has a
+/// listing page: a mistyped route would otherwise quietly turn every page
+/// under it into a plain page.
+fn check_sections(rev: &Revision) -> eyre::Result<()> {
+    let sections = &rev.pak.rc.sections;
+    let mut problems = Vec::new();
+    for (i, section) in sections.iter().enumerate() {
+        let route = section.route.as_str();
+        if !route.starts_with('/') || route == "/" || route.ends_with('/') {
+            problems.push(format!(
+                "section {:?} has route {route:?}, expected something like \"/notes\"",
+                section.name
+            ));
+        } else if !rev.page_routes.contains_key(route) {
+            problems.push(format!(
+                "section {:?} has route {route}, but there's no page there",
+                section.name
+            ));
+        }
+
+        if let Some(other) = sections[..i]
+            .iter()
+            .find(|other| other.route == section.route)
+        {
+            problems.push(format!(
+                "sections {:?} and {:?} both have route {route}",
+                other.name, section.name
+            ));
+        }
+        if sections[..i].iter().any(|other| other.name == section.name) {
+            problems.push(format!("section {:?} is configured twice", section.name));
+        }
+    }
+
+    if !problems.is_empty() {
+        eyre::bail!("invalid sections in home.json: {}", problems.join("; "));
+    }
+    Ok(())
+}
+
+/// Makes sure every tag alias eventually leads to a tag, rather than to
+/// nothing or back to itself.
+fn check_tag_aliases(rc: &RevisionConfig) -> eyre::Result<()> {
+    for (alias, target) in rc.tag_aliases.iter().sorted() {
+        if alias.is_empty() || target.is_empty() {
+            eyre::bail!("tag alias {alias:?} => {target:?}: tags can't be empty");
+        }
+
+        let mut chain = vec![alias.as_str()];
+        let mut tag = target.as_str();
+        while let Some(next) = rc.tag_aliases.get(tag) {
+            chain.push(tag);
+            if chain[..chain.len() - 1].contains(&tag) {
+                eyre::bail!("tag aliases loop: {}", chain.join(" => "));
+            }
+            tag = next;
+        }
+    }
+    Ok(())
+}
+
+/// Makes sure every file declared in `downloads` frontmatter is served by
+/// exactly one page and is actually in the object store, so a revision with
+/// broken download links fails to load like any other broken revision.
+async fn check_downloads(rev: &Revision) -> eyre::Result<()> {
+    let downloads = unique_downloads(rev)?;
+    if downloads.is_empty() {
+        return Ok(());
+    }
+
+    let store = derivations::objectstore_for_tenant(&rev.ti, Environment::default()).await?;
+    let mut checks = tokio::task::JoinSet::new();
+    for (page, download) in &downloads {
+        let store = store.clone();
+        let key = download.key();
+        let page_path = page.path.clone();
+        checks.spawn(async move {
+            let res = store.head(&key).await;
+            (key, page_path, res)
+        });
+    }
+
+    let mut missing = Vec::new();
+    while let Some(check) = checks.join_next().await {
+        let (key, page_path, res) = check?;
+        match res {
+            Ok(_) => {}
+            Err(e) if e.is_not_found() => missing.push(format!("{key} (from {page_path})")),
+            Err(e) => eyre::bail!("checking download {key}: {e}"),
+        }
+    }
+    if !missing.is_empty() {
+        missing.sort();
+        eyre::bail!(
+            "{} download(s) are missing from the object store: {}",
+            missing.len(),
+            missing.join(", ")
+        );
+    }
+
+    tracing::info!("All {} downloads are in the object store", downloads.len());
+    Ok(())
+}
+
+/// Every download in the revision, with the page that declares it. A path
+/// can only be declared once: `/downloads/{path}` could only ever serve one
+/// of them.
+fn unique_downloads(rev: &Revision) -> eyre::Result<Vec<(&LoadedPage, &Download)>> {
+    let mut downloads: Vec<(&LoadedPage, &Download)> = rev
+        .pages
+        .values()
+        .flat_map(|page| page.downloads.iter().map(move |d| (page.as_ref(), d)))
+        .collect();
+    downloads.sort_by(|(a_page, a), (b_page, b)| {
+        a.path
+            .cmp(&b.path)
+            .then_with(|| a_page.path.as_str().cmp(b_page.path.as_str()))
+    });
+
+    let duplicates: Vec<String> = downloads
+        .chunk_by(|(_, a), (_, b)| a.path == b.path)
+        .filter(|declared| declared.len() > 1)
+        .map(|declared| {
+            format!(
+                "{} (from {})",
+                declared[0].1.path,
+                declared
+                    .iter()
+                    .map(|(page, _)| page.path.as_str())
+                    .join(", ")
+            )
+        })
+        .collect();
+    if !duplicates.is_empty() {
+        eyre::bail!(
+            "download(s) declared more than once: {}",
+            duplicates.join("; ")
+        );
+    }
+
+    Ok(downloads)
+}
 
-        let tokens: Vec<&str> = lpage.route.as_str().split('/').collect();
-        let part_number = match tokens.as_slice() {
-            ["", "series", _series_slug, part_str, ..] => part_str
-                .strip_prefix("part-")
-                .and_then(|part_str| part_str.parse::<usize>().ok())
-                .map(PartNumber::new),
-            _ => None,
+/// Resolves `min_tier`, `exclusive_until` and `exclusive_for` into a
+/// (canonical) tier name and an optional end date. An exclusivity window
+/// without a tier means the lowest tier. Dual features without any settings
+/// are exclusive for 6 months, like they've always been.
+fn page_exclusivity(
+    frontmatter: &Frontmatter,
+    rc: &RevisionConfig,
+) -> eyre::Result<(Option<String>, Option<Rfc3339<OffsetDateTime>>)> {
+    let exclusive_for = match (&frontmatter.exclusive_until, &frontmatter.exclusive_for) {
+        (Some(_), Some(_)) => {
+            return Err(eyre!(
+                "only one of `exclusive_until` and `exclusive_for` can be set"
+            ));
         }
-        .ok_or_else(|| {
+        (None, None) if frontmatter.extra.dual_feature && frontmatter.min_tier.is_none() => {
+            Some(time::Duration::days(30 * 6))
+        }
+        (_, Some(exclusive_for)) => Some(parse_exclusivity_duration(exclusive_for)?),
+        (_, None) => None,
+    };
+    let exclusive_until = frontmatter
+        .exclusive_until
+        .as_ref()
+        .map(|d| d.0)
+        .or_else(|| exclusive_for.map(|d| frontmatter.date.0 + d))
+        .map(Rfc3339);
+
+    let min_tier = match frontmatter.min_tier.as_deref() {
+        Some(name) => Some(rc.tier_by_title(name).ok_or_else(|| {
             eyre!(
-                "Invalid series part path: expected `/series/{{slug}}/part-N`, but got '{}'",
-                lpage.route
+                "unknown tier {name:?} in `min_tier`, configured tiers are: {}",
+                rc.tiers().iter().map(|t| t.name.as_str()).join(", ")
             )
-        })?;
+        })?),
+        None if exclusive_until.is_some() => rc.lowest_tier(),
+        None => None,
+    };
+
+    Ok((min_tier.map(|t| t.name.clone()), exclusive_until))
+}
 
+fn mark_series_links(lpage: &mut LoadedPage) -> eyre::Result<()> {
+    if lpage.kind == PageKind::SeriesPart {
+        let series_index_path = lpage.route.parent().unwrap();
+
+        let unlocalized_route = lpage.unlocalized_route();
+        let part_number = unlocalized_route
+            .as_str()
+            .rsplit('/')
+            .next()
+            .and_then(|part_str| part_str.strip_prefix("part-"))
+            .and_then(|part_str| part_str.parse::<usize>().ok())
+            .map(PartNumber::new)
+            .ok_or_else(|| {
+                eyre!(
+                    "Invalid series part path: expected `{{series}}/{{slug}}/part-N`, but got '{}'",
+                    lpage.route
+                )
+            })?;
+
+        // everything else is filled in once all the parts are loaded
         lpage.series_link = Some(SeriesLink {
             index_route: series_index_path.to_owned(),
             part_number,
+            series_title: Default::default(),
+            stats: Default::default(),
+            part: None,
+            prev: None,
+            next: None,
         });
     }
 
@@ -816,3 +1266,272 @@ pub fn lrev_make_template_collection(
     let coll = modtpl.make_collection(compile_args)?;
     Ok(Arc::<dyn TemplateCollection>::from(coll))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use conflux::testing;
+
+    /// A series index at `/series/foo` with parts of the given reading times
+    /// and video durations
+    fn series(parts: &[(usize, i64, Option<u64>)], ongoing: bool) -> eyre::Result<Revision> {
+        let rc = RevisionConfig::default();
+        let mut index = testing::page(&rc, "/series/foo");
+        index.title = "Foo".into();
+        index.ongoing = ongoing;
+
+        let mut pages = vec![index];
+        for &(number, reading_time, duration) in parts {
+            let mut part = testing::page(&rc, &format!("/series/foo/part-{number}"));
+            part.title = format!("Part {number}");
+            part.reading_time = reading_time;
+            part.video_info.duration = duration;
+            mark_series_links(&mut part)?;
+            pages.push(part);
+        }
+
+        let mut rev = testing::revision(rc, pages);
+        link_series_parts(&mut rev)?;
+        Ok(rev)
+    }
+
+    fn link(rev: &Revision, route: &str) -> SeriesLink {
+        testing::page_at(rev, route).series_link.clone().unwrap()
+    }
+
+    #[test]
+    fn test_series_totals() {
+        let rev = series(&[(1, 5, Some(60)), (2, 10, None), (3, 15, Some(120))], true).unwrap();
+
+        let index = testing::page_at(&rev, "/series/foo");
+        assert_eq!(index.reading_time, 30);
+        let routes: Vec<_> = index.parts.iter().map(|p| p.route.as_str()).collect();
+        assert_eq!(
+            routes,
+            [
+                "/series/foo/part-1",
+                "/series/foo/part-2",
+                "/series/foo/part-3"
+            ]
+        );
+
+        let stats = index.series_stats.as_ref().unwrap();
+        assert_eq!(stats.num_parts, 3);
+        assert_eq!(stats.total_reading_time, 30);
+        assert_eq!(stats.total_video_duration, 180);
+        assert!(stats.ongoing);
+
+        // parts get the same stats
+        let link = link(&rev, "/series/foo/part-2");
+        assert_eq!(link.series_title, "Foo");
+        assert_eq!(link.stats.num_parts, 3);
+        assert!(link.stats.ongoing);
+    }
+
+    #[test]
+    fn test_series_cumulative_times() {
+        let rev = series(
+            &[(1, 5, Some(60)), (2, 10, None), (3, 15, Some(120))],
+            false,
+        )
+        .unwrap();
+
+        let cumulative: Vec<_> = (1..=3)
+            .map(|n| {
+                let part = link(&rev, &format!("/series/foo/part-{n}")).part.unwrap();
+                (
+                    part.reading_time,
+                    part.cumulative_reading_time,
+                    part.cumulative_video_duration,
+                )
+            })
+            .collect();
+        assert_eq!(cumulative, [(5, 5, 60), (10, 15, 60), (15, 30, 180)]);
+        assert!(
+            !testing::page_at(&rev, "/series/foo")
+                .series_stats
+                .as_ref()
+                .unwrap()
+                .ongoing
+        );
+    }
+
+    #[test]
+    fn test_series_prev_next() {
+        let rev = series(&[(1, 5, None), (2, 5, None), (3, 5, None)], false).unwrap();
+        let route = |part: Option<Part>| part.map(|p| p.route.to_string());
+
+        let first = link(&rev, "/series/foo/part-1");
+        assert_eq!(route(first.prev), None);
+        assert_eq!(route(first.next).as_deref(), Some("/series/foo/part-2"));
+
+        let middle = link(&rev, "/series/foo/part-2");
+        assert_eq!(route(middle.prev).as_deref(), Some("/series/foo/part-1"));
+        assert_eq!(route(middle.next).as_deref(), Some("/series/foo/part-3"));
+
+        let last = link(&rev, "/series/foo/part-3");
+        assert_eq!(route(last.prev).as_deref(), Some("/series/foo/part-2"));
+        assert_eq!(route(last.next), None);
+    }
+
+    #[test]
+    fn test_series_missing_part() {
+        let err = series(&[(1, 5, None), (3, 5, None)], false).unwrap_err();
+        assert!(
+            format!("{err:?}").contains("Could not find part"),
+            "got {err:?}"
+        );
+    }
+
+    fn download(path: &str) -> Download {
+        Download {
+            path: path.into(),
+            title: path.into(),
+            tier: None,
+            route: Route::new(format!("/downloads/{path}")),
+        }
+    }
+
+    #[test]
+    fn test_check_sections() {
+        let section = |name: &str, route: &str| config_types::SectionConfig {
+            name: name.into(),
+            route: route.into(),
+            kind: config_types::SectionKind::Articles,
+            sort: Default::default(),
+            indexed: true,
+            template: None,
+        };
+        let check = |sections: Vec<config_types::SectionConfig>| {
+            let rc = RevisionConfig {
+                sections,
+                ..Default::default()
+            };
+            let pages = ["/notes", "/notes/some-note", "/talks"].map(|r| testing::page(&rc, r));
+            let rev = testing::revision(rc, pages);
+            check_sections(&rev).map_err(|e| e.to_string())
+        };
+
+        // the built-in sections don't need listing pages
+        check(vec![]).unwrap();
+        check(vec![section("notes", "/notes"), section("talks", "/talks")]).unwrap();
+
+        assert_eq!(
+            check(vec![section("notes", "/notes"), section("til", "/til")]).unwrap_err(),
+            "invalid sections in home.json: section \"til\" has route /til, but there's no page there"
+        );
+        assert_eq!(
+            check(vec![section("notes", "notes/")]).unwrap_err(),
+            "invalid sections in home.json: section \"notes\" has route \"notes/\", \
+            expected something like \"/notes\""
+        );
+        assert_eq!(
+            check(vec![
+                section("notes", "/notes"),
+                section("more-notes", "/notes")
+            ])
+            .unwrap_err(),
+            "invalid sections in home.json: sections \"notes\" and \"more-notes\" both have route /notes"
+        );
+        assert_eq!(
+            check(vec![section("notes", "/notes"), section("notes", "/talks")]).unwrap_err(),
+            "invalid sections in home.json: section \"notes\" is configured twice"
+        );
+    }
+
+    #[test]
+    fn test_tag_aliases() {
+        let rc = |aliases: &[(&str, &str)]| RevisionConfig {
+            tag_aliases: aliases
+                .iter()
+                .map(|(alias, target)| (alias.to_string(), target.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+
+        let chained = rc(&[("async-rust", "async"), ("async", "concurrency")]);
+        check_tag_aliases(&chained).unwrap();
+        assert_eq!(chained.canonical_tag("async-rust"), "concurrency");
+        assert_eq!(chained.canonical_tag("async"), "concurrency");
+        assert_eq!(chained.canonical_tag("rust"), "rust");
+
+        let looping = rc(&[("a", "b"), ("b", "c"), ("c", "a")]);
+        assert_eq!(
+            check_tag_aliases(&looping).unwrap_err().to_string(),
+            "tag aliases loop: a => b => c => a"
+        );
+        // still terminates
+        looping.canonical_tag("a");
+
+        assert_eq!(
+            check_tag_aliases(&rc(&[("rust", "rust")]))
+                .unwrap_err()
+                .to_string(),
+            "tag aliases loop: rust => rust"
+        );
+        assert_eq!(
+            check_tag_aliases(&rc(&[("rust", "")]))
+                .unwrap_err()
+                .to_string(),
+            "tag alias \"rust\" => \"\": tags can't be empty"
+        );
+    }
+
+    #[test]
+    fn test_duplicate_downloads() {
+        let rc = RevisionConfig::default();
+        let mut foo = testing::page(&rc, "/articles/foo");
+        foo.downloads = vec![download("shared/slides.pdf"), download("foo/code.zip")];
+        let mut bar = testing::page(&rc, "/articles/bar");
+        bar.downloads = vec![download("bar/code.zip")];
+
+        let rev = testing::revision(rc.clone(), vec![foo.clone(), bar.clone()]);
+        let paths: Vec<_> = unique_downloads(&rev)
+            .unwrap()
+            .into_iter()
+            .map(|(_, download)| download.path.as_str())
+            .collect();
+        assert_eq!(paths, ["bar/code.zip", "foo/code.zip", "shared/slides.pdf"]);
+
+        bar.downloads.push(download("shared/slides.pdf"));
+        let rev = testing::revision(rc, vec![foo, bar]);
+        let err = unique_downloads(&rev).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "download(s) declared more than once: shared/slides.pdf \
+            (from /content/articles/bar/_index.md, /content/articles/foo/_index.md)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_downloads() {
+        let base_dir =
+            std::env::temp_dir().join(format!("librevision-test-downloads-{}", std::process::id()));
+        let ti = Arc::new(TenantInfo {
+            base_dir: camino::Utf8PathBuf::try_from(base_dir).unwrap(),
+            tc: config_types::TenantConfig::new("example.org".into()),
+        });
+
+        let rc = RevisionConfig::default();
+        let mut page = testing::page(&rc, "/articles/foo");
+        page.downloads = vec![download("foo/slides.pdf")];
+        let mut rev = testing::revision(rc, vec![page]);
+        rev.ti = ti.clone();
+
+        let err = check_downloads(&rev).await.unwrap_err().to_string();
+        assert!(
+            err.contains("extra-files/foo/slides.pdf (from /content/articles/foo/_index.md)"),
+            "got {err}"
+        );
+
+        // in development, the object store is the local cache
+        let file = ti
+            .internal_dir()
+            .join("object-cache/extra-files/foo/slides.pdf");
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(&file, b"%PDF").unwrap();
+        check_downloads(&rev).await.unwrap();
+
+        std::fs::remove_dir_all(&ti.base_dir).unwrap();
+    }
+}
@@ -7,9 +7,9 @@ use std::{
 use camino::{Utf8Path, Utf8PathBuf};
 use config_types::{RevisionConfig, TenantInfo, WebConfig};
 use conflux::{
-    Dimensions, Input, InputHash, InputPath, InputPathRef, MediaKind, MediaProps, Page, Pak,
-    PathMappings, ROOT_INPUT_PATHS, Revision, RevisionId, SvgFontFace, SvgFontFaceCollection,
-    Template,
+    DATA_INPUT_PATH, DataFile, Dimensions, Input, InputHash, InputPath, InputPathRef, MediaKind,
+    MediaProps, Page, Pak, PathMappings, REDIRECTS_INPUT_PATH, ROOT_INPUT_PATHS, Revision,
+    RevisionId, SvgFontFace, SvgFontFaceCollection, Template,
 };
 use content_type::ContentType;
 use cub_types::{IndexedRevision, PathMetadata};
@@ -41,11 +41,23 @@ pub static IGNORED_EXTS: LazyLock<HashSet<&str>> = LazyLock::new(|| {
     set
 });
 
-pub async fn is_path_ignored(path: &Utf8Path) -> bool {
-    is_path_ignored_with_meta(path, None).await
+pub async fn is_path_ignored(mappings: &PathMappings, path: &Utf8Path) -> bool {
+    is_path_ignored_with_meta(mappings, path, None).await
 }
 
-pub async fn is_path_ignored_with_meta(path: &Utf8Path, metadata: Option<&PathMetadata>) -> bool {
+pub async fn is_path_ignored_with_meta(
+    mappings: &PathMappings,
+    path: &Utf8Path,
+    metadata: Option<&PathMetadata>,
+) -> bool {
+    // extensionless, but we want it (other `_redirects` files are just noise)
+    if mappings
+        .to_input_path_maybe(path)
+        .is_some_and(|input_path| input_path == REDIRECTS_INPUT_PATH)
+    {
+        return false;
+    }
+
     let (_base, ext) = InputPathRef::from_str(path.as_str()).explode();
     IGNORED_EXTS.contains(ext)
         && match metadata {
@@ -87,6 +99,9 @@ pub async fn make_revision(
             let mut events = VecDeque::new();
             for input_path in ROOT_INPUT_PATHS {
                 let disk_path = mappings.to_disk_path(input_path)?;
+                if input_path == DATA_INPUT_PATH && !disk_path.exists() {
+                    continue;
+                }
                 let metadata = tokio::fs::metadata(disk_path).await?.into();
                 events.push_back(InputEvent::Created {
                     path: InputPath::from(input_path),
@@ -329,6 +344,16 @@ pub async fn make_revision(
     tracing::info!("Revision config: {}", rc.pretty());
     pak.rc = rc;
 
+    if pak.inputs.contains_key(REDIRECTS_INPUT_PATH) {
+        let redirects_disk_path = mappings.to_disk_path(REDIRECTS_INPUT_PATH)?;
+        let redirects_contents = tokio::fs::read_to_string(&redirects_disk_path)
+            .await
+            .wrap_err_with(|| {
+                format!("Failed to read {REDIRECTS_INPUT_PATH} at {redirects_disk_path}")
+            })?;
+        pak.redirects = Some(redirects_contents);
+    }
+
     let load_pak_start = Instant::now();
     let rev = load_pak(pak, ti, prev_rev.as_deref(), mappings, web).await?;
     let load_pak_duration = load_pak_start.elapsed();
@@ -410,7 +435,7 @@ impl MakeContext {
                         let entry = entry.unwrap();
                         let path = entry.path();
                         let metadata = PathMetadata::from(entry.metadata().unwrap());
-                        if is_path_ignored_with_meta(path, Some(&metadata)).await {
+                        if is_path_ignored_with_meta(&self.mappings, path, Some(&metadata)).await {
                             continue;
                         }
 
@@ -441,7 +466,7 @@ impl MakeContext {
                         let entry = entry.unwrap();
                         let path = entry.path();
                         let metadata = PathMetadata::from(entry.metadata().unwrap());
-                        if is_path_ignored_with_meta(path, Some(&metadata)).await {
+                        if is_path_ignored_with_meta(&self.mappings, path, Some(&metadata)).await {
                             continue;
                         }
 
@@ -543,6 +568,7 @@ pub(crate) enum AddAction {
     InsertPage { path: InputPath, page: Page },
     InsertMediaProps { path: InputPath, props: MediaProps },
     InsertTemplate { path: InputPath, template: Template },
+    InsertData { path: InputPath, data: DataFile },
     Error(eyre::Report),
 }
 
@@ -561,6 +587,9 @@ impl AddAction {
             AddAction::InsertTemplate { path, template } => {
                 pak.templates.insert(path, template);
             }
+            AddAction::InsertData { path, data } => {
+                pak.data.get_or_insert_default().insert(path, data);
+            }
             AddAction::Error(e) => {
                 return Err(e);
             }
@@ -579,7 +608,7 @@ async fn revision_added(
     media_props_cache: Arc<MediaPropsCache>,
 ) -> eyre::Result<()> {
     let disk_path = mappings.to_disk_path(&path)?;
-    if is_path_ignored(&disk_path).await {
+    if is_path_ignored(&mappings, &disk_path).await {
         return Ok(());
     }
     let contents = tokio::fs::read(&disk_path).await?;
@@ -732,6 +761,19 @@ async fn revision_added(
             })
             .await?;
         }
+        ContentType::JSON | ContentType::YAML | ContentType::TOML
+            if path.as_str().starts_with(&format!("{DATA_INPUT_PATH}/")) =>
+        {
+            // parsed when loading the revision, so errors point at the file
+            tx.send(AddAction::InsertData {
+                path: path.to_owned(),
+                data: DataFile {
+                    path: path.to_owned(),
+                    markup: String::from_utf8(contents)?,
+                },
+            })
+            .await?;
+        }
         ContentType::Jinja => {
             tx.send(AddAction::InsertTemplate {
                 path: path.to_owned(),
@@ -775,6 +817,9 @@ pub(crate) async fn revision_removed(revision: &mut Pak, path: &InputPathRef) ->
     revision.pages.remove(path);
     revision.templates.remove(path);
     revision.media_props.remove(path);
+    if let Some(data) = revision.data.as_mut() {
+        data.remove(path);
+    }
 
     Ok(())
 }
@@ -789,6 +834,8 @@ pub fn revision_new() -> Pak {
         media_props: Default::default(),
         svg_font_face_collection: Default::default(),
         rc: Default::default(),
+        redirects: Default::default(),
+        data: Default::default(),
     }
 }
 
@@ -806,13 +853,14 @@ pub async fn wake_revision_events(
     let mut dirs = ROOT_INPUT_PATHS
         .iter()
         .map(|&path| mappings.to_disk_path(path).unwrap())
+        .filter(|disk_path| disk_path.exists())
         .collect::<Vec<_>>();
     while let Some(dir) = dirs.pop() {
         let mut read_dir = tokio::fs::read_dir(dir).await?;
         while let Some(entry) = read_dir.next_entry().await? {
             let path = Utf8PathBuf::from_path_buf(entry.path()).unwrap();
             let metadata = PathMetadata::from(entry.metadata().await?);
-            if is_path_ignored_with_meta(&path, Some(&metadata)).await {
+            if is_path_ignored_with_meta(&mappings, &path, Some(&metadata)).await {
                 continue;
             }
 
@@ -10,9 +10,11 @@ use tracing::warn;
 
 use crate::{RevisionKind, RevisionSpec};
 
+pub mod data;
 pub mod frontmatter;
 pub mod load;
 pub mod make;
+pub mod related;
 pub mod watch;
 
 pub fn revision_error_from_report(e: eyre::Report) -> RevisionError {
@@ -0,0 +1,225 @@
+use std::collections::{HashMap, HashSet};
+
+use conflux::{InputPath, LoadedPage, RelatedPage, Revision, RouteRef};
+use libsearch::Index;
+
+/// How many related pages we keep around per page. Templates usually show
+/// a handful, but some of these might be invisible to the current viewer.
+const MAX_RELATED: usize = 20;
+
+/// How many text-similar pages we ask the search index for
+const MAX_SIMILAR: usize = 30;
+
+const SHARED_TAG_WEIGHT: f32 = 3.0;
+const SHARED_CRATE_WEIGHT: f32 = 2.0;
+const SHARED_REPO_WEIGHT: f32 = 2.0;
+const LINK_WEIGHT: f32 = 4.0;
+
+/// The best text match is worth this much, others are scaled down from there
+const SIMILARITY_WEIGHT: f32 = 5.0;
+
+/// For every indexed page, score every other indexed page by shared tags,
+/// crates, github repos, links in either direction, and text similarity.
+pub(crate) fn compute_related_pages(
+    rev: &Revision,
+    index: &dyn Index,
+) -> HashMap<InputPath, Vec<RelatedPage>> {
+    let candidates: Vec<&LoadedPage> = rev
+        .pages
+        .values()
+        .map(|p| p.as_ref())
+        .filter(|p| p.is_indexed())
+        .collect();
+
+    let links: HashMap<&InputPath, HashSet<InputPath>> = candidates
+        .iter()
+        .map(|p| (&p.path, link_targets(rev, p)))
+        .collect();
+
+    let mut related: HashMap<InputPath, Vec<RelatedPage>> = Default::default();
+
+    for page in &candidates {
+        let mut scores: HashMap<&InputPath, f32> = Default::default();
+
+        let similar = index.similar_pages(page, MAX_SIMILAR);
+        let best = similar.iter().map(|(_, s)| *s).fold(0.0, f32::max);
+        if best > 0.0 {
+            for (path, score) in &similar {
+                // the search index has more than just the candidates
+                if let Some((path, _)) = links.get_key_value(path) {
+                    *scores.entry(*path).or_default() += SIMILARITY_WEIGHT * score / best;
+                }
+            }
+        }
+
+        let page_links = &links[&page.path];
+        for other in &candidates {
+            // translations are never related to pages in other languages
+            if other.path == page.path || other.lang != page.lang {
+                continue;
+            }
+
+            let shared_tags = page.tags.iter().filter(|t| other.tags.contains(t)).count();
+            let shared_crates = page
+                .crates
+                .keys()
+                .filter(|c| other.crates.contains_key(*c))
+                .count();
+            let shared_repos = page
+                .github_repos
+                .iter()
+                .filter(|r| other.github_repos.contains(r))
+                .count();
+            let num_links = [
+                page_links.contains(&other.path),
+                links[&other.path].contains(&page.path),
+            ]
+            .into_iter()
+            .filter(|linked| *linked)
+            .count();
+
+            let score = SHARED_TAG_WEIGHT * shared_tags as f32
+                + SHARED_CRATE_WEIGHT * shared_crates as f32
+                + SHARED_REPO_WEIGHT * shared_repos as f32
+                + LINK_WEIGHT * num_links as f32;
+            if score > 0.0 {
+                *scores.entry(&other.path).or_default() += score;
+            }
+        }
+
+        let mut scored: Vec<(&InputPath, f32)> = scores.into_iter().collect();
+        // best score first, most recent first on ties so results are stable
+        scored.sort_by(|(a_path, a_score), (b_path, b_score)| {
+            b_score
+                .total_cmp(a_score)
+                .then_with(|| rev.pages[*b_path].date.cmp(&rev.pages[*a_path].date))
+                .then_with(|| a_path.cmp(b_path))
+        });
+        scored.truncate(MAX_RELATED);
+
+        related.insert(
+            page.path.clone(),
+            scored
+                .into_iter()
+                .map(|(path, score)| RelatedPage {
+                    path: path.clone(),
+                    score,
+                })
+                .collect(),
+        );
+    }
+
+    related
+}
+
+/// Resolves a page's links to the input paths of pages in this revision.
+/// Links to other sites, assets, or routes we don't know about are ignored.
+fn link_targets(rev: &Revision, page: &LoadedPage) -> HashSet<InputPath> {
+    let base_url = rev.ti.tc.web_base_url(page.web);
+
+    page.links
+        .iter()
+        .filter_map(|href| {
+            let href = href.as_str();
+            let href = href.strip_prefix(base_url.as_str()).unwrap_or(href);
+            if !href.starts_with('/') {
+                return None;
+            }
+            let route = href.split(['#', '?']).next().unwrap_or_default();
+            let route = RouteRef::from_str(route).trim_trailing_slash();
+            rev.page_routes.get(&route).cloned()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config_types::RevisionConfig;
+    use conflux::{Href, testing};
+
+    fn article(rc: &RevisionConfig, route: &str, tags: &[&str]) -> LoadedPage {
+        let mut page = testing::page(rc, route);
+        page.tags = tags.iter().map(|t| t.to_string()).collect();
+        page
+    }
+
+    fn related_routes(rev: &Revision, route: &str) -> Vec<(String, f32)> {
+        let mut indexer = libsearch::load().indexer(rev.pak.rc.default_lang());
+        for (path, page) in &rev.pages {
+            if page.is_indexed() {
+                indexer.insert(path.clone(), page);
+            }
+        }
+        let index = indexer.commit();
+        let related = compute_related_pages(rev, index.as_ref());
+        related[&testing::page_at(rev, route).path]
+            .iter()
+            .map(|r| (rev.pages[&r.path].route.to_string(), r.score))
+            .collect()
+    }
+
+    #[test]
+    fn test_related_pages_scoring() {
+        let rc = RevisionConfig::default();
+        let mut a = article(&rc, "/articles/a", &["rust", "async"]);
+        a.github_repos = vec!["tokio-rs/tokio".into()];
+        let b = article(&rc, "/articles/b", &["rust", "async"]);
+        let mut c = article(&rc, "/articles/c", &["rust"]);
+        c.links = vec![Href::new("/articles/a/#intro".to_string())];
+        let mut d = article(&rc, "/articles/d", &[]);
+        d.github_repos = vec!["tokio-rs/tokio".into()];
+        let e = article(&rc, "/articles/e", &["baking"]);
+        let rev = testing::revision(rc, [a, b, c, d, e]);
+
+        assert_eq!(
+            related_routes(&rev, "/articles/a"),
+            vec![
+                // one shared tag and a link to `a`
+                ("/articles/c".to_string(), SHARED_TAG_WEIGHT + LINK_WEIGHT),
+                ("/articles/b".to_string(), SHARED_TAG_WEIGHT * 2.0),
+                ("/articles/d".to_string(), SHARED_REPO_WEIGHT),
+            ]
+        );
+        // links count in both directions
+        assert_eq!(
+            related_routes(&rev, "/articles/c"),
+            vec![
+                ("/articles/a".to_string(), SHARED_TAG_WEIGHT + LINK_WEIGHT),
+                ("/articles/b".to_string(), SHARED_TAG_WEIGHT),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_related_pages_ties_prefer_recent_pages() {
+        let rc = RevisionConfig::default();
+        let a = article(&rc, "/articles/a", &["rust"]);
+        let older = article(&rc, "/articles/older", &["rust"]);
+        let mut newer = article(&rc, "/articles/newer", &["rust"]);
+        newer.date.0 += time::Duration::days(1);
+        let rev = testing::revision(rc, [a, older, newer]);
+
+        let routes: Vec<_> = related_routes(&rev, "/articles/a")
+            .into_iter()
+            .map(|(route, _)| route)
+            .collect();
+        assert_eq!(routes, vec!["/articles/newer", "/articles/older"]);
+    }
+
+    #[test]
+    fn test_related_pages_only_considers_indexed_pages_in_the_same_language() {
+        let rc = RevisionConfig::default();
+        let a = article(&rc, "/articles/a", &["rust"]);
+        let mut archived = article(&rc, "/articles/archived", &["rust"]);
+        archived.archive = true;
+        let mut translated = article(&rc, "/articles/b", &["rust"]);
+        translated.path = InputPath::new("/content/articles/b/_index.fr.md".to_string());
+        translated.route = "/fr/articles/b".into();
+        translated.lang = "fr".into();
+        let about = article(&rc, "/about", &["rust"]);
+        let rev = testing::revision(rc, [a, archived, translated, about]);
+
+        assert!(related_routes(&rev, "/articles/a").is_empty());
+    }
+}
@@ -105,13 +105,14 @@ pub async fn start_watching(tenant: Arc<dyn CubTenant>, web: WebConfig) -> Resul
     let (event_tx, mut event_rx) = mpsc::channel::<eyre::Result<WatcherEvent>>(128);
 
     // Spawn background task to handle async path filtering
+    let ignore_mappings = PathMappings::from_ti(tenant.ti());
     tokio::spawn(async move {
         while let Some(res) = event_rx.recv().await {
             match res {
                 Ok(event) => {
                     let mut filtered_paths = Vec::new();
                     for path in event.paths {
-                        if !is_path_ignored(&path).await {
+                        if !is_path_ignored(&ignore_mappings, &path).await {
                             filtered_paths.push(path);
                         }
                     }
@@ -64,6 +64,12 @@ impl Mod for ModImpl {
         Box::pin(impls::watch::start_watching(tenant, web))
     }
 
+    /// Rewrites TOML frontmatter (`+++`) in a markdown page as YAML (`---`),
+    /// returns `None` if the page doesn't start with TOML frontmatter
+    fn migrate_frontmatter(&self, path: &str, markup: &str) -> eyre::Result<Option<String>> {
+        impls::frontmatter::migrate_frontmatter(path, markup)
+    }
+
     fn save_pak_to_disk_as_active<'fut>(
         &'fut self,
         pak: &'fut conflux::Pak,
@@ -5,12 +5,17 @@ use conflux::RevisionView;
 use conflux::{Completion, InputPath, LoadedPage, SearchResults, Viewer};
 use conflux::{CompletionKind, Html, SearchResult};
 
+use std::{collections::HashMap, sync::Arc};
+
 use tantivy::{
-    SnippetGenerator, TantivyDocument,
+    SnippetGenerator, TantivyDocument, Term,
     collector::{Count, TopDocs},
+    query::{BooleanQuery, MoreLikeThisQuery, Occur, Query, TermQuery},
     schema::{
-        INDEXED, IndexRecordOption, STORED, Schema, TEXT, TextFieldIndexing, TextOptions, Value,
+        INDEXED, IndexRecordOption, OwnedValue, STORED, Schema, TEXT, TextFieldIndexing,
+        TextOptions, Value,
     },
+    tokenizer::{Language, LowerCaser, RemoveLongFilter, SimpleTokenizer, Stemmer, TextAnalyzer},
 };
 
 pub use eyre::Result;
@@ -25,42 +30,123 @@ pub fn load() -> &'static dyn Mod {
 
 #[autotrait]
 impl Mod for ModImpl {
-    fn indexer(&self) -> Box<dyn Indexer> {
+    /// `default_lang` is the language of pages that don't specify one, and
+    /// the one we search in when callers don't ask for a specific language.
+    fn indexer(&self, default_lang: &str) -> Box<dyn Indexer> {
+        let isi = indicium::simple::SearchIndexBuilder::default()
+            .max_string_len(Some(0))
+            .build();
+
+        Box::new(IndexerImpl {
+            isi,
+            default_lang: default_lang.to_string(),
+            langs: Default::default(),
+        })
+    }
+}
+
+/// The tantivy index for pages in a single language
+struct LangIndexer {
+    schema: Schema,
+    index: tantivy::Index,
+    index_writer: tantivy::IndexWriter<TantivyDocument>,
+}
+
+impl LangIndexer {
+    fn new(lang: &str) -> Self {
+        let tokenizer = stemming_tokenizer(lang);
+
         let mut schema_builder = Schema::builder();
 
         let text_options = TextOptions::default().set_indexing_options(
             TextFieldIndexing::default()
-                .set_tokenizer("en_stem")
+                .set_tokenizer(&tokenizer.name)
                 .set_index_option(IndexRecordOption::WithFreqsAndPositions),
         );
 
         schema_builder.add_text_field("path", TEXT | STORED);
         schema_builder.add_bool_field("draft", INDEXED);
+        // members-only for good (see `LoadedPage::min_tier`). Pages that are
+        // only exclusive until some date are filtered when they're shown.
+        schema_builder.add_bool_field("exclusive", INDEXED);
         schema_builder.add_bool_field("dual_feature", INDEXED);
         schema_builder.add_text_field("title", text_options.clone());
         schema_builder.add_text_field("body", text_options);
         let schema = schema_builder.build();
 
         let index = tantivy::Index::create_in_ram(schema.clone());
+        if let Some(analyzer) = tokenizer.analyzer {
+            index.tokenizers().register(&tokenizer.name, analyzer);
+        }
         let index_writer = index.writer(100_000_000).unwrap();
-        let isi = indicium::simple::SearchIndexBuilder::default()
-            .max_string_len(Some(0))
-            .build();
 
-        Box::new(IndexerImpl {
-            isi,
+        Self {
+            schema,
             index,
             index_writer,
-            schema,
-        })
+        }
+    }
+}
+
+struct Tokenizer {
+    name: String,
+    /// `None` for tokenizers tantivy registers by default
+    analyzer: Option<TextAnalyzer>,
+}
+
+/// Picks a stemming tokenizer for a language code like `fr` or `pt-BR`,
+/// falling back to tantivy's non-stemming default tokenizer.
+fn stemming_tokenizer(lang: &str) -> Tokenizer {
+    let primary = lang.split('-').next().unwrap_or_default();
+    let language = match primary {
+        "en" => {
+            return Tokenizer {
+                name: "en_stem".to_string(),
+                analyzer: None,
+            };
+        }
+        "ar" => Language::Arabic,
+        "da" => Language::Danish,
+        "de" => Language::German,
+        "el" => Language::Greek,
+        "es" => Language::Spanish,
+        "fi" => Language::Finnish,
+        "fr" => Language::French,
+        "hu" => Language::Hungarian,
+        "it" => Language::Italian,
+        "nl" => Language::Dutch,
+        "no" | "nb" | "nn" => Language::Norwegian,
+        "pt" => Language::Portuguese,
+        "ro" => Language::Romanian,
+        "ru" => Language::Russian,
+        "sv" => Language::Swedish,
+        "ta" => Language::Tamil,
+        "tr" => Language::Turkish,
+        _ => {
+            tracing::debug!("No stemmer for language {lang:?}, using default tokenizer");
+            return Tokenizer {
+                name: "default".to_string(),
+                analyzer: None,
+            };
+        }
+    };
+
+    Tokenizer {
+        name: format!("{primary}_stem"),
+        analyzer: Some(
+            TextAnalyzer::builder(SimpleTokenizer::default())
+                .filter(RemoveLongFilter::limit(40))
+                .filter(LowerCaser)
+                .filter(Stemmer::new(language))
+                .build(),
+        ),
     }
 }
 
 struct IndexerImpl {
     isi: indicium::simple::SearchIndex<InputPath>,
-    schema: Schema,
-    index: tantivy::Index,
-    index_writer: tantivy::IndexWriter<TantivyDocument>,
+    default_lang: String,
+    langs: HashMap<String, LangIndexer>,
 }
 
 // TODO: fallible ops
@@ -73,60 +159,100 @@ impl Indexer for IndexerImpl {
             &IndexableCompat(vec![page.title.clone(), page.plain_text.clone()]),
         );
 
+        let li = self
+            .langs
+            .entry(page.lang.clone())
+            .or_insert_with(|| LangIndexer::new(&page.lang));
+
         let mut doc = TantivyDocument::default();
-        let path = self.schema.get_field("path").unwrap();
-        let draft = self.schema.get_field("draft").unwrap();
-        let dual_feature = self.schema.get_field("dual_feature").unwrap();
-        let title = self.schema.get_field("title").unwrap();
-        let body = self.schema.get_field("body").unwrap();
+        let path = li.schema.get_field("path").unwrap();
+        let draft = li.schema.get_field("draft").unwrap();
+        let exclusive = li.schema.get_field("exclusive").unwrap();
+        let dual_feature = li.schema.get_field("dual_feature").unwrap();
+        let title = li.schema.get_field("title").unwrap();
+        let body = li.schema.get_field("body").unwrap();
 
         doc.add_text(path, key.as_str());
         doc.add_bool(draft, page.draft);
+        doc.add_bool(
+            exclusive,
+            page.min_tier.is_some() && page.exclusive_until.is_none(),
+        );
         doc.add_bool(dual_feature, page.video_info.dual_feature);
         doc.add_text(title, &page.title);
         doc.add_text(body, &page.plain_text);
 
-        self.index_writer.add_document(doc).unwrap();
+        li.index_writer.add_document(doc).unwrap();
     }
 
     fn commit(self: Box<Self>) -> Box<dyn Index> {
-        let isi = self.isi;
-        let index = self.index;
-        let mut index_writer = self.index_writer;
-        let schema = self.schema;
-
-        index_writer.commit().unwrap();
-        let index_reader = index
-            .reader_builder()
-            .reload_policy(tantivy::ReloadPolicy::OnCommitWithDelay)
-            .try_into()
-            .unwrap();
+        let langs = self
+            .langs
+            .into_iter()
+            .map(|(lang, li)| {
+                let LangIndexer {
+                    schema,
+                    index,
+                    mut index_writer,
+                } = li;
+
+                index_writer.commit().unwrap();
+                let index_reader = index
+                    .reader_builder()
+                    .reload_policy(tantivy::ReloadPolicy::OnCommitWithDelay)
+                    .try_into()
+                    .unwrap();
+
+                (
+                    lang,
+                    LangIndex {
+                        schema,
+                        index,
+                        index_reader,
+                    },
+                )
+            })
+            .collect();
 
         Box::new(IndexImpl {
-            isi,
-            schema,
-            index,
-            index_reader,
+            isi: self.isi,
+            default_lang: self.default_lang,
+            langs,
         })
     }
 }
 
 struct IndexImpl {
     isi: indicium::simple::SearchIndex<InputPath>,
+    default_lang: String,
+    langs: HashMap<String, LangIndex>,
+}
+
+struct LangIndex {
     schema: Schema,
     index: tantivy::Index,
     index_reader: tantivy::IndexReader,
 }
 
 impl IndexImpl {
+    /// `None` if there are no indexed pages in that language
+    fn lang_index(&self, lang: Option<&str>) -> Option<&LangIndex> {
+        self.langs.get(lang.unwrap_or(&self.default_lang))
+    }
+
     fn search_inner(
         &self,
         rv: &dyn RevisionView,
         viewer: &Viewer,
         query: &str,
+        lang: Option<&str>,
         per_page: usize,
         page_number: usize,
     ) -> Result<SearchResults> {
+        let Some(li) = self.lang_index(lang) else {
+            return Ok(Default::default());
+        };
+
         let query: String = if viewer.is_admin {
             query.to_string()
         } else {
@@ -135,18 +261,18 @@ impl IndexImpl {
             format!("{query} AND draft:false")
         };
 
-        let searcher = self.index_reader.searcher();
+        let searcher = li.index_reader.searcher();
         let mut query_parser = tantivy::query::QueryParser::for_index(
-            &self.index,
+            &li.index,
             vec![
-                self.schema.get_field("title").unwrap(),
-                self.schema.get_field("body").unwrap(),
-                self.schema.get_field("draft").unwrap(),
+                li.schema.get_field("title").unwrap(),
+                li.schema.get_field("body").unwrap(),
+                li.schema.get_field("draft").unwrap(),
             ],
         );
 
-        let title = self.schema.get_field("title").unwrap();
-        let body = self.schema.get_field("body").unwrap();
+        let title = li.schema.get_field("title").unwrap();
+        let body = li.schema.get_field("body").unwrap();
         query_parser.set_field_boost(title, 3.0);
 
         tracing::debug!("query = {query}");
@@ -190,7 +316,7 @@ impl IndexImpl {
         let mut body_snippet_generator = SnippetGenerator::create(&searcher, &*query, body)?;
         body_snippet_generator.set_max_num_chars(350);
 
-        let path = self.schema.get_field("path").unwrap();
+        let path = li.schema.get_field("path").unwrap();
         let rev = rv.rev()?;
 
         for (_score, doc_address) in top_docs {
@@ -198,7 +324,12 @@ impl IndexImpl {
 
             let doc_path = doc.get_first(path).unwrap();
             let doc_path = InputPath::new(doc_path.as_str().unwrap().to_owned());
-            let page = rev.pages.get(&doc_path).unwrap().clone();
+            let mut page = rev.pages.get(&doc_path).unwrap().clone();
+            if page.is_paywalled_for(viewer, &rev.pak.rc) {
+                // snippets only come from the excerpt, and the body isn't
+                // available to templates at all
+                page = Arc::new(page.to_paywalled(Default::default()));
+            }
 
             let title_snippet = title_snippet_generator.snippet(&page.title).to_html();
             let body_snippet = body_snippet_generator.snippet(&page.plain_text);
@@ -341,17 +472,18 @@ impl IndexImpl {
         rv: &dyn RevisionView,
         viewer: &Viewer,
         query_str: &str,
+        lang: Option<&str>,
         web: WebConfig,
     ) -> eyre::Result<Vec<Completion>> {
         let mut results: Vec<Completion> = Default::default();
 
         // use tantivy look for articles with titles that strongly match
-        {
-            let title = self.schema.get_field("title").unwrap();
-            let searcher = self.index_reader.searcher();
+        if let Some(li) = self.lang_index(lang) {
+            let title = li.schema.get_field("title").unwrap();
+            let searcher = li.index_reader.searcher();
             let query_parser = tantivy::query::QueryParser::for_index(
-                &self.index,
-                vec![self.schema.get_field("title").unwrap()],
+                &li.index,
+                vec![li.schema.get_field("title").unwrap()],
             );
             let query_str = if viewer.is_admin {
                 query_str.to_string()
@@ -376,9 +508,7 @@ impl IndexImpl {
 
             for (score, doc_address) in top_docs {
                 let doc: TantivyDocument = searcher.doc(doc_address)?;
-                let doc_path = doc
-                    .get_first(self.schema.get_field("path").unwrap())
-                    .unwrap();
+                let doc_path = doc.get_first(li.schema.get_field("path").unwrap()).unwrap();
                 let doc_path = InputPath::new(doc_path.as_str().unwrap().to_owned());
                 tracing::debug!("score = {score}, doc_path = {doc_path}");
 
@@ -414,6 +544,70 @@ impl IndexImpl {
     }
 }
 
+impl IndexImpl {
+    fn similar_pages_inner(
+        &self,
+        page: &LoadedPage,
+        limit: usize,
+    ) -> eyre::Result<Vec<(InputPath, f32)>> {
+        // only pages in the same language can be similar, text-wise
+        let Some(li) = self.lang_index(Some(&page.lang)) else {
+            return Ok(Default::default());
+        };
+
+        let searcher = li.index_reader.searcher();
+        let path = li.schema.get_field("path").unwrap();
+        let title = li.schema.get_field("title").unwrap();
+        let body = li.schema.get_field("body").unwrap();
+        let draft = li.schema.get_field("draft").unwrap();
+        let exclusive = li.schema.get_field("exclusive").unwrap();
+
+        // the defaults are tuned for huge corpora, we only have a few hundred pages
+        let more_like_this = MoreLikeThisQuery::builder()
+            .with_min_doc_frequency(1)
+            .with_min_term_frequency(2)
+            .with_min_word_length(3)
+            .with_max_query_terms(50)
+            .with_document_fields(vec![
+                (title, vec![OwnedValue::from(page.title.as_str())]),
+                (body, vec![OwnedValue::from(page.plain_text.as_str())]),
+            ]);
+        // related pages are shown to everyone, they must not point readers
+        // at drafts, nor at members-only pages through their text
+        let excluded = |field| -> (Occur, Box<dyn Query>) {
+            let term = Term::from_field_bool(field, true);
+            (
+                Occur::MustNot,
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+            )
+        };
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, Box::new(more_like_this)),
+            excluded(draft),
+            excluded(exclusive),
+        ]);
+
+        // one extra, since the page itself is usually the best match
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(limit + 1))?;
+
+        let mut results = Vec::with_capacity(top_docs.len());
+        for (score, doc_address) in top_docs {
+            let doc: TantivyDocument = searcher.doc(doc_address)?;
+            let doc_path = doc
+                .get_first(path)
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| eyre::eyre!("indexed document has no path"))?;
+            if doc_path == page.path.as_str() {
+                continue;
+            }
+            results.push((InputPath::new(doc_path.to_owned()), score));
+        }
+        results.truncate(limit);
+
+        Ok(results)
+    }
+}
+
 #[autotrait]
 impl Index for IndexImpl {
     fn autocomplete(
@@ -421,9 +615,10 @@ impl Index for IndexImpl {
         rv: &dyn RevisionView,
         viewer: &Viewer,
         query: &str,
+        lang: Option<&str>,
         web: WebConfig,
     ) -> Vec<Completion> {
-        match self.autocomplete_inner(rv, viewer, query, web) {
+        match self.autocomplete_inner(rv, viewer, query, lang, web) {
             Ok(results) => results,
             Err(e) => {
                 tracing::warn!("Failed to autocomplete: {e}");
@@ -437,10 +632,11 @@ impl Index for IndexImpl {
         rv: &dyn RevisionView,
         viewer: &Viewer,
         query: &str,
+        lang: Option<&str>,
         per_page: usize,
         page_number: usize,
     ) -> SearchResults {
-        match self.search_inner(rv, viewer, query, per_page, page_number) {
+        match self.search_inner(rv, viewer, query, lang, per_page, page_number) {
             Ok(results) => results,
             Err(e) => {
                 tracing::warn!("Failed to search index: {e}");
@@ -448,6 +644,19 @@ impl Index for IndexImpl {
             }
         }
     }
+
+    /// Returns indexed pages whose text is similar to the given page's,
+    /// best match first, along with their (unnormalized) scores. Drafts and
+    /// members-only pages are never returned.
+    fn similar_pages(&self, page: &LoadedPage, limit: usize) -> Vec<(InputPath, f32)> {
+        match self.similar_pages_inner(page, limit) {
+            Ok(results) => results,
+            Err(e) => {
+                tracing::warn!("Failed to find pages similar to {}: {e}", page.path);
+                Default::default()
+            }
+        }
+    }
 }
 
 struct IndexableCompat(Vec<String>);
@@ -498,3 +707,86 @@ const CUSTOM_FRAGMENT_ENCODE_SET: &AsciiSet = &FRAGMENT_ENCODE_SET_BASE
 fn fragment_urlencode(input: &[u8]) -> String {
     percent_encode(input, CUSTOM_FRAGMENT_ENCODE_SET).to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config_types::RevisionConfig;
+
+    const TEXT: &str = "Rust futures are polled by an executor. The executor polls futures \
+        until they are ready, and futures register wakers with the executor.";
+
+    fn page_with_text(rc: &RevisionConfig, route: &str, text: &str) -> LoadedPage {
+        let mut page = conflux::testing::page(rc, route);
+        page.title = "Futures and executors".into();
+        page.plain_text = text.into();
+        page
+    }
+
+    fn similar_routes(pages: &[LoadedPage], to: &LoadedPage) -> Vec<String> {
+        let mut indexer = load().indexer("en");
+        for page in pages {
+            indexer.insert(page.path.clone(), page);
+        }
+        let index = indexer.commit();
+        index
+            .similar_pages(to, 10)
+            .into_iter()
+            .map(|(path, _)| {
+                let page = pages.iter().find(|p| p.path == path).unwrap();
+                page.route.to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_similar_pages_skips_the_page_itself() {
+        let rc = RevisionConfig::default();
+        let page = page_with_text(&rc, "/articles/a", TEXT);
+        let other = page_with_text(&rc, "/articles/b", TEXT);
+        let unrelated = page_with_text(
+            &rc,
+            "/articles/c",
+            "Bread needs flour, water, salt and yeast. Knead it, then let it rest.",
+        );
+
+        let similar = similar_routes(&[page.clone(), other, unrelated], &page);
+        assert_eq!(similar, vec!["/articles/b"]);
+    }
+
+    #[test]
+    fn test_similar_pages_excludes_drafts_and_exclusive_pages() {
+        let rc = RevisionConfig::default();
+        let page = page_with_text(&rc, "/articles/a", TEXT);
+        let public = page_with_text(&rc, "/articles/public", TEXT);
+        let mut draft = page_with_text(&rc, "/articles/draft", TEXT);
+        draft.draft = true;
+        let mut exclusive = page_with_text(&rc, "/articles/exclusive", TEXT);
+        exclusive.min_tier = Some("Silver".into());
+        let mut formerly_exclusive = page_with_text(&rc, "/articles/formerly-exclusive", TEXT);
+        formerly_exclusive.min_tier = Some("Silver".into());
+        // the test page's date is long gone
+        formerly_exclusive.exclusive_until = Some(formerly_exclusive.date.clone());
+
+        let mut similar = similar_routes(
+            &[page.clone(), public, draft, exclusive, formerly_exclusive],
+            &page,
+        );
+        similar.sort();
+        assert_eq!(
+            similar,
+            vec!["/articles/formerly-exclusive", "/articles/public"]
+        );
+    }
+
+    #[test]
+    fn test_similar_pages_stays_within_a_language() {
+        let rc = RevisionConfig::default();
+        let page = page_with_text(&rc, "/articles/a", TEXT);
+        let mut french = page_with_text(&rc, "/fr/articles/a", TEXT);
+        french.lang = "fr".into();
+
+        let similar = similar_routes(&[page.clone(), french], &page);
+        assert!(similar.is_empty(), "got {similar:?}");
+    }
+}
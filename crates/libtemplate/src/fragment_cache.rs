@@ -0,0 +1,230 @@
+use std::sync::{Arc, Mutex};
+
+use conflux::{RevisionId, Viewer};
+use minijinja::{
+    Error, State,
+    value::{Kwargs, Value},
+};
+use time::OffsetDateTime;
+
+use crate::conversions::ToMinijinaResult;
+use crate::global_functions_and_filters::{current_viewer, get_revision_view};
+
+/// How many fragments we keep per revision, least recently used ones go
+/// first.
+const FRAGMENT_CACHE_CAPACITY: usize = 1024;
+
+/// Rendered output of `{% call cache(key) %}` blocks. There's one per
+/// template collection, and so one per revision: switching revisions drops
+/// the whole thing. Things that change between revisions (sponsors) clear it
+/// too, see `TemplateCollection::clear_fragment_cache`, and so does the next
+/// page going live or opening up.
+pub(crate) struct FragmentCache {
+    cache: dumbcache::Cache,
+
+    /// When the cached fragments get stale, see
+    /// `Revision::next_time_boundary`
+    expires_at: Mutex<Option<OffsetDateTime>>,
+}
+
+impl FragmentCache {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            cache: dumbcache::Cache::new("template fragments", FRAGMENT_CACHE_CAPACITY),
+            expires_at: Mutex::new(None),
+        })
+    }
+
+    /// Used as `{% call cache(key) %}...{% endcall %}`: the block is
+    /// rendered once per (template, revision, viewer tier, key), then served
+    /// from memory. Viewers with the same tier share fragments, so they
+    /// mustn't show anything personal, like the viewer's name.
+    pub(crate) fn call(&self, state: &State, key: Value, kwargs: Kwargs) -> Result<Value, Error> {
+        let caller: Option<Value> = kwargs.get("caller")?;
+        kwargs.assert_all_used()?;
+        let Some(caller) = caller else {
+            return Err(Error::new(
+                minijinja::ErrorKind::InvalidOperation,
+                "cache: use it as a block, `{% call cache(key) %}...{% endcall %}`",
+            ));
+        };
+
+        let rv = get_revision_view(state);
+        let rev = rv.rev().mj()?;
+        let now = OffsetDateTime::now_utc();
+        self.expire(now);
+
+        let cache_key = cache_key(state.name(), &rev.pak.id, &current_viewer(state), &key);
+
+        if let Some(html) = self.cache.get(&cache_key) {
+            return Ok(Value::from_safe_string(html));
+        }
+
+        let html = caller.call(state, &[])?.to_string();
+        self.cache.insert(cache_key, html.clone());
+        if let Some(boundary) = rev.next_time_boundary(now) {
+            let mut expires_at = self.expires_at.lock().unwrap();
+            *expires_at = Some(expires_at.map_or(boundary, |at| at.min(boundary)));
+        }
+
+        let stats = self.cache.stats();
+        tracing::debug!(
+            "Fragment cache miss for {key} in {}, hit rate {:.2}% over {} lookups",
+            state.name(),
+            stats.hit_rate() * 100.0,
+            stats.hits + stats.misses,
+        );
+        Ok(Value::from_safe_string(html))
+    }
+
+    /// Drops every fragment once a page they could show went live or opened
+    /// up since they were rendered
+    fn expire(&self, now: OffsetDateTime) {
+        let mut expires_at = self.expires_at.lock().unwrap();
+        if expires_at.is_some_and(|at| at <= now) {
+            *expires_at = None;
+            self.clear("a page went live or opened up");
+        }
+    }
+
+    /// Drops every fragment, for when something they show changed without a
+    /// new revision
+    pub(crate) fn clear(&self, reason: &str) {
+        tracing::debug!("Clearing {} cache ({reason})", self.cache.name());
+        self.cache.clear();
+    }
+}
+
+/// Admins get their own fragments: they see drafts and everything
+/// members-only, whatever their tier.
+fn cache_key(template: &str, rev_id: &RevisionId, viewer: &Viewer, key: &Value) -> String {
+    let admin = if viewer.is_admin { "+admin" } else { "" };
+    format!("{template}\0{rev_id}\0{}{admin}\0{key}", viewer.tier_rank)
+}
+
+impl Drop for FragmentCache {
+    fn drop(&mut self) {
+        let stats = self.cache.stats();
+        if stats.hits + stats.misses == 0 {
+            return;
+        }
+        tracing::info!(
+            "Dropping {} cache: hit rate {:.2}% ({} hits, {} misses), {}/{} entries",
+            self.cache.name(),
+            stats.hit_rate() * 100.0,
+            stats.hits,
+            stats.misses,
+            stats.len,
+            stats.capacity,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use conflux::testing;
+    use merde::time::Rfc3339;
+    use minijinja::Environment;
+
+    use super::*;
+    use crate::RevisionViewHolder;
+
+    #[test]
+    fn test_fragments_are_cached_until_cleared() {
+        let rev = testing::revision(Default::default(), vec![]);
+        let fragment_cache = FragmentCache::new();
+        let mut environment = Environment::new();
+        environment.add_function("cache", {
+            let fragment_cache = fragment_cache.clone();
+            move |state: &State, key: Value, kwargs: Kwargs| fragment_cache.call(state, key, kwargs)
+        });
+        environment
+            .add_template(
+                "sidebar.html",
+                r#"{% call cache("n") %}{{ n }}{% endcall %}"#,
+            )
+            .unwrap();
+        let template = environment.get_template("sidebar.html").unwrap();
+        let render = |n: u32| {
+            let ctx = Value::from_iter([
+                (
+                    "__revision_view",
+                    Value::from_object(RevisionViewHolder(Arc::new(rev.clone()))),
+                ),
+                ("n", Value::from(n)),
+            ]);
+            template.render(ctx).unwrap()
+        };
+
+        assert_eq!(render(1), "1");
+        assert_eq!(render(2), "1");
+        fragment_cache.clear("sponsors updated");
+        assert_eq!(render(3), "3");
+    }
+
+    #[test]
+    fn test_fragments_expire_when_a_page_goes_live() {
+        let rc = Default::default();
+        let mut scheduled = testing::page(&rc, "/articles/scheduled");
+        let publish_at = OffsetDateTime::now_utc() + time::Duration::hours(1);
+        scheduled.date = Rfc3339(publish_at);
+        let rev = Arc::new(testing::revision(rc, vec![scheduled]));
+
+        let fragment_cache = FragmentCache::new();
+        let mut environment = Environment::new();
+        environment.add_function("cache", {
+            let fragment_cache = fragment_cache.clone();
+            move |state: &State, key: Value, kwargs: Kwargs| fragment_cache.call(state, key, kwargs)
+        });
+        environment
+            .add_template("list.html", r#"{% call cache("n") %}{{ n }}{% endcall %}"#)
+            .unwrap();
+        let template = environment.get_template("list.html").unwrap();
+        let render = |n: u32| {
+            let ctx = Value::from_iter([
+                (
+                    "__revision_view",
+                    Value::from_object(RevisionViewHolder(rev.clone())),
+                ),
+                ("n", Value::from(n)),
+            ]);
+            template.render(ctx).unwrap()
+        };
+
+        assert_eq!(render(1), "1");
+        assert_eq!(*fragment_cache.expires_at.lock().unwrap(), Some(publish_at));
+        assert_eq!(render(2), "1");
+
+        // as if that hour went by
+        fragment_cache.expire(publish_at);
+        assert_eq!(render(3), "3");
+    }
+
+    #[test]
+    fn test_fragments_are_per_tier() {
+        let rev_id = RevisionId::new("rev_testing".to_string());
+        let key = Value::from("sidebar");
+        let anon = Viewer::anon();
+        let silver = Viewer {
+            tier_rank: 2,
+            ..Viewer::anon()
+        };
+        let admin = Viewer {
+            is_admin: true,
+            ..silver.clone()
+        };
+        let keys =
+            [&anon, &silver, &admin].map(|viewer| cache_key("sidebar.html", &rev_id, viewer, &key));
+        assert_ne!(keys[0], keys[1]);
+        assert_ne!(keys[1], keys[2]);
+
+        let other_silver = Viewer {
+            tier: Some("Silver".into()),
+            ..silver.clone()
+        };
+        assert_eq!(
+            keys[1],
+            cache_key("sidebar.html", &rev_id, &other_silver, &key)
+        );
+    }
+}
@@ -1,16 +1,19 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use closest::{GetOrHelp, ResourceKind};
-use config_types::WebConfig;
-use conflux::{InputPath, InputPathRef, RevisionView, RouteRef, Viewer};
+use config_types::{TaxonomyConfig, WebConfig};
+use conflux::{InputPath, InputPathRef, LoadedPage, Revision, RevisionView, RouteRef, Viewer};
 use itertools::Itertools;
 use minijinja::{Environment, Error, Value, value::Kwargs};
+use mom_types::GlobalStateView;
 use rand::seq::SliceRandom;
 use time::OffsetDateTime;
 
 use crate::{
-    GlobalsVal, Listing, ListingKind, LoadedPageVal, MediaVal, RevisionViewHolder,
-    SearchResultsVal, ToVal, conversions::ToMinijinaResult,
+    AsMinijinjaValue, GlobalsVal, Listing, ListingKind, LoadedPageVal, MediaVal,
+    RevisionViewHolder, SearchResultsVal,
+    conversions::ToMinijinaResult,
+    structured_data::{json_ld_script_tag, page_json_ld},
 };
 
 fn urlencode(input: String) -> String {
@@ -57,6 +60,15 @@ pub fn get_globals(state: &minijinja::State) -> Result<Arc<GlobalsVal>, Error> {
         .ok_or_else(|| Error::new(minijinja::ErrorKind::InvalidOperation, "globals not found"))
 }
 
+/// The viewer templates render for. Shortcodes don't get globals, so they only
+/// see what anonymous users see.
+pub(crate) fn current_viewer(state: &minijinja::State) -> Viewer {
+    match get_globals(state) {
+        Ok(globals) => globals.viewer(),
+        Err(_) => Viewer::anon(),
+    }
+}
+
 pub fn get_web_config(state: &minijinja::State) -> Result<WebConfig, Error> {
     let port = state
         .lookup("web_port")
@@ -133,6 +145,19 @@ fn get_media(state: &minijinja::State, mut path: InputPath) -> Result<Value, Err
     }))
 }
 
+/// Returns the parsed contents of a file in `/data`, e.g. `get_data("talks")`
+/// for `/data/talks.yaml`
+fn get_data(state: &minijinja::State, name: String) -> Result<Value, Error> {
+    let rv = get_revision_view(state);
+    let data = rv.cachebuster().data(&name).map_err(|e| {
+        Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("data not found: {e}"),
+        )
+    })?;
+    Ok(data.value.clone())
+}
+
 fn get_page_from_path(state: &minijinja::State, path: String) -> Result<Value, Error> {
     let rv = get_revision_view(state);
     let rev = rv.rev().map_err(|e| {
@@ -151,7 +176,7 @@ fn get_page_from_path(state: &minijinja::State, path: String) -> Result<Value, E
                 format!("page not found: {e}"),
             )
         })?;
-    Ok(page.clone().to_val())
+    Ok(LoadedPageVal::seen_by(page, &current_viewer(state), &rev.pak.rc).into())
 }
 
 fn get_page_from_route(state: &minijinja::State, path: String) -> Result<Value, Error> {
@@ -162,24 +187,18 @@ fn get_page_from_route(state: &minijinja::State, path: String) -> Result<Value,
         .get_or_help(ResourceKind::Route, RouteRef::from_str(&path))
         .mj()?;
     let page = rev.pages.get_or_help(ResourceKind::Page, path).mj()?;
-    let page = page.clone().to_val();
-    Ok(page)
+    Ok(LoadedPageVal::seen_by(page, &current_viewer(state), &rev.pak.rc).into())
 }
 
 // This is used to generate the RSS feed
 fn get_recent_pages(state: &minijinja::State) -> Result<Value, Error> {
-    let viewer = Viewer {
-        is_admin: false,
-        has_bronze: false,
-        has_silver: false,
-    };
+    let viewer = Viewer::anon();
 
     // pages that are article or series_part, and listed, sorted by date descending,
     // limit to 25 items
     let rv = get_revision_view(state);
-    let pages = rv
-        .rev()
-        .mj()?
+    let rev = rv.rev().mj()?;
+    let pages = rev
         .pages
         .values()
         .filter(|p| p.is_article() || p.is_series_part())
@@ -187,12 +206,173 @@ fn get_recent_pages(state: &minijinja::State) -> Result<Value, Error> {
         .sorted_by_key(|p| p.date)
         .rev()
         .take(25)
-        .cloned()
-        .map(|p| p.to_val())
+        // feeds must not leak the full text of exclusive pages
+        .map(|p| Value::from(LoadedPageVal::seen_by(p, &viewer, &rev.pak.rc)))
+        .collect::<Vec<_>>();
+    Ok(Value::from(pages))
+}
+
+fn get_related_pages(
+    state: &minijinja::State,
+    page: Value,
+    limit: Option<usize>,
+) -> Result<Value, Error> {
+    let page = page.downcast_object_ref::<LoadedPageVal>().ok_or_else(|| {
+        Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            "get_related_pages expects a page as its first argument",
+        )
+    })?;
+    let limit = limit.unwrap_or(5);
+    let viewer = current_viewer(state);
+
+    let rv = get_revision_view(state);
+    let rev = rv.rev().mj()?;
+    let pages = rev
+        .related
+        .get(&page.path)
+        .map(|related| related.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|related| rev.pages.get(&related.path))
+        .filter(|p| p.is_listed(&viewer))
+        .take(limit)
+        .map(|p| Value::from(LoadedPageVal::seen_by(p, &viewer, &rev.pak.rc)))
         .collect::<Vec<_>>();
     Ok(Value::from(pages))
 }
 
+/// Whether the current viewer has the named tier (e.g. "Silver") or a higher
+/// one, as configured in `tiers`.
+fn viewer_has_tier(state: &minijinja::State, name: String) -> Result<bool, Error> {
+    let viewer = current_viewer(state);
+
+    let rv = get_revision_view(state);
+    let rev = rv.rev().mj()?;
+    if rev.pak.rc.tier_by_title(&name).is_none() {
+        return Err(Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("viewer_has_tier: unknown tier {name:?}"),
+        ));
+    }
+    Ok(viewer.has_tier(&rev.pak.rc, &name))
+}
+
+/// Configured tiers, lowest first
+fn get_tiers(state: &minijinja::State) -> Result<Value, Error> {
+    let rv = get_revision_view(state);
+    let rev = rv.rev().mj()?;
+    let mut tiers = rev.pak.rc.tiers().to_vec();
+    tiers.sort_by_key(|t| t.rank);
+    Ok(Value::from_serialize(tiers))
+}
+
+/// Verified webmentions for a route, newest first
+fn get_webmentions(state: &minijinja::State, route: String) -> Result<Value, Error> {
+    // shortcodes don't get globals, and thus no webmentions
+    let Ok(globals) = get_globals(state) else {
+        return Ok(Value::from(Vec::<Value>::new()));
+    };
+
+    let webmentions = globals.gv.gsv_webmentions();
+    let mentions = webmentions
+        .by_route
+        .get(RouteRef::from_str(&route))
+        .map(|mentions| {
+            mentions
+                .iter()
+                .map(|m| {
+                    Value::from_iter([
+                        ("source", Value::from(m.source.clone())),
+                        ("title", Value::from(m.title.clone())),
+                        ("verified_at", m.verified_at.clone().mj()),
+                    ])
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    Ok(Value::from(mentions))
+}
+
+/// All language versions of a page the viewer can see, original first
+fn get_translations(state: &minijinja::State, page: Value) -> Result<Value, Error> {
+    let page = page.downcast_object_ref::<LoadedPageVal>().ok_or_else(|| {
+        Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            "get_translations expects a page as its first argument",
+        )
+    })?;
+
+    let viewer = current_viewer(state);
+    let rv = get_revision_view(state);
+    let rev = rv.rev().mj()?;
+    let pages = visible_translations(rev, &viewer, page)
+        .into_iter()
+        .map(|p| Value::from(LoadedPageVal::seen_by(p, &viewer, &rev.pak.rc)))
+        .collect::<Vec<_>>();
+    Ok(Value::from(pages))
+}
+
+/// `<link rel="alternate" hreflang="...">` tags for every language version of
+/// a page, plus `x-default` for the original. Empty for untranslated pages.
+fn get_hreflang_links(state: &minijinja::State, page: Value) -> Result<Value, Error> {
+    let page = page.downcast_object_ref::<LoadedPageVal>().ok_or_else(|| {
+        Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            "get_hreflang_links expects a page as its first argument",
+        )
+    })?;
+
+    let rv = get_revision_view(state);
+    let rev = rv.rev().mj()?;
+    let web = get_web_config(state)?;
+    let versions = visible_translations(rev, &current_viewer(state), page);
+    if versions.len() < 2 {
+        return Ok(Value::from_safe_string(String::new()));
+    }
+
+    let mut links = Vec::with_capacity(versions.len() + 1);
+    for version in &versions {
+        links.push(format!(
+            r#"<link rel="alternate" hreflang="{}" href="{}">"#,
+            html_escape::encode_double_quoted_attribute(&version.lang),
+            version.canonical_url(web)
+        ));
+    }
+    if let Some(original) = versions.iter().find(|p| p.translation_of.is_none()) {
+        links.push(format!(
+            r#"<link rel="alternate" hreflang="x-default" href="{}">"#,
+            original.canonical_url(web)
+        ));
+    }
+    Ok(Value::from_safe_string(links.join("\n")))
+}
+
+fn visible_translations<'a>(
+    rev: &'a Revision,
+    viewer: &Viewer,
+    page: &LoadedPage,
+) -> Vec<&'a Arc<LoadedPage>> {
+    rev.translations_of(page)
+        .into_iter()
+        .filter(|p| p.path == page.path || p.is_listed(viewer))
+        .collect()
+}
+
+fn get_structured_data(state: &minijinja::State, page: Value) -> Result<Value, Error> {
+    let page = page.downcast_object_ref::<LoadedPageVal>().ok_or_else(|| {
+        Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            "get_structured_data expects a page as its first argument",
+        )
+    })?;
+
+    let rv = get_revision_view(state);
+    let rev = rv.rev().mj()?;
+    let json_ld = page_json_ld(page, rev, rv.cachebuster(), get_web_config(state)?);
+    Ok(Value::from_safe_string(json_ld_script_tag(&json_ld)))
+}
+
 fn url_encode(value: String) -> Result<String, Error> {
     Ok(urlencoding::encode(&value).into_owned())
 }
@@ -350,100 +530,320 @@ fn escape_for_attribute(input: String) -> Result<String, Error> {
 }
 
 fn random_article(state: &minijinja::State) -> Result<Value, Error> {
-    let viewer = Viewer {
-        is_admin: false,
-        has_bronze: false,
-        has_silver: false,
-    };
+    let viewer = Viewer::anon();
 
     let rv = get_revision_view(state);
-    let pages = rv
+    let rev = rv
         .rev()
-        .map_err(|e| Error::new(minijinja::ErrorKind::InvalidOperation, e.to_string()))?
+        .map_err(|e| Error::new(minijinja::ErrorKind::InvalidOperation, e.to_string()))?;
+    let pages = rev
         .pages
         .values()
         .filter(|p| p.is_article() && p.is_listed(&viewer))
         .filter(|p| p.tags.iter().any(|t| t == "rust"))
         .collect::<Vec<_>>();
 
-    let page = (*pages.choose(&mut rand::thread_rng()).ok_or_else(|| {
+    let page = pages.choose(&mut rand::thread_rng()).ok_or_else(|| {
         Error::new(
             minijinja::ErrorKind::InvalidOperation,
             "No articles available",
         )
-    })?)
-    .clone();
+    })?;
 
-    Ok(page.to_val())
+    Ok(LoadedPageVal::seen_by(page, &viewer, &rev.pak.rc).into())
 }
 
 fn get_tag_listing(state: &minijinja::State, args: Kwargs) -> Result<Value, Error> {
     let tag = args.get::<String>("tag")?;
     let page_number = args.get::<usize>("page_number").unwrap_or(1);
     let per_page = args.get::<usize>("per_page").unwrap_or(25);
+    let viewer = Viewer::anon();
 
+    let rv = get_revision_view(state);
+    let rev = rv.rev().mj()?;
+    Ok(Value::from(tag_listing(
+        rev,
+        &viewer,
+        &tag,
+        page_number,
+        per_page,
+    )?))
+}
+
+/// One page of the pages tagged with `tag` (or a tag it's an alias of), most
+/// recent first. Unknown tags give an empty listing.
+pub(crate) fn tag_listing(
+    rev: &Revision,
+    viewer: &Viewer,
+    tag: &str,
+    page_number: usize,
+    per_page: usize,
+) -> Result<Listing, Error> {
+    let tag = rev.pak.rc.canonical_tag(tag);
+    let paths = match rev.tags.get_or_help(ResourceKind::Tag, tag) {
+        Ok(paths) => paths.as_slice(),
+        Err(_e) => &[],
+    };
+    listing_of(rev, viewer, paths, page_number, per_page)
+}
+
+/// One page of the pages at `paths` that `viewer` gets to see listed, most
+/// recent first, as `viewer` sees them.
+fn listing_of(
+    rev: &Revision,
+    viewer: &Viewer,
+    paths: &[InputPath],
+    page_number: usize,
+    per_page: usize,
+) -> Result<Listing, Error> {
     let zero_indexed_page_number = page_number.checked_sub(1).ok_or_else(|| {
         Error::new(
             minijinja::ErrorKind::InvalidOperation,
             "page out of range: must be >= 1",
         )
     })?;
-    let viewer = Viewer {
-        is_admin: false,
-        has_bronze: false,
-        has_silver: false,
-    };
-
-    let rv = get_revision_view(state);
-    let rev = rv.rev().mj()?;
 
-    let paths = match rev.tags.get_or_help(ResourceKind::Tag, &tag) {
-        Ok(paths) => paths,
-        Err(_e) => {
-            // return an empty listing
-            return Ok(Value::from(Listing {
-                kind: ListingKind::Articles,
-                items: Default::default(),
-                page_number,
-                per_page,
-                has_more: false,
-            }));
-        }
-    };
     let mut pages = paths
         .iter()
         .filter_map(|p| rev.pages.get(p))
-        .filter(|p| p.is_listed(&viewer))
+        .filter(|p| p.is_listed(viewer))
         .sorted_by_key(|p| std::cmp::Reverse(p.date))
         .skip(zero_indexed_page_number * per_page)
         .take(per_page + 1)
-        .cloned()
-        .map(LoadedPageVal)
+        .map(|p| LoadedPageVal::seen_by(p, viewer, &rev.pak.rc))
         .collect::<Vec<_>>();
     let has_more = pages.len() > per_page;
     if has_more {
         pages.pop();
     }
-    Ok(Value::from(Listing {
+    Ok(Listing {
         kind: ListingKind::Articles,
         items: pages,
         page_number,
         per_page,
         has_more,
-    }))
+    })
+}
+
+/// Details about a tag, from `/content/tags/{tag}.md` if there's one
+fn get_tag(state: &minijinja::State, name: String) -> Result<Value, Error> {
+    let viewer = Viewer::anon();
+    let rv = get_revision_view(state);
+    let rev = rv.rev().mj()?;
+    let name = rev.pak.rc.canonical_tag(&name).to_string();
+    let count = listed_tag_count(rev, &viewer, &name);
+    Ok(Value::from_iter(tag_fields(
+        state, rev, &viewer, name, count,
+    )?))
+}
+
+/// Every tag with at least one listed page, by name, for tag clouds. `weight`
+/// goes from 1 (least used) to 5 (most used).
+fn get_tags(state: &minijinja::State) -> Result<Value, Error> {
+    let viewer = Viewer::anon();
+    let rv = get_revision_view(state);
+    let rev = rv.rev().mj()?;
+
+    let counts = rev
+        .tags
+        .keys()
+        .map(|tag| (tag, listed_tag_count(rev, &viewer, tag)))
+        .filter(|(_, count)| *count > 0)
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .collect::<Vec<_>>();
+    let max = counts.iter().map(|(_, count)| *count).max().unwrap_or(1);
+
+    let tags = counts
+        .into_iter()
+        .map(|(tag, count)| {
+            let mut fields = tag_fields(state, rev, &viewer, tag.clone(), count)?;
+            // log scale, so one huge tag doesn't flatten all the others
+            let weight = if max > 1 {
+                1 + ((count as f64).ln() / (max as f64).ln() * 4.0).round() as u32
+            } else {
+                1
+            };
+            fields.push(("weight", Value::from(weight)));
+            Ok(Value::from_iter(fields))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(Value::from(tags))
+}
+
+fn listed_tag_count(rev: &Revision, viewer: &Viewer, tag: &str) -> usize {
+    rev.tags
+        .get(tag)
+        .map(|paths| {
+            paths
+                .iter()
+                .filter_map(|p| rev.pages.get(p))
+                .filter(|p| p.is_listed(viewer))
+                .count()
+        })
+        .unwrap_or_default()
+}
+
+/// Fields of a tag for templates. The description comes from the tag's page,
+/// which is subject to drafts and tiers like any other page.
+fn tag_fields(
+    state: &minijinja::State,
+    rev: &Revision,
+    viewer: &Viewer,
+    name: String,
+    count: usize,
+) -> Result<Vec<(&'static str, Value)>, Error> {
+    let page = rev
+        .pages
+        .get(&InputPath::new(format!("/content/tags/{name}.md")))
+        .filter(|p| p.is_listed(viewer))
+        .map(|p| LoadedPageVal::seen_by(p, viewer, &rev.pak.rc));
+    let thumb_path = InputPath::new(format!("/content/tags/{name}.jxl"));
+    let thumb = match rev.media.get(&thumb_path) {
+        Some(media) => Value::from(MediaVal {
+            path: thumb_path,
+            media: media.clone(),
+            web: get_web_config(state)?,
+        }),
+        None => Value::from(()),
+    };
+
+    let display_name = page
+        .map(|p| p.title.clone())
+        .unwrap_or_else(|| name.clone());
+    let description = page.map(|p| Value::from_safe_string(p.html.clone()));
+
+    Ok(vec![
+        ("display_name", Value::from(display_name)),
+        ("description", description.unwrap_or_default()),
+        ("thumb", thumb),
+        ("count", Value::from(count)),
+        ("route", Value::from(format!("/tags/{name}"))),
+        ("name", Value::from(name)),
+    ])
+}
+
+/// Terms of a taxonomy with how many listed pages they have, by name
+fn get_taxonomy(state: &minijinja::State, name: String) -> Result<Value, Error> {
+    let viewer = Viewer::anon();
+    let rv = get_revision_view(state);
+    let rev = rv.rev().mj()?;
+    let (taxonomy, terms) = taxonomy_terms(rev, &name)?;
+
+    let terms = terms
+        .iter()
+        .map(|(term, paths)| {
+            let count = paths
+                .iter()
+                .filter_map(|p| rev.pages.get(p))
+                .filter(|p| p.is_listed(&viewer))
+                .count();
+            (term, count)
+        })
+        .filter(|(_, count)| *count > 0)
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(term, count)| {
+            Value::from_iter([
+                ("name", Value::from(term.clone())),
+                ("count", Value::from(count)),
+                ("route", Value::from(format!("{}/{term}", taxonomy.route()))),
+            ])
+        })
+        .collect::<Vec<_>>();
+    Ok(Value::from(terms))
+}
+
+fn get_taxonomy_listing(state: &minijinja::State, args: Kwargs) -> Result<Value, Error> {
+    let taxonomy = args.get::<String>("taxonomy")?;
+    let term = args.get::<String>("term")?;
+    let page_number = args.get::<Option<usize>>("page_number")?.unwrap_or(1);
+    let per_page = args.get::<Option<usize>>("per_page")?.unwrap_or(25);
+    args.assert_all_used()?;
+    let viewer = current_viewer(state);
+
+    let rv = get_revision_view(state);
+    let rev = rv.rev().mj()?;
+    let (_, terms) = taxonomy_terms(rev, &taxonomy)?;
+    let paths = terms.get(&term).map(Vec::as_slice).unwrap_or_default();
+    Ok(Value::from(listing_of(
+        rev,
+        &viewer,
+        paths,
+        page_number,
+        per_page,
+    )?))
+}
+
+/// A taxonomy's config and its terms (with the pages that have them)
+fn taxonomy_terms<'a>(
+    rev: &'a Revision,
+    name: &str,
+) -> Result<(&'a TaxonomyConfig, &'a HashMap<String, Vec<InputPath>>), Error> {
+    let taxonomy = rev.pak.rc.taxonomy(name).ok_or_else(|| {
+        Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("unknown taxonomy {name:?}"),
+        )
+    })?;
+    let terms = rev
+        .taxonomies
+        .get_or_help(ResourceKind::Taxonomy, name)
+        .mj()?;
+    Ok((taxonomy, terms))
+}
+
+/// An author from the registry in `home.json`, for bylines
+fn get_author(state: &minijinja::State, id: String) -> Result<Value, Error> {
+    let rv = get_revision_view(state);
+    let rev = rv.rev().mj()?;
+    let author = rev.pak.rc.author(&id).ok_or_else(|| {
+        Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("unknown author {id:?}"),
+        )
+    })?;
+
+    let avatar = match &author.avatar {
+        Some(path) => {
+            let path = InputPath::new(path.clone());
+            let media = rv.cachebuster().media(&path).map_err(|e| {
+                Error::new(
+                    minijinja::ErrorKind::InvalidOperation,
+                    format!("avatar of author {id:?} not found: {e}"),
+                )
+            })?;
+            Value::from(MediaVal {
+                path,
+                media: media.clone(),
+                web: get_web_config(state)?,
+            })
+        }
+        None => Value::from(()),
+    };
+    // only link to their page if there's a taxonomy to list their pages
+    let route = rev
+        .pak
+        .rc
+        .taxonomy("authors")
+        .map(|taxonomy| format!("{}/{}", taxonomy.route(), author.id));
+
+    Ok(Value::from_iter([
+        ("id", Value::from(author.id.clone())),
+        ("name", Value::from(author.name.clone())),
+        ("bio", Value::from(author.bio.clone())),
+        ("avatar", avatar),
+        ("links", Value::from_serialize(&author.links)),
+        ("route", Value::from(route)),
+    ]))
 }
 
 fn search_page(state: &minijinja::State, args: Kwargs) -> Result<Value, Error> {
     let query = args.get::<String>("query")?;
     let per_page = args.get::<usize>("per_page")?;
     let page_number = args.get::<usize>("page_number")?;
+    let lang = args.get::<Option<String>>("lang")?;
     args.assert_all_used()?;
 
-    let viewer = Viewer {
-        is_admin: false,
-        has_bronze: false,
-        has_silver: false,
-    };
+    let viewer = Viewer::anon();
 
     let rv = get_revision_view(state);
     let gv = state
@@ -451,10 +851,16 @@ fn search_page(state: &minijinja::State, args: Kwargs) -> Result<Value, Error> {
         .and_then(|v| v.downcast_object::<GlobalsVal>())
         .ok_or_else(|| Error::new(minijinja::ErrorKind::InvalidOperation, "globals not found"))?;
 
-    let results = gv
-        .index
-        .search(rv.as_ref(), &viewer, &query, per_page, page_number);
-    Ok(SearchResultsVal(results).into())
+    let results = gv.index.search(
+        rv.as_ref(),
+        &viewer,
+        &query,
+        lang.as_deref(),
+        per_page,
+        page_number,
+    );
+    let rev = rv.rev().mj()?;
+    Ok(SearchResultsVal::seen_by(results, &viewer, &rev.pak.rc).into())
 }
 
 pub(crate) fn register_all(environment: &mut Environment<'static>) {
@@ -464,7 +870,15 @@ pub(crate) fn register_all(environment: &mut Environment<'static>) {
 
     environment.add_function("asset_url", asset_url);
     environment.add_function("get_media", get_media);
+    environment.add_function("get_data", get_data);
     environment.add_function("get_recent_pages", get_recent_pages);
+    environment.add_function("get_related_pages", get_related_pages);
+    environment.add_function("get_structured_data", get_structured_data);
+    environment.add_function("get_translations", get_translations);
+    environment.add_function("get_hreflang_links", get_hreflang_links);
+    environment.add_function("viewer_has_tier", viewer_has_tier);
+    environment.add_function("get_tiers", get_tiers);
+    environment.add_function("get_webmentions", get_webmentions);
     environment.add_function("url_encode", url_encode);
     environment.add_function("html_escape", html_escape);
     environment.add_function("get_page_from_route", get_page_from_route);
@@ -475,6 +889,11 @@ pub(crate) fn register_all(environment: &mut Environment<'static>) {
 
     environment.add_function("random_article", random_article);
     environment.add_function("get_tag_listing", get_tag_listing);
+    environment.add_function("get_tag", get_tag);
+    environment.add_function("get_tags", get_tags);
+    environment.add_function("get_taxonomy", get_taxonomy);
+    environment.add_function("get_taxonomy_listing", get_taxonomy_listing);
+    environment.add_function("get_author", get_author);
     environment.add_function("search_page", search_page);
 
     ///////////////////////////////////////////////////////////////
@@ -506,6 +925,232 @@ pub(crate) fn register_all(environment: &mut Environment<'static>) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use config_types::RevisionConfig;
+    use conflux::{Html, PageKind, RelatedPage, Route, SearchResult, SearchResults, testing};
+
+    /// Goes past `PAYWALL_EXCERPT_LENGTH`, so only the first paragraph
+    /// survives the paywall
+    fn secret_html() -> String {
+        format!(
+            "<p>{}</p><p>The butler did it</p>",
+            "lorem ipsum ".repeat(100)
+        )
+    }
+
+    /// An exclusive article, reachable through every function that hands out
+    /// pages: by route, as a related page, a translation, a child, in tag,
+    /// taxonomy and section listings, and as the only random article.
+    fn exclusive_revision() -> Revision {
+        let mut rc = RevisionConfig::default();
+        rc.taxonomies.push(TaxonomyConfig {
+            name: "categories".into(),
+            feed: false,
+        });
+
+        let mut listing = testing::page(&rc, "/articles");
+        let public = testing::page(&rc, "/articles/public");
+
+        let mut secret = testing::page(&rc, "/articles/secret");
+        secret.min_tier = Some("Silver".into());
+        secret.html = secret_html();
+        secret.tags = vec!["rust".into()];
+        listing.children = vec![secret.path.clone()];
+
+        let mut translated = secret.clone();
+        translated.path = InputPath::new("/content/articles/secret/_index.fr.md".into());
+        translated.route = Route::new("/fr/articles/secret".into());
+        translated.lang = "fr".into();
+        translated.translation_of = Some(secret.path.clone());
+
+        let mut rev = testing::revision(
+            rc,
+            [listing, public.clone(), secret.clone(), translated.clone()],
+        );
+        rev.related.insert(
+            public.path.clone(),
+            vec![RelatedPage {
+                path: secret.path.clone(),
+                score: 1.0,
+            }],
+        );
+        rev.translations
+            .insert(secret.path.clone(), vec![translated.path.clone()]);
+        rev.taxonomies.insert(
+            "categories".into(),
+            [("mysteries".to_string(), vec![secret.path.clone()])].into(),
+        );
+        rev
+    }
+
+    fn render(rev: Revision, template: &str) -> String {
+        let mut environment = Environment::new();
+        register_all(&mut environment);
+        let ctx = Value::from_iter([(
+            "__revision_view",
+            Value::from_object(RevisionViewHolder(Arc::new(rev))),
+        )]);
+        environment.render_str(template, ctx).unwrap()
+    }
+
+    fn assert_truncated(html: &str) {
+        assert!(
+            html.contains("lorem ipsum"),
+            "excerpt missing from {html:?}"
+        );
+        assert!(!html.contains("butler"), "full text leaked in {html:?}");
+    }
+
+    #[test]
+    fn test_tag_and_taxonomy_listings_paginate_the_same_way() {
+        let mut rc = RevisionConfig::default();
+        rc.taxonomies.push(TaxonomyConfig {
+            name: "categories".into(),
+            feed: false,
+        });
+        let pages = (1..=5)
+            .map(|i| {
+                let mut page = testing::page(&rc, &format!("/articles/post-{i}"));
+                page.title = format!("Post {i}");
+                page.date.0 += time::Duration::days(i);
+                page.draft = i == 3;
+                page.tags = vec!["rust".into()];
+                page
+            })
+            .collect::<Vec<_>>();
+        let paths = pages.iter().map(|p| p.path.clone()).collect::<Vec<_>>();
+        let mut rev = testing::revision(rc, pages);
+        rev.tags.insert("rust".into(), paths.clone());
+        rev.taxonomies
+            .insert("categories".into(), [("stories".to_string(), paths)].into());
+
+        for template in [
+            r#"{% set l = get_tag_listing(tag="rust", page_number=N, per_page=2) %}"#,
+            r#"{% set l = get_taxonomy_listing(taxonomy="categories", term="stories", page_number=N, per_page=2) %}"#,
+        ] {
+            let page = |n: usize| {
+                let template = format!(
+                    "{}{{{{ l.items | map(attribute='title') | join(',') }}}}|{{{{ l.has_more }}}}",
+                    template.replace("N", &n.to_string())
+                );
+                render(rev.clone(), &template)
+            };
+            // newest first, drafts left out
+            assert_eq!(page(1), "Post 5,Post 4|true");
+            assert_eq!(page(2), "Post 2,Post 1|false");
+            assert_eq!(page(3), "|false");
+        }
+
+        let viewer = Viewer::anon();
+        let err = tag_listing(&rev, &viewer, "rust", 0, 2).unwrap_err();
+        assert!(err.to_string().contains("page out of range"), "got {err}");
+    }
+
+    #[test]
+    fn test_tag_descriptions_respect_drafts_and_tiers() {
+        let rc = RevisionConfig::default();
+        let mut article = testing::page(&rc, "/articles/foo");
+        article.tags = vec!["rust".into()];
+        let mut tag_page = testing::page(&rc, "/tags/rust");
+        tag_page.path = InputPath::new("/content/tags/rust.md".into());
+        tag_page.title = "Rust".into();
+
+        let description = |tag_page: LoadedPage| {
+            let mut rev = testing::revision(rc.clone(), [article.clone(), tag_page]);
+            rev.tags.insert("rust".into(), vec![article.path.clone()]);
+            render(
+                rev,
+                r#"{% set t = get_tag("rust") %}{{ t.display_name }}: {{ t.description }}"#,
+            )
+        };
+
+        assert_eq!(
+            description(tag_page.clone()),
+            "Rust: <p>Once upon a time</p>"
+        );
+
+        let mut draft = tag_page.clone();
+        draft.draft = true;
+        assert_eq!(description(draft), "rust: ");
+
+        let mut exclusive = tag_page;
+        exclusive.min_tier = Some("Silver".into());
+        exclusive.html = secret_html();
+        assert_truncated(&description(exclusive));
+    }
+
+    #[test]
+    fn test_exclusive_pages_are_truncated_everywhere() {
+        let templates = [
+            r#"{{ get_page_from_route("/articles/secret").html }}"#,
+            r#"{{ get_page_from_path("/content/articles/secret/_index.md").html }}"#,
+            r#"{% for p in get_related_pages(get_page_from_route("/articles/public")) %}{{ p.html }}{% endfor %}"#,
+            r#"{% for p in get_translations(get_page_from_route("/articles/secret")) %}{{ p.html }}{% endfor %}"#,
+            r#"{% for p in get_recent_pages() %}{{ p.html }}{% endfor %}"#,
+            r#"{{ random_article().html }}"#,
+            r#"{% for p in get_tag_listing(tag="rust").items %}{{ p.html }}{% endfor %}"#,
+            r#"{% for p in get_taxonomy_listing(taxonomy="categories", term="mysteries").items %}{{ p.html }}{% endfor %}"#,
+        ];
+        for template in templates {
+            let html = render(exclusive_revision(), template);
+            assert_truncated(&html);
+        }
+    }
+
+    #[test]
+    fn test_exclusive_pages_are_truncated_in_section_listings_and_children() {
+        let rev = exclusive_revision();
+        let viewer = Viewer::anon();
+        let listing =
+            LoadedPageVal::seen_by(testing::page_at(&rev, "/articles"), &viewer, &rev.pak.rc);
+        assert_eq!(listing.kind, PageKind::ArticleListing);
+
+        let items = listing
+            .get_listing(&rev, &viewer, None, None)
+            .unwrap()
+            .items;
+        let secret = items
+            .iter()
+            .find(|p| p.route.as_str() == "/articles/secret")
+            .unwrap();
+        assert_truncated(&secret.html);
+
+        let children = listing.get_children(&rev, &viewer);
+        assert_eq!(children.len(), 1);
+        assert_truncated(&children[0].html);
+    }
+
+    #[test]
+    fn test_exclusive_pages_are_truncated_in_search_results() {
+        let rev = exclusive_revision();
+        let results = SearchResults {
+            results: vec![SearchResult {
+                page: testing::page_at(&rev, "/articles/secret").clone(),
+                title_snippet: Html::new("A page".into()),
+                body_snippet: Html::new("lorem".into()),
+                fragments: Default::default(),
+            }],
+            terms: vec!["lorem".into()],
+            num_results: 1,
+            has_more: false,
+        };
+        let results = SearchResultsVal::seen_by(results, &Viewer::anon(), &rev.pak.rc);
+        assert_truncated(&results.results[0].page.html);
+    }
+
+    #[test]
+    fn test_admins_see_exclusive_pages_in_full() {
+        let rev = exclusive_revision();
+        let viewer = Viewer {
+            is_admin: true,
+            ..Viewer::anon()
+        };
+        let page = LoadedPageVal::seen_by(
+            testing::page_at(&rev, "/articles/secret"),
+            &viewer,
+            &rev.pak.rc,
+        );
+        assert_eq!(page.html, secret_html());
+    }
 
     #[test]
     fn test_urlencode_vs_fragment_urlencode() {
@@ -60,4 +60,10 @@ impl CacheBuster for TrackingCacheBuster {
         self.deps.lock().unwrap().insert(path.to_owned());
         self.rv.cachebuster().media(path)
     }
+
+    fn data(&self, name: &str) -> conflux::Result<&conflux::LoadedDataFile> {
+        let data = self.rv.cachebuster().data(name)?;
+        self.deps.lock().unwrap().insert(data.path.clone());
+        Ok(data)
+    }
 }
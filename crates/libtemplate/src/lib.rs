@@ -4,24 +4,25 @@ use std::{
     ops::Deref,
     str::FromStr,
     sync::{Arc, Mutex},
-    time::Duration,
 };
 
 mod conversions;
+mod fragment_cache;
 mod global_functions_and_filters;
 mod impls;
 mod prettify_minijinja_errors;
+mod structured_data;
 
 use crate::conversions::ToMinijinaResult;
-use crate::global_functions_and_filters::{get_globals, get_revision_view};
+use crate::global_functions_and_filters::{get_globals, get_revision_view, tag_listing};
 use autotrait::autotrait;
 use closest::{GetOrHelp, ResourceKind};
-use config_types::{TenantInfo, WebConfig, is_production};
+use config_types::{RevisionConfig, SectionSort, TenantInfo, WebConfig, is_production};
 use conflux::{
-    AccessOverride, InputPath, LoadedPage, Media, OffsetDateTime, PageKind, RevisionView, RouteRef,
-    SearchResult, SearchResults, Viewer,
+    AccessOverride, ExtraValue, InputPath, LoadedPage, Media, OffsetDateTime, PageKind, Revision,
+    RevisionView, RouteRef, SearchResult, SearchResults, Viewer,
 };
-use credentials::UserInfo;
+use credentials::{UserInfo, UserPreferences};
 use eyre::eyre;
 use global_functions_and_filters::truncate_core;
 use image_types::{ICodec, LogicalPixels};
@@ -31,9 +32,9 @@ use libsearch::Index;
 use merde::time::Rfc3339;
 use minijinja::{
     Environment,
-    value::{Kwargs, Object, Value},
+    value::{Enumerator, Kwargs, Object, Value},
 };
-use mom_types::GlobalStateView;
+use mom_types::{GlobalStateView, Sponsor};
 use prettify_minijinja_errors::PrettifyExt;
 use rand::seq::SliceRandom;
 use template_types::{
@@ -68,12 +69,25 @@ impl Mod for ModImpl {
 
         global_functions_and_filters::register_all(&mut environment);
 
-        Ok(Box::new(TemplateCollectionImpl { environment }))
+        // lives as long as the environment, so it's gone with the revision
+        let fragment_cache = fragment_cache::FragmentCache::new();
+        environment.add_function("cache", {
+            let fragment_cache = fragment_cache.clone();
+            move |state: &minijinja::State, key: Value, kwargs: Kwargs| {
+                fragment_cache.call(state, key, kwargs)
+            }
+        });
+
+        Ok(Box::new(TemplateCollectionImpl {
+            environment,
+            fragment_cache,
+        }))
     }
 }
 
 struct TemplateCollectionImpl {
     environment: Environment<'static>,
+    fragment_cache: Arc<fragment_cache::FragmentCache>,
 }
 
 impl TemplateCollection for TemplateCollectionImpl {
@@ -91,6 +105,7 @@ impl TemplateCollection for TemplateCollectionImpl {
         let globals = GlobalsVal {
             page: args.page.clone(),
             user_info: args.user_info,
+            preferences: args.preferences,
             additional_globals: args.additional_globals,
             raw_query: args.raw_query.to_owned(),
             url_params: form_urlencoded::parse(args.raw_query.as_bytes())
@@ -165,6 +180,10 @@ impl TemplateCollection for TemplateCollectionImpl {
             },
         })
     }
+
+    fn clear_fragment_cache(&self, reason: &str) {
+        self.fragment_cache.clear(reason);
+    }
 }
 
 pub fn shortcode_name_to_input_path(name: &str) -> InputPath {
@@ -197,14 +216,42 @@ impl Deref for LoadedPageVal {
 }
 
 impl LoadedPageVal {
+    /// The page as the viewer gets to see it: members-only sections filled in
+    /// or replaced with teasers, and only an excerpt if it's exclusive and the
+    /// viewer doesn't have the required tier. Every page handed to templates
+    /// goes through this, so the full text of exclusive pages never leaks
+    /// through listings, feeds or lookups.
+    pub(crate) fn seen_by(page: &Arc<LoadedPage>, viewer: &Viewer, rc: &RevisionConfig) -> Self {
+        let html = if page.members_sections.is_empty() {
+            None
+        } else {
+            Some(page.html_for(viewer, rc))
+        };
+
+        let page = if page.is_paywalled_for(viewer, rc) {
+            let excerpt = libhtmlrewrite::load().truncate_html(
+                html.as_deref().unwrap_or(&page.html),
+                conflux::PAYWALL_EXCERPT_LENGTH,
+            );
+            Arc::new(page.to_paywalled(excerpt))
+        } else if let Some(html) = html {
+            Arc::new(LoadedPage {
+                html,
+                ..(**page).clone()
+            })
+        } else {
+            page.clone()
+        };
+        Self(page)
+    }
+
     pub(crate) fn get_listing(
         &self,
-        globals: &GlobalsVal,
+        rev: &Revision,
+        viewer: &Viewer,
         page_number: Option<u64>,
         per_page: Option<u64>,
     ) -> eyre::Result<Listing> {
-        let viewer = globals.viewer();
-
         let page = page_number.unwrap_or(1) as usize;
         let zero_indexed_page_number = page
             .checked_sub(1)
@@ -237,19 +284,22 @@ impl LoadedPageVal {
             _ => eyre::bail!("Not a listing page"),
         };
 
-        let rv = globals.rv.as_ref();
-        let pages = rv
-            .rev()?
+        let pages = rev
             .pages
             .values()
-            .filter(|p| p.is_listed(&viewer))
+            .filter(|p| p.is_listed(viewer))
             .filter(|p| filter(p.as_ref()))
             .cloned();
 
         let pages = match listing_kind {
             ListingKind::Articles | ListingKind::Episodes | ListingKind::Series => {
-                // most recent first
-                pages.sorted_by_key(|p| std::cmp::Reverse(p.date))
+                // only what's in this section, e.g. not `/notes` in `/articles`
+                let pages = pages.filter(|p| p.same_section(self));
+                match self.listing_sort() {
+                    SectionSort::Newest => pages.sorted_by_key(|p| std::cmp::Reverse(p.date)),
+                    SectionSort::Oldest => pages.sorted_by_key(|p| p.date),
+                    SectionSort::Title => pages.sorted_by(|a, b| a.title.cmp(&b.title)),
+                }
             }
             ListingKind::SeriesParts => {
                 // oldest first
@@ -261,7 +311,7 @@ impl LoadedPageVal {
             .into_iter()
             .skip(zero_indexed_page_number * per_page)
             .take(per_page + 1)
-            .map(LoadedPageVal)
+            .map(|p| LoadedPageVal::seen_by(&p, viewer, &rev.pak.rc))
             .collect::<Vec<_>>();
 
         let has_more = pages.len() > per_page;
@@ -278,12 +328,11 @@ impl LoadedPageVal {
         })
     }
 
-    pub(crate) fn get_children(&self, globals: &GlobalsVal) -> Vec<LoadedPageVal> {
-        let rev = globals.rv.rev().unwrap();
+    pub(crate) fn get_children(&self, rev: &Revision, viewer: &Viewer) -> Vec<LoadedPageVal> {
         self.children
             .iter()
             .filter_map(|path| rev.pages.get(path))
-            .map(|child| LoadedPageVal(child.clone()))
+            .map(|child| LoadedPageVal::seen_by(child, viewer, &rev.pak.rc))
             .collect()
     }
 }
@@ -301,6 +350,9 @@ impl Object for LoadedPageVal {
         Some(match key.as_str()? {
             "path" => self.path.clone().into(),
             "route" => self.route.clone().into(),
+            "lang" => self.lang.clone().into(),
+            "translation_of" => self.translation_of.clone()?.into(),
+            "is_translation" => self.translation_of.is_some().into(),
             "thumb" => Value::from(self.thumb.clone().map(|m| MediaVal {
                 path: m.path,
                 media: m.media,
@@ -341,12 +393,25 @@ impl Object for LoadedPageVal {
             "crates" => Value::from_serialize(&self.crates),
             "github_repos" => Value::from_serialize(&self.github_repos),
             "links" => Value::from_serialize(&self.links),
+            "downloads" => Value::from_serialize(&self.downloads),
             "title" => self.title.clone().into(),
             "date" => self.date.mj(),
             "draft" => self.draft.into(),
             "archive" => self.archive.into(),
             "aliases" => Value::from_serialize(&self.aliases),
             "tags" => Value::from_serialize(&self.tags),
+            "taxonomies" => Value::from_serialize(&self.taxonomies),
+            "extra" => Value::from_iter(self.extra.iter().map(|(name, value)| {
+                let value = match value {
+                    ExtraValue::String(s) => Value::from(s.clone()),
+                    ExtraValue::Integer(i) => Value::from(*i),
+                    ExtraValue::Float(f) => Value::from(*f),
+                    ExtraValue::Boolean(b) => Value::from(*b),
+                    ExtraValue::Date(d) => d.mj(),
+                    ExtraValue::Strings(strings) => Value::from_serialize(strings),
+                };
+                (name.clone(), value)
+            })),
             "draft_code" => self.draft_code.clone().into(),
             "updated_at" => self.updated_at?.mj(),
             "rust_version" => self.rust_version.clone()?.into(),
@@ -356,6 +421,7 @@ impl Object for LoadedPageVal {
             "hide_metadata" => self.hide_metadata.into(),
             "series_link" => self.series_link.clone().map(Value::from_serialize).into(),
             "parts" => Value::from_serialize(&self.parts),
+            "series_stats" => self.series_stats.clone().map(Value::from_serialize).into(),
 
             "created_or_updated_at" => self.updated_at.unwrap_or(self.date).mj(),
             "is_old" => {
@@ -372,7 +438,7 @@ impl Object for LoadedPageVal {
             }
 
             // getters!
-            "url" => Value::from(self.canonical_url(self.0.web)),
+            "url" | "canonical_url" => Value::from(self.canonical_url(self.0.web)),
             "comments_page_url" => {
                 let mut u =
                     RouteRef::from_str("/api/comments").to_web_url(&self.0.ti.tc, self.0.web);
@@ -381,24 +447,20 @@ impl Object for LoadedPageVal {
                     .append_pair("title", &self.title);
                 Value::from_safe_string(u.as_str().to_string())
             }
+            "section" => Value::from(self.section.as_ref().map(|s| s.name.clone())),
             "is_articles_index" => (self.kind == PageKind::ArticleListing).into(),
             "is_series_index" => (self.kind == PageKind::SeriesListing).into(),
             "is_series_parts_index" => (self.kind == PageKind::SeriesIndex).into(),
 
-            "exclusive_until" => {
-                // 6 months
-                const EXCLUSIVITY_DURATION: Duration = Duration::from_secs(60 * 60 * 24 * 30 * 6);
-                if self.video_info.dual_feature {
-                    let unlocks_at = self.date.0 + EXCLUSIVITY_DURATION;
-                    if unlocks_at > OffsetDateTime::now_utc() {
-                        Rfc3339(unlocks_at).mj()
-                    } else {
-                        Value::from(false)
-                    }
-                } else {
-                    Value::from(false)
+            "exclusive_until" => match &self.exclusive_until {
+                Some(unlocks_at)
+                    if self.min_tier.is_some() && unlocks_at.0 > OffsetDateTime::now_utc() =>
+                {
+                    Rfc3339(unlocks_at.0).mj()
                 }
-            }
+                _ => Value::from(false),
+            },
+            "min_tier" => Value::from(self.min_tier.clone()),
 
             "video_info" => {
                 let video_info = self.video_info.clone();
@@ -427,7 +489,9 @@ impl Object for LoadedPageVal {
                 let per_page = kwargs.get("per_page")?;
                 kwargs.assert_all_used()?;
 
-                match self.get_listing(get_globals(state)?.as_ref(), page_number, per_page) {
+                let globals = get_globals(state)?;
+                let rev = globals.rv.rev().mj()?;
+                match self.get_listing(rev, &globals.viewer(), page_number, per_page) {
                     Ok(listing) => Ok(listing.into()),
                     Err(e) => Err(minijinja::Error::new(
                         minijinja::ErrorKind::InvalidOperation,
@@ -436,7 +500,9 @@ impl Object for LoadedPageVal {
                 }
             }
             "get_children" => {
-                let children = self.get_children(get_globals(state)?.as_ref());
+                let globals = get_globals(state)?;
+                let rev = globals.rv.rev().mj()?;
+                let children = self.get_children(rev, &globals.viewer());
                 Ok(Value::from(children))
             }
             _ => Err(minijinja::Error::new(
@@ -447,22 +513,6 @@ impl Object for LoadedPageVal {
     }
 }
 
-pub(crate) trait ToVal {
-    fn to_val(self: Arc<Self>) -> Value;
-}
-
-impl ToVal for LoadedPage {
-    fn to_val(self: Arc<Self>) -> Value {
-        LoadedPageVal::from(self).into()
-    }
-}
-
-impl From<Arc<LoadedPage>> for LoadedPageVal {
-    fn from(page: Arc<LoadedPage>) -> Self {
-        Self(page)
-    }
-}
-
 impl From<LoadedPageVal> for Value {
     fn from(val: LoadedPageVal) -> Self {
         Value::from_object(val)
@@ -472,6 +522,7 @@ impl From<LoadedPageVal> for Value {
 pub(crate) struct GlobalsVal {
     pub(crate) page: Option<Arc<LoadedPage>>,
     pub(crate) user_info: Option<UserInfo>,
+    pub(crate) preferences: Arc<UserPreferences>,
     pub(crate) additional_globals: DataObject,
     pub(crate) raw_query: String,
     pub(crate) url_params: HashMap<String, String>,
@@ -506,6 +557,26 @@ impl GlobalsVal {
             AccessOverride::from_raw_query(&self.raw_query),
         )
     }
+
+    /// Credited sponsors, highest tier first, with avatars served from our CDN
+    fn sponsors(&self) -> Value {
+        let ti = self.gv.gsv_ti();
+        let sponsors = self.gv.gsv_sponsors();
+        let sponsors = sponsors
+            .sponsors
+            .iter()
+            .map(|s| {
+                Value::from_object(SponsorVal {
+                    sponsor: s.clone(),
+                    avatar_url: s
+                        .avatar
+                        .as_ref()
+                        .map(|avatar| avatar.route.to_cdn_url_string(&ti.tc, self.web)),
+                })
+            })
+            .collect::<Vec<_>>();
+        Value::from(sponsors)
+    }
 }
 
 impl fmt::Debug for GlobalsVal {
@@ -514,6 +585,48 @@ impl fmt::Debug for GlobalsVal {
     }
 }
 
+/// A credited sponsor. Renders as their name, like sponsors did back when
+/// they were plain strings.
+#[derive(Debug)]
+pub(crate) struct SponsorVal {
+    sponsor: Sponsor,
+    avatar_url: Option<String>,
+}
+
+impl Object for SponsorVal {
+    fn is_true(self: &Arc<Self>) -> bool {
+        true
+    }
+
+    fn render(self: &Arc<Self>, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.sponsor.name)
+    }
+
+    fn enumerate(self: &Arc<Self>) -> Enumerator {
+        Enumerator::Str(&[
+            "name",
+            "tier",
+            "provider",
+            "avatar_url",
+            "profile_url",
+            "joined_at",
+        ])
+    }
+
+    fn get_value(self: &Arc<Self>, key: &Value) -> Option<Value> {
+        let s = &self.sponsor;
+        Some(match key.as_str()? {
+            "name" => s.name.clone().into(),
+            "tier" => s.tier.clone().into(),
+            "provider" => s.provider.clone().into(),
+            "avatar_url" => self.avatar_url.clone().into(),
+            "profile_url" => s.profile_url.clone().into(),
+            "joined_at" => s.joined_at.clone().map(|d| d.mj()).unwrap_or_default(),
+            _ => return None,
+        })
+    }
+}
+
 impl Object for GlobalsVal {
     fn is_true(self: &Arc<Self>) -> bool {
         true
@@ -527,6 +640,7 @@ impl Object for GlobalsVal {
         Some(match key.as_str()? {
             "minijinja" => "yes".into(),
             "revision" => self.rv.rev().ok()?.pak.id.clone().into(),
+            // already as the viewer sees it, the caller took care of that
             "page" => Value::from_object(LoadedPageVal(self.page.clone()?)),
             "env" => if is_production() {
                 "production"
@@ -536,12 +650,13 @@ impl Object for GlobalsVal {
             .into(),
             "url_params" => self.url_params.clone().into(),
             "user_info" => Value::from_serialize(&self.user_info),
+            "preferences" => Value::from_serialize(self.preferences.as_ref()),
             "viewer" => Value::from_serialize(self.viewer()),
             "config" => Value::from_object(ConfigVal {
                 ti: self.gv.gsv_ti().clone(),
                 web: self.web,
             }),
-            "sponsors" => Value::from_serialize(self.gv.gsv_sponsors().as_ref()),
+            "sponsors" => self.sponsors(),
             "globals" => Value::from_dyn_object(self.clone()),
             "web_port" => self.web.port.into(),
             "__revision_view" => Value::from_object(RevisionViewHolder(self.rv.clone())),
@@ -563,25 +678,22 @@ impl Object for GlobalsVal {
             "random_article" => {
                 let viewer = self.viewer();
 
-                let pages = self
-                    .rv
-                    .rev()
-                    .map_err(|e| {
-                        minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, e.to_string())
-                    })?
+                let rev = self.rv.rev().map_err(|e| {
+                    minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, e.to_string())
+                })?;
+                let pages = rev
                     .pages
                     .values()
                     .filter(|p| p.is_article() && p.is_listed(&viewer))
                     .filter(|p| p.tags.iter().any(|t| t == "rust"))
                     .collect::<Vec<_>>();
-                let page = (*pages.choose(&mut rand::thread_rng()).ok_or_else(|| {
+                let page = pages.choose(&mut rand::thread_rng()).ok_or_else(|| {
                     minijinja::Error::new(
                         minijinja::ErrorKind::InvalidOperation,
                         "No articles available",
                     )
-                })?)
-                .clone();
-                Ok(page.to_val())
+                })?;
+                Ok(LoadedPageVal::seen_by(page, &viewer, &rev.pak.rc).into())
             }
             "get_tag_listing" => {
                 let arg = args.first().cloned().ok_or_else(|| {
@@ -596,50 +708,14 @@ impl Object for GlobalsVal {
                 let page_number = kwargs.get::<usize>("page_number").unwrap_or(1);
                 let per_page = kwargs.get::<usize>("per_page").unwrap_or(25);
 
-                let zero_indexed_page_number = page_number.checked_sub(1).ok_or_else(|| {
-                    minijinja::Error::new(
-                        minijinja::ErrorKind::InvalidOperation,
-                        "page out of range: must be >= 1",
-                    )
-                })?;
-                let viewer = self.viewer();
-
                 let rev = self.rv.rev().mj()?;
-
-                let paths = match rev.tags.get_or_help(ResourceKind::Tag, &tag) {
-                    Ok(paths) => paths,
-                    Err(_e) => {
-                        // return an empty listing
-                        return Ok(Value::from(Listing {
-                            kind: ListingKind::Articles,
-                            items: Default::default(),
-                            page_number,
-                            per_page,
-                            has_more: false,
-                        }));
-                    }
-                };
-                let mut pages = paths
-                    .iter()
-                    .filter_map(|p| rev.pages.get(p))
-                    .filter(|p| p.is_listed(&viewer))
-                    .sorted_by_key(|p| std::cmp::Reverse(p.date))
-                    .skip(zero_indexed_page_number * per_page)
-                    .take(per_page + 1)
-                    .cloned()
-                    .map(LoadedPageVal)
-                    .collect::<Vec<_>>();
-                let has_more = pages.len() > per_page;
-                if has_more {
-                    pages.pop();
-                }
-                Ok(Value::from(Listing {
-                    kind: ListingKind::Articles,
-                    items: pages,
+                Ok(Value::from(tag_listing(
+                    rev,
+                    &self.viewer(),
+                    &tag,
                     page_number,
                     per_page,
-                    has_more,
-                }))
+                )?))
             }
             "search_page" => {
                 let arg = args.first().cloned().ok_or_else(|| {
@@ -652,14 +728,21 @@ impl Object for GlobalsVal {
                 let query = kwargs.get::<String>("query")?;
                 let per_page = kwargs.get::<usize>("per_page")?;
                 let page_number = kwargs.get::<usize>("page_number")?;
+                let lang = kwargs.get::<Option<String>>("lang")?;
                 kwargs.assert_all_used()?;
 
                 let viewer = self.viewer();
 
-                let results =
-                    self.index
-                        .search(self.rv.as_ref(), &viewer, &query, per_page, page_number);
-                Ok(SearchResultsVal(results).into())
+                let results = self.index.search(
+                    self.rv.as_ref(),
+                    &viewer,
+                    &query,
+                    lang.as_deref(),
+                    per_page,
+                    page_number,
+                );
+                let rev = self.rv.rev().mj()?;
+                Ok(SearchResultsVal::seen_by(results, &viewer, &rev.pak.rc).into())
             }
             _ => Err(minijinja::Error::new(
                 minijinja::ErrorKind::UnknownMethod,
@@ -761,6 +844,21 @@ impl Object for ConfigVal {
 #[repr(transparent)]
 pub(crate) struct SearchResultsVal(pub(crate) SearchResults);
 
+impl SearchResultsVal {
+    /// Search results with their pages as the viewer gets to see them, cf.
+    /// [`LoadedPageVal::seen_by`]
+    pub(crate) fn seen_by(
+        mut results: SearchResults,
+        viewer: &Viewer,
+        rc: &RevisionConfig,
+    ) -> Self {
+        for result in &mut results.results {
+            result.page = LoadedPageVal::seen_by(&result.page, viewer, rc).0;
+        }
+        Self(results)
+    }
+}
+
 impl std::fmt::Debug for SearchResultsVal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.0.fmt(f)
@@ -848,6 +946,7 @@ impl minijinja::value::Object for SearchResultVal {
         key: &minijinja::value::Value,
     ) -> Option<minijinja::value::Value> {
         Some(match key.as_str()? {
+            // projected for the viewer in `SearchResultsVal::seen_by`
             "page" => LoadedPageVal(self.page.clone()).into(),
             "title_snippet" => {
                 minijinja::value::Value::from_safe_string(self.title_snippet.to_string())
@@ -1044,3 +1143,53 @@ impl Object for MediaVal {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minijinja::context;
+
+    #[test]
+    fn test_sponsors_render_as_their_name() {
+        let sponsor = SponsorVal {
+            sponsor: Sponsor {
+                name: "Ferris".into(),
+                tier: Some("Gold".into()),
+                provider: "patreon".into(),
+                identity: "patreon:1".into(),
+                avatar: None,
+                profile_url: Some("https://example.org/ferris".into()),
+                joined_at: None,
+            },
+            avatar_url: Some("https://cdn.example.org/ferris.webp".into()),
+        };
+        let sponsors = vec![Value::from_object(sponsor)];
+
+        let env = Environment::new();
+        let render = |template: &str| {
+            env.render_str(template, context! { sponsors => sponsors.clone() })
+                .unwrap()
+        };
+        assert_eq!(
+            render("{% for sponsor in sponsors %}{{ sponsor }}{% endfor %}"),
+            "Ferris"
+        );
+        assert_eq!(
+            render(
+                "{% for sponsor in sponsors %}{{ sponsor.name }} ({{ sponsor.tier }}, \
+                {{ sponsor.provider }}) {{ sponsor.avatar_url }} {{ sponsor.profile_url }}{% endfor %}"
+            ),
+            "Ferris (Gold, patreon) https://cdn.example.org/ferris.webp https://example.org/ferris"
+        );
+        assert_eq!(
+            render("{{ sponsors | join(', ') }}|{{ sponsors[0] | length }}"),
+            "Ferris|6"
+        );
+        assert_eq!(
+            render(
+                "{% for tier, s in sponsors | groupby('tier') %}{{ tier }}: {{ s | join }}{% endfor %}"
+            ),
+            "Gold: Ferris"
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use config_types::WebConfig;
+use conflux::{CacheBuster, LoadedPage, PageKind, Revision, RouteRef, VideoInfo};
+use serde_json::{Map, Value as JsonValue, json};
+use time::format_description::well_known::Rfc3339;
+
+use crate::global_functions_and_filters::truncate_core;
+
+/// Builds the schema.org JSON-LD graph for a page: the page itself (as a
+/// `BlogPosting`, `Article`, `CreativeWorkSeries`, `VideoObject` or plain
+/// `WebPage` depending on its kind), and a `BreadcrumbList` built from the
+/// route's parents.
+pub(crate) fn page_json_ld(
+    page: &LoadedPage,
+    rev: &Revision,
+    cachebuster: &dyn CacheBuster,
+    web: WebConfig,
+) -> JsonValue {
+    let url = page.canonical_url(web);
+    let image = page
+        .thumb
+        .as_ref()
+        .or(page.parent_thumb.as_ref())
+        .and_then(|thumb| cachebuster.asset_url(web, &thumb.path).ok())
+        .map(|u| u.to_string());
+
+    let mut node = Map::new();
+    node.insert("@id".into(), json!(url.as_str()));
+    node.insert("url".into(), json!(url.as_str()));
+    node.insert("name".into(), json!(page.title));
+    node.insert("headline".into(), json!(page.title));
+    node.insert(
+        "description".into(),
+        json!(truncate_core(&page.plain_text, 200)),
+    );
+    node.insert("datePublished".into(), json!(format_date(page.date.0)));
+    node.insert(
+        "dateModified".into(),
+        json!(format_date(page.updated_at.unwrap_or(page.date).0)),
+    );
+    node.insert("mainEntityOfPage".into(), json!(url.as_str()));
+    node.insert("inLanguage".into(), json!(page.lang));
+    if !page.tags.is_empty() {
+        node.insert("keywords".into(), json!(page.tags));
+    }
+    if let Some(image) = &image {
+        node.insert("image".into(), json!(image));
+    }
+
+    let video = video_object(page, image.as_deref());
+
+    let kind = match page.kind {
+        PageKind::Article => "BlogPosting",
+        PageKind::SeriesPart => "Article",
+        PageKind::SeriesIndex => "CreativeWorkSeries",
+        PageKind::Episode if video.is_some() => "VideoObject",
+        _ => "WebPage",
+    };
+    node.insert("@type".into(), json!(kind));
+
+    match page.kind {
+        PageKind::Article | PageKind::SeriesPart => {
+            node.insert("wordCount".into(), json!(word_count(&page.plain_text)));
+            if let Some(video) = video {
+                node.insert("video".into(), video);
+            }
+        }
+        PageKind::Episode => {
+            if let Some(JsonValue::Object(video)) = video {
+                // the episode *is* the video, merge the video-specific fields in
+                for (k, v) in video {
+                    if k != "@type" {
+                        node.entry(k).or_insert(v);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(series_link) = &page.series_link {
+        if let Some(index) = rev
+            .page_routes
+            .get(&series_link.index_route)
+            .and_then(|path| rev.pages.get(path))
+        {
+            let index_url = index.canonical_url(web);
+            node.insert(
+                "isPartOf".into(),
+                json!({
+                    "@type": "CreativeWorkSeries",
+                    "@id": index_url.as_str(),
+                    "name": index.title,
+                    "url": index_url.as_str(),
+                }),
+            );
+            node.insert(
+                "position".into(),
+                json!(series_link.part_number.as_usize() + 1),
+            );
+        }
+    }
+
+    if page.kind == PageKind::SeriesIndex && !page.parts.is_empty() {
+        let parts = page
+            .parts
+            .iter()
+            .enumerate()
+            .map(|(i, part)| {
+                json!({
+                    "@type": "Article",
+                    "name": part.title,
+                    "url": part.route.to_web_url_string(&page.ti.tc, web).as_str(),
+                    "position": i + 1,
+                })
+            })
+            .collect::<Vec<_>>();
+        node.insert("hasPart".into(), json!(parts));
+    }
+
+    json!({
+        "@context": "https://schema.org",
+        "@graph": [node, breadcrumb_list(page, rev, web)],
+    })
+}
+
+/// `VideoObject` for pages that have a video attached (youtube or tube)
+fn video_object(page: &LoadedPage, thumbnail: Option<&str>) -> Option<JsonValue> {
+    let VideoInfo {
+        youtube,
+        tube,
+        duration,
+        ..
+    } = &page.video_info;
+
+    let embed_url = match (youtube, tube) {
+        (Some(youtube), _) => format!("https://www.youtube.com/embed/{youtube}"),
+        (None, Some(tube)) => tube.clone(),
+        (None, None) => return None,
+    };
+
+    let mut video = Map::new();
+    video.insert("@type".into(), json!("VideoObject"));
+    video.insert("name".into(), json!(page.title));
+    video.insert(
+        "description".into(),
+        json!(truncate_core(&page.plain_text, 200)),
+    );
+    video.insert("uploadDate".into(), json!(format_date(page.date.0)));
+    video.insert("embedUrl".into(), json!(embed_url));
+    if let Some(thumbnail) = thumbnail {
+        video.insert("thumbnailUrl".into(), json!(thumbnail));
+    }
+    if let Some(duration) = duration {
+        video.insert("duration".into(), json!(iso8601_duration(*duration)));
+    }
+    Some(JsonValue::Object(video))
+}
+
+/// Home, then every ancestor of the page's route that is itself a page, then
+/// the page.
+fn breadcrumb_list(page: &LoadedPage, rev: &Revision, web: WebConfig) -> JsonValue {
+    let mut ancestors: Vec<&RouteRef> = Vec::new();
+    let mut current = page.route.parent();
+    while let Some(route) = current {
+        ancestors.push(route);
+        current = route.parent();
+    }
+    ancestors.reverse();
+
+    let mut crumbs: Vec<(String, String)> = Vec::new();
+    for route in ancestors {
+        if route.as_str() == "/" {
+            crumbs.push((
+                page.ti.tc.name.to_string(),
+                route.to_web_url_string(&page.ti.tc, web).to_string(),
+            ));
+        } else if let Some(ancestor) = rev
+            .page_routes
+            .get(route)
+            .and_then(|path| rev.pages.get(path))
+        {
+            crumbs.push((
+                ancestor.title.clone(),
+                ancestor.canonical_url(web).to_string(),
+            ));
+        }
+    }
+    crumbs.push((page.title.clone(), page.canonical_url(web).to_string()));
+
+    let items = crumbs
+        .into_iter()
+        .enumerate()
+        .map(|(i, (name, item))| {
+            json!({
+                "@type": "ListItem",
+                "position": i + 1,
+                "name": name,
+                "item": item,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "@type": "BreadcrumbList",
+        "itemListElement": items,
+    })
+}
+
+fn format_date(date: time::OffsetDateTime) -> String {
+    date.format(&Rfc3339).unwrap_or_default()
+}
+
+fn word_count(plain_text: &str) -> usize {
+    plain_text.split_whitespace().count()
+}
+
+/// e.g. 3725 seconds => `PT1H2M5S`
+fn iso8601_duration(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, (seconds % 3600) / 60, seconds % 60);
+    let mut s = "PT".to_string();
+    if hours > 0 {
+        s.push_str(&format!("{hours}H"));
+    }
+    if minutes > 0 {
+        s.push_str(&format!("{minutes}M"));
+    }
+    if seconds > 0 || (hours == 0 && minutes == 0) {
+        s.push_str(&format!("{seconds}S"));
+    }
+    s
+}
+
+/// Wraps JSON-LD in a script tag. `</` is escaped so that titles or
+/// descriptions can't close the tag early.
+pub(crate) fn json_ld_script_tag(json_ld: &JsonValue) -> String {
+    let json = json_ld.to_string().replace("</", "<\\/");
+    format!(r#"<script type="application/ld+json">{json}</script>"#)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iso8601_duration() {
+        assert_eq!(iso8601_duration(0), "PT0S");
+        assert_eq!(iso8601_duration(59), "PT59S");
+        assert_eq!(iso8601_duration(120), "PT2M");
+        assert_eq!(iso8601_duration(3725), "PT1H2M5S");
+    }
+
+    #[test]
+    fn test_json_ld_script_tag_escapes_closing_tags() {
+        let tag = json_ld_script_tag(&json!({ "name": "</script><script>alert(1)" }));
+        assert_eq!(tag.matches("</script>").count(), 1);
+    }
+}
@@ -1,17 +1,31 @@
 use std::{collections::HashSet, sync::Arc};
 
 use config_types::{TenantInfo, WebConfig};
-use conflux::{Href, InputPath, InputPathRef, MarkdownRef, RevisionView, Toc};
+use conflux::{Href, InputPath, InputPathRef, MarkdownRef, MembersSection, RevisionView, Toc};
 use template_types::TemplateCollection;
 
 pub struct CollectDependenciesResult {
-    // the YAML frontmatter, to be parsed later
+    // the frontmatter, to be parsed later
     pub frontmatter: Option<String>,
 
+    // whether the frontmatter is YAML or TOML
+    pub frontmatter_format: FrontmatterFormat,
+
     // dependencies
     pub deps: HashSet<InputPath>,
 }
 
+/// How a page's frontmatter is written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrontmatterFormat {
+    /// between `---` lines
+    #[default]
+    Yaml,
+
+    /// between `+++` lines, at the very start of the page (like Zola and Hugo)
+    Toml,
+}
+
 pub struct ProcessMarkdownArgs<'a> {
     pub path: &'a InputPathRef,
     pub markdown: &'a MarkdownRef,
@@ -25,9 +39,12 @@ pub struct ProcessMarkdownArgs<'a> {
 /// The result of processing markdown
 #[derive(Default)]
 pub struct ProcessMarkdownResult {
-    // the YAML frontmatter, to be parsed later
+    // the frontmatter, to be parsed later
     pub frontmatter: Option<String>,
 
+    // whether the frontmatter is YAML or TOML
+    pub frontmatter_format: FrontmatterFormat,
+
     // dependencies
     pub deps: HashSet<InputPath>,
 
@@ -42,4 +59,7 @@ pub struct ProcessMarkdownResult {
 
     // all links
     pub links: HashSet<Href>,
+
+    // members-only sections, their placeholders are in the HTML output
+    pub members_sections: Vec<MembersSection>,
 }
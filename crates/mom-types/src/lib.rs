@@ -1,10 +1,12 @@
 use camino::Utf8PathBuf;
-use conflux::{Derivation, DerivationHash, Input, InputPath, Pak};
+use conflux::{Derivation, DerivationHash, Input, InputPath, Pak, Route};
+use credentials::{AuthBundle, Profile, UserPreferences, UserPreferencesPatch};
 use derivations::DerivationInfo;
 use media_types::{TargetFormat, TranscodingProgress};
+use merde::time::Rfc3339;
 use objectstore_types::ObjectStoreKey;
-use serde::Serialize;
 use std::{collections::HashMap, sync::Arc, time::Instant};
+use time::OffsetDateTime;
 
 use config_types::{MomConfig, TenantConfig, TenantDomain, TenantInfo, WebConfig};
 
@@ -16,17 +18,75 @@ pub trait GlobalStateView: Send + Sync + 'static {
     fn gsv_ti(&self) -> Arc<TenantInfo> {
         unimplemented!()
     }
+
+    fn gsv_webmentions(&self) -> Arc<Webmentions> {
+        unimplemented!()
+    }
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Debug, Clone)]
 pub struct Sponsors {
-    pub sponsors: Vec<String>,
+    /// Highest tier first, then longest-standing
+    pub sponsors: Vec<Sponsor>,
 }
 
 merde::derive! {
     impl (Serialize, Deserialize) for struct Sponsors { sponsors }
 }
 
+/// Someone credited on the site for sponsoring
+#[derive(Debug, Clone)]
+pub struct Sponsor {
+    pub name: String,
+
+    /// Name of the tier they're credited at, as configured in `tiers`
+    pub tier: Option<String>,
+
+    /// `patreon` or `github`
+    pub provider: String,
+
+    /// e.g. `patreon:1234`, matches `Profile::identities`
+    pub identity: String,
+
+    /// Their avatar, served from our CDN rather than hotlinked
+    pub avatar: Option<SponsorAvatar>,
+
+    pub profile_url: Option<String>,
+
+    /// When they started sponsoring, if the provider tells us
+    pub joined_at: Option<Rfc3339<OffsetDateTime>>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct Sponsor { name, tier, provider, identity, avatar, profile_url, joined_at }
+}
+
+/// A sponsor's avatar, copied to the object store and resized like any other
+/// bitmap asset.
+#[derive(Debug, Clone)]
+pub struct SponsorAvatar {
+    pub params: DeriveParams,
+
+    /// Where cub serves the derivation
+    pub route: Route,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct SponsorAvatar { params, route }
+}
+
+/// Sent by cub when someone doesn't (or again does) want to be credited
+#[derive(Debug, Clone)]
+pub struct SponsorOptOutArgs {
+    /// Every identity of the person asking, so it applies to all their sponsorships
+    pub identities: Vec<String>,
+    pub opt_out: bool,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct SponsorOptOutArgs { identities, opt_out }
+}
+
 #[derive(Debug, Clone)]
 pub struct TranscodeJobInfo {
     pub started: Instant,
@@ -400,6 +460,395 @@ merde::derive! {
     impl (Serialize, Deserialize) for struct ListMissingResponse { missing }
 }
 
+/// A single comment, as stored by mom
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub id: i64,
+
+    /// The comment this is a reply to, if any
+    pub parent_id: Option<i64>,
+
+    /// The author's `Profile::global_id`, e.g. `patreon:1234`
+    pub author_id: String,
+    pub author_name: String,
+    pub author_thumb_url: String,
+
+    /// Markdown source, as typed by the author (empty once deleted)
+    pub markdown: String,
+
+    /// Rendered and sanitized HTML (empty once deleted)
+    pub html: String,
+
+    pub created_at: Rfc3339<OffsetDateTime>,
+    pub edited_at: Option<Rfc3339<OffsetDateTime>>,
+
+    /// Deleted comments are kept (with their content cleared) so that
+    /// replies still have a parent.
+    pub deleted: bool,
+
+    /// Hidden by a moderator: only admins get to see those.
+    pub hidden: bool,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct Comment {
+        id,
+        parent_id,
+        author_id,
+        author_name,
+        author_thumb_url,
+        markdown,
+        html,
+        created_at,
+        edited_at,
+        deleted,
+        hidden
+    }
+}
+
+/// All the comments for a given route, oldest first
+#[derive(Debug, Clone)]
+pub struct CommentThread {
+    pub route: Route,
+    pub comments: Vec<Comment>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct CommentThread { route, comments }
+}
+
+#[derive(Debug, Clone)]
+pub struct ListCommentsArgs {
+    pub route: Route,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct ListCommentsArgs { route }
+}
+
+#[derive(Debug, Clone)]
+pub struct PostCommentArgs {
+    pub route: Route,
+    pub parent_id: Option<i64>,
+    pub markdown: String,
+
+    /// Who's posting, taken from the (already authenticated) auth bundle
+    pub author: Profile,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct PostCommentArgs { route, parent_id, markdown, author }
+}
+
+#[derive(Debug, Clone)]
+pub struct EditCommentArgs {
+    pub comment_id: i64,
+    pub markdown: String,
+
+    /// Must match the comment's author
+    pub author_id: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct EditCommentArgs { comment_id, markdown, author_id }
+}
+
+#[derive(Debug, Clone)]
+pub struct DeleteCommentArgs {
+    pub comment_id: i64,
+
+    /// Must match the comment's author
+    pub author_id: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct DeleteCommentArgs { comment_id, author_id }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModerationAction {
+    /// Hide a comment from everyone but admins
+    Hide,
+    /// Undo `Hide`
+    Unhide,
+    /// Prevent someone from commenting, and hide all their comments
+    Ban,
+    /// Allow someone to comment again (their comments stay hidden)
+    Unban,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for enum ModerationAction string_like {
+        "hide" => Hide,
+        "unhide" => Unhide,
+        "ban" => Ban,
+        "unban" => Unban,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModerateCommentArgs {
+    pub action: ModerationAction,
+
+    /// Required for hide/unhide. For ban/unban, designates the author
+    /// when `global_id` isn't given.
+    pub comment_id: Option<i64>,
+
+    /// Who to ban/unban, as returned by `Profile::global_id`
+    pub global_id: Option<String>,
+
+    pub reason: Option<String>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct ModerateCommentArgs { action, comment_id, global_id, reason }
+}
+
+/// Sent whenever comments change, so cubs can drop their cached threads
+#[derive(Debug, Clone)]
+pub struct CommentsUpdated {
+    pub routes: Vec<Route>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct CommentsUpdated { routes }
+}
+
+/// Sent by cub when someone POSTs to `/api/webmention`
+#[derive(Debug, Clone)]
+pub struct ReceiveWebmentionArgs {
+    /// The page that (supposedly) links to us
+    pub source: String,
+
+    /// The absolute URL of our page, as given by the sender
+    pub target: String,
+
+    /// The route `target` resolved to
+    pub route: Route,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct ReceiveWebmentionArgs { source, target, route }
+}
+
+/// A mention of one of our pages that has been verified to link to it
+#[derive(Debug, Clone)]
+pub struct Webmention {
+    pub source: String,
+
+    /// The `<title>` of the source page, if it had one
+    pub title: Option<String>,
+
+    pub verified_at: Rfc3339<OffsetDateTime>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct Webmention { source, title, verified_at }
+}
+
+/// All verified webmentions for a tenant, newest first for each route
+#[derive(Debug, Clone, Default)]
+pub struct Webmentions {
+    pub by_route: HashMap<Route, Vec<Webmention>>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct Webmentions { by_route }
+}
+
+/// Sent by cub after deploying a revision: mom notifies every external
+/// target it hasn't notified yet for that source.
+#[derive(Debug, Clone)]
+pub struct SendWebmentionsArgs {
+    pub pages: Vec<OutgoingLinks>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct SendWebmentionsArgs { pages }
+}
+
+#[derive(Debug, Clone)]
+pub struct OutgoingLinks {
+    /// The absolute URL of our page
+    pub source: String,
+
+    /// Absolute URLs of external pages it links to
+    pub targets: Vec<String>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct OutgoingLinks { source, targets }
+}
+
+#[derive(Debug, Clone)]
+pub struct GetUserPreferencesArgs {
+    /// As returned by `Profile::global_id`
+    pub global_id: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct GetUserPreferencesArgs { global_id }
+}
+
+#[derive(Debug, Clone)]
+pub struct UpdateUserPreferencesArgs {
+    /// As returned by `Profile::global_id`
+    pub global_id: String,
+    pub patch: UserPreferencesPatch,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct UpdateUserPreferencesArgs { global_id, patch }
+}
+
+/// Sent when someone changes their preferences, so cubs can update their cache
+#[derive(Debug, Clone)]
+pub struct UserPreferencesUpdated {
+    pub global_id: String,
+    pub preferences: UserPreferences,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct UserPreferencesUpdated { global_id, preferences }
+}
+
+/// Sent by cub when someone asks for a sign-in link
+#[derive(Debug, Clone)]
+pub struct RequestMagicLinkArgs {
+    pub email: String,
+
+    /// Address of whoever asked, so mom can throttle requests per client
+    pub client_ip: Option<String>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct RequestMagicLinkArgs { email, client_ip }
+}
+
+/// Sent by cub when someone follows a sign-in link
+#[derive(Debug, Clone)]
+pub struct MagicLinkCallbackArgs {
+    pub token: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct MagicLinkCallbackArgs { token }
+}
+
+#[derive(Debug, Clone)]
+pub struct MagicLinkCallbackResponse {
+    pub auth_bundle: AuthBundle,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct MagicLinkCallbackResponse { auth_bundle }
+}
+
+/// Sent by cub when the auth bundle of someone who signed in with email has
+/// expired, to pick up any tier they've been granted since.
+#[derive(Debug, Clone)]
+pub struct MagicLinkRefreshCredentialsArgs {
+    pub email: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct MagicLinkRefreshCredentialsArgs { email }
+}
+
+/// Sent by cub when someone who signed in with email picks a name to go by
+#[derive(Debug, Clone)]
+pub struct SetMagicLinkNameArgs {
+    pub email: String,
+    pub full_name: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct SetMagicLinkNameArgs { email, full_name }
+}
+
+/// Sent by cub when someone who's logged in signs in with another provider,
+/// asking to link both.
+#[derive(Debug, Clone)]
+pub struct LinkIdentitiesArgs {
+    /// Who they were logged in as
+    pub current: Profile,
+
+    /// Who they just signed in as
+    pub new: Profile,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct LinkIdentitiesArgs { current, new }
+}
+
+#[derive(Debug, Clone)]
+pub struct ListIdentitiesArgs {
+    /// As returned by `Profile::global_id`
+    pub global_id: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct ListIdentitiesArgs { global_id }
+}
+
+#[derive(Debug, Clone)]
+pub struct UnlinkIdentityArgs {
+    /// As returned by `Profile::global_id`
+    pub global_id: String,
+
+    /// As returned by `Profile::identities`, e.g. `github:5678`
+    pub identity: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct UnlinkIdentityArgs { global_id, identity }
+}
+
+#[derive(Debug, Clone)]
+pub struct LinkedIdentity {
+    /// As returned by `Profile::identities`, e.g. `github:5678`
+    pub identity: String,
+    pub linked_at: Rfc3339<OffsetDateTime>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct LinkedIdentity { identity, linked_at }
+}
+
+/// Identities that were linked together, for the admin view
+#[derive(Debug, Clone)]
+pub struct LinkedAccount {
+    pub account_id: i64,
+    pub identities: Vec<LinkedIdentity>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct LinkedAccount { account_id, identities }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordDownloadArgs {
+    /// Path under `extra-files/`, e.g. `rust-101/slides.pdf`
+    pub path: String,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct RecordDownloadArgs { path }
+}
+
+/// How many times a page attachment was downloaded, for the admin view
+#[derive(Debug, Clone)]
+pub struct DownloadCount {
+    /// Path under `extra-files/`
+    pub path: String,
+    pub count: i64,
+    pub last_downloaded_at: Rfc3339<OffsetDateTime>,
+}
+
+merde::derive! {
+    impl (Serialize, Deserialize) for struct DownloadCount { path, count, last_downloaded_at }
+}
+
 #[derive(Debug)]
 pub enum MomEvent {
     GoodMorning(GoodMorning),
@@ -428,6 +877,9 @@ merde::derive! {
 pub enum TenantEventPayload {
     RevisionChanged(Box<Pak>),
     SponsorsUpdated(Sponsors),
+    CommentsUpdated(CommentsUpdated),
+    WebmentionsUpdated(Webmentions),
+    UserPreferencesUpdated(UserPreferencesUpdated),
 }
 
 impl std::fmt::Debug for TenantEventPayload {
@@ -447,6 +899,19 @@ impl std::fmt::Debug for TenantEventPayload {
                 "TenantEvent::SponsorsUpdated({} sponsors)",
                 sponsors.sponsors.len()
             ),
+            TenantEventPayload::CommentsUpdated(ev) => write!(
+                f,
+                "TenantEvent::CommentsUpdated({} routes)",
+                ev.routes.len()
+            ),
+            TenantEventPayload::WebmentionsUpdated(webmentions) => write!(
+                f,
+                "TenantEvent::WebmentionsUpdated({} routes)",
+                webmentions.by_route.len()
+            ),
+            TenantEventPayload::UserPreferencesUpdated(ev) => {
+                write!(f, "TenantEvent::UserPreferencesUpdated({})", ev.global_id)
+            }
         }
     }
 }
@@ -457,6 +922,9 @@ merde::derive! {
     {
         "RevisionChanged" => RevisionChanged,
         "SponsorsUpdated" => SponsorsUpdated,
+        "CommentsUpdated" => CommentsUpdated,
+        "WebmentionsUpdated" => WebmentionsUpdated,
+        "UserPreferencesUpdated" => UserPreferencesUpdated,
     }
 }
 
@@ -476,6 +944,9 @@ pub struct TenantInitialState {
     /// The sponsors for this tenant
     pub sponsors: Option<Sponsors>,
 
+    /// Verified webmentions for this tenant
+    pub webmentions: Option<Webmentions>,
+
     /// The configuration for this tenant
     pub tc: TenantConfig,
 
@@ -484,7 +955,7 @@ pub struct TenantInitialState {
 }
 
 merde::derive! {
-    impl (Serialize, Deserialize) for struct TenantInitialState { pak, sponsors, tc, base_dir }
+    impl (Serialize, Deserialize) for struct TenantInitialState { pak, sponsors, webmentions, tc, base_dir }
 }
 
 impl std::fmt::Debug for TenantInitialState {
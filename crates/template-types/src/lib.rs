@@ -5,7 +5,7 @@ use std::{
 
 use config_types::WebConfig;
 use conflux::{InputPath, LoadedPage, RevisionView, RouteRef};
-use credentials::UserInfo;
+use credentials::{UserInfo, UserPreferences};
 use libsearch::Index;
 use mom_types::GlobalStateView;
 
@@ -23,6 +23,10 @@ pub trait TemplateCollection: Send + Sync {
         rv: Arc<dyn RevisionView>,
         web: WebConfig,
     ) -> eyre::Result<RenderShortcodeResult>;
+
+    /// Forgets rendered `{% call cache(key) %}` blocks, for when something
+    /// they might show changed without a new revision (e.g. sponsors)
+    fn clear_fragment_cache(&self, _reason: &str) {}
 }
 
 impl TemplateCollection for () {
@@ -72,6 +76,9 @@ pub struct RenderTemplateArgs<'a> {
     /// Gotten from cookies
     pub user_info: Option<UserInfo>,
 
+    /// The viewer's preferences (defaults if logged out)
+    pub preferences: Arc<UserPreferences>,
+
     /// Web configuration
     pub web: WebConfig,
 